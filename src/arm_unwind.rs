@@ -0,0 +1,618 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::is_executable_rva;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x1c4;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum ArmArchitecture {
+    Arm64,
+    // ARMv7 in Thumb-2 mode
+    ArmNt,
+}
+
+// Packed ARM64 unwind data, lengths and sizes already scaled to bytes
+#[derive(Debug)]
+pub struct PackedArm64 {
+    pub flag: u8,
+    pub function_length: u32,
+    pub reg_f: u8,
+    pub reg_i: u8,
+    pub h: bool,
+    pub cr: u8,
+    pub frame_size: u32,
+}
+
+// Packed ARMv7 unwind data, lengths and sizes already scaled to bytes
+#[derive(Debug)]
+pub struct PackedArm {
+    pub flag: u8,
+    pub function_length: u32,
+    pub ret: u8,
+    pub h: bool,
+    pub reg: u8,
+    pub r: bool,
+    pub l: bool,
+    pub c: bool,
+    pub stack_adjust: u32,
+}
+
+pub struct EpilogScope {
+    pub start_offset: u32,
+    pub start_index: u16,
+    // ARMv7 epilogs can be conditional
+    pub condition: Option<u8>,
+}
+
+pub struct ArmUnwindCode {
+    // Index of the first byte of the code in the unwind code array
+    pub index: usize,
+    pub bytes: Vec<u8>,
+    pub operation: String,
+}
+
+pub struct XdataRecord {
+    pub rva: u32,
+    pub function_length: u32,
+    pub version: u8,
+    pub x: bool,
+    pub e: bool,
+    // ARMv7 only, the function is a fragment without prolog
+    pub f: Option<bool>,
+    pub epilog_count: u16,
+    pub code_words: u8,
+    pub epilog_scopes: Vec<EpilogScope>,
+    pub codes: Vec<ArmUnwindCode>,
+    pub exception_handler: Option<u32>,
+    pub handler_data_rva: Option<u32>,
+}
+
+pub enum ArmUnwindData {
+    PackedArm64(PackedArm64),
+    PackedArm(PackedArm),
+    Xdata(XdataRecord),
+    Reserved(u32),
+}
+
+pub struct ArmRuntimeFunction {
+    pub architecture: ArmArchitecture,
+    // Without the Thumb bit for ARMv7
+    pub begin_address: u32,
+    pub unwind_data: u32,
+    pub unwind: Option<ArmUnwindData>,
+    pub anomalies: Vec<String>,
+}
+
+// Walks the .pdata entries of an ARM64 or ARMv7 image lazily
+pub struct ArmRuntimeFunctions<'a> {
+    file: &'a [u8],
+    headers: &'a Headers,
+    architecture: ArmArchitecture,
+    offset: usize,
+    index: usize,
+    count: usize,
+    previous_begin: Option<u32>,
+}
+
+
+// Conversion implementation for the structs
+
+impl PackedArm64 {
+    pub fn from_u32(val: u32) -> PackedArm64 {
+        PackedArm64 {
+            flag: (val & 0x3) as u8,
+            function_length: ((val >> 2) & 0x7ff) * 4,
+            reg_f: ((val >> 13) & 0x7) as u8,
+            reg_i: ((val >> 16) & 0xf) as u8,
+            h: (val >> 20) & 1 != 0,
+            cr: ((val >> 21) & 0x3) as u8,
+            frame_size: (val >> 23) * 16,
+        }
+    }
+}
+
+impl PackedArm {
+    pub fn from_u32(val: u32) -> PackedArm {
+        PackedArm {
+            flag: (val & 0x3) as u8,
+            function_length: ((val >> 2) & 0x7ff) * 2,
+            ret: ((val >> 13) & 0x3) as u8,
+            h: (val >> 15) & 1 != 0,
+            reg: ((val >> 16) & 0x7) as u8,
+            r: (val >> 19) & 1 != 0,
+            l: (val >> 20) & 1 != 0,
+            c: (val >> 21) & 1 != 0,
+            stack_adjust: (val >> 22) * 4,
+        }
+    }
+}
+
+impl ArmRuntimeFunction {
+    pub fn function_length(&self) -> Option<u32> {
+        match &self.unwind {
+            Some(ArmUnwindData::PackedArm64(packed)) => Some(packed.function_length),
+            Some(ArmUnwindData::PackedArm(packed)) => Some(packed.function_length),
+            Some(ArmUnwindData::Xdata(xdata)) => Some(xdata.function_length),
+            _ => None,
+        }
+    }
+
+    pub fn end_address(&self) -> Option<u32> {
+        self.function_length().map(|length| self.begin_address + length)
+    }
+}
+
+
+// Unwind code decoding
+
+fn register_range(first: &str, start: u32, count: u32) -> String {
+    if count <= 1 {
+        format!("{}{}", first, start)
+    } else {
+        format!("{}{}-{}{}", first, start, first, start + count - 1)
+    }
+}
+
+// Returns the size of the code starting with `byte` and its mnemonic
+fn decode_arm64_code(bytes: &[u8]) -> (usize, String) {
+    let b0 = bytes[0] as u32;
+    let b1 = bytes.get(1).copied().unwrap_or(0) as u32;
+    let b2 = bytes.get(2).copied().unwrap_or(0) as u32;
+    let b3 = bytes.get(3).copied().unwrap_or(0) as u32;
+    let pair = (b0 << 8) | b1;
+    match b0 {
+        0x00..=0x1f => (1, format!("alloc_s sp, #{}", (b0 & 0x1f) * 16)),
+        0x20..=0x3f => (1, format!("save_r19r20_x [sp, #-{}]!", (b0 & 0x1f) * 8)),
+        0x40..=0x7f => (1, format!("save_fplr [sp, #{}]", (b0 & 0x3f) * 8)),
+        0x80..=0xbf => (1, format!("save_fplr_x [sp, #-{}]!", ((b0 & 0x3f) + 1) * 8)),
+        0xc0..=0xc7 => (2, format!("alloc_m sp, #{}", (pair & 0x7ff) * 16)),
+        0xc8..=0xcb => (2, format!("save_regp x{}, [sp, #{}]", 19 + ((pair >> 6) & 0xf), (pair & 0x3f) * 8)),
+        0xcc..=0xcf => (2, format!("save_regp_x x{}, [sp, #-{}]!", 19 + ((pair >> 6) & 0xf), ((pair & 0x3f) + 1) * 8)),
+        0xd0..=0xd3 => (2, format!("save_reg x{}, [sp, #{}]", 19 + ((pair >> 6) & 0xf), (pair & 0x3f) * 8)),
+        0xd4 | 0xd5 => (2, format!("save_reg_x x{}, [sp, #-{}]!", 19 + ((pair >> 5) & 0xf), ((pair & 0x1f) + 1) * 8)),
+        0xd6 | 0xd7 => (2, format!("save_lrpair x{}, lr, [sp, #{}]", 19 + 2 * ((pair >> 6) & 0x7), (pair & 0x3f) * 8)),
+        0xd8 | 0xd9 => (2, format!("save_fregp d{}, [sp, #{}]", 8 + ((pair >> 6) & 0x7), (pair & 0x3f) * 8)),
+        0xda | 0xdb => (2, format!("save_fregp_x d{}, [sp, #-{}]!", 8 + ((pair >> 6) & 0x7), ((pair & 0x3f) + 1) * 8)),
+        0xdc | 0xdd => (2, format!("save_freg d{}, [sp, #{}]", 8 + ((pair >> 6) & 0x7), (pair & 0x3f) * 8)),
+        0xde => (2, format!("save_freg_x d{}, [sp, #-{}]!", 8 + ((b1 >> 5) & 0x7), ((b1 & 0x1f) + 1) * 8)),
+        0xdf => (2, format!("alloc_z sp, #{} * VL", b1)),
+        0xe0 => (4, format!("alloc_l sp, #{}", ((b1 << 16) | (b2 << 8) | b3) * 16)),
+        0xe1 => (1, String::from("set_fp")),
+        0xe2 => (2, format!("add_fp fp, sp, #{}", b1 * 8)),
+        0xe3 => (1, String::from("nop")),
+        0xe4 => (1, String::from("end")),
+        0xe5 => (1, String::from("end_c")),
+        0xe6 => (1, String::from("save_next")),
+        0xe7 => (3, format!("save_any_reg 0x{:02x}{:02x}", b1, b2)),
+        0xe8 => (1, String::from("MSFT_OP_TRAP_FRAME")),
+        0xe9 => (1, String::from("MSFT_OP_MACHINE_FRAME")),
+        0xea => (1, String::from("MSFT_OP_CONTEXT")),
+        0xeb => (1, String::from("MSFT_OP_EC_CONTEXT")),
+        0xec => (1, String::from("MSFT_OP_CLEAR_UNWOUND_TO_CALL")),
+        0xfc => (1, String::from("pac_sign_lr")),
+        _ => (1, format!("reserved 0x{:02x}", b0)),
+    }
+}
+
+fn arm_register_list(mask: u32) -> String {
+    let names: Vec<String> = (0..16).filter(|x| mask & (1 << x) != 0).map(|x| match x {
+        13 => String::from("sp"),
+        14 => String::from("lr"),
+        15 => String::from("pc"),
+        x => format!("r{}", x),
+    }).collect();
+    format!("{{{}}}", names.join(", "))
+}
+
+fn decode_arm_code(bytes: &[u8]) -> (usize, String) {
+    let b0 = bytes[0] as u32;
+    let b1 = bytes.get(1).copied().unwrap_or(0) as u32;
+    let b2 = bytes.get(2).copied().unwrap_or(0) as u32;
+    let b3 = bytes.get(3).copied().unwrap_or(0) as u32;
+    let pair = (b0 << 8) | b1;
+    match b0 {
+        0x00..=0x7f => (1, format!("add sp, sp, #{}", b0 * 4)),
+        0x80..=0xbf => (2, format!("pop {}", arm_register_list((pair & 0x1fff) | ((pair & 0x2000) << 1)))),
+        0xc0..=0xcf => (1, format!("mov sp, r{}", b0 & 0xf)),
+        0xd0..=0xd7 => (1, format!("pop {{{}{}}}", register_range("r", 4, (b0 & 0x3) + 1), if b0 & 0x4 != 0 { ", lr" } else { "" })),
+        0xd8..=0xdf => (1, format!("pop.w {{{}{}}}", register_range("r", 4, (b0 & 0x3) + 5), if b0 & 0x4 != 0 { ", lr" } else { "" })),
+        0xe0..=0xe7 => (1, format!("vpop {{{}}}", register_range("d", 8, (b0 & 0x7) + 1))),
+        0xe8..=0xeb => (2, format!("addw sp, sp, #{}", (pair & 0x3ff) * 4)),
+        0xec | 0xed => (2, format!("pop {}", arm_register_list((b1 & 0xff) | ((b0 & 1) << 14)))),
+        0xee if b1 < 0x10 => (2, format!("microsoft specific 0x{:x}", b1)),
+        0xef if b1 < 0x10 => (2, format!("ldr lr, [sp], #{}", (b1 & 0xf) * 4)),
+        0xf5 => (2, format!("vpop {{{}}}", register_range("d", b1 >> 4, (b1 & 0xf).saturating_sub(b1 >> 4) + 1))),
+        0xf6 => (2, format!("vpop {{{}}}", register_range("d", 16 + (b1 >> 4), (b1 & 0xf).saturating_sub(b1 >> 4) + 1))),
+        0xf7 => (3, format!("add sp, sp, #{}", ((b1 << 8) | b2) * 4)),
+        0xf8 => (4, format!("add sp, sp, #{}", ((b1 << 16) | (b2 << 8) | b3) * 4)),
+        0xf9 => (3, format!("add.w sp, sp, #{}", ((b1 << 8) | b2) * 4)),
+        0xfa => (4, format!("add.w sp, sp, #{}", ((b1 << 16) | (b2 << 8) | b3) * 4)),
+        0xfb => (1, String::from("nop")),
+        0xfc => (1, String::from("nop.w")),
+        0xfd => (1, String::from("end + nop")),
+        0xfe => (1, String::from("end + nop.w")),
+        0xff => (1, String::from("end")),
+        _ => (1, format!("reserved 0x{:02x}", b0)),
+    }
+}
+
+fn decode_codes(bytes: &[u8], architecture: ArmArchitecture) -> Vec<ArmUnwindCode> {
+    let mut codes = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let (size, operation) = match architecture {
+            ArmArchitecture::Arm64 => decode_arm64_code(&bytes[index..]),
+            ArmArchitecture::ArmNt => decode_arm_code(&bytes[index..]),
+        };
+        let size = size.min(bytes.len() - index);
+        codes.push(ArmUnwindCode { index, bytes: bytes[index..index + size].to_vec(), operation });
+        index += size;
+    }
+    // Whatever follows the last end code only pads the array to a whole word
+    if let Some(last_end) = codes.iter().rposition(|code| code.operation.starts_with("end")) {
+        codes.truncate(last_end + 1);
+    }
+    codes
+}
+
+
+// Parsing
+
+fn parse_xdata(file: &[u8], headers: &Headers, rva: u32, architecture: ArmArchitecture, anomalies: &mut Vec<String>) -> Option<XdataRecord> {
+    let offset = match rva_to_offset(headers, rva) {
+        Some(offset) => offset,
+        None => {
+            anomalies.push(format!(".xdata record at RVA 0x{:x} is not backed by the file", rva));
+            return None;
+        }
+    };
+    let header = match read_u32_at(file, offset) {
+        Some(header) => header,
+        None => {
+            anomalies.push(format!(".xdata record at RVA 0x{:x} is truncated", rva));
+            return None;
+        }
+    };
+    let is_arm64 = architecture == ArmArchitecture::Arm64;
+    let mut xdata = XdataRecord {
+        rva,
+        function_length: if is_arm64 { (header & 0x3ffff) * 4 } else { (header & 0x3ffff) * 2 },
+        version: ((header >> 18) & 0x3) as u8,
+        x: (header >> 20) & 1 != 0,
+        e: (header >> 21) & 1 != 0,
+        f: if is_arm64 { None } else { Some((header >> 22) & 1 != 0) },
+        epilog_count: if is_arm64 { ((header >> 22) & 0x1f) as u16 } else { ((header >> 23) & 0x1f) as u16 },
+        code_words: if is_arm64 { (header >> 27) as u8 } else { (header >> 28) as u8 },
+        epilog_scopes: Vec::new(),
+        codes: Vec::new(),
+        exception_handler: None,
+        handler_data_rva: None,
+    };
+    if xdata.version != 0 {
+        anomalies.push(format!(".xdata record at RVA 0x{:x} has the unknown version {}", rva, xdata.version));
+    }
+    let mut index = offset + 4;
+    // Counts that do not fit in the first word move to an extension word
+    if xdata.epilog_count == 0 && xdata.code_words == 0 {
+        let extension = read_u32_at(file, index).unwrap_or(0);
+        xdata.epilog_count = (extension & 0xffff) as u16;
+        xdata.code_words = ((extension >> 16) & 0xff) as u8;
+        index += 4;
+    }
+    // With E set the epilog count is the index of the single epilog's first code
+    if !xdata.e {
+        for x in 0..xdata.epilog_count as usize {
+            let scope = match read_u32_at(file, index) {
+                Some(scope) => scope,
+                None => {
+                    anomalies.push(format!("Epilog scopes of .xdata record 0x{:x} are truncated", rva));
+                    return Some(xdata);
+                }
+            };
+            xdata.epilog_scopes.push(if is_arm64 {
+                EpilogScope { start_offset: (scope & 0x3ffff) * 4, start_index: (scope >> 22) as u16, condition: None }
+            } else {
+                EpilogScope { start_offset: (scope & 0x3ffff) * 2, start_index: (scope >> 24) as u16, condition: Some(((scope >> 20) & 0xf) as u8) }
+            });
+            index += 4;
+        }
+    }
+    let code_size = xdata.code_words as usize * 4;
+    match file.get(index..index + code_size) {
+        Some(bytes) => xdata.codes = decode_codes(bytes, architecture),
+        None => {
+            anomalies.push(format!("Unwind codes of .xdata record 0x{:x} are truncated", rva));
+            return Some(xdata);
+        }
+    }
+    for scope in &xdata.epilog_scopes {
+        if scope.start_index as usize >= code_size {
+            anomalies.push(format!("Epilog at offset 0x{:x} starts past the unwind codes", scope.start_offset));
+        }
+    }
+    index += code_size;
+    if xdata.x {
+        xdata.exception_handler = read_u32_at(file, index);
+        xdata.handler_data_rva = Some(rva + (index - offset) as u32 + 4);
+        match xdata.exception_handler {
+            Some(handler) if !is_executable_rva(headers, handler) => {
+                anomalies.push(format!("Exception handler 0x{:x} is not in an executable section", handler));
+            }
+            None => anomalies.push(format!("Exception handler of .xdata record 0x{:x} is not backed by the file", rva)),
+            _ => {}
+        }
+    }
+    Some(xdata)
+}
+
+impl<'a> Iterator for ArmRuntimeFunctions<'a> {
+    type Item = ArmRuntimeFunction;
+
+    fn next(&mut self) -> Option<ArmRuntimeFunction> {
+        if self.index >= self.count {
+            return None;
+        }
+        let entry = self.offset + self.index * 8;
+        self.index += 1;
+        let begin_address = read_u32_at(self.file, entry)?;
+        let unwind_data = read_u32_at(self.file, entry + 4)?;
+        let mut function = ArmRuntimeFunction {
+            architecture: self.architecture,
+            begin_address: if self.architecture == ArmArchitecture::ArmNt { begin_address & !1 } else { begin_address },
+            unwind_data,
+            unwind: None,
+            anomalies: Vec::new(),
+        };
+        function.unwind = Some(match (unwind_data & 3, self.architecture) {
+            (0, architecture) => match parse_xdata(self.file, self.headers, unwind_data, architecture, &mut function.anomalies) {
+                Some(xdata) => ArmUnwindData::Xdata(xdata),
+                None => ArmUnwindData::Reserved(unwind_data),
+            },
+            (3, _) => {
+                function.anomalies.push(format!("Function 0x{:x} uses the reserved unwind flag 3", function.begin_address));
+                ArmUnwindData::Reserved(unwind_data)
+            }
+            (_, ArmArchitecture::Arm64) => ArmUnwindData::PackedArm64(PackedArm64::from_u32(unwind_data)),
+            (_, ArmArchitecture::ArmNt) => ArmUnwindData::PackedArm(PackedArm::from_u32(unwind_data)),
+        });
+        if self.architecture == ArmArchitecture::ArmNt && begin_address & 1 == 0 {
+            function.anomalies.push(format!("Function 0x{:x} does not have the Thumb bit set", begin_address));
+        }
+        if !is_executable_rva(self.headers, function.begin_address) {
+            function.anomalies.push(format!("Function 0x{:x} is not in an executable section", function.begin_address));
+        }
+        if self.previous_begin.is_some_and(|previous| previous >= function.begin_address) {
+            function.anomalies.push(format!("Function 0x{:x} breaks the ordering of the exception directory", function.begin_address));
+        }
+        self.previous_begin = Some(function.begin_address);
+        Some(function)
+    }
+}
+
+pub fn arm_runtime_functions<'a>(file: &'a [u8], headers: &'a Headers) -> ArmRuntimeFunctions<'a> {
+    let architecture = match headers.coff_headers.target_machine {
+        IMAGE_FILE_MACHINE_ARMNT => ArmArchitecture::ArmNt,
+        _ => ArmArchitecture::Arm64,
+    };
+    let mut functions = ArmRuntimeFunctions { file, headers, architecture, offset: 0, index: 0, count: 0, previous_begin: None };
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION];
+    let machine = headers.coff_headers.target_machine;
+    if directory.virtual_address == 0 || (machine != IMAGE_FILE_MACHINE_ARMNT && machine != IMAGE_FILE_MACHINE_ARM64) {
+        return functions;
+    }
+    if let Some(offset) = rva_to_offset(headers, directory.virtual_address) {
+        functions.offset = offset;
+        // Entries not entirely backed by the file are dropped
+        functions.count = (directory.size as usize / 8).min(file.len().saturating_sub(offset) / 8);
+    }
+    functions
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for ArmRuntimeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08x}", self.begin_address)?;
+        if let Some(end) = self.end_address() {
+            write!(f, "-0x{:08x}", end)?;
+        }
+        match &self.unwind {
+            Some(ArmUnwindData::PackedArm64(packed)) => write!(f, " packed {:?}", packed),
+            Some(ArmUnwindData::PackedArm(packed)) => write!(f, " packed {:?}", packed),
+            Some(ArmUnwindData::Xdata(xdata)) => write!(f, " {}", xdata),
+            _ => write!(f, " unwind 0x{:x}", self.unwind_data),
+        }
+    }
+}
+
+impl fmt::Display for XdataRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "xdata 0x{:x} version: {} code_words: {}", self.rva, self.version, self.code_words)?;
+        if self.e {
+            write!(f, " single epilog at code {}", self.epilog_count)?;
+        } else {
+            write!(f, " epilogs: {}", self.epilog_count)?;
+        }
+        if self.f == Some(true) {
+            write!(f, " fragment")?;
+        }
+        if let Some(handler) = self.exception_handler {
+            write!(f, " handler: 0x{:x}", handler)?;
+        }
+        for scope in &self.epilog_scopes {
+            write!(f, "\n  epilog at +0x{:x} code {}", scope.start_offset, scope.start_index)?;
+            if let Some(condition) = scope.condition {
+                write!(f, " condition 0x{:x}", condition)?;
+            }
+        }
+        for code in &self.codes {
+            let bytes: Vec<String> = code.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            write!(f, "\n  {:>3}: {:<12} {}", code.index, bytes.join(" "), code.operation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn image(machine: u16, functions: &[[u32; 2]]) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(false, file.len());
+        headers.coff_headers.target_machine = machine;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION].size = functions.len() as u32 * 8;
+        for (x, function) in functions.iter().enumerate() {
+            put(&mut file, 0x800 + x * 8, &function[0].to_le_bytes());
+            put(&mut file, 0x804 + x * 8, &function[1].to_le_bytes());
+        }
+        (file, headers)
+    }
+
+    fn xdata(function: &ArmRuntimeFunction) -> &XdataRecord {
+        match &function.unwind {
+            Some(ArmUnwindData::Xdata(xdata)) => xdata,
+            _ => panic!("expected an .xdata record"),
+        }
+    }
+
+    fn operations(function: &ArmRuntimeFunction) -> Vec<String> {
+        xdata(function).codes.iter().map(|code| format!("{} {}", code.index, code.operation)).collect()
+    }
+
+    #[test]
+    fn parses_arm64_functions() {
+        let packed = 1 | 0x10 << 2 | 2 << 16 | 3 << 21 | 2 << 23;
+        let (mut file, headers) = image(IMAGE_FILE_MACHINE_ARM64, &[[0x500, packed], [0x540, 0x900], [0x580, 0x940]]);
+        put(&mut file, 0x900, &(8u32 | 1 << 20 | 1 << 22 | 2 << 27).to_le_bytes());
+        put(&mut file, 0x904, &(6u32 | 4 << 22).to_le_bytes());
+        put(&mut file, 0x908, &[0xc8, 0x02, 0x81, 0xe4, 0xe1, 0xe4, 0xe3, 0xe3]);
+        put(&mut file, 0x910, &0x600u32.to_le_bytes());
+        // Counts moved to the extension word, E set
+        put(&mut file, 0x940, &(1u32 | 1 << 21).to_le_bytes());
+        put(&mut file, 0x944, &(1u32 << 16).to_le_bytes());
+        put(&mut file, 0x948, &[0x02, 0xe4, 0, 0]);
+        let functions: Vec<ArmRuntimeFunction> = arm_runtime_functions(&file, &headers).collect();
+        assert!(functions.iter().all(|function| function.anomalies.is_empty()));
+        assert!(functions.iter().all(|function| function.architecture == ArmArchitecture::Arm64));
+        match &functions[0].unwind {
+            Some(ArmUnwindData::PackedArm64(packed)) => assert_eq!(format!("{:?}", packed), "PackedArm64 { flag: 1, function_length: 64, reg_f: 0, reg_i: 2, h: false, cr: 3, frame_size: 32 }"),
+            _ => panic!("expected packed unwind data"),
+        }
+        assert_eq!(functions[0].end_address(), Some(0x540));
+
+        let record = xdata(&functions[1]);
+        assert_eq!((record.function_length, record.version, record.x, record.e, record.f), (32, 0, true, false, None));
+        assert_eq!((record.epilog_count, record.code_words), (1, 2));
+        assert_eq!((record.epilog_scopes[0].start_offset, record.epilog_scopes[0].start_index), (24, 4));
+        assert_eq!((record.exception_handler, record.handler_data_rva), (Some(0x600), Some(0x914)));
+        assert_eq!(operations(&functions[1]), vec!["0 save_regp x19, [sp, #16]", "2 save_fplr_x [sp, #-16]!", "3 end", "4 set_fp", "5 end"]);
+
+        let record = xdata(&functions[2]);
+        assert_eq!((record.e, record.epilog_count, record.code_words), (true, 0, 1));
+        assert!(record.epilog_scopes.is_empty());
+        assert_eq!(operations(&functions[2]), vec!["0 alloc_s sp, #32", "1 end"]);
+        assert_eq!(functions[2].end_address(), Some(0x584));
+    }
+
+    #[test]
+    fn parses_armnt_functions() {
+        let packed = 1 | 0x20 << 2 | 3 << 16 | 1 << 20 | 1 << 21 | 4 << 22;
+        let (mut file, headers) = image(IMAGE_FILE_MACHINE_ARMNT, &[[0x501, packed], [0x541, 0x900], [0x580, packed]]);
+        put(&mut file, 0x900, &(8u32 | 1 << 22 | 1 << 23 | 1 << 28).to_le_bytes());
+        put(&mut file, 0x904, &(6u32 | 0xe << 20 | 2 << 24).to_le_bytes());
+        put(&mut file, 0x908, &[0x04, 0xd5, 0xff, 0x00]);
+        let functions: Vec<ArmRuntimeFunction> = arm_runtime_functions(&file, &headers).collect();
+        assert_eq!(functions[0].begin_address, 0x500);
+        match &functions[0].unwind {
+            Some(ArmUnwindData::PackedArm(packed)) => assert_eq!(format!("{:?}", packed), "PackedArm { flag: 1, function_length: 64, ret: 0, h: false, reg: 3, r: false, l: true, c: true, stack_adjust: 16 }"),
+            _ => panic!("expected packed unwind data"),
+        }
+        let record = xdata(&functions[1]);
+        assert_eq!((record.function_length, record.f, record.epilog_count, record.code_words), (16, Some(true), 1, 1));
+        let scope = &record.epilog_scopes[0];
+        assert_eq!((scope.start_offset, scope.start_index, scope.condition), (12, 2, Some(0xe)));
+        assert_eq!(operations(&functions[1]), vec!["0 add sp, sp, #16", "1 pop {r4-r5, lr}", "2 end"]);
+        assert!(functions[1].anomalies.is_empty());
+        assert_eq!(functions[2].anomalies, vec![String::from("Function 0x580 does not have the Thumb bit set")]);
+
+        let (file, headers) = image(0x8664, &[[0x500, packed]]);
+        assert_eq!(arm_runtime_functions(&file, &headers).count(), 0);
+    }
+
+    #[test]
+    fn malformed_functions() {
+        let (mut file, headers) = image(IMAGE_FILE_MACHINE_ARM64, &[
+            [0x500, 0x503],
+            [0x4f0, 0x3000],
+            [0x520, 0x900],
+            [0x1100, 0xff8],
+            [0x1200, 0xffc],
+            [0x1300, 0xff4],
+        ]);
+        put(&mut file, 0x900, &(1u32 << 18 | 1 << 20 | 1 << 22 | 1 << 27).to_le_bytes());
+        put(&mut file, 0x904, &(8u32 << 22).to_le_bytes());
+        put(&mut file, 0x908, &[0xe4, 0, 0, 0]);
+        put(&mut file, 0x90c, &0x2000u32.to_le_bytes());
+        put(&mut file, 0xff8, &(1u32 << 21 | 1 << 22 | 2 << 27).to_le_bytes());
+        put(&mut file, 0xffc, &(1u32 << 22 | 1 << 27).to_le_bytes());
+        put(&mut file, 0xff4, &(1u32 << 20 | 1 << 21 | 1 << 22 | 2 << 27).to_le_bytes());
+        let functions: Vec<ArmRuntimeFunction> = arm_runtime_functions(&file, &headers).collect();
+        assert!(matches!(functions[0].unwind, Some(ArmUnwindData::Reserved(0x503))));
+        assert_eq!(functions[0].anomalies, vec![String::from("Function 0x500 uses the reserved unwind flag 3")]);
+        assert!(matches!(functions[1].unwind, Some(ArmUnwindData::Reserved(0x3000))));
+        assert_eq!(functions[1].anomalies, vec![
+            String::from(".xdata record at RVA 0x3000 is not backed by the file"),
+            String::from("Function 0x4f0 breaks the ordering of the exception directory"),
+        ]);
+        let record = xdata(&functions[2]);
+        assert_eq!((record.version, record.x, record.e, record.epilog_count, record.code_words), (1, true, false, 1, 1));
+        assert_eq!(record.epilog_scopes.iter().map(|scope| (scope.start_offset, scope.start_index)).collect::<Vec<(u32, u16)>>(), vec![(0, 8)]);
+        assert_eq!((record.exception_handler, record.handler_data_rva), (Some(0x2000), Some(0x910)));
+        assert_eq!(functions[2].anomalies, vec![
+            String::from(".xdata record at RVA 0x900 has the unknown version 1"),
+            String::from("Epilog at offset 0x0 starts past the unwind codes"),
+            String::from("Exception handler 0x2000 is not in an executable section"),
+        ]);
+        // Codes and scopes cut by the end of the file are left empty
+        let record = xdata(&functions[3]);
+        assert_eq!((record.e, record.epilog_count, record.code_words), (true, 1, 2));
+        assert!(record.codes.is_empty());
+        assert_eq!(functions[3].anomalies, vec![
+            String::from("Unwind codes of .xdata record 0xff8 are truncated"),
+            String::from("Function 0x1100 is not in an executable section"),
+        ]);
+        let record = xdata(&functions[4]);
+        assert_eq!((record.e, record.epilog_count, record.code_words), (false, 1, 1));
+        assert!(record.epilog_scopes.is_empty());
+        assert!(record.codes.is_empty());
+        assert_eq!(functions[4].anomalies, vec![
+            String::from("Epilog scopes of .xdata record 0xffc are truncated"),
+            String::from("Function 0x1200 is not in an executable section"),
+        ]);
+        let record = xdata(&functions[5]);
+        assert_eq!(record.code_words, 2);
+        assert!(!record.codes.is_empty());
+        assert_eq!((record.exception_handler, record.handler_data_rva), (None, Some(0x1004)));
+        assert_eq!(functions[5].anomalies, vec![
+            String::from("Exception handler of .xdata record 0xff4 is not backed by the file"),
+            String::from("Function 0x1300 is not in an executable section"),
+        ]);
+    }
+}
@@ -0,0 +1,415 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::ops::Range;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::certificates::IMAGE_DIRECTORY_ENTRY_SECURITY;
+use crate::pkcs7::PageHash;
+use crate::pkcs7::SignedData;
+use crate::pkcs7::hex;
+use crate::sha::HashAlgorithm;
+use crate::sha::Hasher;
+use crate::utils::is_pe32;
+
+// Offset of CheckSum in the optional header, the same for PE32 and PE32+
+const CHECKSUM_OFFSET: usize = 64;
+pub const PAGE_SIZE: usize = 4096;
+// Zero padding of partial pages
+static ZERO_PAGE: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
+
+// Structures definitions
+
+// The parts of the file covered by the Authenticode digest, in hashing order
+#[derive(Default)]
+pub struct HashedRanges {
+    pub ranges: Vec<Range<usize>>,
+    pub anomalies: Vec<String>,
+}
+
+pub struct DigestCheck {
+    pub algorithm: HashAlgorithm,
+    pub computed: Vec<u8>,
+    pub signed: Vec<u8>,
+}
+
+pub struct PageCheck {
+    pub offset: u32,
+    pub section: Option<String>,
+    pub computed: Vec<u8>,
+    pub signed: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct PageHashCheck {
+    pub algorithm: Option<HashAlgorithm>,
+    pub pages: Vec<PageCheck>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl DigestCheck {
+    pub fn matches(&self) -> bool {
+        self.computed == self.signed
+    }
+}
+
+impl PageCheck {
+    pub fn matches(&self) -> bool {
+        self.computed == self.signed
+    }
+}
+
+impl PageHashCheck {
+    // Pages modified after signing
+    pub fn tampered(&self) -> impl Iterator<Item = &PageCheck> {
+        self.pages.iter().filter(|page| !page.matches())
+    }
+}
+
+// File offset of the optional header CheckSum field
+pub fn checksum_offset(headers: &Headers) -> usize {
+    headers.dos_headers.offset_to_pe_headers as usize + 24 + CHECKSUM_OFFSET
+}
+
+// File offset of the security entry of the data directories
+pub fn security_directory_offset(headers: &Headers) -> usize {
+    let data_directories = if is_pe32(headers) { 96 } else { 112 };
+    headers.dos_headers.offset_to_pe_headers as usize + 24 + data_directories + IMAGE_DIRECTORY_ENTRY_SECURITY * 8
+}
+
+// Optional header CheckSum: 16 bit one's complement style sum of the file, the CheckSum field read as zero, plus the file size
+pub fn compute_checksum(file: &[u8], headers: &Headers) -> u32 {
+    let checksum = checksum_offset(headers);
+    let mut sum: u64 = 0;
+    for (x, word) in file.chunks(2).enumerate() {
+        if x * 2 == checksum || x * 2 == checksum + 2 {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u64;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    (sum as u32).wrapping_add(file.len() as u32)
+}
+
+
+// Parsing
+
+// Headers without CheckSum and the security entry, sections by file offset, then anything before the certificate table
+pub fn hashed_ranges(file: &[u8], headers: &Headers) -> HashedRanges {
+    let mut hashed = HashedRanges::default();
+    let checksum = checksum_offset(headers);
+    let security = security_directory_offset(headers);
+    let size_of_headers = (headers.optional_headers.windows_specific.size_of_headers as usize).min(file.len());
+    if security + 8 > size_of_headers {
+        hashed.anomalies.push(String::from("Security directory entry lies outside SizeOfHeaders"));
+        return hashed;
+    }
+    hashed.ranges.push(0..checksum);
+    hashed.ranges.push(checksum + 4..security);
+    hashed.ranges.push(security + 8..size_of_headers);
+    let mut sections: Vec<(usize, usize)> = headers.sections.iter()
+        .filter(|section| section.size_of_raw_data != 0)
+        .map(|section| (section.pointer_to_raw_data as usize, section.size_of_raw_data as usize))
+        .collect();
+    sections.sort();
+    let mut sum_of_bytes = size_of_headers;
+    for (start, size) in sections {
+        let end = (start + size).min(file.len());
+        if end < start + size {
+            hashed.anomalies.push(format!("Section data at 0x{:x} is truncated by 0x{:x} bytes", start, start + size - end));
+        }
+        if start < end {
+            hashed.ranges.push(start..end);
+            sum_of_bytes += end - start;
+        }
+    }
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY];
+    let table_start = directory.virtual_address as usize;
+    let end = if table_start != 0 && table_start <= file.len() { table_start } else { file.len() };
+    if table_start != 0 && table_start + (directory.size as usize) < file.len() {
+        hashed.anomalies.push(String::from("Data after the certificate table is not covered by the signature"));
+    }
+    if sum_of_bytes < end {
+        hashed.ranges.push(sum_of_bytes..end);
+    }
+    hashed
+}
+
+pub fn authentihash(file: &[u8], headers: &Headers, algorithm: HashAlgorithm) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    for range in hashed_ranges(file, headers).ranges {
+        hasher.update(&file[range]);
+    }
+    hasher.finish()
+}
+
+// Recomputes the image digest with the algorithm of the signature and compares it with the signed one
+pub fn check_digest(file: &[u8], headers: &Headers, signed_data: &SignedData) -> Option<DigestCheck> {
+    let indirect_data = signed_data.indirect_data.as_ref()?;
+    let algorithm = indirect_data.digest_algorithm.hash_algorithm()?;
+    Some(DigestCheck { algorithm, computed: authentihash(file, headers, algorithm), signed: indirect_data.digest.clone() })
+}
+
+
+fn page_hash(algorithm: HashAlgorithm, data: &[&[u8]], padding: usize) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    for part in data {
+        hasher.update(part);
+    }
+    hasher.update(&ZERO_PAGE[..padding.min(PAGE_SIZE)]);
+    hasher.finish()
+}
+
+// Page hashes as signtool /ph computes them: the headers without CheckSum and the security entry, padded
+// as if those 12 bytes were kept, then every 4K page of the sections in section table order, zero padded,
+// and a final entry with the end of the last section in the table and an empty hash
+pub fn page_hashes(file: &[u8], headers: &Headers, algorithm: HashAlgorithm, anomalies: &mut Vec<String>) -> Vec<PageHash> {
+    let mut pages = Vec::new();
+    let ranges = hashed_ranges(file, headers).ranges;
+    if ranges.len() < 3 {
+        return pages;
+    }
+    let size_of_headers = ranges[2].end;
+    let header: Vec<&[u8]> = ranges[..3].iter().map(|range| &file[range.clone()]).collect();
+    pages.push(PageHash { offset: 0, hash: page_hash(algorithm, &header, PAGE_SIZE.saturating_sub(size_of_headers)) });
+    let sections = headers.sections.iter()
+        .filter(|section| section.size_of_raw_data != 0)
+        .map(|section| (section.pointer_to_raw_data as usize, section.size_of_raw_data as usize));
+    let mut end = size_of_headers;
+    for (start, size) in sections {
+        // Only the bytes present in the file are paged, SizeOfRawData is not trusted
+        let section_end = (start + size).min(file.len());
+        if section_end < start + size {
+            anomalies.push(format!("Section data at 0x{:x} is truncated by 0x{:x} bytes", start, start + size - section_end.max(start)));
+        }
+        for offset in (start..section_end).step_by(PAGE_SIZE) {
+            let offset_u32 = match u32::try_from(offset) {
+                Ok(offset) => offset,
+                Err(_) => break,
+            };
+            let data = &file[offset..(offset + PAGE_SIZE).min(section_end)];
+            pages.push(PageHash { offset: offset_u32, hash: page_hash(algorithm, &[data], PAGE_SIZE - data.len()) });
+        }
+        end = section_end;
+    }
+    match u32::try_from(end) {
+        Ok(end) => pages.push(PageHash { offset: end, hash: vec![0; algorithm.digest_size()] }),
+        Err(_) => anomalies.push(format!("Image end 0x{:x} does not fit a page hash offset", end)),
+    }
+    pages
+}
+
+// Recomputes the page hashes of the signature, None when it carries none
+pub fn check_page_hashes(file: &[u8], headers: &Headers, signed_data: &SignedData) -> Option<PageHashCheck> {
+    let signed = signed_data.indirect_data.as_ref()?.page_hashes.as_ref()?;
+    let mut check = PageHashCheck { algorithm: Some(signed.algorithm), ..Default::default() };
+    let computed = page_hashes(file, headers, signed.algorithm, &mut check.anomalies);
+    let signed_order: Vec<u32> = signed.pages.iter().map(|page| page.offset).filter(|offset| computed.iter().any(|page| page.offset == *offset)).collect();
+    let computed_order: Vec<u32> = computed.iter().map(|page| page.offset).filter(|offset| signed_order.contains(offset)).collect();
+    if signed_order != computed_order {
+        check.anomalies.push(String::from("Page hashes do not follow the section table order"));
+    }
+    match (signed.pages.last(), computed.last()) {
+        (Some(last), Some(end)) if last.offset != end.offset => check.anomalies.push(format!("Page hashes end at 0x{:x} instead of 0x{:x}", last.offset, end.offset)),
+        _ => {}
+    }
+    let computed = &computed[..computed.len().saturating_sub(1)];
+    let signed_pages = &signed.pages[..signed.pages.len().saturating_sub(1)];
+    for page in computed {
+        match signed_pages.iter().find(|signed| signed.offset == page.offset) {
+            Some(signed) => {
+                let section = headers.sections.iter()
+                    .find(|section| (section.pointer_to_raw_data..section.pointer_to_raw_data.saturating_add(section.size_of_raw_data)).contains(&page.offset))
+                    .map(|section| section.name.clone());
+                check.pages.push(PageCheck { offset: page.offset, section, computed: page.hash.clone(), signed: signed.hash.clone() });
+            }
+            None => check.anomalies.push(format!("Page at 0x{:x} has no page hash", page.offset)),
+        }
+    }
+    for signed in signed_pages.iter().filter(|signed| !computed.iter().any(|page| page.offset == signed.offset)) {
+        check.anomalies.push(format!("Page hash at 0x{:x} does not match a page of the image", signed.offset));
+    }
+    Some(check)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for DigestCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} computed: {} signed: {} {}", self.algorithm, hex(&self.computed), hex(&self.signed),
+        if self.matches() { "match" } else { "MISMATCH" })
+    }
+}
+
+impl fmt::Display for PageCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08x} {:<8} computed: {} signed: {} {}", self.offset, self.section.as_deref().unwrap_or("headers"),
+        hex(&self.computed), hex(&self.signed), if self.matches() { "match" } else { "TAMPERED" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkcs7::parse_signed_data;
+    use crate::pkcs7::tests::authenticode;
+    use crate::pkcs7::tests::page_hashes_signature;
+    use crate::rsa::tests::bytes;
+    use crate::sha::digest;
+    use crate::structs::SectionHeader;
+    use crate::utils::fixtures::flat_headers;
+
+    const PE_HEADERS: usize = 0x80;
+    const TABLE: usize = 0x1100;
+
+    fn section(name: &str, offset: u32, size: u32) -> SectionHeader {
+        SectionHeader { name: String::from(name), virtual_size: size, virtual_address: offset, size_of_raw_data: size, pointer_to_raw_data: offset, ..Default::default() }
+    }
+
+    // Headers, .data and .text listed out of file order, 0x100 bytes of overlay and a 0x10 byte certificate table
+    fn image(pe_32: bool) -> (Vec<u8>, Headers) {
+        let mut headers = flat_headers(pe_32, 0x1000);
+        headers.dos_headers.offset_to_pe_headers = PE_HEADERS as u32;
+        headers.sections = vec![section(".data", 0xa00, 0x600), section(".text", 0x400, 0x600)];
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].virtual_address = TABLE as u32;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].size = 0x10;
+        let file = (0..TABLE + 0x10).map(|x| (x * 7 + 3) as u8).collect();
+        (file, headers)
+    }
+
+    #[test]
+    fn checksum() {
+        let (mut file, headers) = image(true);
+        assert_eq!(checksum_offset(&headers), 0xd8);
+        assert_eq!(compute_checksum(&file, &headers), 0xbb08);
+        // The CheckSum field itself is not summed
+        file[0xd8..0xdc].copy_from_slice(&0x12345678u32.to_le_bytes());
+        assert_eq!(compute_checksum(&file, &headers), 0xbb08);
+        // An odd trailing byte is summed as a zero extended word
+        let file: Vec<u8> = (0..TABLE + 0x11).map(|x| (x * 7 + 3) as u8).collect();
+        assert_eq!(compute_checksum(&file, &headers), 0xbb7c);
+    }
+
+    #[test]
+    fn known_authentihashes() {
+        for (pe_32, security, expected) in [
+            (true, 0x118, "6e53c20adf419b265d6281b2ba92b96acb86c5b771346706b72ddf4f6d430a5f"),
+            (false, 0x128, "a60f3b36a776efddc3e8caf3e57c8dd43b8f3f392676778e125dfd1dc4d9294a"),
+        ] {
+            let (mut file, headers) = image(pe_32);
+            assert_eq!(security_directory_offset(&headers), security);
+            let hashed = hashed_ranges(&file, &headers);
+            assert!(hashed.anomalies.is_empty());
+            assert_eq!(hashed.ranges, vec![0..0xd8, 0xdc..security, security + 8..0x400, 0x400..0xa00, 0xa00..0x1000, 0x1000..TABLE]);
+            assert_eq!(authentihash(&file, &headers, HashAlgorithm::Sha256), bytes(expected));
+
+            // CheckSum, the security entry and the certificate table are not covered, the overlay is
+            let signature = parse_signed_data(&authenticode(&bytes(expected), &[])).unwrap();
+            for offset in [0xd8, 0xdb, security, security + 7, TABLE, TABLE + 0xf] {
+                file[offset] ^= 0xff;
+                assert!(check_digest(&file, &headers, &signature).unwrap().matches());
+            }
+            for offset in [0x3c, security - 1, security + 8, 0x400, 0xfff, 0x10ff] {
+                file[offset] ^= 0xff;
+                assert!(!check_digest(&file, &headers, &signature).unwrap().matches());
+                file[offset] ^= 0xff;
+            }
+            let check = check_digest(&file, &headers, &signature).unwrap();
+            assert_eq!(check.algorithm, HashAlgorithm::Sha256);
+            assert_eq!(check.signed, bytes(expected));
+        }
+    }
+
+    #[test]
+    fn hashed_ranges_anomalies() {
+        let (file, mut headers) = image(true);
+        // Data after the certificate table
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].size = 8;
+        assert_eq!(hashed_ranges(&file, &headers).anomalies, vec![String::from("Data after the certificate table is not covered by the signature")]);
+        // Without a certificate table everything after the sections is hashed
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY] = Default::default();
+        assert_eq!(hashed_ranges(&file, &headers).ranges.last(), Some(&(0x1000..TABLE + 0x10)));
+        assert_eq!(authentihash(&file, &headers, HashAlgorithm::Sha1), digest(HashAlgorithm::Sha1, &[&file[..0xd8], &file[0xdc..0x118], &file[0x120..]].concat()));
+        headers.sections[0].size_of_raw_data = 0x800;
+        let hashed = hashed_ranges(&file, &headers);
+        assert_eq!(hashed.ranges[4], 0xa00..0x1110);
+        assert_eq!(hashed.anomalies, vec![String::from("Section data at 0xa00 is truncated by 0xf0 bytes")]);
+        headers.optional_headers.windows_specific.size_of_headers = 0x100;
+        assert_eq!(hashed_ranges(&file, &headers).anomalies, vec![String::from("Security directory entry lies outside SizeOfHeaders")]);
+    }
+
+    // .data listed before .text, both spanning a partial page
+    fn paged_image() -> (Vec<u8>, Headers) {
+        let mut headers = flat_headers(true, 0x3600);
+        headers.dos_headers.offset_to_pe_headers = PE_HEADERS as u32;
+        headers.sections = vec![section(".data", 0x2400, 0x1200), section(".text", 0x400, 0x2000)];
+        let file = (0..0x3600).map(|x| (x * 13 + 5) as u8).collect();
+        (file, headers)
+    }
+
+    fn zero_padded(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+        digest(algorithm, &[data, &ZERO_PAGE[..PAGE_SIZE - data.len()]].concat())
+    }
+
+    #[test]
+    fn page_hashes_in_section_table_order() {
+        let (file, headers) = paged_image();
+        for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256] {
+            let mut anomalies = Vec::new();
+            let pages = page_hashes(&file, &headers, algorithm, &mut anomalies);
+            assert!(anomalies.is_empty());
+            assert_eq!(pages.iter().map(|page| page.offset).collect::<Vec<u32>>(), vec![0, 0x2400, 0x3400, 0x400, 0x1400, 0x2400]);
+            // The 12 bytes of CheckSum and the security entry are left out but still counted in the padding
+            let header = [&file[..0xd8], &file[0xdc..0x118], &file[0x120..0x400], &ZERO_PAGE[..PAGE_SIZE - 0x400]].concat();
+            assert_eq!(pages[0].hash, digest(algorithm, &header));
+            assert_eq!(pages[1].hash, digest(algorithm, &file[0x2400..0x3400]));
+            assert_eq!(pages[2].hash, zero_padded(algorithm, &file[0x3400..0x3600]));
+            assert_eq!(pages[3].hash, digest(algorithm, &file[0x400..0x1400]));
+            assert_eq!(pages[5].hash, vec![0; algorithm.digest_size()]);
+
+            // The table stores 4 + 20 or 4 + 32 bytes per entry
+            let signed_data = parse_signed_data(&page_hashes_signature(algorithm, &pages)).unwrap();
+            let signed = signed_data.indirect_data.as_ref().unwrap().page_hashes.as_ref().unwrap();
+            assert_eq!(signed.algorithm, algorithm);
+            assert_eq!(signed.pages.len(), 6);
+            assert_eq!(signed.pages[4].offset, 0x1400);
+            assert_eq!(signed.pages[4].hash, pages[4].hash);
+            let check = check_page_hashes(&file, &headers, &signed_data).unwrap();
+            assert!(check.anomalies.is_empty());
+            assert_eq!(check.pages.len(), 5);
+            assert_eq!(check.tampered().count(), 0);
+        }
+    }
+
+    #[test]
+    fn tampered_pages() {
+        let (mut file, headers) = paged_image();
+        let mut anomalies = Vec::new();
+        let pages = page_hashes(&file, &headers, HashAlgorithm::Sha256, &mut anomalies);
+        let signed_data = parse_signed_data(&page_hashes_signature(HashAlgorithm::Sha256, &pages)).unwrap();
+        file[0xd8] ^= 0xff;
+        file[0x1500] ^= 0xff;
+        file[0x200] ^= 0xff;
+        let check = check_page_hashes(&file, &headers, &signed_data).unwrap();
+        let tampered: Vec<(u32, Option<String>)> = check.tampered().map(|page| (page.offset, page.section.clone())).collect();
+        assert_eq!(tampered, vec![(0, None), (0x1400, Some(String::from(".text")))]);
+
+        // Pages signed in file order, one of them missing, and the end of the last section in file order
+        let mut reordered: Vec<PageHash> = [0, 3, 1, 2].iter().map(|x| PageHash { offset: pages[*x].offset, hash: pages[*x].hash.clone() }).collect();
+        reordered.push(PageHash { offset: 0x3600, hash: vec![0; 32] });
+        let signed_data = parse_signed_data(&page_hashes_signature(HashAlgorithm::Sha256, &reordered)).unwrap();
+        let check = check_page_hashes(&file, &headers, &signed_data).unwrap();
+        assert_eq!(check.anomalies, vec![
+            String::from("Page hashes do not follow the section table order"),
+            String::from("Page hashes end at 0x3600 instead of 0x2400"),
+            String::from("Page at 0x1400 has no page hash"),
+        ]);
+        assert!(check_page_hashes(&file, &headers, &parse_signed_data(&authenticode(&[0; 32], &[])).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::cmp::Ordering;
+use std::vec::Vec;
+
+// Unsigned big integers, just what RSA needs: modular exponentiation with an odd modulus
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub struct BigUint {
+    // Little endian 32 bit limbs without trailing zeros
+    limbs: Vec<u32>,
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    let length = a.len().max(b.len());
+    for x in (0..length).rev() {
+        let (left, right) = (a.get(x).copied().unwrap_or(0), b.get(x).copied().unwrap_or(0));
+        if left != right {
+            return left.cmp(&right);
+        }
+    }
+    Ordering::Equal
+}
+
+// a -= b, a must not be smaller than b
+fn subtract(a: &mut [u32], b: &[u32]) {
+    let mut borrow = 0i64;
+    for (x, limb) in a.iter_mut().enumerate() {
+        let value = *limb as i64 - b.get(x).copied().unwrap_or(0) as i64 - borrow;
+        *limb = value as u32;
+        borrow = if value < 0 { 1 } else { 0 };
+    }
+}
+
+impl BigUint {
+    pub fn from_be_bytes(bytes: &[u8]) -> BigUint {
+        let mut limbs: Vec<u32> = bytes.rchunks(4).map(|chunk| chunk.iter().fold(0u32, |value, byte| (value << 8) | *byte as u32)).collect();
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+
+    // Big endian, left padded to length; None when the value does not fit
+    pub fn to_be_bytes(&self, length: usize) -> Option<Vec<u8>> {
+        let bytes: Vec<u8> = self.limbs.iter().rev().flat_map(|limb| limb.to_be_bytes()).skip_while(|byte| *byte == 0).collect();
+        if bytes.len() > length {
+            return None;
+        }
+        let mut output = vec![0u8; length - bytes.len()];
+        output.extend_from_slice(&bytes);
+        Some(output)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_odd(&self) -> bool {
+        self.limbs.first().is_some_and(|limb| limb & 1 == 1)
+    }
+
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(last) => self.limbs.len() * 32 - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.limbs.get(index / 32).is_some_and(|limb| (limb >> (index % 32)) & 1 == 1)
+    }
+
+    pub fn compare(&self, other: &BigUint) -> Ordering {
+        compare(&self.limbs, &other.limbs)
+    }
+
+    // self^exponent mod modulus, the base must already be reduced and the modulus odd
+    pub fn mod_pow(&self, exponent: &BigUint, modulus: &BigUint) -> Option<BigUint> {
+        if !modulus.is_odd() || modulus.bits() < 2 || self.compare(modulus) != Ordering::Less {
+            return None;
+        }
+        let montgomery = Montgomery::new(modulus);
+        let base = montgomery.to_montgomery(&self.limbs);
+        let mut result = montgomery.to_montgomery(&[1]);
+        for x in (0..exponent.bits()).rev() {
+            result = montgomery.multiply(&result, &result);
+            if exponent.bit(x) {
+                result = montgomery.multiply(&result, &base);
+            }
+        }
+        let mut limbs = montgomery.multiply(&result, &[1]);
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        Some(BigUint { limbs })
+    }
+}
+
+struct Montgomery {
+    modulus: Vec<u32>,
+    // -modulus^-1 mod 2^32
+    inverse: u32,
+    // R^2 mod modulus with R = 2^(32 * limbs)
+    r_squared: Vec<u32>,
+}
+
+impl Montgomery {
+    fn new(modulus: &BigUint) -> Montgomery {
+        let n = modulus.limbs.clone();
+        let mut inverse: u32 = 1;
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(n[0].wrapping_mul(inverse)));
+        }
+        // Doubling 1 modulo n, 64 bits per limb, gives R^2 mod n
+        let mut r_squared = vec![0u32; n.len() + 1];
+        r_squared[0] = 1;
+        for _ in 0..n.len() * 64 {
+            let mut carry = 0u32;
+            for limb in r_squared.iter_mut() {
+                let next = *limb >> 31;
+                *limb = (*limb << 1) | carry;
+                carry = next;
+            }
+            if compare(&r_squared, &n) != Ordering::Less {
+                subtract(&mut r_squared, &n);
+            }
+        }
+        r_squared.truncate(n.len());
+        Montgomery { modulus: n, inverse: inverse.wrapping_neg(), r_squared }
+    }
+
+    // a * b * R^-1 mod n, coarsely integrated operand scanning
+    fn multiply(&self, a: &[u32], b: &[u32]) -> Vec<u32> {
+        let n = &self.modulus;
+        let k = n.len();
+        let mut t = vec![0u32; k + 2];
+        for x in 0..k {
+            let a_limb = a.get(x).copied().unwrap_or(0) as u64;
+            let mut carry = 0u64;
+            for (y, limb) in t.iter_mut().take(k).enumerate() {
+                let sum = *limb as u64 + a_limb * b.get(y).copied().unwrap_or(0) as u64 + carry;
+                *limb = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k] = sum as u32;
+            t[k + 1] = (sum >> 32) as u32;
+            let m = t[0].wrapping_mul(self.inverse) as u64;
+            let mut carry = (t[0] as u64 + m * n[0] as u64) >> 32;
+            for y in 1..k {
+                let sum = t[y] as u64 + m * n[y] as u64 + carry;
+                t[y - 1] = sum as u32;
+                carry = sum >> 32;
+            }
+            let sum = t[k] as u64 + carry;
+            t[k - 1] = sum as u32;
+            t[k] = t[k + 1] + (sum >> 32) as u32;
+            t[k + 1] = 0;
+        }
+        t.truncate(k + 1);
+        if compare(&t, n) != Ordering::Less {
+            subtract(&mut t, n);
+        }
+        t.truncate(k);
+        t
+    }
+
+    fn to_montgomery(&self, value: &[u32]) -> Vec<u32> {
+        self.multiply(value, &self.r_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(value: u128) -> BigUint {
+        BigUint::from_be_bytes(&value.to_be_bytes())
+    }
+
+    #[test]
+    fn bytes() {
+        let value = BigUint::from_be_bytes(&[0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(value.bits(), 33);
+        assert_eq!(value.to_be_bytes(5).unwrap(), [0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(value.to_be_bytes(8).unwrap(), [0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert!(value.to_be_bytes(4).is_none());
+        assert!(BigUint::from_be_bytes(&[0, 0]).is_zero());
+        assert_eq!(number(7).compare(&number(1 << 40)), Ordering::Less);
+    }
+
+    #[test]
+    fn mod_pow() {
+        assert_eq!(number(4).mod_pow(&number(13), &number(497)).unwrap(), number(445));
+        // Fermat's little theorem with the Mersenne primes 2^61 - 1 and 2^127 - 1
+        let prime = (1u128 << 61) - 1;
+        assert_eq!(number(3).mod_pow(&number(prime - 1), &number(prime)).unwrap(), number(1));
+        let prime = (1u128 << 127) - 1;
+        assert_eq!(number(0x0123456789abcdef0fedcba987654321).mod_pow(&number(0xdeadbeefcafebabe1234), &number(prime)).unwrap(), number(0x5e085960710367672bab68b9c2b24c4a));
+        let modulus = 0xffffffffffffffffffffffffffffff61;
+        assert_eq!(number(0xfedcba9876543210fedcba9876543210).mod_pow(&number(65537), &number(modulus)).unwrap(), number(0x98ba77dc393fbad8854abeb8e03dbba3));
+        assert_eq!(number(5).mod_pow(&number(0), &number(497)).unwrap(), number(1));
+        assert!(number(0).mod_pow(&number(3), &number(497)).unwrap().is_zero());
+    }
+
+    #[test]
+    fn mod_pow_rejects() {
+        // Even modulus, modulus below 2 and a base that is not reduced
+        assert!(number(3).mod_pow(&number(5), &number(496)).is_none());
+        assert!(number(0).mod_pow(&number(5), &number(1)).is_none());
+        assert!(number(497).mod_pow(&number(5), &number(497)).is_none());
+    }
+}
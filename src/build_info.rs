@@ -0,0 +1,278 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::utils::read_u32_at;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum PogoKind {
+    // Link time code generation without profile
+    Ltcg,
+    // Profile guided optimization, instrumented build
+    Pgi,
+    // Profile guided optimization, optimized build
+    Pgo,
+    // Profile guided optimization, update
+    Pgu,
+    Other(u32),
+}
+
+// One section contribution, e.g. ".text$mn" or ".rdata$zzzdbg"
+pub struct PogoEntry {
+    pub rva: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+pub struct Pogo {
+    pub kind: PogoKind,
+    pub entries: Vec<PogoEntry>,
+}
+
+// Counters emitted by the MSVC linker since VS2013 Update 3
+pub struct VcFeature {
+    pub pre_vc11: u32,
+    pub c_cpp: u32,
+    pub gs: u32,
+    pub sdl: u32,
+    pub guard_n: u32,
+}
+
+pub struct Repro {
+    // Empty when the linker only put the hash in the TimeDateStamp fields
+    pub hash: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum ExDllCharacteristic {
+    IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT,
+    IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT_STRICT_MODE,
+    IMAGE_DLLCHARACTERISTICS_EX_CET_SET_CONTEXT_IP_VALIDATION_RELAXED_MODE,
+    IMAGE_DLLCHARACTERISTICS_EX_CET_DYNAMIC_APIS_ALLOW_IN_PROC,
+    IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_1,
+    IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_2,
+    IMAGE_DLLCHARACTERISTICS_EX_FORWARD_CFI_COMPAT,
+    IMAGE_DLLCHARACTERISTICS_EX_HOTPATCH_COMPATIBLE,
+    UNKNOWN(u32),
+}
+
+pub struct ExDllCharacteristics {
+    pub flags: u32,
+    pub flags_list: Vec<ExDllCharacteristic>,
+}
+
+
+// Conversion implementation for the structs
+
+impl PogoKind {
+    pub fn from_u32(val: u32) -> PogoKind {
+        match &val.to_be_bytes() {
+            b"LTCG" => PogoKind::Ltcg,
+            b"PGI\0" => PogoKind::Pgi,
+            b"PGO\0" => PogoKind::Pgo,
+            b"PGU\0" => PogoKind::Pgu,
+            _ => PogoKind::Other(val),
+        }
+    }
+}
+
+impl ExDllCharacteristic {
+    pub fn from_u32(val: u32) -> Vec<ExDllCharacteristic> {
+        let mut flags = Vec::new();
+        for bit in 0..32 {
+            let flag = 1u32 << bit;
+            if val & flag == 0 {
+                continue;
+            }
+            flags.push(match flag {
+                0x01 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT,
+                0x02 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT_STRICT_MODE,
+                0x04 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_SET_CONTEXT_IP_VALIDATION_RELAXED_MODE,
+                0x08 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_DYNAMIC_APIS_ALLOW_IN_PROC,
+                0x10 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_1,
+                0x20 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_RESERVED_2,
+                0x40 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_FORWARD_CFI_COMPAT,
+                0x80 => ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_HOTPATCH_COMPATIBLE,
+                _ => ExDllCharacteristic::UNKNOWN(flag),
+            });
+        }
+        flags
+    }
+}
+
+impl Pogo {
+    pub fn find(&self, name: &str) -> Option<&PogoEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+}
+
+impl ExDllCharacteristics {
+    pub fn has(&self, flag: ExDllCharacteristic) -> bool {
+        self.flags_list.contains(&flag)
+    }
+
+    pub fn is_cet_compatible(&self) -> bool {
+        self.has(ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT)
+    }
+}
+
+
+// Parsing
+
+// The signature is a multi-character constant such as 'LTCG', entries follow until the end of the data
+pub fn parse_pogo(data: &[u8]) -> Option<Pogo> {
+    let kind = PogoKind::from_u32(read_u32_at(data, 0)?);
+    let mut entries = Vec::new();
+    let mut offset = 4;
+    while offset + 8 < data.len() {
+        let rva = read_u32_at(data, offset)?;
+        let size = read_u32_at(data, offset + 4)?;
+        let name_data = &data[offset + 8..];
+        let end = match name_data.iter().position(|byte| *byte == 0) {
+            Some(end) => end,
+            None => break,
+        };
+        entries.push(PogoEntry { rva, size, name: String::from_utf8_lossy(&name_data[..end]).to_string() });
+        // Name and terminator are padded to a 4 byte boundary
+        offset += 8 + ((end + 1 + 3) & !3);
+    }
+    Some(Pogo { kind, entries })
+}
+
+pub fn parse_vc_feature(data: &[u8]) -> Option<VcFeature> {
+    Some(VcFeature {
+        pre_vc11: read_u32_at(data, 0)?,
+        c_cpp: read_u32_at(data, 4)?,
+        gs: read_u32_at(data, 8)?,
+        sdl: read_u32_at(data, 12)?,
+        guard_n: read_u32_at(data, 16)?,
+    })
+}
+
+pub fn parse_repro(data: &[u8]) -> Option<Repro> {
+    if data.is_empty() {
+        return Some(Repro { hash: Vec::new() });
+    }
+    let length = read_u32_at(data, 0)? as usize;
+    Some(Repro { hash: data.get(4..4 + length)?.to_vec() })
+}
+
+pub fn parse_ex_dll_characteristics(data: &[u8]) -> Option<ExDllCharacteristics> {
+    let flags = read_u32_at(data, 0)?;
+    Some(ExDllCharacteristics { flags, flags_list: ExDllCharacteristic::from_u32(flags) })
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for Pogo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "POGO {:?}, {} entries", self.kind, self.entries.len())?;
+        for entry in &self.entries {
+            write!(f, "\n0x{:08x} size: 0x{:08x} {}", entry.rva, entry.size, entry.name)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for VcFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pre-VC++ 11.00: {} C/C++: {} /GS: {} /sdl: {} guardN: {}", self.pre_vc11, self.c_cpp, self.gs, self.sdl, self.guard_n)
+    }
+}
+
+impl fmt::Display for Repro {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.hash.is_empty() {
+            return write!(f, "REPRO hash stored in TimeDateStamp");
+        }
+        write!(f, "REPRO hash: {}", self.hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+    }
+}
+
+impl fmt::Display for ExDllCharacteristics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "flags: 0x{:x} {:?}", self.flags, self.flags_list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::parse_debug_directory;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    #[test]
+    fn parses_pogo_entries() {
+        let mut data = b"GCTL".to_vec();
+        data.extend_from_slice(&0x1000u32.to_le_bytes());
+        data.extend_from_slice(&0x200u32.to_le_bytes());
+        data.extend_from_slice(b".text$mn\0\0\0\0");
+        data.extend_from_slice(&0x2000u32.to_le_bytes());
+        data.extend_from_slice(&0x10u32.to_le_bytes());
+        data.extend_from_slice(b".rdata$zzzdbg\0\0\0");
+        // Unterminated trailing name
+        data.extend_from_slice(&[0, 0x30, 0, 0, 4, 0, 0, 0, b'.', b'x']);
+        let pogo = parse_pogo(&data).unwrap();
+        assert_eq!(pogo.kind, PogoKind::Ltcg);
+        let entries: Vec<(u32, u32, &str)> = pogo.entries.iter().map(|entry| (entry.rva, entry.size, entry.name.as_str())).collect();
+        assert_eq!(entries, vec![(0x1000, 0x200, ".text$mn"), (0x2000, 0x10, ".rdata$zzzdbg")]);
+        assert_eq!(pogo.find(".rdata$zzzdbg").map(|entry| entry.rva), Some(0x2000));
+        assert_eq!(parse_pogo(b"\0UGP").unwrap().kind, PogoKind::Pgu);
+        assert_eq!(parse_pogo(b"ABCD").unwrap().kind, PogoKind::Other(0x44434241));
+        assert!(parse_pogo(b"PG").is_none());
+    }
+
+    #[test]
+    fn parses_fixed_entries() {
+        let data: Vec<u8> = [1u32, 0x2a, 0x20, 0x10, 0x8].iter().flat_map(|value| value.to_le_bytes()).collect();
+        let vc_feature = parse_vc_feature(&data).unwrap();
+        assert_eq!((vc_feature.pre_vc11, vc_feature.c_cpp, vc_feature.gs, vc_feature.sdl, vc_feature.guard_n), (1, 0x2a, 0x20, 0x10, 8));
+        assert!(parse_vc_feature(&data[..16]).is_none());
+
+        assert_eq!(parse_repro(&[4, 0, 0, 0, 1, 2, 3, 4, 5]).unwrap().hash, vec![1, 2, 3, 4]);
+        assert!(parse_repro(&[]).unwrap().hash.is_empty());
+        assert!(parse_repro(&[0x20, 0, 0, 0, 1]).is_none());
+
+        let characteristics = parse_ex_dll_characteristics(&0x00000141u32.to_le_bytes()).unwrap();
+        assert!(characteristics.is_cet_compatible());
+        assert_eq!(characteristics.flags_list, vec![
+            ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_CET_COMPAT,
+            ExDllCharacteristic::IMAGE_DLLCHARACTERISTICS_EX_FORWARD_CFI_COMPAT,
+            ExDllCharacteristic::UNKNOWN(0x100),
+        ]);
+    }
+
+    #[test]
+    fn decodes_debug_entries() {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(false, file.len());
+        // Debug directory of four 28 byte entries at RVA 0x800
+        headers.optional_headers.data_directories.directories[6].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[6].size = 4 * 28;
+        // (type, size, pointer) of VC_FEATURE, ILTCG, REPRO without data and EX_DLLCHARACTERISTICS
+        for (x, (debug_type, size, pointer)) in [(12u32, 20u32, 0x900u32), (14, 0, 0), (16, 0, 0), (20, 4, 0x920)].iter().enumerate() {
+            put(&mut file, 0x80c + x * 28, &debug_type.to_le_bytes());
+            put(&mut file, 0x810 + x * 28, &size.to_le_bytes());
+            put(&mut file, 0x818 + x * 28, &pointer.to_le_bytes());
+        }
+        put(&mut file, 0x904, &3u32.to_le_bytes());
+        put(&mut file, 0x920, &1u32.to_le_bytes());
+        let debug = parse_debug_directory(&file, &headers).unwrap();
+        assert!(debug.anomalies.is_empty());
+        assert_eq!(debug.vc_feature().map(|vc_feature| vc_feature.c_cpp), Some(3));
+        assert!(debug.has_iltcg());
+        assert!(debug.repro().unwrap().hash.is_empty());
+        assert!(debug.ex_dll_characteristics().unwrap().is_cet_compatible());
+        assert!(debug.pogo().is_none());
+    }
+}
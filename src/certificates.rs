@@ -0,0 +1,260 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::pkcs7::SignedData;
+use crate::pkcs7::parse_signed_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+pub const IMAGE_DIRECTORY_ENTRY_SECURITY: usize = 4;
+const WIN_CERTIFICATE_HEADER_SIZE: usize = 8;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum CertificateRevision {
+    WIN_CERT_REVISION_1_0,
+    WIN_CERT_REVISION_2_0,
+    UNKNOWN(u16),
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum CertificateType {
+    WIN_CERT_TYPE_X509,
+    WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+    WIN_CERT_TYPE_RESERVED_1,
+    WIN_CERT_TYPE_TS_STACK_SIGNED,
+    UNKNOWN(u16),
+}
+
+pub struct WinCertificate {
+    // File offset of the WIN_CERTIFICATE header
+    pub offset: usize,
+    pub length: u32,
+    pub revision: CertificateRevision,
+    pub certificate_type: CertificateType,
+    pub data: Vec<u8>,
+    pub signed_data: Option<SignedData>,
+}
+
+#[derive(Default)]
+pub struct CertificateTable {
+    // The security directory holds a file offset, not an RVA
+    pub offset: u32,
+    pub size: u32,
+    pub entries: Vec<WinCertificate>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl CertificateRevision {
+    pub fn from_u16(val: u16) -> CertificateRevision {
+        match val {
+            0x0100 => CertificateRevision::WIN_CERT_REVISION_1_0,
+            0x0200 => CertificateRevision::WIN_CERT_REVISION_2_0,
+            _ => CertificateRevision::UNKNOWN(val),
+        }
+    }
+}
+
+impl CertificateType {
+    pub fn from_u16(val: u16) -> CertificateType {
+        match val {
+            1 => CertificateType::WIN_CERT_TYPE_X509,
+            2 => CertificateType::WIN_CERT_TYPE_PKCS_SIGNED_DATA,
+            3 => CertificateType::WIN_CERT_TYPE_RESERVED_1,
+            4 => CertificateType::WIN_CERT_TYPE_TS_STACK_SIGNED,
+            _ => CertificateType::UNKNOWN(val),
+        }
+    }
+}
+
+impl CertificateTable {
+    // The Authenticode signature Windows checks, the first PKCS#7 entry
+    pub fn signature(&self) -> Option<&SignedData> {
+        self.entries.iter().find_map(|entry| entry.signed_data.as_ref())
+    }
+}
+
+
+// Parsing
+
+pub fn parse_certificate_table(file: &[u8], headers: &Headers) -> Option<CertificateTable> {
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY];
+    if directory.virtual_address == 0 && directory.size == 0 {
+        return None;
+    }
+    let mut table = CertificateTable { offset: directory.virtual_address, size: directory.size, ..Default::default() };
+    let start = directory.virtual_address as usize;
+    let end = start + directory.size as usize;
+    if end > file.len() {
+        table.anomalies.push(format!("Certificate table 0x{:x}-0x{:x} extends past the end of the file 0x{:x}", start, end, file.len()));
+    } else if end < file.len() {
+        table.anomalies.push(format!("0x{:x} bytes follow the certificate table", file.len() - end));
+    }
+    if !start.is_multiple_of(8) {
+        table.anomalies.push(format!("Certificate table offset 0x{:x} is not 8 byte aligned", start));
+    }
+    let headers_end = headers.optional_headers.windows_specific.size_of_headers as usize;
+    let sections_end = headers.sections.iter().map(|section| section.pointer_to_raw_data as usize + section.size_of_raw_data as usize).max().unwrap_or(headers_end);
+    if start < sections_end.max(headers_end) {
+        table.anomalies.push(format!("Certificate table at 0x{:x} overlaps the headers or section data", start));
+    }
+    let mut offset = start;
+    while offset + WIN_CERTIFICATE_HEADER_SIZE <= end.min(file.len()) {
+        let length = read_u32_at(file, offset)?;
+        if (length as usize) < WIN_CERTIFICATE_HEADER_SIZE {
+            table.anomalies.push(format!("WIN_CERTIFICATE at 0x{:x} has an invalid length 0x{:x}", offset, length));
+            break;
+        }
+        let data_end = offset + length as usize;
+        if data_end > end || data_end > file.len() {
+            table.anomalies.push(format!("WIN_CERTIFICATE at 0x{:x} with length 0x{:x} exceeds the certificate table", offset, length));
+            break;
+        }
+        let mut entry = WinCertificate {
+            offset,
+            length,
+            revision: CertificateRevision::from_u16(read_u16_at(file, offset + 4)?),
+            certificate_type: CertificateType::from_u16(read_u16_at(file, offset + 6)?),
+            data: file[offset + WIN_CERTIFICATE_HEADER_SIZE..data_end].to_vec(),
+            signed_data: None,
+        };
+        if entry.certificate_type == CertificateType::WIN_CERT_TYPE_PKCS_SIGNED_DATA {
+            entry.signed_data = parse_signed_data(&entry.data);
+            if entry.signed_data.is_none() {
+                table.anomalies.push(format!("PKCS#7 SignedData at 0x{:x} could not be decoded", offset));
+            }
+        }
+        if entry.revision != CertificateRevision::WIN_CERT_REVISION_2_0 {
+            table.anomalies.push(format!("WIN_CERTIFICATE at 0x{:x} has revision {:?}", offset, entry.revision));
+        }
+        table.entries.push(entry);
+        // Entries are padded to an 8 byte boundary
+        offset = (data_end + 7) & !7;
+    }
+    Some(table)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for WinCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WIN_CERTIFICATE
+
+---------------------------
+offset: 0x{:x}
+length: 0x{:x}
+revision: {:?}
+certificate_type: {:?}
+---------------------------",
+        self.offset, self.length, self.revision, self.certificate_type)?;
+        if let Some(signed_data) = &self.signed_data {
+            write!(f, "\n\n{}", signed_data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkcs7::tests::authenticode;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    const TABLE: usize = 0x1000;
+
+    fn win_certificate(length: u32, revision: u16, certificate_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&length.to_le_bytes());
+        entry.extend_from_slice(&revision.to_le_bytes());
+        entry.extend_from_slice(&certificate_type.to_le_bytes());
+        entry.extend_from_slice(data);
+        entry
+    }
+
+    // Image of 0x1000 bytes followed by the table, the directory entry covers size bytes
+    fn parse(offset: usize, size: usize, table: &[u8]) -> CertificateTable {
+        let mut headers = flat_headers(true, TABLE);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].virtual_address = offset as u32;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].size = size as u32;
+        let mut file = vec![0; TABLE];
+        put(&mut file, offset, table);
+        parse_certificate_table(&file, &headers).unwrap()
+    }
+
+    #[test]
+    fn parses_entries() {
+        let signature = authenticode(&[0; 32], &[]);
+        let length = 8 + signature.len();
+        let mut table = win_certificate(length as u32, 0x0200, 2, &signature);
+        table.resize(length.next_multiple_of(8), 0);
+        let second = table.len();
+        table.extend(win_certificate(12, 0x0100, 1, &[1, 2, 3, 4]));
+        table.resize(table.len() + 4, 0);
+        let parsed = parse(TABLE, table.len(), &table);
+        assert_eq!(parsed.offset, TABLE as u32);
+        assert_eq!(parsed.entries.len(), 2);
+        assert_eq!(parsed.entries[0].offset, TABLE);
+        assert_eq!(parsed.entries[0].length, length as u32);
+        assert_eq!(parsed.entries[0].revision, CertificateRevision::WIN_CERT_REVISION_2_0);
+        assert_eq!(parsed.entries[0].certificate_type, CertificateType::WIN_CERT_TYPE_PKCS_SIGNED_DATA);
+        assert_eq!(parsed.entries[0].data, signature);
+        assert_eq!(parsed.entries[1].offset, TABLE + second);
+        assert_eq!(parsed.entries[1].certificate_type, CertificateType::WIN_CERT_TYPE_X509);
+        assert_eq!(parsed.entries[1].data, vec![1, 2, 3, 4]);
+        assert!(parsed.entries[1].signed_data.is_none());
+        assert_eq!(parsed.signature().unwrap().raw, signature);
+        assert_eq!(parsed.anomalies, vec![format!("WIN_CERTIFICATE at 0x{:x} has revision WIN_CERT_REVISION_1_0", TABLE + second)]);
+    }
+
+    #[test]
+    fn malformed_entries() {
+        let headers = flat_headers(true, TABLE);
+        assert!(parse_certificate_table(&[0; TABLE], &headers).is_none());
+
+        let table = win_certificate(4, 0x0200, 2, &[0; 8]);
+        let parsed = parse(TABLE, table.len(), &table);
+        assert!(parsed.entries.is_empty());
+        assert_eq!(parsed.anomalies, vec![String::from("WIN_CERTIFICATE at 0x1000 has an invalid length 0x4")]);
+
+        // The entry runs past the directory, not past the file
+        let mut table = win_certificate(0x20, 0x0200, 1, &[0; 0x18]);
+        table.extend(win_certificate(0x20, 0x0200, 1, &[0; 0x18]));
+        let parsed = parse(TABLE, 0x30, &table);
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.anomalies, vec![
+            String::from("0x10 bytes follow the certificate table"),
+            String::from("WIN_CERTIFICATE at 0x1020 with length 0x20 exceeds the certificate table"),
+        ]);
+
+        // Misaligned table in the section data, whose PKCS#7 entry is not a SignedData, and a directory past the end of the file
+        let mut table = win_certificate(0xd, 0x0200, 2, &[0x30, 0x03, 0x02, 0x01, 0x01]);
+        // Padding is to the next 8 byte file offset, not relative to the table
+        table.resize(0x14, 0);
+        table.extend(win_certificate(0x40, 0x0200, 1, &[0; 8]));
+        let parsed = parse(0xffc, 0x100, &table);
+        assert_eq!(parsed.entries.len(), 1);
+        assert_eq!(parsed.entries[0].length, 0xd);
+        assert_eq!(parsed.anomalies, vec![
+            String::from("Certificate table 0xffc-0x10fc extends past the end of the file 0x1020"),
+            String::from("Certificate table offset 0xffc is not 8 byte aligned"),
+            String::from("Certificate table at 0xffc overlaps the headers or section data"),
+            String::from("PKCS#7 SignedData at 0xffc could not be decoded"),
+            String::from("WIN_CERTIFICATE at 0x1010 with length 0x40 exceeds the certificate table"),
+        ]);
+    }
+}
@@ -0,0 +1,312 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::load_config::GuardFlag;
+use crate::load_config::LoadConfig;
+use crate::utils::is_executable_rva;
+use crate::utils::read_u32_at;
+use crate::utils::read_u64_at;
+use crate::utils::rva_to_offset;
+use crate::utils::section_for_rva;
+use crate::utils::slice_at_rva;
+use crate::utils::va_to_rva;
+
+const IMAGE_DLLCHARACTERISTICS_GUARD_CF: u16 = 0x4000;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum GuardEntryFlag {
+    IMAGE_GUARD_FLAG_FID_SUPPRESSED = 0x01,
+    IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED = 0x02,
+    IMAGE_GUARD_FLAG_FID_LANGEXCPTHANDLER = 0x04,
+    IMAGE_GUARD_FLAG_FID_XFG = 0x08,
+}
+
+pub struct GuardEntry {
+    pub rva: u32,
+    // Extra bytes following the RVA, their count comes from the GuardFlags of the load config
+    pub metadata: Vec<u8>,
+    pub flags: Vec<GuardEntryFlag>,
+    pub section: Option<String>,
+    // XFG type hashes are stored in the 8 bytes preceding the function
+    pub xfg_hash: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct GuardTable {
+    pub name: &'static str,
+    pub va: u64,
+    pub count: u64,
+    pub entries: Vec<GuardEntry>,
+}
+
+#[derive(Default)]
+pub struct GuardTables {
+    pub metadata_size: u32,
+    pub instrumented: bool,
+    pub guard_cf_dll_characteristic: bool,
+    pub function_table: GuardTable,
+    pub address_taken_iat: GuardTable,
+    pub long_jump_targets: GuardTable,
+    pub eh_continuation_targets: GuardTable,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl GuardEntryFlag {
+    pub fn from_u8(val: u8) -> Vec<GuardEntryFlag> {
+        let mut flags = Vec::new();
+        for flag in [GuardEntryFlag::IMAGE_GUARD_FLAG_FID_SUPPRESSED, GuardEntryFlag::IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED, GuardEntryFlag::IMAGE_GUARD_FLAG_FID_LANGEXCPTHANDLER, GuardEntryFlag::IMAGE_GUARD_FLAG_FID_XFG] {
+            if val & flag as u8 != 0 {
+                flags.push(flag);
+            }
+        }
+        flags
+    }
+}
+
+impl GuardEntry {
+    pub fn is_suppressed(&self) -> bool {
+        self.metadata.first().map(|flags| flags & GuardEntryFlag::IMAGE_GUARD_FLAG_FID_SUPPRESSED as u8 != 0).unwrap_or(false)
+    }
+
+    pub fn is_export_suppressed(&self) -> bool {
+        self.metadata.first().map(|flags| flags & GuardEntryFlag::IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED as u8 != 0).unwrap_or(false)
+    }
+}
+
+impl GuardTables {
+    // The loader only enforces CFG when the image opts in and the compiler instrumented the calls
+    pub fn is_cfg_enforced(&self) -> bool {
+        self.instrumented && self.guard_cf_dll_characteristic
+    }
+}
+
+
+// Parsing
+
+fn parse_table(file: &[u8], headers: &Headers, name: &'static str, va: Option<u64>, count: Option<u64>, metadata_size: u32, anomalies: &mut Vec<String>) -> GuardTable {
+    let mut table = GuardTable { name, va: va.unwrap_or(0), count: count.unwrap_or(0), entries: Vec::new() };
+    if table.va == 0 || table.count == 0 {
+        return table;
+    }
+    let offset = match va_to_rva(headers, table.va).and_then(|rva| rva_to_offset(headers, rva)) {
+        Some(offset) => offset,
+        None => {
+            anomalies.push(format!("{} at VA 0x{:x} is not backed by the file", name, table.va));
+            return table;
+        }
+    };
+    let entry_size = 4 + metadata_size as usize;
+    let mut previous: Option<u32> = None;
+    let mut unsorted = false;
+    for x in 0..table.count as usize {
+        let index = offset + x * entry_size;
+        let (rva, metadata) = match (read_u32_at(file, index), file.get(index + 4..index + entry_size)) {
+            (Some(rva), Some(metadata)) => (rva, metadata.to_vec()),
+            _ => {
+                anomalies.push(format!("{} declares {} entries but the file ends after {}", name, table.count, x));
+                break;
+            }
+        };
+        if previous.is_some_and(|previous| previous >= rva) {
+            unsorted = true;
+        }
+        previous = Some(rva);
+        let flags = GuardEntryFlag::from_u8(metadata.first().copied().unwrap_or(0));
+        let xfg_hash = if flags.iter().any(|flag| matches!(flag, GuardEntryFlag::IMAGE_GUARD_FLAG_FID_XFG)) {
+            rva.checked_sub(8).and_then(|hash_rva| slice_at_rva(file, headers, hash_rva, 8)).and_then(|hash| read_u64_at(hash, 0))
+        } else {
+            None
+        };
+        table.entries.push(GuardEntry {
+            rva,
+            metadata,
+            flags,
+            section: section_for_rva(headers, rva).map(|section| section.name.clone()),
+            xfg_hash,
+        });
+    }
+    if unsorted {
+        anomalies.push(format!("{} is not sorted, the loader binary searches it", name));
+    }
+    table
+}
+
+pub fn parse_guard_tables(file: &[u8], headers: &Headers, load_config: &LoadConfig) -> GuardTables {
+    let mut tables = GuardTables {
+        metadata_size: load_config.guard_cf_function_table_stride(),
+        instrumented: load_config.has_guard_flag(GuardFlag::IMAGE_GUARD_CF_INSTRUMENTED),
+        guard_cf_dll_characteristic: headers.optional_headers.windows_specific.dll_characteristics & IMAGE_DLLCHARACTERISTICS_GUARD_CF != 0,
+        ..Default::default()
+    };
+    let metadata_size = tables.metadata_size;
+    tables.function_table = parse_table(file, headers, "GuardCFFunctionTable", load_config.guard_cf_function_table, load_config.guard_cf_function_count, metadata_size, &mut tables.anomalies);
+    tables.address_taken_iat = parse_table(file, headers, "GuardAddressTakenIatEntryTable", load_config.guard_address_taken_iat_entry_table, load_config.guard_address_taken_iat_entry_count, metadata_size, &mut tables.anomalies);
+    tables.long_jump_targets = parse_table(file, headers, "GuardLongJumpTargetTable", load_config.guard_long_jump_target_table, load_config.guard_long_jump_target_count, metadata_size, &mut tables.anomalies);
+    tables.eh_continuation_targets = parse_table(file, headers, "GuardEHContinuationTable", load_config.guard_eh_continuation_table, load_config.guard_eh_continuation_count, metadata_size, &mut tables.anomalies);
+
+    // Call, jump and continuation targets must be code, IAT entries only have to be mapped
+    for table in [&tables.function_table, &tables.long_jump_targets, &tables.eh_continuation_targets] {
+        for entry in &table.entries {
+            if !is_executable_rva(headers, entry.rva) {
+                tables.anomalies.push(format!("{} entry 0x{:x} is not in an executable section", table.name, entry.rva));
+            }
+        }
+    }
+    for entry in &tables.address_taken_iat.entries {
+        if entry.section.is_none() {
+            tables.anomalies.push(format!("GuardAddressTakenIatEntryTable entry 0x{:x} is outside of every section", entry.rva));
+        }
+    }
+
+    let declared = [
+        (GuardFlag::IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT, &tables.function_table),
+        (GuardFlag::IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT, &tables.long_jump_targets),
+        (GuardFlag::IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT, &tables.eh_continuation_targets),
+    ];
+    for (flag, table) in declared {
+        if load_config.has_guard_flag(flag) && table.va == 0 {
+            tables.anomalies.push(format!("{:?} is set but {} is missing", flag, table.name));
+        }
+    }
+    if tables.guard_cf_dll_characteristic && !tables.instrumented {
+        tables.anomalies.push(String::from("IMAGE_DLLCHARACTERISTICS_GUARD_CF is set but the image is not CF instrumented"));
+    }
+    tables
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for GuardTables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Control Flow Guard
+
+---------------------------
+enforced: {}
+instrumented: {}
+guard_cf_dll_characteristic: {}
+metadata_size: {}
+---------------------------",
+        self.is_cfg_enforced(), self.instrumented, self.guard_cf_dll_characteristic, self.metadata_size)?;
+        for table in [&self.function_table, &self.address_taken_iat, &self.long_jump_targets, &self.eh_continuation_targets] {
+            write!(f, "\n{}", table)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GuardTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at 0x{:x} ({} entries)", self.name, self.va, self.count)?;
+        for entry in &self.entries {
+            write!(f, "\n  0x{:08x} {}", entry.rva, entry.section.as_deref().unwrap_or("-"))?;
+            if !entry.flags.is_empty() {
+                write!(f, " {:?}", entry.flags)?;
+            }
+            if let Some(hash) = entry.xfg_hash {
+                write!(f, " xfg_hash: 0x{:016x}", hash)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::structs::SectionHeader;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    #[test]
+    fn parses_function_table_with_metadata() {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(false, file.len());
+        headers.optional_headers.windows_specific.dll_characteristics = IMAGE_DLLCHARACTERISTICS_GUARD_CF;
+        for (x, (rva, flags)) in [(0x610u32, 0u8), (0x620, 0x01), (0x640, 0x0a)].iter().enumerate() {
+            put(&mut file, 0x800 + x * 5, &rva.to_le_bytes());
+            put(&mut file, 0x804 + x * 5, &[*flags]);
+        }
+        put(&mut file, 0x638, &0x1122334455667788u64.to_le_bytes());
+        put(&mut file, 0x900, &0x2000u32.to_le_bytes());
+        let load_config = LoadConfig {
+            guard_flags: Some(0x10010500),
+            guard_cf_function_table: Some(0x140000800),
+            guard_cf_function_count: Some(3),
+            guard_address_taken_iat_entry_table: Some(0x140000900),
+            guard_address_taken_iat_entry_count: Some(1),
+            ..Default::default()
+        };
+        let tables = parse_guard_tables(&file, &headers, &load_config);
+        assert!(tables.is_cfg_enforced());
+        assert_eq!(tables.metadata_size, 1);
+        let entries = &tables.function_table.entries;
+        assert_eq!(entries.iter().map(|entry| entry.rva).collect::<Vec<u32>>(), vec![0x610, 0x620, 0x640]);
+        assert!(!entries[0].is_suppressed());
+        assert!(entries[1].is_suppressed());
+        assert!(entries[2].is_export_suppressed());
+        assert_eq!(format!("{:?}", entries[2].flags), "[IMAGE_GUARD_FLAG_EXPORT_SUPPRESSED, IMAGE_GUARD_FLAG_FID_XFG]");
+        assert_eq!(entries[2].xfg_hash, Some(0x1122334455667788));
+        assert_eq!(entries[0].xfg_hash, None);
+        assert_eq!(entries[0].section.as_deref(), Some(".text"));
+        assert_eq!(tables.address_taken_iat.entries[0].section, None);
+        assert_eq!(tables.anomalies, vec![
+            String::from("GuardAddressTakenIatEntryTable entry 0x2000 is outside of every section"),
+            String::from("IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT is set but GuardLongJumpTargetTable is missing"),
+        ]);
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(true, file.len());
+        headers.optional_headers.windows_specific.dll_characteristics = IMAGE_DLLCHARACTERISTICS_GUARD_CF;
+        headers.sections.push(SectionHeader {
+            name: String::from(".data"),
+            virtual_size: 0x1000,
+            virtual_address: 0x1000,
+            characteristics: 0xc0000040,
+            ..Default::default()
+        });
+        for (x, rva) in [0x620u32, 0x610, 0x1100].iter().enumerate() {
+            put(&mut file, 0x800 + x * 4, &rva.to_le_bytes());
+        }
+        put(&mut file, 0xff8, &0x700u32.to_le_bytes());
+        put(&mut file, 0xffc, &0x710u32.to_le_bytes());
+        let load_config = LoadConfig {
+            guard_cf_function_table: Some(0x400800),
+            guard_cf_function_count: Some(3),
+            guard_long_jump_target_table: Some(0x400ff8),
+            guard_long_jump_target_count: Some(4),
+            guard_eh_continuation_table: Some(0x402000),
+            guard_eh_continuation_count: Some(1),
+            ..Default::default()
+        };
+        let tables = parse_guard_tables(&file, &headers, &load_config);
+        assert!(!tables.is_cfg_enforced());
+        assert_eq!(tables.metadata_size, 0);
+        let functions: Vec<u32> = tables.function_table.entries.iter().map(|entry| entry.rva).collect();
+        assert_eq!(functions, vec![0x620, 0x610, 0x1100]);
+        assert_eq!(tables.function_table.entries[2].section.as_deref(), Some(".data"));
+        let long_jumps: Vec<u32> = tables.long_jump_targets.entries.iter().map(|entry| entry.rva).collect();
+        assert_eq!(long_jumps, vec![0x700, 0x710]);
+        assert!(tables.eh_continuation_targets.entries.is_empty());
+        assert_eq!(tables.anomalies, vec![
+            String::from("GuardCFFunctionTable is not sorted, the loader binary searches it"),
+            String::from("GuardLongJumpTargetTable declares 4 entries but the file ends after 2"),
+            String::from("GuardEHContinuationTable at VA 0x402000 is not backed by the file"),
+            String::from("GuardCFFunctionTable entry 0x1100 is not in an executable section"),
+            String::from("IMAGE_DLLCHARACTERISTICS_GUARD_CF is set but the image is not CF instrumented"),
+        ]);
+    }
+}
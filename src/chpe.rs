@@ -0,0 +1,540 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::dvrt::DynamicRelocationSymbol;
+use crate::dvrt::Fixup;
+use crate::dvrt::parse_dynamic_relocations;
+use crate::load_config::LoadConfig;
+use crate::utils::read_u32_at;
+use crate::utils::read_u64_at;
+use crate::utils::rva_to_offset;
+use crate::utils::slice_at_rva;
+use crate::utils::va_to_rva;
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+const IMAGE_DIRECTORY_ENTRY_IAT: usize = 12;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum CodeRangeKind {
+    Arm64,
+    Arm64EC,
+    Amd64,
+    X86,
+}
+
+pub struct CodeRange {
+    pub start: u32,
+    pub length: u32,
+    pub kind: CodeRangeKind,
+}
+
+// IMAGE_ARM64EC_CODE_RANGE_ENTRY_POINT
+pub struct EntryPointThunk {
+    pub start_rva: u32,
+    pub end_rva: u32,
+    pub entry_point: u32,
+}
+
+// IMAGE_ARM64EC_REDIRECTION_ENTRY
+pub struct Redirection {
+    pub source: u32,
+    pub destination: u32,
+}
+
+// IMAGE_ARM64EC_METADATA, the v2 fields are zero in version 1 structures
+#[derive(Default)]
+pub struct Arm64ecMetadata {
+    pub version: u32,
+    pub code_map: u32,
+    pub code_map_count: u32,
+    pub code_ranges_to_entry_points: u32,
+    pub redirection_metadata: u32,
+    pub os_arm64x_dispatch_call_no_redirect: u32,
+    pub os_arm64x_dispatch_ret: u32,
+    pub os_arm64x_dispatch_call: u32,
+    pub os_arm64x_dispatch_icall: u32,
+    pub os_arm64x_dispatch_icall_cfg: u32,
+    pub alternate_entry_point: u32,
+    pub auxiliary_iat: u32,
+    pub code_ranges_to_entry_points_count: u32,
+    pub redirection_metadata_count: u32,
+    pub get_x64_information_function_pointer: u32,
+    pub set_x64_information_function_pointer: u32,
+    pub extra_rfe_table: u32,
+    pub extra_rfe_table_size: u32,
+    pub os_arm64x_dispatch_fptr: u32,
+    pub auxiliary_iat_copy: u32,
+    pub auxiliary_delayload_iat: u32,
+    pub auxiliary_delayload_iat_copy: u32,
+    pub hybrid_image_info_bitfield: u32,
+    pub code_ranges: Vec<CodeRange>,
+    pub entry_point_thunks: Vec<EntryPointThunk>,
+    pub redirections: Vec<Redirection>,
+    pub auxiliary_iat_entries: Vec<u64>,
+}
+
+// IMAGE_CHPE_METADATA_X86, the dispatch pointers are VAs
+#[derive(Default)]
+pub struct ChpeMetadataX86 {
+    pub version: u32,
+    pub code_address_range_offset: u32,
+    pub code_address_range_count: u32,
+    pub wow_a64_exception_handler_function_pointer: u32,
+    pub wow_a64_dispatch_call_function_pointer: u32,
+    pub wow_a64_dispatch_indirect_call_function_pointer: u32,
+    pub wow_a64_dispatch_indirect_call_cfg_function_pointer: u32,
+    pub wow_a64_dispatch_ret_function_pointer: u32,
+    pub wow_a64_dispatch_ret_leaf_function_pointer: u32,
+    pub wow_a64_dispatch_jump_function_pointer: u32,
+    pub compiler_iat_pointer: Option<u32>,
+    pub wow_a64_rdtsc_function_pointer: Option<u32>,
+    pub code_ranges: Vec<CodeRange>,
+}
+
+pub enum HybridMetadata {
+    Arm64EC(Arm64ecMetadata),
+    X86(ChpeMetadataX86),
+}
+
+pub struct Chpe {
+    pub rva: u32,
+    pub metadata: Option<HybridMetadata>,
+    pub anomalies: Vec<String>,
+}
+
+// The image as seen by the other personality, once the ARM64X relocations are applied
+pub struct AlternateView {
+    pub file: Vec<u8>,
+    pub headers: Headers,
+    pub applied: usize,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl Chpe {
+    // Architecture of the code at the given RVA according to the code map
+    pub fn code_kind(&self, rva: u32) -> Option<CodeRangeKind> {
+        let ranges = match &self.metadata {
+            Some(HybridMetadata::Arm64EC(metadata)) => &metadata.code_ranges,
+            Some(HybridMetadata::X86(metadata)) => &metadata.code_ranges,
+            None => return None,
+        };
+        ranges.iter().find(|range| rva >= range.start && rva - range.start < range.length).map(|range| range.kind)
+    }
+}
+
+
+// Parsing
+
+fn read_fields(data: &[u8], count: usize) -> Vec<u32> {
+    (0..count).map(|x| read_u32_at(data, x * 4).unwrap_or(0)).collect()
+}
+
+// Reads `count` records of `size` bytes at `rva`, reporting tables that are not backed by the file
+fn records<'a>(file: &'a [u8], headers: &Headers, name: &str, rva: u32, count: u32, size: usize, anomalies: &mut Vec<String>) -> Vec<&'a [u8]> {
+    if rva == 0 || count == 0 {
+        return Vec::new();
+    }
+    match slice_at_rva(file, headers, rva, count as usize * size) {
+        Some(data) => data.chunks_exact(size).collect(),
+        None => {
+            anomalies.push(format!("{} at RVA 0x{:x} with {} entries is not backed by the file", name, rva, count));
+            Vec::new()
+        }
+    }
+}
+
+fn parse_arm64ec(file: &[u8], headers: &Headers, data: &[u8], anomalies: &mut Vec<String>) -> Arm64ecMetadata {
+    let version = read_u32_at(data, 0).unwrap_or(0);
+    let field_count = if version >= 2 { 23 } else { 20 };
+    if data.len() < field_count * 4 {
+        anomalies.push(format!("ARM64EC metadata version {} is truncated", version));
+    }
+    let fields = read_fields(data, field_count);
+    let field = |x: usize| fields.get(x).copied().unwrap_or(0);
+    let mut metadata = Arm64ecMetadata {
+        version,
+        code_map: field(1),
+        code_map_count: field(2),
+        code_ranges_to_entry_points: field(3),
+        redirection_metadata: field(4),
+        os_arm64x_dispatch_call_no_redirect: field(5),
+        os_arm64x_dispatch_ret: field(6),
+        os_arm64x_dispatch_call: field(7),
+        os_arm64x_dispatch_icall: field(8),
+        os_arm64x_dispatch_icall_cfg: field(9),
+        alternate_entry_point: field(10),
+        auxiliary_iat: field(11),
+        code_ranges_to_entry_points_count: field(12),
+        redirection_metadata_count: field(13),
+        get_x64_information_function_pointer: field(14),
+        set_x64_information_function_pointer: field(15),
+        extra_rfe_table: field(16),
+        extra_rfe_table_size: field(17),
+        os_arm64x_dispatch_fptr: field(18),
+        auxiliary_iat_copy: field(19),
+        auxiliary_delayload_iat: field(20),
+        auxiliary_delayload_iat_copy: field(21),
+        hybrid_image_info_bitfield: field(22),
+        ..Default::default()
+    };
+    // The low two bits of StartOffset hold the architecture of the range
+    for entry in records(file, headers, "ARM64EC code map", metadata.code_map, metadata.code_map_count, 8, anomalies) {
+        let start_offset = read_u32_at(entry, 0).unwrap_or(0);
+        let kind = match start_offset & 3 {
+            0 => CodeRangeKind::Arm64,
+            1 => CodeRangeKind::Arm64EC,
+            2 => CodeRangeKind::Amd64,
+            _ => {
+                anomalies.push(format!("Code range at 0x{:x} has the reserved type 3", start_offset & !3));
+                continue;
+            }
+        };
+        metadata.code_ranges.push(CodeRange { start: start_offset & !3, length: read_u32_at(entry, 4).unwrap_or(0), kind });
+    }
+    for entry in records(file, headers, "ARM64EC entry point thunks", metadata.code_ranges_to_entry_points, metadata.code_ranges_to_entry_points_count, 12, anomalies) {
+        metadata.entry_point_thunks.push(EntryPointThunk {
+            start_rva: read_u32_at(entry, 0).unwrap_or(0),
+            end_rva: read_u32_at(entry, 4).unwrap_or(0),
+            entry_point: read_u32_at(entry, 8).unwrap_or(0),
+        });
+    }
+    for entry in records(file, headers, "ARM64EC redirection metadata", metadata.redirection_metadata, metadata.redirection_metadata_count, 8, anomalies) {
+        metadata.redirections.push(Redirection { source: read_u32_at(entry, 0).unwrap_or(0), destination: read_u32_at(entry, 4).unwrap_or(0) });
+    }
+    // The auxiliary IAT mirrors the regular IAT entry for entry
+    let iat_size = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_IAT].size / 8;
+    for entry in records(file, headers, "ARM64EC auxiliary IAT", metadata.auxiliary_iat, iat_size, 8, anomalies) {
+        metadata.auxiliary_iat_entries.push(read_u64_at(entry, 0).unwrap_or(0));
+    }
+    metadata
+}
+
+fn parse_x86(file: &[u8], headers: &Headers, data: &[u8], anomalies: &mut Vec<String>) -> ChpeMetadataX86 {
+    let version = read_u32_at(data, 0).unwrap_or(0);
+    let field_count = match version {
+        0 | 1 => 10,
+        2 => 11,
+        _ => 12,
+    };
+    if data.len() < field_count * 4 {
+        anomalies.push(format!("CHPE metadata version {} is truncated", version));
+    }
+    let fields = read_fields(data, field_count);
+    let mut metadata = ChpeMetadataX86 {
+        version,
+        code_address_range_offset: fields[1],
+        code_address_range_count: fields[2],
+        wow_a64_exception_handler_function_pointer: fields[3],
+        wow_a64_dispatch_call_function_pointer: fields[4],
+        wow_a64_dispatch_indirect_call_function_pointer: fields[5],
+        wow_a64_dispatch_indirect_call_cfg_function_pointer: fields[6],
+        wow_a64_dispatch_ret_function_pointer: fields[7],
+        wow_a64_dispatch_ret_leaf_function_pointer: fields[8],
+        wow_a64_dispatch_jump_function_pointer: fields[9],
+        compiler_iat_pointer: fields.get(10).copied(),
+        wow_a64_rdtsc_function_pointer: fields.get(11).copied(),
+        code_ranges: Vec::new(),
+    };
+    // Bit 0 of StartOffset marks native ARM64 code, the rest of the image is x86
+    for entry in records(file, headers, "CHPE code address ranges", metadata.code_address_range_offset, metadata.code_address_range_count, 8, anomalies) {
+        let start_offset = read_u32_at(entry, 0).unwrap_or(0);
+        let kind = if start_offset & 1 != 0 { CodeRangeKind::Arm64 } else { CodeRangeKind::X86 };
+        metadata.code_ranges.push(CodeRange { start: start_offset & !1, length: read_u32_at(entry, 4).unwrap_or(0), kind });
+    }
+    metadata
+}
+
+pub fn parse_chpe(file: &[u8], headers: &Headers, load_config: &LoadConfig) -> Option<Chpe> {
+    let va = match load_config.chpe_metadata_pointer {
+        Some(va) if va != 0 => va,
+        _ => return None,
+    };
+    let mut chpe = Chpe { rva: 0, metadata: None, anomalies: Vec::new() };
+    let data = match va_to_rva(headers, va).and_then(|rva| Some((rva, rva_to_offset(headers, rva)?))) {
+        Some((rva, offset)) if offset < file.len() => {
+            chpe.rva = rva;
+            &file[offset..]
+        }
+        _ => {
+            chpe.anomalies.push(format!("CHPE metadata at VA 0x{:x} is not backed by the file", va));
+            return Some(chpe);
+        }
+    };
+    chpe.metadata = Some(if headers.coff_headers.target_machine == IMAGE_FILE_MACHINE_I386 {
+        HybridMetadata::X86(parse_x86(file, headers, data, &mut chpe.anomalies))
+    } else {
+        HybridMetadata::Arm64EC(parse_arm64ec(file, headers, data, &mut chpe.anomalies))
+    });
+    Some(chpe)
+}
+
+
+// Alternate view
+
+// Applies the ARM64X relocations of the DVRT to a copy of the file and parses the resulting headers
+pub fn alternate_view(file: &[u8], headers: &Headers, load_config: &LoadConfig) -> Option<AlternateView> {
+    let table = parse_dynamic_relocations(file, headers, load_config)?;
+    let mut view = AlternateView { file: file.to_vec(), headers: Headers::default(), applied: 0, anomalies: table.anomalies.clone() };
+    let mut found = false;
+    for fixup in table.fixups_for(DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64X) {
+        found = true;
+        let (offset, size) = match fixup.fixup {
+            Fixup::Arm64xZeroFill { size } | Fixup::Arm64xAssignValue { size, .. } => (rva_to_offset(headers, fixup.rva), size as usize),
+            Fixup::Arm64xAddDelta { .. } => (rva_to_offset(headers, fixup.rva), 4),
+            _ => continue,
+        };
+        let target = match offset.and_then(|offset| view.file.get_mut(offset..offset + size)) {
+            Some(target) => target,
+            None => {
+                view.anomalies.push(format!("ARM64X relocation at RVA 0x{:x} is not backed by the file", fixup.rva));
+                continue;
+            }
+        };
+        match fixup.fixup {
+            Fixup::Arm64xZeroFill { .. } => target.fill(0),
+            Fixup::Arm64xAssignValue { value, .. } => target.copy_from_slice(&value.to_le_bytes()[..size]),
+            Fixup::Arm64xAddDelta { delta } => {
+                let value = u32::from_le_bytes([target[0], target[1], target[2], target[3]]).wrapping_add(delta as u32);
+                target.copy_from_slice(&value.to_le_bytes());
+            }
+            _ => {}
+        }
+        view.applied += 1;
+    }
+    if !found {
+        return None;
+    }
+    view.headers = crate::parse_headers(&view.file);
+    Some(view)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for Chpe {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.metadata {
+            Some(HybridMetadata::Arm64EC(metadata)) => write!(f, "{}", metadata),
+            Some(HybridMetadata::X86(metadata)) => write!(f, "{}", metadata),
+            None => write!(f, "CHPE metadata at RVA 0x{:x} could not be read", self.rva),
+        }
+    }
+}
+
+impl fmt::Display for Arm64ecMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ARM64EC Metadata
+
+---------------------------
+version: {}
+code_map: 0x{:x} ({} ranges)
+code_ranges_to_entry_points: 0x{:x} ({} entries)
+redirection_metadata: 0x{:x} ({} entries)
+os_arm64x_dispatch_call_no_redirect: 0x{:x}
+os_arm64x_dispatch_ret: 0x{:x}
+os_arm64x_dispatch_call: 0x{:x}
+os_arm64x_dispatch_icall: 0x{:x}
+os_arm64x_dispatch_icall_cfg: 0x{:x}
+alternate_entry_point: 0x{:x}
+auxiliary_iat: 0x{:x}
+get_x64_information_function_pointer: 0x{:x}
+set_x64_information_function_pointer: 0x{:x}
+extra_rfe_table: 0x{:x} (0x{:x} bytes)
+os_arm64x_dispatch_fptr: 0x{:x}
+auxiliary_iat_copy: 0x{:x}
+auxiliary_delayload_iat: 0x{:x}
+auxiliary_delayload_iat_copy: 0x{:x}
+hybrid_image_info_bitfield: 0x{:x}
+---------------------------",
+        self.version, self.code_map, self.code_map_count, self.code_ranges_to_entry_points, self.code_ranges_to_entry_points_count, self.redirection_metadata, self.redirection_metadata_count, self.os_arm64x_dispatch_call_no_redirect, self.os_arm64x_dispatch_ret, self.os_arm64x_dispatch_call, self.os_arm64x_dispatch_icall, self.os_arm64x_dispatch_icall_cfg, self.alternate_entry_point, self.auxiliary_iat, self.get_x64_information_function_pointer, self.set_x64_information_function_pointer, self.extra_rfe_table, self.extra_rfe_table_size, self.os_arm64x_dispatch_fptr, self.auxiliary_iat_copy, self.auxiliary_delayload_iat, self.auxiliary_delayload_iat_copy, self.hybrid_image_info_bitfield)?;
+        for range in &self.code_ranges {
+            write!(f, "\n{}", range)?;
+        }
+        for thunk in &self.entry_point_thunks {
+            write!(f, "\nentry thunk 0x{:08x}-0x{:08x} -> 0x{:08x}", thunk.start_rva, thunk.end_rva, thunk.entry_point)?;
+        }
+        for redirection in &self.redirections {
+            write!(f, "\nredirection 0x{:08x} -> 0x{:08x}", redirection.source, redirection.destination)?;
+        }
+        for (x, entry) in self.auxiliary_iat_entries.iter().enumerate() {
+            write!(f, "\nauxiliary IAT[{}] 0x{:x}", x, entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ChpeMetadataX86 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CHPE Metadata
+
+---------------------------
+version: {}
+code_address_range_offset: 0x{:x} ({} ranges)
+wow_a64_exception_handler_function_pointer: 0x{:x}
+wow_a64_dispatch_call_function_pointer: 0x{:x}
+wow_a64_dispatch_indirect_call_function_pointer: 0x{:x}
+wow_a64_dispatch_indirect_call_cfg_function_pointer: 0x{:x}
+wow_a64_dispatch_ret_function_pointer: 0x{:x}
+wow_a64_dispatch_ret_leaf_function_pointer: 0x{:x}
+wow_a64_dispatch_jump_function_pointer: 0x{:x}
+---------------------------",
+        self.version, self.code_address_range_offset, self.code_address_range_count, self.wow_a64_exception_handler_function_pointer, self.wow_a64_dispatch_call_function_pointer, self.wow_a64_dispatch_indirect_call_function_pointer, self.wow_a64_dispatch_indirect_call_cfg_function_pointer, self.wow_a64_dispatch_ret_function_pointer, self.wow_a64_dispatch_ret_leaf_function_pointer, self.wow_a64_dispatch_jump_function_pointer)?;
+        for range in &self.code_ranges {
+            write!(f, "\n{}", range)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CodeRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} 0x{:08x}-0x{:08x}", self.kind, self.start, self.start as u64 + self.length as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn put_fields(file: &mut Vec<u8>, offset: usize, fields: &[u32]) {
+        for (x, field) in fields.iter().enumerate() {
+            put(file, offset + x * 4, &field.to_le_bytes());
+        }
+    }
+
+    fn chpe_at(va: u64) -> LoadConfig {
+        LoadConfig { chpe_metadata_pointer: Some(va), ..Default::default() }
+    }
+
+    #[test]
+    fn parses_arm64ec_metadata() {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(false, file.len());
+        headers.coff_headers.target_machine = 0xaa64;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_IAT].size = 16;
+        let mut fields = [0u32; 23];
+        fields[0] = 2;
+        fields[1] = 0x900;
+        fields[2] = 4;
+        fields[3] = 0x940;
+        fields[4] = 0x960;
+        fields[10] = 0x640;
+        fields[11] = 0x980;
+        fields[12] = 1;
+        fields[13] = 1;
+        fields[22] = 1;
+        put_fields(&mut file, 0x800, &fields);
+        put_fields(&mut file, 0x900, &[0x401, 0x100, 0x502, 0x80, 0x600, 0x40, 0x703, 0x10]);
+        put_fields(&mut file, 0x940, &[0x500, 0x580, 0x620]);
+        put_fields(&mut file, 0x960, &[0x6a0, 0x610]);
+        put(&mut file, 0x980, &0x1400006a0u64.to_le_bytes());
+        put(&mut file, 0x988, &0x1400006b0u64.to_le_bytes());
+        let chpe = parse_chpe(&file, &headers, &chpe_at(0x140000800)).unwrap();
+        assert_eq!(chpe.rva, 0x800);
+        assert_eq!(chpe.anomalies, vec![String::from("Code range at 0x700 has the reserved type 3")]);
+        assert_eq!(chpe.code_kind(0x4ff), Some(CodeRangeKind::Arm64EC));
+        assert_eq!(chpe.code_kind(0x540), Some(CodeRangeKind::Amd64));
+        assert_eq!(chpe.code_kind(0x600), Some(CodeRangeKind::Arm64));
+        assert_eq!(chpe.code_kind(0x700), None);
+        let metadata = match chpe.metadata {
+            Some(HybridMetadata::Arm64EC(metadata)) => metadata,
+            _ => panic!("expected ARM64EC metadata"),
+        };
+        assert_eq!((metadata.version, metadata.alternate_entry_point, metadata.hybrid_image_info_bitfield), (2, 0x640, 1));
+        assert_eq!(metadata.code_ranges.len(), 3);
+        let thunk = &metadata.entry_point_thunks[0];
+        assert_eq!((thunk.start_rva, thunk.end_rva, thunk.entry_point), (0x500, 0x580, 0x620));
+        assert_eq!((metadata.redirections[0].source, metadata.redirections[0].destination), (0x6a0, 0x610));
+        assert_eq!(metadata.auxiliary_iat_entries, vec![0x1400006a0, 0x1400006b0]);
+    }
+
+    #[test]
+    fn parses_x86_metadata() {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(true, file.len());
+        headers.coff_headers.target_machine = IMAGE_FILE_MACHINE_I386;
+        put_fields(&mut file, 0x800, &[1, 0x900, 2, 0x401000, 0x401010, 0x401020, 0x401030, 0x401040, 0x401050, 0x401060]);
+        put_fields(&mut file, 0x900, &[0x401, 0x100, 0x500, 0x100]);
+        let chpe = parse_chpe(&file, &headers, &chpe_at(0x400800)).unwrap();
+        assert!(chpe.anomalies.is_empty());
+        assert_eq!(chpe.code_kind(0x480), Some(CodeRangeKind::Arm64));
+        assert_eq!(chpe.code_kind(0x580), Some(CodeRangeKind::X86));
+        let metadata = match chpe.metadata {
+            Some(HybridMetadata::X86(metadata)) => metadata,
+            _ => panic!("expected x86 CHPE metadata"),
+        };
+        assert_eq!(metadata.wow_a64_dispatch_jump_function_pointer, 0x401060);
+        assert_eq!(metadata.compiler_iat_pointer, None);
+        assert_eq!(metadata.wow_a64_rdtsc_function_pointer, None);
+        assert!(parse_chpe(&file, &headers, &LoadConfig::default()).is_none());
+    }
+
+    #[test]
+    fn malformed_metadata() {
+        let mut file = vec![0; 0x1000];
+        let headers = flat_headers(false, file.len());
+        put_fields(&mut file, 0xfc0, &[2, 0x3000, 2]);
+        let chpe = parse_chpe(&file, &headers, &chpe_at(0x140000fc0)).unwrap();
+        assert_eq!(chpe.rva, 0xfc0);
+        match &chpe.metadata {
+            Some(HybridMetadata::Arm64EC(metadata)) => {
+                assert_eq!((metadata.version, metadata.code_map, metadata.code_map_count), (2, 0x3000, 2));
+                // Fields past the end of the file read as zero
+                assert_eq!(metadata.hybrid_image_info_bitfield, 0);
+                assert!(metadata.code_ranges.is_empty());
+            }
+            _ => panic!("expected ARM64EC metadata"),
+        }
+        assert_eq!(chpe.anomalies, vec![
+            String::from("ARM64EC metadata version 2 is truncated"),
+            String::from("ARM64EC code map at RVA 0x3000 with 2 entries is not backed by the file"),
+        ]);
+        let chpe = parse_chpe(&file, &headers, &chpe_at(0x140002000)).unwrap();
+        assert!(chpe.metadata.is_none());
+        assert_eq!(chpe.anomalies, vec![String::from("CHPE metadata at VA 0x140002000 is not backed by the file")]);
+    }
+
+    #[test]
+    fn applies_arm64x_relocations() {
+        let mut file = vec![0; 0x1000];
+        let headers = flat_headers(false, file.len());
+        put(&mut file, 0x100, &0xffffffffu32.to_le_bytes());
+        put(&mut file, 0x200, &0x10u32.to_le_bytes());
+        // Switch the machine and the magic, clear a dword and add 8 to another, then touch a page outside of the file
+        let entries: Vec<u8> = [0x5004u16, 0x8664, 0x5018, 0x020b, 0x8100, 0x2200, 2, 0].iter().flat_map(|entry| entry.to_le_bytes()).collect();
+        let mut blocks = 0u32.to_le_bytes().to_vec();
+        blocks.extend_from_slice(&(8 + entries.len() as u32).to_le_bytes());
+        blocks.extend(entries);
+        blocks.extend_from_slice(&0x2000u32.to_le_bytes());
+        blocks.extend_from_slice(&12u32.to_le_bytes());
+        blocks.extend_from_slice(&[0, 0x80, 0, 0]);
+        put_fields(&mut file, 0x800, &[1, 12 + blocks.len() as u32]);
+        put(&mut file, 0x808, &6u64.to_le_bytes());
+        put(&mut file, 0x810, &(blocks.len() as u32).to_le_bytes());
+        put(&mut file, 0x814, &blocks);
+        let load_config = LoadConfig { dynamic_value_reloc_table: Some(0x140000800), ..Default::default() };
+        let view = alternate_view(&file, &headers, &load_config).unwrap();
+        assert_eq!(view.applied, 4);
+        assert_eq!(view.anomalies, vec![String::from("ARM64X relocation at RVA 0x2000 is not backed by the file")]);
+        assert_eq!(view.headers.coff_headers.target_machine, 0x8664);
+        assert_eq!(view.headers.optional_headers.standard_fields.magic, 0x20b);
+        assert_eq!(read_u32_at(&view.file, 0x100), Some(0));
+        assert_eq!(read_u32_at(&view.file, 0x200), Some(0x18));
+
+        // A table without ARM64X relocations has no alternate view
+        put(&mut file, 0x808, &3u64.to_le_bytes());
+        assert!(alternate_view(&file, &headers, &load_config).is_none());
+    }
+}
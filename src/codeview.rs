@@ -0,0 +1,167 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use crate::structs::Headers;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum CodeViewFormat {
+    // PDB 7.0
+    Rsds,
+    // PDB 2.0
+    Nb10,
+    // Debug information embedded in the image, NB09 or NB11 for instance
+    Other([u8; 4]),
+}
+
+pub struct CodeView {
+    pub format: CodeViewFormat,
+    pub guid: Option<[u8; 16]>,
+    pub signature: Option<u32>,
+    pub offset: Option<u32>,
+    pub age: u32,
+    pub pdb_path: String,
+}
+
+
+// Conversion implementation for the structs
+
+impl CodeView {
+    // Registry style GUID as shown by debuggers
+    pub fn guid_string(&self) -> Option<String> {
+        let guid = self.guid?;
+        Some(format!("{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{}",
+        read_u32_at(&guid, 0)?, read_u16_at(&guid, 4)?, read_u16_at(&guid, 6)?, guid[8], guid[9],
+        guid[10..].iter().map(|byte| format!("{:02X}", byte)).collect::<String>()))
+    }
+
+    // The PDB file name without its directory, whichever separator the linker used
+    pub fn pdb_file_name(&self) -> &str {
+        self.pdb_path.rsplit(['\\', '/']).next().unwrap_or(&self.pdb_path)
+    }
+
+    // Symbol server key of the PDB: GUID (or NB10 signature) followed by the age
+    pub fn pdb_symbol_key(&self) -> Option<String> {
+        match self.format {
+            CodeViewFormat::Rsds => Some(format!("{}{:x}", self.guid_string()?.replace('-', ""), self.age)),
+            CodeViewFormat::Nb10 => Some(format!("{:08X}{:x}", self.signature?, self.age)),
+            CodeViewFormat::Other(_) => None,
+        }
+    }
+
+    pub fn pdb_symbol_path(&self) -> Option<String> {
+        let name = self.pdb_file_name();
+        Some(format!("{}/{}/{}", name, self.pdb_symbol_key()?, name))
+    }
+}
+
+// Symbol server key of the image itself: TimeDateStamp followed by SizeOfImage
+pub fn binary_symbol_key(headers: &Headers) -> String {
+    format!("{:08X}{:x}", headers.coff_headers.time_date_stamp, headers.optional_headers.windows_specific.size_of_image)
+}
+
+
+// Parsing
+
+fn read_path(data: &[u8]) -> String {
+    let end = data.iter().position(|byte| *byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+pub fn parse_codeview(data: &[u8]) -> Option<CodeView> {
+    let magic: [u8; 4] = data.get(0..4)?.try_into().ok()?;
+    match &magic {
+        b"RSDS" => Some(CodeView {
+            format: CodeViewFormat::Rsds,
+            guid: Some(data.get(4..20)?.try_into().ok()?),
+            signature: None,
+            offset: None,
+            age: read_u32_at(data, 20)?,
+            pdb_path: read_path(data.get(24..)?),
+        }),
+        b"NB10" => Some(CodeView {
+            format: CodeViewFormat::Nb10,
+            guid: None,
+            offset: Some(read_u32_at(data, 4)?),
+            signature: Some(read_u32_at(data, 8)?),
+            age: read_u32_at(data, 12)?,
+            pdb_path: read_path(data.get(16..)?),
+        }),
+        _ => Some(CodeView {
+            format: CodeViewFormat::Other(magic),
+            guid: None,
+            signature: None,
+            offset: None,
+            age: 0,
+            pdb_path: String::new(),
+        }),
+    }
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for CodeView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.format {
+            CodeViewFormat::Rsds => write!(f, "RSDS guid: {} age: {} pdb: {}", self.guid_string().unwrap_or_default(), self.age, self.pdb_path),
+            CodeViewFormat::Nb10 => write!(f, "NB10 signature: 0x{:08x} age: {} pdb: {}", self.signature.unwrap_or(0), self.age, self.pdb_path),
+            CodeViewFormat::Other(magic) => write!(f, "CodeView signature {}", String::from_utf8_lossy(&magic)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: [u8; 16] = [0xe0, 0x04, 0x25, 0x3f, 0x89, 0x4f, 0xd3, 0x11, 0x9a, 0x0c, 0x03, 0x05, 0xe8, 0x2c, 0x33, 0x01];
+
+    #[test]
+    fn parses_rsds_records() {
+        let mut data = b"RSDS".to_vec();
+        data.extend_from_slice(&GUID);
+        data.extend_from_slice(&0x2au32.to_le_bytes());
+        data.extend_from_slice(b"C:\\build\\x64/app.pdb\0\0\0");
+        let codeview = parse_codeview(&data).unwrap();
+        assert_eq!(codeview.format, CodeViewFormat::Rsds);
+        assert_eq!(codeview.pdb_path, "C:\\build\\x64/app.pdb");
+        assert_eq!(codeview.pdb_file_name(), "app.pdb");
+        assert_eq!(codeview.guid_string().as_deref(), Some("3F2504E0-4F89-11D3-9A0C-0305E82C3301"));
+        assert_eq!(codeview.pdb_symbol_key().as_deref(), Some("3F2504E04F8911D39A0C0305E82C33012a"));
+        assert_eq!(codeview.pdb_symbol_path().as_deref(), Some("app.pdb/3F2504E04F8911D39A0C0305E82C33012a/app.pdb"));
+        assert!(parse_codeview(&data[..20]).is_none());
+    }
+
+    #[test]
+    fn parses_nb10_and_other_records() {
+        let mut data = b"NB10".to_vec();
+        for value in [0u32, 0x3a1b2c3d, 3] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(b"app.pdb");
+        let codeview = parse_codeview(&data).unwrap();
+        assert_eq!((codeview.offset, codeview.signature, codeview.age), (Some(0), Some(0x3a1b2c3d), 3));
+        assert_eq!(codeview.guid_string(), None);
+        assert_eq!(codeview.pdb_symbol_path().as_deref(), Some("app.pdb/3A1B2C3D3/app.pdb"));
+
+        let codeview = parse_codeview(b"NB11\0\0\0\0").unwrap();
+        assert_eq!(codeview.format, CodeViewFormat::Other(*b"NB11"));
+        assert_eq!(codeview.pdb_symbol_key(), None);
+        assert!(parse_codeview(b"NB1").is_none());
+    }
+
+    #[test]
+    fn formats_binary_symbol_key() {
+        let mut headers = Headers::default();
+        headers.coff_headers.time_date_stamp = 0x5f00ab12;
+        headers.optional_headers.windows_specific.size_of_image = 0x3e000;
+        assert_eq!(binary_symbol_key(&headers), "5F00AB123e000");
+    }
+}
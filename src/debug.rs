@@ -0,0 +1,464 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::codeview::CodeView;
+use crate::codeview::parse_codeview;
+use crate::build_info::Pogo;
+use crate::build_info::VcFeature;
+use crate::build_info::Repro;
+use crate::build_info::ExDllCharacteristics;
+use crate::build_info::parse_pogo;
+use crate::build_info::parse_vc_feature;
+use crate::build_info::parse_repro;
+use crate::build_info::parse_ex_dll_characteristics;
+use crate::portable_pdb::EmbeddedPdb;
+use crate::portable_pdb::PdbChecksum;
+use crate::portable_pdb::parse_embedded_pdb;
+use crate::portable_pdb::parse_pdb_checksum;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::read_utf16_at;
+use crate::utils::rva_to_offset;
+use crate::utils::slice_at_rva;
+
+const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+const IMAGE_DEBUG_DIRECTORY_SIZE: usize = 28;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum DebugType {
+    IMAGE_DEBUG_TYPE_UNKNOWN,
+    IMAGE_DEBUG_TYPE_COFF,
+    IMAGE_DEBUG_TYPE_CODEVIEW,
+    IMAGE_DEBUG_TYPE_FPO,
+    IMAGE_DEBUG_TYPE_MISC,
+    IMAGE_DEBUG_TYPE_EXCEPTION,
+    IMAGE_DEBUG_TYPE_FIXUP,
+    IMAGE_DEBUG_TYPE_OMAP_TO_SRC,
+    IMAGE_DEBUG_TYPE_OMAP_FROM_SRC,
+    IMAGE_DEBUG_TYPE_BORLAND,
+    IMAGE_DEBUG_TYPE_RESERVED10,
+    IMAGE_DEBUG_TYPE_CLSID,
+    IMAGE_DEBUG_TYPE_VC_FEATURE,
+    IMAGE_DEBUG_TYPE_POGO,
+    IMAGE_DEBUG_TYPE_ILTCG,
+    IMAGE_DEBUG_TYPE_MPX,
+    IMAGE_DEBUG_TYPE_REPRO,
+    IMAGE_DEBUG_TYPE_EMBEDDED_PORTABLE_PDB,
+    IMAGE_DEBUG_TYPE_SPGO,
+    IMAGE_DEBUG_TYPE_PDBCHECKSUM,
+    IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS,
+    UNKNOWN(u32),
+}
+
+// IMAGE_DEBUG_MISC, usually the name of the image the debug information belongs to
+pub struct DebugMisc {
+    pub data_type: u32,
+    pub length: u32,
+    pub unicode: bool,
+    pub data: String,
+}
+
+// FPO_DATA, frame pointer omission records of old x86 images
+pub struct FpoData {
+    pub start: u32,
+    pub procedure_size: u32,
+    pub locals: u32,
+    pub parameters: u16,
+    pub prolog_size: u8,
+    pub saved_registers: u8,
+    pub has_seh: bool,
+    pub use_bp: bool,
+    pub frame_type: u8,
+}
+
+pub enum DebugData {
+    CodeView(CodeView),
+    Misc(DebugMisc),
+    Fpo(Vec<FpoData>),
+    Pogo(Pogo),
+    VcFeature(VcFeature),
+    // No payload, the entry only marks an incremental LTCG link
+    Iltcg,
+    Repro(Repro),
+    ExDllCharacteristics(ExDllCharacteristics),
+    EmbeddedPortablePdb(EmbeddedPdb),
+    PdbChecksum(PdbChecksum),
+}
+
+pub struct DebugEntry {
+    pub characteristics: u32,
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub debug_type: DebugType,
+    pub size_of_data: u32,
+    pub address_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub data: Vec<u8>,
+    // Set when a decoder exists for the type
+    pub decoded: Option<DebugData>,
+}
+
+#[derive(Default)]
+pub struct DebugDirectory {
+    pub entries: Vec<DebugEntry>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl DebugType {
+    pub fn from_u32(val: u32) -> DebugType {
+        match val {
+            0 => DebugType::IMAGE_DEBUG_TYPE_UNKNOWN,
+            1 => DebugType::IMAGE_DEBUG_TYPE_COFF,
+            2 => DebugType::IMAGE_DEBUG_TYPE_CODEVIEW,
+            3 => DebugType::IMAGE_DEBUG_TYPE_FPO,
+            4 => DebugType::IMAGE_DEBUG_TYPE_MISC,
+            5 => DebugType::IMAGE_DEBUG_TYPE_EXCEPTION,
+            6 => DebugType::IMAGE_DEBUG_TYPE_FIXUP,
+            7 => DebugType::IMAGE_DEBUG_TYPE_OMAP_TO_SRC,
+            8 => DebugType::IMAGE_DEBUG_TYPE_OMAP_FROM_SRC,
+            9 => DebugType::IMAGE_DEBUG_TYPE_BORLAND,
+            10 => DebugType::IMAGE_DEBUG_TYPE_RESERVED10,
+            11 => DebugType::IMAGE_DEBUG_TYPE_CLSID,
+            12 => DebugType::IMAGE_DEBUG_TYPE_VC_FEATURE,
+            13 => DebugType::IMAGE_DEBUG_TYPE_POGO,
+            14 => DebugType::IMAGE_DEBUG_TYPE_ILTCG,
+            15 => DebugType::IMAGE_DEBUG_TYPE_MPX,
+            16 => DebugType::IMAGE_DEBUG_TYPE_REPRO,
+            17 => DebugType::IMAGE_DEBUG_TYPE_EMBEDDED_PORTABLE_PDB,
+            18 => DebugType::IMAGE_DEBUG_TYPE_SPGO,
+            19 => DebugType::IMAGE_DEBUG_TYPE_PDBCHECKSUM,
+            20 => DebugType::IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS,
+            _ => DebugType::UNKNOWN(val),
+        }
+    }
+}
+
+impl DebugDirectory {
+    pub fn find(&self, debug_type: DebugType) -> Option<&DebugEntry> {
+        self.entries.iter().find(|entry| entry.debug_type == debug_type)
+    }
+
+    pub fn codeview(&self) -> Option<&CodeView> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::CodeView(codeview)) => Some(codeview),
+            _ => None,
+        })
+    }
+
+    pub fn pogo(&self) -> Option<&Pogo> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::Pogo(pogo)) => Some(pogo),
+            _ => None,
+        })
+    }
+
+    pub fn vc_feature(&self) -> Option<&VcFeature> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::VcFeature(vc_feature)) => Some(vc_feature),
+            _ => None,
+        })
+    }
+
+    pub fn repro(&self) -> Option<&Repro> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::Repro(repro)) => Some(repro),
+            _ => None,
+        })
+    }
+
+    pub fn ex_dll_characteristics(&self) -> Option<&ExDllCharacteristics> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::ExDllCharacteristics(characteristics)) => Some(characteristics),
+            _ => None,
+        })
+    }
+
+    pub fn embedded_portable_pdb(&self) -> Option<&EmbeddedPdb> {
+        self.entries.iter().find_map(|entry| match &entry.decoded {
+            Some(DebugData::EmbeddedPortablePdb(pdb)) => Some(pdb),
+            _ => None,
+        })
+    }
+
+    // Several checksums may be present, one per algorithm
+    pub fn pdb_checksums(&self) -> Vec<&PdbChecksum> {
+        self.entries.iter().filter_map(|entry| match &entry.decoded {
+            Some(DebugData::PdbChecksum(checksum)) => Some(checksum),
+            _ => None,
+        }).collect()
+    }
+
+    pub fn has_iltcg(&self) -> bool {
+        self.find(DebugType::IMAGE_DEBUG_TYPE_ILTCG).is_some()
+    }
+}
+
+
+// Decoders
+
+fn decode_misc(data: &[u8]) -> Option<DebugMisc> {
+    let length = read_u32_at(data, 4)?;
+    let unicode = *data.get(8)? != 0;
+    let payload = data.get(12..(length as usize).min(data.len()))?;
+    let text = if unicode {
+        read_utf16_at(payload, 0, payload.len() / 2)?
+    } else {
+        String::from_utf8_lossy(payload).to_string()
+    };
+    Some(DebugMisc { data_type: read_u32_at(data, 0)?, length, unicode, data: text.trim_end_matches('\0').to_string() })
+}
+
+fn decode_fpo(data: &[u8]) -> Vec<FpoData> {
+    data.chunks_exact(16).map(|record| {
+        let bits = read_u16_at(record, 14).unwrap_or(0);
+        FpoData {
+            start: read_u32_at(record, 0).unwrap_or(0),
+            procedure_size: read_u32_at(record, 4).unwrap_or(0),
+            locals: read_u32_at(record, 8).unwrap_or(0),
+            parameters: read_u16_at(record, 12).unwrap_or(0),
+            prolog_size: (bits & 0xff) as u8,
+            saved_registers: ((bits >> 8) & 0x7) as u8,
+            has_seh: bits & 0x800 != 0,
+            use_bp: bits & 0x1000 != 0,
+            frame_type: (bits >> 14) as u8,
+        }
+    }).collect()
+}
+
+fn decode_entry(debug_type: DebugType, data: &[u8]) -> Option<DebugData> {
+    match debug_type {
+        DebugType::IMAGE_DEBUG_TYPE_CODEVIEW => parse_codeview(data).map(DebugData::CodeView),
+        DebugType::IMAGE_DEBUG_TYPE_MISC => decode_misc(data).map(DebugData::Misc),
+        DebugType::IMAGE_DEBUG_TYPE_FPO => Some(DebugData::Fpo(decode_fpo(data))),
+        DebugType::IMAGE_DEBUG_TYPE_POGO => parse_pogo(data).map(DebugData::Pogo),
+        DebugType::IMAGE_DEBUG_TYPE_VC_FEATURE => parse_vc_feature(data).map(DebugData::VcFeature),
+        DebugType::IMAGE_DEBUG_TYPE_ILTCG => Some(DebugData::Iltcg),
+        DebugType::IMAGE_DEBUG_TYPE_REPRO => parse_repro(data).map(DebugData::Repro),
+        DebugType::IMAGE_DEBUG_TYPE_EX_DLLCHARACTERISTICS => parse_ex_dll_characteristics(data).map(DebugData::ExDllCharacteristics),
+        DebugType::IMAGE_DEBUG_TYPE_EMBEDDED_PORTABLE_PDB => parse_embedded_pdb(data).map(DebugData::EmbeddedPortablePdb),
+        DebugType::IMAGE_DEBUG_TYPE_PDBCHECKSUM => parse_pdb_checksum(data).map(DebugData::PdbChecksum),
+        _ => None,
+    }
+}
+
+
+// Parsing
+
+// The raw pointer is authoritative, debug data does not have to be mapped
+fn entry_data<'a>(file: &'a [u8], headers: &Headers, entry: &DebugEntry) -> Option<&'a [u8]> {
+    let size = entry.size_of_data as usize;
+    if entry.pointer_to_raw_data != 0 {
+        let start = entry.pointer_to_raw_data as usize;
+        if let Some(data) = file.get(start..start.checked_add(size)?) {
+            return Some(data);
+        }
+    }
+    if entry.address_of_raw_data != 0 {
+        return slice_at_rva(file, headers, entry.address_of_raw_data, size);
+    }
+    None
+}
+
+pub fn parse_debug_directory(file: &[u8], headers: &Headers) -> Option<DebugDirectory> {
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG];
+    if directory.virtual_address == 0 {
+        return None;
+    }
+    let mut debug = DebugDirectory::default();
+    let offset = match rva_to_offset(headers, directory.virtual_address) {
+        Some(offset) => offset,
+        None => {
+            debug.anomalies.push(format!("Debug directory at RVA 0x{:x} is not backed by the file", directory.virtual_address));
+            return Some(debug);
+        }
+    };
+    if !(directory.size as usize).is_multiple_of(IMAGE_DEBUG_DIRECTORY_SIZE) {
+        debug.anomalies.push(format!("Debug directory size 0x{:x} is not a multiple of 0x{:x}", directory.size, IMAGE_DEBUG_DIRECTORY_SIZE));
+    }
+    for x in 0..directory.size as usize / IMAGE_DEBUG_DIRECTORY_SIZE {
+        let record = match file.get(offset + x * IMAGE_DEBUG_DIRECTORY_SIZE..offset + (x + 1) * IMAGE_DEBUG_DIRECTORY_SIZE) {
+            Some(record) => record,
+            None => {
+                debug.anomalies.push(format!("Debug directory is truncated after {} entries", x));
+                break;
+            }
+        };
+        let mut entry = DebugEntry {
+            characteristics: read_u32_at(record, 0).unwrap_or(0),
+            time_date_stamp: read_u32_at(record, 4).unwrap_or(0),
+            major_version: read_u16_at(record, 8).unwrap_or(0),
+            minor_version: read_u16_at(record, 10).unwrap_or(0),
+            debug_type: DebugType::from_u32(read_u32_at(record, 12).unwrap_or(0)),
+            size_of_data: read_u32_at(record, 16).unwrap_or(0),
+            address_of_raw_data: read_u32_at(record, 20).unwrap_or(0),
+            pointer_to_raw_data: read_u32_at(record, 24).unwrap_or(0),
+            data: Vec::new(),
+            decoded: None,
+        };
+        match entry_data(file, headers, &entry) {
+            Some(data) => {
+                entry.data = data.to_vec();
+                entry.decoded = decode_entry(entry.debug_type, data);
+                if entry.debug_type == DebugType::IMAGE_DEBUG_TYPE_EMBEDDED_PORTABLE_PDB && entry.decoded.is_none() {
+                    debug.anomalies.push(String::from("Embedded portable PDB could not be decompressed"));
+                }
+            }
+            None if entry.size_of_data != 0 => {
+                debug.anomalies.push(format!("Data of debug entry {:?} is not backed by the file", entry.debug_type));
+            }
+            // ILTCG and REPRO entries may carry no data at all
+            None => entry.decoded = decode_entry(entry.debug_type, &[]),
+        }
+        debug.entries.push(entry);
+    }
+    Some(debug)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for DebugEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}
+
+---------------------------
+characteristics: 0x{:x}
+time_date_stamp: 0x{:x}
+version: {}.{}
+size_of_data: 0x{:x}
+address_of_raw_data: 0x{:x}
+pointer_to_raw_data: 0x{:x}
+---------------------------",
+        self.debug_type, self.characteristics, self.time_date_stamp, self.major_version, self.minor_version, self.size_of_data, self.address_of_raw_data, self.pointer_to_raw_data)?;
+        if let Some(decoded) = &self.decoded {
+            write!(f, "\n{}", decoded)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DebugData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebugData::CodeView(codeview) => write!(f, "{}", codeview),
+            DebugData::Misc(misc) => write!(f, "data_type: {} unicode: {} data: {}", misc.data_type, misc.unicode, misc.data),
+            DebugData::Fpo(records) => {
+                write!(f, "{} FPO records", records.len())?;
+                for record in records {
+                    write!(f, "\n0x{:08x} size: 0x{:x} locals: {} parameters: {} prolog: {} registers: {} seh: {} bp: {} frame: {}",
+                    record.start, record.procedure_size, record.locals, record.parameters, record.prolog_size, record.saved_registers, record.has_seh, record.use_bp, record.frame_type)?;
+                }
+                Ok(())
+            }
+            DebugData::Pogo(pogo) => write!(f, "{}", pogo),
+            DebugData::VcFeature(vc_feature) => write!(f, "{}", vc_feature),
+            DebugData::Iltcg => write!(f, "Incremental LTCG"),
+            DebugData::Repro(repro) => write!(f, "{}", repro),
+            DebugData::ExDllCharacteristics(characteristics) => write!(f, "{}", characteristics),
+            DebugData::EmbeddedPortablePdb(pdb) => write!(f, "{}", pdb),
+            DebugData::PdbChecksum(checksum) => write!(f, "{}", checksum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+    use crate::utils::fixtures::utf16;
+
+    // Writes IMAGE_DEBUG_DIRECTORY entries at RVA 0x800 as (type, size, address, pointer)
+    fn image(entries: &[(u32, u32, u32, u32)]) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(true, file.len());
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].size = (entries.len() * IMAGE_DEBUG_DIRECTORY_SIZE) as u32;
+        for (x, (debug_type, size, address, pointer)) in entries.iter().enumerate() {
+            let offset = 0x800 + x * IMAGE_DEBUG_DIRECTORY_SIZE;
+            put(&mut file, offset + 4, &0x5f000000u32.to_le_bytes());
+            for (y, value) in [*debug_type, *size, *address, *pointer].iter().enumerate() {
+                put(&mut file, offset + 12 + y * 4, &value.to_le_bytes());
+            }
+        }
+        (file, headers)
+    }
+
+    #[test]
+    fn enumerates_entries() {
+        let (mut file, headers) = image(&[(4, 0x14, 0, 0x900), (4, 0x1c, 0x920, 0), (3, 0x20, 0, 0x940), (14, 0, 0, 0), (0x42, 4, 0, 0x960)]);
+        put(&mut file, 0x900, &[1, 0, 0, 0, 0x14, 0, 0, 0, 0, 0, 0, 0]);
+        put(&mut file, 0x90c, b"app.exe\0");
+        put(&mut file, 0x920, &[1, 0, 0, 0, 0x1c, 0, 0, 0, 1, 0, 0, 0]);
+        put(&mut file, 0x92c, &utf16("app.dll\0"));
+        for (x, value) in [0x500u32, 0x40, 2].iter().enumerate() {
+            put(&mut file, 0x940 + x * 4, &value.to_le_bytes());
+        }
+        put(&mut file, 0x94c, &[3, 0, 0x05, 0x5b]);
+        put(&mut file, 0x960, &[0xde, 0xad, 0xbe, 0xef]);
+        let debug = parse_debug_directory(&file, &headers).unwrap();
+        assert!(debug.anomalies.is_empty());
+        assert_eq!(debug.entries.len(), 5);
+        assert_eq!(debug.entries[0].time_date_stamp, 0x5f000000);
+        let misc: Vec<(bool, &str)> = debug.entries.iter().filter_map(|entry| match &entry.decoded {
+            Some(DebugData::Misc(misc)) => Some((misc.unicode, misc.data.as_str())),
+            _ => None,
+        }).collect();
+        assert_eq!(misc, vec![(false, "app.exe"), (true, "app.dll")]);
+        match &debug.entries[2].decoded {
+            Some(DebugData::Fpo(records)) => {
+                assert_eq!(records.len(), 2);
+                let fpo = &records[0];
+                assert_eq!((fpo.start, fpo.procedure_size, fpo.locals, fpo.parameters), (0x500, 0x40, 2, 3));
+                assert_eq!((fpo.prolog_size, fpo.saved_registers, fpo.has_seh, fpo.use_bp, fpo.frame_type), (5, 3, true, true, 1));
+            }
+            _ => panic!("expected FPO records"),
+        }
+        assert!(debug.has_iltcg());
+        assert_eq!(debug.entries[4].debug_type, DebugType::UNKNOWN(0x42));
+        assert_eq!(debug.entries[4].data, vec![0xde, 0xad, 0xbe, 0xef]);
+        assert!(debug.entries[4].decoded.is_none());
+        assert!(debug.find(DebugType::IMAGE_DEBUG_TYPE_CODEVIEW).is_none());
+    }
+
+    #[test]
+    fn malformed_directories() {
+        let (file, mut headers) = image(&[(2, 0x18, 0x3000, 0x3000)]);
+        let debug = parse_debug_directory(&file, &headers).unwrap();
+        assert_eq!(debug.entries.len(), 1);
+        let entry = &debug.entries[0];
+        assert_eq!(entry.debug_type, DebugType::IMAGE_DEBUG_TYPE_CODEVIEW);
+        assert_eq!((entry.size_of_data, entry.address_of_raw_data, entry.pointer_to_raw_data), (0x18, 0x3000, 0x3000));
+        assert!(entry.data.is_empty());
+        assert!(entry.decoded.is_none());
+        assert_eq!(debug.anomalies, vec![String::from("Data of debug entry IMAGE_DEBUG_TYPE_CODEVIEW is not backed by the file")]);
+
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].virtual_address = 0xfd0;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].size = 60;
+        let debug = parse_debug_directory(&file, &headers).unwrap();
+        assert_eq!(debug.entries.len(), 1);
+        assert_eq!(debug.entries[0].debug_type, DebugType::IMAGE_DEBUG_TYPE_UNKNOWN);
+        assert_eq!(debug.anomalies, vec![
+            String::from("Debug directory size 0x3c is not a multiple of 0x1c"),
+            String::from("Debug directory is truncated after 1 entries"),
+        ]);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].virtual_address = 0x2000;
+        let debug = parse_debug_directory(&file, &headers).unwrap();
+        assert!(debug.entries.is_empty());
+        assert_eq!(debug.anomalies, vec![String::from("Debug directory at RVA 0x2000 is not backed by the file")]);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_DEBUG].virtual_address = 0;
+        assert!(parse_debug_directory(&file, &headers).is_none());
+    }
+}
@@ -0,0 +1,429 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::vec::Vec;
+
+// Minimal DER (X.690) reader and writer, enough for PKCS#7, X.509 and RFC 3161 structures
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_PRINTABLE_STRING: u8 = 0x13;
+pub const TAG_T61_STRING: u8 = 0x14;
+pub const TAG_IA5_STRING: u8 = 0x16;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_VISIBLE_STRING: u8 = 0x1a;
+pub const TAG_UNIVERSAL_STRING: u8 = 0x1c;
+pub const TAG_BMP_STRING: u8 = 0x1e;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+
+#[derive(Clone, Copy)]
+pub struct Element<'a> {
+    // Identifier octet, high tag numbers are not used by the structures we read
+    pub tag: u8,
+    // Tag, length and content
+    pub raw: &'a [u8],
+    pub content: &'a [u8],
+}
+
+pub struct Children<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Element<'a> {
+    pub fn is_constructed(&self) -> bool {
+        self.tag & 0x20 != 0
+    }
+
+    pub fn is_context(&self, number: u8) -> bool {
+        self.tag & 0xc0 == 0x80 && self.tag & 0x1f == number
+    }
+
+    pub fn children(&self) -> Children<'a> {
+        Children { data: self.content }
+    }
+
+    pub fn child(&self, index: usize) -> Option<Element<'a>> {
+        self.children().nth(index)
+    }
+
+    // Context specific child [number], explicit or implicit depending on the caller
+    pub fn context_child(&self, number: u8) -> Option<Element<'a>> {
+        self.children().find(|child| child.is_context(number))
+    }
+
+    pub fn as_oid(&self) -> Option<String> {
+        if self.tag != TAG_OID {
+            return None;
+        }
+        oid_to_string(self.content)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if self.tag != TAG_BOOLEAN {
+            return None;
+        }
+        Some(*self.content.first()? != 0)
+    }
+
+    // Small integers only, serial numbers and moduli are kept as bytes
+    pub fn as_u64(&self) -> Option<u64> {
+        if self.tag != TAG_INTEGER || self.content.is_empty() || self.content.len() > 9 {
+            return None;
+        }
+        let mut value: u64 = 0;
+        for byte in self.content {
+            // Only a leading zero padding byte may be shifted out
+            if value >> 56 != 0 {
+                return None;
+            }
+            value = (value << 8) | *byte as u64;
+        }
+        Some(value)
+    }
+
+    // Unsigned magnitude of an INTEGER, without the sign padding byte
+    pub fn as_unsigned_bytes(&self) -> Option<&'a [u8]> {
+        if self.tag != TAG_INTEGER {
+            return None;
+        }
+        let mut bytes = self.content;
+        while bytes.len() > 1 && bytes[0] == 0 {
+            bytes = &bytes[1..];
+        }
+        Some(bytes)
+    }
+
+    pub fn as_bit_string(&self) -> Option<&'a [u8]> {
+        if self.tag != TAG_BIT_STRING {
+            return None;
+        }
+        self.content.get(1..)
+    }
+
+    pub fn as_string(&self) -> Option<String> {
+        match self.tag {
+            TAG_UTF8_STRING | TAG_PRINTABLE_STRING | TAG_IA5_STRING | TAG_VISIBLE_STRING => Some(String::from_utf8_lossy(self.content).to_string()),
+            // Teletex is treated as Latin-1, which is what issuers put in practice
+            TAG_T61_STRING => Some(self.content.iter().map(|byte| *byte as char).collect()),
+            TAG_BMP_STRING => Some(String::from_utf16_lossy(&self.content.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect::<Vec<u16>>())),
+            TAG_UNIVERSAL_STRING => Some(self.content.chunks_exact(4).filter_map(|quad| char::from_u32(u32::from_be_bytes([quad[0], quad[1], quad[2], quad[3]]))).collect()),
+            _ => None,
+        }
+    }
+
+    // Seconds since the Unix epoch
+    pub fn as_time(&self) -> Option<i64> {
+        let text = std::str::from_utf8(self.content).ok()?;
+        let text = text.strip_suffix('Z')?;
+        let (year, rest) = match self.tag {
+            TAG_UTC_TIME => {
+                let year: i64 = text.get(0..2)?.parse().ok()?;
+                (if year >= 50 { 1900 + year } else { 2000 + year }, text.get(2..)?)
+            }
+            TAG_GENERALIZED_TIME => (text.get(0..4)?.parse().ok()?, text.get(4..)?),
+            _ => return None,
+        };
+        let field = |start: usize| -> Option<i64> { rest.get(start..start + 2)?.parse().ok() };
+        let seconds = if rest.len() >= 10 { field(8)? } else { 0 };
+        Some(days_from_civil(year, field(0)?, field(2)?) * 86400 + field(4)? * 3600 + field(6)? * 60 + seconds)
+    }
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = Element<'a>;
+
+    fn next(&mut self) -> Option<Element<'a>> {
+        let (element, rest) = parse_element(self.data)?;
+        self.data = rest;
+        Some(element)
+    }
+}
+
+pub fn parse_element(data: &[u8]) -> Option<(Element<'_>, &[u8])> {
+    let tag = *data.first()?;
+    if tag & 0x1f == 0x1f {
+        return None;
+    }
+    let first = *data.get(1)? as usize;
+    let (length, header) = if first & 0x80 == 0 {
+        (first, 2)
+    } else {
+        let count = first & 0x7f;
+        // Indefinite lengths are BER only
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let mut length = 0usize;
+        for x in 0..count {
+            length = (length << 8) | *data.get(2 + x)? as usize;
+        }
+        (length, 2 + count)
+    };
+    let end = header.checked_add(length)?;
+    let raw = data.get(..end)?;
+    Some((Element { tag, raw, content: &raw[header..] }, &data[end..]))
+}
+
+pub fn parse(data: &[u8]) -> Option<Element<'_>> {
+    parse_element(data).map(|(element, _)| element)
+}
+
+pub fn oid_to_string(data: &[u8]) -> Option<String> {
+    let mut arcs: Vec<u64> = Vec::new();
+    let mut value: u64 = 0;
+    for byte in data {
+        if value >> 57 != 0 {
+            return None;
+        }
+        value = (value << 7) | (*byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = (value / 40).min(2);
+                arcs.push(first);
+                arcs.push(value - first * 40);
+            } else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+    if arcs.is_empty() {
+        return None;
+    }
+    Some(arcs.iter().map(|arc| arc.to_string()).collect::<Vec<String>>().join("."))
+}
+
+// Howard Hinnant's algorithm, proleptic Gregorian calendar
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+pub fn format_time(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86400);
+    let seconds = timestamp.rem_euclid(86400);
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60)
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for character in text.bytes() {
+        let value = match character {
+            b'A'..=b'Z' => character - b'A',
+            b'a'..=b'z' => character - b'a' + 26,
+            b'0'..=b'9' => character - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+// Contents of the PEM blocks with the given label, e.g. "CERTIFICATE"
+pub fn pem_blocks(data: &[u8], label: &str) -> Vec<Vec<u8>> {
+    let text = String::from_utf8_lossy(data);
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+    let mut blocks = Vec::new();
+    let mut rest = text.as_ref();
+    while let Some(start) = rest.find(&begin) {
+        let body = &rest[start + begin.len()..];
+        let stop = match body.find(&end) {
+            Some(stop) => stop,
+            None => break,
+        };
+        if let Some(block) = base64_decode(&body[..stop]) {
+            blocks.push(block);
+        }
+        rest = &body[stop + end.len()..];
+    }
+    blocks
+}
+
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut output = vec![tag];
+    if content.len() < 0x80 {
+        output.push(content.len() as u8);
+    } else {
+        let length = (content.len() as u32).to_be_bytes();
+        let skip = length.iter().take_while(|byte| **byte == 0).count();
+        output.push(0x80 | (4 - skip) as u8);
+        output.extend_from_slice(&length[skip..]);
+    }
+    output.extend_from_slice(content);
+    output
+}
+
+pub fn encode_sequence(elements: &[&[u8]]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &elements.concat())
+}
+
+// SET OF, DER sorts the encodings
+pub fn encode_set(elements: &[&[u8]]) -> Vec<u8> {
+    let mut sorted = elements.to_vec();
+    sorted.sort();
+    encode(TAG_SET, &sorted.concat())
+}
+
+// Constructed context specific [number]
+pub fn encode_context(number: u8, content: &[u8]) -> Vec<u8> {
+    encode(0xa0 | number, content)
+}
+
+// Unsigned magnitude, a zero byte is prepended when the high bit is set
+pub fn encode_integer(value: &[u8]) -> Vec<u8> {
+    let start = value.iter().position(|byte| *byte != 0).unwrap_or(value.len().saturating_sub(1));
+    let mut content = Vec::new();
+    if value.get(start).is_none_or(|byte| byte & 0x80 != 0) {
+        content.push(0);
+    }
+    content.extend_from_slice(value.get(start..).unwrap_or_default());
+    encode(TAG_INTEGER, &content)
+}
+
+pub fn encode_oid(oid: &str) -> Vec<u8> {
+    let arcs: Vec<u64> = oid.split('.').filter_map(|arc| arc.parse().ok()).collect();
+    let mut content = Vec::new();
+    let first = arcs.first().copied().unwrap_or(0) * 40 + arcs.get(1).copied().unwrap_or(0);
+    for arc in std::iter::once(first).chain(arcs.iter().skip(2).copied()) {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest != 0 {
+            bytes.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        content.extend(bytes.iter().rev());
+    }
+    encode(TAG_OID, &content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_forms() {
+        for (size, header) in [(0, vec![0x04, 0x00]), (127, vec![0x04, 0x7f]), (128, vec![0x04, 0x81, 0x80]), (255, vec![0x04, 0x81, 0xff]), (256, vec![0x04, 0x82, 0x01, 0x00]), (70000, vec![0x04, 0x83, 0x01, 0x11, 0x70])] {
+            let content = vec![0x5a; size];
+            let encoded = encode(TAG_OCTET_STRING, &content);
+            assert_eq!(&encoded[..header.len()], &header[..]);
+            let (element, rest) = parse_element(&encoded).unwrap();
+            assert_eq!(element.tag, TAG_OCTET_STRING);
+            assert_eq!(element.content, &content[..]);
+            assert_eq!(element.raw, &encoded[..]);
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[test]
+    fn malformed_lengths() {
+        // Indefinite length, more than four length bytes, content past the end and a high tag number
+        assert!(parse(&[0x30, 0x80, 0x00, 0x00]).is_none());
+        assert!(parse(&[0x04, 0x85, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00]).is_none());
+        assert!(parse(&[0x04, 0x82, 0x01, 0x00, 0x00]).is_none());
+        assert!(parse(&[0x1f, 0x01, 0x00]).is_none());
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(encode_integer(&[0x80]), [0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(encode_integer(&[0x00, 0x00, 0x01]), [0x02, 0x01, 0x01]);
+        assert_eq!(encode_integer(&[0x00, 0x00]), [0x02, 0x01, 0x00]);
+        assert_eq!(encode_integer(&[]), [0x02, 0x01, 0x00]);
+        let modulus: Vec<u8> = (0..=255).rev().collect();
+        let encoded = encode_integer(&modulus);
+        let element = parse(&encoded).unwrap();
+        assert_eq!(element.content.len(), 257);
+        assert_eq!(element.as_unsigned_bytes().unwrap(), &modulus[..]);
+        assert_eq!(parse(&encode_integer(&[0x01, 0x00, 0x01])).unwrap().as_u64(), Some(65537));
+        // Nine bytes fit only behind a zero padding byte
+        assert_eq!(parse(&encode_integer(&[0xff; 8])).unwrap().as_u64(), Some(u64::MAX));
+        assert_eq!(parse(&[0x02, 0x08, 0x01, 0, 0, 0, 0, 0, 0, 0]).unwrap().as_u64(), Some(1 << 56));
+        assert_eq!(parse(&[0x02, 0x09, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap().as_u64(), None);
+        assert_eq!(parse(&[0x02, 0x09, 0x00, 0x80, 0, 0, 0, 0, 0, 0, 0]).unwrap().as_u64(), Some(1 << 63));
+    }
+
+    #[test]
+    fn oids() {
+        let encoded = encode_oid("1.2.840.113549.1.1.11");
+        assert_eq!(encoded, [0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b]);
+        assert_eq!(parse(&encoded).unwrap().as_oid().unwrap(), "1.2.840.113549.1.1.11");
+        for oid in ["2.5.4.3", "1.3.6.1.4.1.311.2.1.4", "2.999.1"] {
+            assert_eq!(parse(&encode_oid(oid)).unwrap().as_oid().unwrap(), oid);
+        }
+        // Arcs up to 64 bits, anything wider is rejected instead of wrapping
+        let mut widest = vec![0x2a, 0x81];
+        widest.extend_from_slice(&[0xff; 8]);
+        widest.push(0x7f);
+        assert_eq!(oid_to_string(&widest).unwrap(), format!("1.2.{}", u64::MAX));
+        let mut wider = vec![0x2a, 0x82];
+        wider.extend_from_slice(&[0x80; 8]);
+        wider.push(0x00);
+        assert_eq!(oid_to_string(&wider), None);
+    }
+
+    #[test]
+    fn constructed() {
+        let first = encode_oid("2.5.4.3");
+        let second = encode_integer(&[0x05]);
+        let set = encode_set(&[&first, &second]);
+        // DER orders the SET OF by encoding
+        assert_eq!(parse(&set).unwrap().child(0).unwrap().tag, TAG_INTEGER);
+        let context = encode_context(1, &second);
+        let sequence = encode_sequence(&[&first, &context, &set]);
+        let element = parse(&sequence).unwrap();
+        assert!(element.is_constructed());
+        assert_eq!(element.children().count(), 3);
+        assert_eq!(element.context_child(1).unwrap().child(0).unwrap().as_u64(), Some(5));
+        assert_eq!(element.child(2).unwrap().raw, &set[..]);
+    }
+
+    #[test]
+    fn times() {
+        let utc = encode(TAG_UTC_TIME, b"250101000000Z");
+        assert_eq!(parse(&utc).unwrap().as_time(), Some(1735689600));
+        let generalized = encode(TAG_GENERALIZED_TIME, b"20491231235959Z");
+        let time = parse(&generalized).unwrap().as_time().unwrap();
+        assert_eq!(format_time(time), "2049-12-31 23:59:59 UTC");
+        // Two digit years from 50 on are in the 1900s
+        assert_eq!(parse(&encode(TAG_UTC_TIME, b"700101000000Z")).unwrap().as_time(), Some(0));
+    }
+
+    #[test]
+    fn pem() {
+        let text = b"junk\n-----BEGIN CERTIFICATE-----\nMAMCAQU=\n-----END CERTIFICATE-----\n-----BEGIN CERTIFICATE-----\nBAA=\n-----END CERTIFICATE-----\n";
+        assert_eq!(pem_blocks(text, "CERTIFICATE"), vec![vec![0x30, 0x03, 0x02, 0x01, 0x05], vec![0x04, 0x00]]);
+        assert!(pem_blocks(text, "X509 CRL").is_empty());
+    }
+}
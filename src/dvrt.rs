@@ -0,0 +1,575 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::load_config::GuardFlag;
+use crate::load_config::LoadConfig;
+use crate::utils::is_pe32;
+use crate::utils::read_pointer_at;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::read_u64_at;
+use crate::utils::rva_to_offset;
+use crate::utils::va_to_rva;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum DynamicRelocationSymbol {
+    IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE,
+    IMAGE_DYNAMIC_RELOCATION_GUARD_RF_EPILOGUE,
+    IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER,
+    IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER,
+    IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH,
+    IMAGE_DYNAMIC_RELOCATION_ARM64X,
+    IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE,
+    IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER,
+    // Older linkers reference the VA of the symbol being patched
+    SYMBOL(u64),
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+pub enum Fixup {
+    ImportControlTransfer { indirect_call: bool, iat_index: u32 },
+    IndirectControlTransfer { indirect_call: bool, rex_w_prefix: bool, cfg_check: bool },
+    SwitchTableBranch { register_number: u8 },
+    Arm64xZeroFill { size: u8 },
+    Arm64xAssignValue { size: u8, value: u64 },
+    Arm64xAddDelta { delta: i64 },
+    Arm64KernelImportCallTransfer { register_index: u8, import_type: u8, iat_index: u16 },
+    // Plain IMAGE_REL_BASED_* entry, used by symbol relocations and function overrides
+    BaseRelocation { relocation_type: u8 },
+}
+
+pub struct DynamicFixup {
+    pub rva: u32,
+    pub fixup: Fixup,
+}
+
+pub struct FunctionOverride {
+    pub original_rva: u32,
+    pub bdd_offset: u32,
+    pub override_rvas: Vec<u32>,
+    pub fixups: Vec<DynamicFixup>,
+}
+
+pub struct DynamicRelocation {
+    pub symbol: DynamicRelocationSymbol,
+    // Only present in version 2 headers
+    pub symbol_group: Option<u32>,
+    pub flags: Option<u32>,
+    pub fixups: Vec<DynamicFixup>,
+    pub function_overrides: Vec<FunctionOverride>,
+    // Payloads this parser does not decode, such as the RF prologue and epilogue descriptions
+    pub raw: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct DynamicRelocationTable {
+    pub rva: u32,
+    pub version: u32,
+    pub size: u32,
+    pub retpoline: bool,
+    pub relocations: Vec<DynamicRelocation>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl DynamicRelocationSymbol {
+    pub fn from_u64(val: u64) -> DynamicRelocationSymbol {
+        match val {
+            1 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE,
+            2 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_RF_EPILOGUE,
+            3 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER,
+            4 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER,
+            5 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH,
+            6 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64X,
+            7 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE,
+            8 => DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER,
+            _ => DynamicRelocationSymbol::SYMBOL(val),
+        }
+    }
+}
+
+impl DynamicRelocationTable {
+    pub fn fixups_for(&self, symbol: DynamicRelocationSymbol) -> impl Iterator<Item = &DynamicFixup> {
+        self.relocations.iter().filter(move |relocation| relocation.symbol == symbol).flat_map(|relocation| relocation.fixups.iter())
+    }
+}
+
+
+// Parsing
+
+// ARM64X entries are variable sized: a 16 bit header optionally followed by a value or a delta
+fn parse_arm64x_entries(data: &[u8], page_rva: u32, fixups: &mut Vec<DynamicFixup>, anomalies: &mut Vec<String>) {
+    let mut index = 0;
+    while let Some(header) = read_u16_at(data, index) {
+        index += 2;
+        // Blocks are padded to 4 bytes with a null entry
+        if header == 0 && index >= data.len() {
+            break;
+        }
+        let rva = page_rva + (header & 0xfff) as u32;
+        let size_bits = ((header >> 14) & 0x3) as u8;
+        let fixup = match (header >> 12) & 0x3 {
+            0 => Fixup::Arm64xZeroFill { size: 1 << size_bits },
+            1 => {
+                let size = 1u8 << size_bits;
+                let value = match size {
+                    1 => data.get(index).map(|value| *value as u64),
+                    2 => read_u16_at(data, index).map(|value| value as u64),
+                    4 => read_u32_at(data, index).map(|value| value as u64),
+                    _ => read_u64_at(data, index),
+                };
+                index += size as usize;
+                match value {
+                    Some(value) => Fixup::Arm64xAssignValue { size, value },
+                    None => {
+                        anomalies.push(format!("ARM64X value for RVA 0x{:x} is truncated", rva));
+                        return;
+                    }
+                }
+            }
+            2 => {
+                let value = match read_u16_at(data, index) {
+                    Some(value) => value as i64,
+                    None => {
+                        anomalies.push(format!("ARM64X delta for RVA 0x{:x} is truncated", rva));
+                        return;
+                    }
+                };
+                index += 2;
+                // Bit 0 of the size field is the sign, bit 1 selects a scale of 8 instead of 4
+                let scale = if size_bits & 2 != 0 { 8 } else { 4 };
+                let delta = if size_bits & 1 != 0 { -(value * scale) } else { value * scale };
+                Fixup::Arm64xAddDelta { delta }
+            }
+            _ => {
+                anomalies.push(format!("ARM64X entry for RVA 0x{:x} has the reserved type 3", rva));
+                return;
+            }
+        };
+        fixups.push(DynamicFixup { rva, fixup });
+    }
+}
+
+fn parse_fixed_entries(data: &[u8], page_rva: u32, symbol: DynamicRelocationSymbol, fixups: &mut Vec<DynamicFixup>) {
+    let wide = matches!(symbol, DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER | DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER);
+    let entry_size = if wide { 4 } else { 2 };
+    let count = data.len() / entry_size;
+    for (x, entry) in data.chunks_exact(entry_size).enumerate() {
+        let value = if wide { u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) } else { u16::from_le_bytes([entry[0], entry[1]]) as u32 };
+        // Blocks of 16 bit entries are padded to 4 bytes with a null entry
+        if !wide && value == 0 && x == count - 1 && count.is_multiple_of(2) {
+            continue;
+        }
+        let rva = page_rva + (value & 0xfff);
+        let fixup = match symbol {
+            DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER => Fixup::ImportControlTransfer {
+                indirect_call: value & 0x1000 != 0,
+                iat_index: value >> 13,
+            },
+            DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER => Fixup::IndirectControlTransfer {
+                indirect_call: value & 0x1000 != 0,
+                rex_w_prefix: value & 0x2000 != 0,
+                cfg_check: value & 0x4000 != 0,
+            },
+            DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH => Fixup::SwitchTableBranch {
+                register_number: (value >> 12) as u8,
+            },
+            DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64_KERNEL_IMPORT_CALL_TRANSFER => Fixup::Arm64KernelImportCallTransfer {
+                register_index: ((value >> 12) & 0x1f) as u8,
+                import_type: ((value >> 17) & 1) as u8,
+                iat_index: (value >> 18) as u16,
+            },
+            _ => {
+                // Absolute padding entries carry no fixup
+                if value >> 12 == 0 {
+                    continue;
+                }
+                Fixup::BaseRelocation { relocation_type: (value >> 12) as u8 }
+            }
+        };
+        fixups.push(DynamicFixup { rva, fixup });
+    }
+}
+
+// Walks IMAGE_BASE_RELOCATION blocks, decoding their entries according to the symbol
+fn parse_blocks(data: &[u8], symbol: DynamicRelocationSymbol, fixups: &mut Vec<DynamicFixup>, anomalies: &mut Vec<String>) {
+    let mut index = 0;
+    while index + 8 <= data.len() {
+        let page_rva = read_u32_at(data, index).unwrap_or(0);
+        let block_size = read_u32_at(data, index + 4).unwrap_or(0) as usize;
+        if block_size < 8 || index + block_size > data.len() {
+            anomalies.push(format!("{:?} block for page 0x{:x} has an invalid size 0x{:x}", symbol, page_rva, block_size));
+            return;
+        }
+        let entries = &data[index + 8..index + block_size];
+        if symbol == DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64X {
+            parse_arm64x_entries(entries, page_rva, fixups, anomalies);
+        } else {
+            parse_fixed_entries(entries, page_rva, symbol, fixups);
+        }
+        index += block_size;
+    }
+}
+
+// IMAGE_FUNCTION_OVERRIDE_HEADER followed by IMAGE_FUNCTION_OVERRIDE_DYNAMIC_RELOCATION records
+fn parse_function_overrides(data: &[u8], relocation: &mut DynamicRelocation, anomalies: &mut Vec<String>) {
+    let overrides_size = match read_u32_at(data, 0) {
+        Some(size) if 4 + size as usize <= data.len() => size as usize,
+        _ => {
+            anomalies.push(String::from("Function override header is truncated"));
+            return;
+        }
+    };
+    let mut index = 4;
+    while index + 16 <= 4 + overrides_size {
+        let original_rva = read_u32_at(data, index).unwrap_or(0);
+        let bdd_offset = read_u32_at(data, index + 4).unwrap_or(0);
+        let rva_size = read_u32_at(data, index + 8).unwrap_or(0) as usize;
+        let base_reloc_size = read_u32_at(data, index + 12).unwrap_or(0) as usize;
+        index += 16;
+        let (rvas, relocs) = match (data.get(index..index + rva_size), data.get(index + rva_size..index + rva_size + base_reloc_size)) {
+            (Some(rvas), Some(relocs)) => (rvas, relocs),
+            _ => {
+                anomalies.push(format!("Function override for RVA 0x{:x} is truncated", original_rva));
+                return;
+            }
+        };
+        let mut function_override = FunctionOverride {
+            original_rva,
+            bdd_offset,
+            override_rvas: rvas.chunks_exact(4).map(|rva| u32::from_le_bytes([rva[0], rva[1], rva[2], rva[3]])).collect(),
+            fixups: Vec::new(),
+        };
+        parse_blocks(relocs, DynamicRelocationSymbol::SYMBOL(0), &mut function_override.fixups, anomalies);
+        relocation.function_overrides.push(function_override);
+        index += rva_size + base_reloc_size;
+    }
+}
+
+fn decode_payload(data: &[u8], relocation: &mut DynamicRelocation, anomalies: &mut Vec<String>) {
+    match relocation.symbol {
+        DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE | DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_RF_EPILOGUE => {
+            relocation.raw = data.to_vec();
+        }
+        DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE => parse_function_overrides(data, relocation, anomalies),
+        symbol => parse_blocks(data, symbol, &mut relocation.fixups, anomalies),
+    }
+}
+
+// The table is located through DynamicValueRelocTableOffset/Section, or the older DynamicValueRelocTable VA
+fn table_rva(headers: &Headers, load_config: &LoadConfig) -> Option<u32> {
+    match (load_config.dynamic_value_reloc_table_offset, load_config.dynamic_value_reloc_table_section) {
+        (Some(offset), Some(section)) if section != 0 => {
+            let section = headers.sections.get(section as usize - 1)?;
+            section.virtual_address.checked_add(offset)
+        }
+        _ => match load_config.dynamic_value_reloc_table {
+            Some(va) if va != 0 => va_to_rva(headers, va),
+            _ => None,
+        },
+    }
+}
+
+pub fn parse_dynamic_relocations(file: &[u8], headers: &Headers, load_config: &LoadConfig) -> Option<DynamicRelocationTable> {
+    let mut table = DynamicRelocationTable {
+        retpoline: load_config.has_guard_flag(GuardFlag::IMAGE_GUARD_RETPOLINE_PRESENT),
+        ..Default::default()
+    };
+    let declared = load_config.dynamic_value_reloc_table_section.unwrap_or(0) != 0 || load_config.dynamic_value_reloc_table.unwrap_or(0) != 0;
+    if !declared {
+        return None;
+    }
+    table.rva = match table_rva(headers, load_config) {
+        Some(rva) => rva,
+        None => {
+            table.anomalies.push(String::from("Dynamic value relocation table points outside of the image"));
+            return Some(table);
+        }
+    };
+    let offset = match rva_to_offset(headers, table.rva) {
+        Some(offset) if offset + 8 <= file.len() => offset,
+        _ => {
+            table.anomalies.push(format!("Dynamic value relocation table at RVA 0x{:x} is not backed by the file", table.rva));
+            return Some(table);
+        }
+    };
+    table.version = read_u32_at(file, offset).unwrap_or(0);
+    table.size = read_u32_at(file, offset + 4).unwrap_or(0);
+    let mut end = offset + 8 + table.size as usize;
+    if end > file.len() {
+        table.anomalies.push(format!("Dynamic value relocation table of size 0x{:x} is truncated by the end of the file", table.size));
+        end = file.len();
+    }
+    let pe_32 = is_pe32(headers);
+    let pointer_size = if pe_32 { 4 } else { 8 };
+    let mut index = offset + 8;
+    while index < end {
+        let mut relocation = DynamicRelocation {
+            symbol: DynamicRelocationSymbol::SYMBOL(0),
+            symbol_group: None,
+            flags: None,
+            fixups: Vec::new(),
+            function_overrides: Vec::new(),
+            raw: Vec::new(),
+        };
+        // IMAGE_DYNAMIC_RELOCATION32/64 or their _V2 counterparts
+        let (payload_start, payload_size) = match table.version {
+            1 => {
+                let symbol = read_pointer_at(file, index, pe_32);
+                let base_reloc_size = read_u32_at(file, index + pointer_size);
+                match (symbol, base_reloc_size) {
+                    (Some(symbol), Some(size)) if index + pointer_size + 4 <= end => {
+                        relocation.symbol = DynamicRelocationSymbol::from_u64(symbol);
+                        (index + pointer_size + 4, size as usize)
+                    }
+                    _ => {
+                        table.anomalies.push(String::from("Dynamic relocation header is truncated"));
+                        break;
+                    }
+                }
+            }
+            2 => {
+                let header_size = read_u32_at(file, index).unwrap_or(0) as usize;
+                let fixup_info_size = read_u32_at(file, index + 4).unwrap_or(0) as usize;
+                let symbol = read_pointer_at(file, index + 8, pe_32);
+                if header_size < 16 + pointer_size || index + header_size > end || symbol.is_none() {
+                    table.anomalies.push(format!("Dynamic relocation V2 header has an invalid size 0x{:x}", header_size));
+                    break;
+                }
+                relocation.symbol = DynamicRelocationSymbol::from_u64(symbol.unwrap_or(0));
+                relocation.symbol_group = read_u32_at(file, index + 8 + pointer_size);
+                relocation.flags = read_u32_at(file, index + 12 + pointer_size);
+                (index + header_size, fixup_info_size)
+            }
+            version => {
+                table.anomalies.push(format!("Dynamic value relocation table has the unknown version {}", version));
+                break;
+            }
+        };
+        let payload = match file.get(payload_start..payload_start + payload_size) {
+            Some(payload) if payload_start + payload_size <= end => payload,
+            _ => {
+                table.anomalies.push(format!("Fixups of {:?} overrun the dynamic value relocation table", relocation.symbol));
+                break;
+            }
+        };
+        decode_payload(payload, &mut relocation, &mut table.anomalies);
+        table.relocations.push(relocation);
+        index = payload_start + payload_size;
+    }
+    Some(table)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for DynamicRelocationTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dynamic Value Relocation Table
+
+---------------------------
+rva: 0x{:x}
+version: {}
+size: 0x{:x}
+retpoline: {}
+---------------------------",
+        self.rva, self.version, self.size, self.retpoline)?;
+        for relocation in &self.relocations {
+            write!(f, "\n{:?}", relocation.symbol)?;
+            if let (Some(symbol_group), Some(flags)) = (relocation.symbol_group, relocation.flags) {
+                write!(f, " group: {} flags: 0x{:x}", symbol_group, flags)?;
+            }
+            write!(f, " ({} fixups)", relocation.fixups.len())?;
+            for fixup in &relocation.fixups {
+                write!(f, "\n  0x{:08x} {:?}", fixup.rva, fixup.fixup)?;
+            }
+            for function_override in &relocation.function_overrides {
+                write!(f, "\n  override of 0x{:08x} by {:x?} bdd_offset: 0x{:x} ({} fixups)",
+                function_override.original_rva, function_override.override_rvas, function_override.bdd_offset, function_override.fixups.len())?;
+            }
+            if !relocation.raw.is_empty() {
+                write!(f, "\n  0x{:x} undecoded bytes", relocation.raw.len())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    // Table at RVA 0x800, located through section 1 as recent linkers do
+    fn image(pe_32: bool, version: u32, body: &[u8]) -> (Vec<u8>, Headers, LoadConfig) {
+        let mut file = vec![0; 0x1000];
+        put(&mut file, 0x800, &version.to_le_bytes());
+        put(&mut file, 0x804, &(body.len() as u32).to_le_bytes());
+        put(&mut file, 0x808, body);
+        let load_config = LoadConfig {
+            dynamic_value_reloc_table_offset: Some(0x400),
+            dynamic_value_reloc_table_section: Some(1),
+            ..Default::default()
+        };
+        (file, flat_headers(pe_32, 0x1000), load_config)
+    }
+
+    fn relocation_v1(symbol: u64, payload: &[u8]) -> Vec<u8> {
+        let mut data = symbol.to_le_bytes().to_vec();
+        data.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn block(page_rva: u32, entries: &[u8]) -> Vec<u8> {
+        let mut data = page_rva.to_le_bytes().to_vec();
+        data.extend_from_slice(&(8 + entries.len() as u32).to_le_bytes());
+        data.extend_from_slice(entries);
+        data
+    }
+
+    fn words(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn parses_version_1_relocations() {
+        let mut overrides = Vec::new();
+        overrides.extend_from_slice(&32u32.to_le_bytes());
+        for value in [0x600u32, 0x40, 4, 12, 0x700] {
+            overrides.extend_from_slice(&value.to_le_bytes());
+        }
+        overrides.extend(block(0x1000, &words(&[0xa008, 0])));
+        let mut arm64x = words(&[0x0030, 0x9040]);
+        arm64x.extend_from_slice(&0xdeadbeefu32.to_le_bytes());
+        arm64x.extend(words(&[0x6050, 3]));
+
+        let mut body = Vec::new();
+        body.extend(relocation_v1(3, &block(0x1000, &(0x10u32 | 0x1000 | 5 << 13).to_le_bytes())));
+        body.extend(relocation_v1(4, &block(0x2000, &words(&[0x7020, 0]))));
+        body.extend(relocation_v1(6, &block(0x3000, &arm64x)));
+        body.extend(relocation_v1(7, &overrides));
+        body.extend(relocation_v1(1, &[1, 2, 3]));
+        body.extend(relocation_v1(0x140001000, &block(0x4000, &words(&[0xa010, 0]))));
+        let (file, headers, load_config) = image(false, 1, &body);
+        let table = parse_dynamic_relocations(&file, &headers, &load_config).unwrap();
+        assert!(table.anomalies.is_empty());
+        assert_eq!((table.rva, table.version, table.size), (0x800, 1, body.len() as u32));
+        let symbols: Vec<String> = table.relocations.iter().map(|relocation| format!("{:?}", relocation.symbol)).collect();
+        assert_eq!(symbols, vec![
+            "IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER",
+            "IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER",
+            "IMAGE_DYNAMIC_RELOCATION_ARM64X",
+            "IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE",
+            "IMAGE_DYNAMIC_RELOCATION_GUARD_RF_PROLOGUE",
+            "SYMBOL(5368713216)",
+        ]);
+        let fixups: Vec<String> = table.relocations.iter().flat_map(|relocation| relocation.fixups.iter()).map(|fixup| format!("0x{:x} {:?}", fixup.rva, fixup.fixup)).collect();
+        assert_eq!(fixups, vec![
+            "0x1010 ImportControlTransfer { indirect_call: true, iat_index: 5 }",
+            "0x2020 IndirectControlTransfer { indirect_call: true, rex_w_prefix: true, cfg_check: true }",
+            "0x3030 Arm64xZeroFill { size: 1 }",
+            "0x3040 Arm64xAssignValue { size: 4, value: 3735928559 }",
+            "0x3050 Arm64xAddDelta { delta: -12 }",
+            "0x4010 BaseRelocation { relocation_type: 10 }",
+        ]);
+        let function_override = &table.relocations[3].function_overrides[0];
+        assert_eq!((function_override.original_rva, function_override.bdd_offset), (0x600, 0x40));
+        assert_eq!(function_override.override_rvas, vec![0x700]);
+        assert_eq!(function_override.fixups.len(), 1);
+        assert_eq!(table.relocations[4].raw, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn parses_version_2_relocations() {
+        let mut body = Vec::new();
+        let payload = block(0x1000, &words(&[0x3010, 0]));
+        for value in [20u32, payload.len() as u32, 5, 2, 0x8] {
+            body.extend_from_slice(&value.to_le_bytes());
+        }
+        body.extend(payload);
+        let (file, headers, _) = image(true, 2, &body);
+        // Older linkers only fill the VA of the table
+        let load_config = LoadConfig { dynamic_value_reloc_table: Some(0x400800), guard_flags: Some(0x00100000), ..Default::default() };
+        let table = parse_dynamic_relocations(&file, &headers, &load_config).unwrap();
+        assert!(table.retpoline);
+        assert!(table.anomalies.is_empty());
+        assert_eq!((table.relocations[0].symbol_group, table.relocations[0].flags), (Some(2), Some(8)));
+        let fixups: Vec<String> = table.fixups_for(DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_SWITCHTABLE_BRANCH).map(|fixup| format!("0x{:x} {:?}", fixup.rva, fixup.fixup)).collect();
+        assert_eq!(fixups, vec!["0x1010 SwitchTableBranch { register_number: 3 }"]);
+        assert!(parse_dynamic_relocations(&file, &headers, &LoadConfig::default()).is_none());
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let parse = |pe_32: bool, version: u32, body: &[u8]| {
+            let (file, headers, load_config) = image(pe_32, version, body);
+            parse_dynamic_relocations(&file, &headers, &load_config).unwrap()
+        };
+        let symbols = |table: &DynamicRelocationTable| table.relocations.iter().map(|relocation| relocation.symbol).collect::<Vec<DynamicRelocationSymbol>>();
+
+        // Headers that cannot be decoded stop the walk before any relocation
+        let table = parse(false, 3, &[0; 16]);
+        assert_eq!((table.version, table.size), (3, 16));
+        assert!(table.relocations.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Dynamic value relocation table has the unknown version 3")]);
+        let table = parse(false, 1, &[0; 4]);
+        assert!(table.relocations.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Dynamic relocation header is truncated")]);
+        let table = parse(true, 2, &[8, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(table.version, 2);
+        assert!(table.relocations.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Dynamic relocation V2 header has an invalid size 0x8")]);
+        let table = parse(false, 1, &relocation_v1(3, &[0; 4])[..12]);
+        assert!(table.relocations.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Fixups of IMAGE_DYNAMIC_RELOCATION_GUARD_IMPORT_CONTROL_TRANSFER overrun the dynamic value relocation table")]);
+
+        // Payloads that cannot be decoded keep the relocation and the fixups decoded before the error
+        let table = parse(false, 1, &relocation_v1(4, &[0, 0x10, 0, 0, 4, 0, 0, 0]));
+        assert_eq!(symbols(&table), vec![DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER]);
+        assert!(table.relocations[0].fixups.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("IMAGE_DYNAMIC_RELOCATION_GUARD_INDIR_CONTROL_TRANSFER block for page 0x1000 has an invalid size 0x4")]);
+        let table = parse(false, 1, &relocation_v1(6, &block(0x1000, &words(&[0x0008, 0x3010]))));
+        assert_eq!(symbols(&table), vec![DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_ARM64X]);
+        let fixups = &table.relocations[0].fixups;
+        assert_eq!(fixups.len(), 1);
+        assert_eq!(fixups[0].rva, 0x1008);
+        assert!(matches!(fixups[0].fixup, Fixup::Arm64xZeroFill { size: 1 }));
+        assert_eq!(table.anomalies, vec![String::from("ARM64X entry for RVA 0x1010 has the reserved type 3")]);
+        let table = parse(false, 1, &relocation_v1(6, &block(0x1000, &words(&[0xd010, 0]))));
+        assert!(table.relocations[0].fixups.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("ARM64X value for RVA 0x1010 is truncated")]);
+        let table = parse(false, 1, &relocation_v1(7, &[0x10, 0, 0, 0]));
+        assert_eq!(symbols(&table), vec![DynamicRelocationSymbol::IMAGE_DYNAMIC_RELOCATION_FUNCTION_OVERRIDE]);
+        assert!(table.relocations[0].function_overrides.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Function override header is truncated")]);
+
+        // Table located past the end of the file, then outside of the image
+        let (mut file, headers, mut load_config) = image(false, 1, &[]);
+        put(&mut file, 0x804, &0x1000u32.to_le_bytes());
+        let table = parse_dynamic_relocations(&file, &headers, &load_config).unwrap();
+        assert_eq!((table.rva, table.version, table.size), (0x800, 1, 0x1000));
+        assert_eq!(table.anomalies[0], "Dynamic value relocation table of size 0x1000 is truncated by the end of the file");
+        load_config.dynamic_value_reloc_table_offset = Some(0xffc);
+        let table = parse_dynamic_relocations(&file, &headers, &load_config).unwrap();
+        assert_eq!((table.rva, table.version), (0x13fc, 0));
+        assert!(table.relocations.is_empty());
+        assert_eq!(table.anomalies, vec![String::from("Dynamic value relocation table at RVA 0x13fc is not backed by the file")]);
+        load_config.dynamic_value_reloc_table_section = Some(2);
+        let table = parse_dynamic_relocations(&file, &headers, &load_config).unwrap();
+        assert_eq!(table.rva, 0);
+        assert_eq!(table.anomalies, vec![String::from("Dynamic value relocation table points outside of the image")]);
+    }
+}
@@ -0,0 +1,356 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::resources::Resource;
+use crate::resources::Resources;
+use crate::resources::ResourceId;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum ContentKind {
+    Pe,
+    Zip,
+    Png,
+    Gif,
+    Jpeg,
+    Bmp,
+    Ico,
+    Cab,
+    SevenZip,
+    Gzip,
+    Pdf,
+    Xml,
+    Html,
+    // Carries the extension guessed from the scripting language
+    Script(&'static str),
+    Text,
+    Unknown,
+}
+
+pub struct ExtractedResource {
+    pub resource: Resource,
+    pub file_name: String,
+    pub content: ContentKind,
+    pub data: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct Extraction {
+    pub resources: Vec<ExtractedResource>,
+    pub findings: Vec<String>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl ContentKind {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ContentKind::Pe => "exe",
+            ContentKind::Zip => "zip",
+            ContentKind::Png => "png",
+            ContentKind::Gif => "gif",
+            ContentKind::Jpeg => "jpg",
+            ContentKind::Bmp => "bmp",
+            ContentKind::Ico => "ico",
+            ContentKind::Cab => "cab",
+            ContentKind::SevenZip => "7z",
+            ContentKind::Gzip => "gz",
+            ContentKind::Pdf => "pdf",
+            ContentKind::Xml => "xml",
+            ContentKind::Html => "html",
+            ContentKind::Script(extension) => extension,
+            ContentKind::Text => "txt",
+            ContentKind::Unknown => "bin",
+        }
+    }
+}
+
+
+// Content sniffing
+
+// An MZ header is only trusted when e_lfanew leads to a PE signature
+fn is_pe_at(data: &[u8], offset: usize) -> bool {
+    if data.get(offset..offset + 2) != Some(b"MZ") {
+        return false;
+    }
+    match read_u32_at(data, offset + 0x3c) {
+        Some(e_lfanew) => data.get(offset + e_lfanew as usize..offset + e_lfanew as usize + 4) == Some(b"PE\0\0"),
+        None => false,
+    }
+}
+
+// Decodes enough of the start of a blob to look for text markers, UTF-16 included
+fn text_prefix(data: &[u8]) -> Option<String> {
+    let sample = &data[..data.len().min(1024)];
+    let text = match sample {
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        [0xff, 0xfe, rest @ ..] => {
+            let units: Vec<u16> = rest.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(sample).to_string(),
+    };
+    let printable = text.chars().filter(|c| !c.is_control() || c.is_whitespace()).count();
+    if text.is_empty() || printable * 10 < text.chars().count() * 9 {
+        return None;
+    }
+    Some(text.trim_start().to_ascii_lowercase())
+}
+
+pub fn sniff(data: &[u8]) -> ContentKind {
+    if is_pe_at(data, 0) {
+        return ContentKind::Pe;
+    }
+    let magics: [(&[u8], ContentKind); 12] = [
+        (b"PK\x03\x04", ContentKind::Zip),
+        (b"PK\x05\x06", ContentKind::Zip),
+        (b"\x89PNG\r\n\x1a\n", ContentKind::Png),
+        (b"GIF87a", ContentKind::Gif),
+        (b"GIF89a", ContentKind::Gif),
+        (b"\xff\xd8\xff", ContentKind::Jpeg),
+        (b"BM", ContentKind::Bmp),
+        (b"\x00\x00\x01\x00", ContentKind::Ico),
+        (b"MSCF", ContentKind::Cab),
+        (b"7z\xbc\xaf\x27\x1c", ContentKind::SevenZip),
+        (b"\x1f\x8b", ContentKind::Gzip),
+        (b"%PDF", ContentKind::Pdf),
+    ];
+    for (magic, kind) in magics {
+        if data.starts_with(magic) {
+            return kind;
+        }
+    }
+    let text = match text_prefix(data) {
+        Some(text) => text,
+        None => return ContentKind::Unknown,
+    };
+    if text.starts_with("<!doctype html") || text.starts_with("<html") || text.contains("<body") || text.contains("<head") {
+        return ContentKind::Html;
+    }
+    if text.starts_with("<?xml") || text.starts_with("<assembly") {
+        return ContentKind::Xml;
+    }
+    let script_markers = [
+        ("#!", "sh"),
+        ("@echo off", "bat"),
+        ("<script", "html"),
+        ("option explicit", "vbs"),
+        ("wscript.", "vbs"),
+        ("createobject(", "vbs"),
+        ("param(", "ps1"),
+        ("write-host", "ps1"),
+        ("function ", "js"),
+        ("var ", "js"),
+    ];
+    for (marker, extension) in script_markers {
+        if text.contains(marker) {
+            return ContentKind::Script(extension);
+        }
+    }
+    ContentKind::Text
+}
+
+
+// Extraction
+
+// RT_BITMAP stores a packed DIB, the BITMAPFILEHEADER has to be recreated to get a valid .bmp
+pub fn bitmap_to_file(data: &[u8]) -> Option<Vec<u8>> {
+    let header_size = read_u32_at(data, 0)? as usize;
+    // BITMAPCOREHEADER has 16-bit dimensions, which moves bcBitCount to offset 10, and 3 byte palette entries
+    let (bit_count, palette_entry_size) = if header_size == 12 { (read_u16_at(data, 10)? as usize, 3) } else { (read_u16_at(data, 14)? as usize, 4) };
+    let compression = read_u32_at(data, 16).unwrap_or(0);
+    let colors_used = read_u32_at(data, 32).unwrap_or(0) as usize;
+    let mut bits_offset = 14 + header_size;
+    // BI_BITFIELDS and BI_ALPHABITFIELDS masks follow a plain BITMAPINFOHEADER
+    if header_size == 40 && compression == 3 {
+        bits_offset += 12;
+    } else if header_size == 40 && compression == 6 {
+        bits_offset += 16;
+    }
+    if bit_count <= 8 {
+        let colors = if colors_used != 0 && header_size != 12 { colors_used.min(256) } else { 1 << bit_count };
+        bits_offset += colors * palette_entry_size;
+    } else if header_size != 12 {
+        bits_offset += colors_used.min(256) * 4;
+    }
+    if bits_offset - 14 > data.len() {
+        return None;
+    }
+    let mut output = Vec::with_capacity(data.len() + 14);
+    output.extend_from_slice(b"BM");
+    output.extend_from_slice(&((data.len() + 14) as u32).to_le_bytes());
+    output.extend_from_slice(&[0, 0, 0, 0]);
+    output.extend_from_slice(&(bits_offset as u32).to_le_bytes());
+    output.extend_from_slice(data);
+    Some(output)
+}
+
+fn file_name_component(id: &ResourceId) -> String {
+    match id {
+        ResourceId::Id(id) => id.to_string(),
+        ResourceId::Name(name) => name.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect(),
+    }
+}
+
+fn type_component(resource_type: &ResourceType) -> String {
+    match resource_type {
+        ResourceType::NAMED(name) => file_name_component(&ResourceId::Name(name.clone())),
+        ResourceType::UNKNOWN(id) => id.to_string(),
+        known => format!("{:?}", known),
+    }
+}
+
+pub fn extract_resources(file: &[u8], headers: &Headers, resources: &Resources) -> Extraction {
+    let mut extraction = Extraction::default();
+    for resource in &resources.resources {
+        let data = match resource_data(file, headers, resource) {
+            Some(data) => data,
+            None => {
+                extraction.anomalies.push(format!("Resource {} is not backed by the file", resource));
+                continue;
+            }
+        };
+        let (content, data) = match resource.resource_type {
+            ResourceType::RT_BITMAP => match bitmap_to_file(data) {
+                Some(bitmap) => (ContentKind::Bmp, bitmap),
+                None => {
+                    extraction.anomalies.push(format!("Bitmap {} has a malformed header", resource.name));
+                    (ContentKind::Unknown, data.to_vec())
+                }
+            },
+            ResourceType::RT_MANIFEST => (ContentKind::Xml, data.to_vec()),
+            _ => (sniff(data), data.to_vec()),
+        };
+        // Executables may also be appended to or nested inside another payload
+        for offset in 0..data.len() {
+            if is_pe_at(&data, offset) {
+                let machine = read_u32_at(&data, offset + 0x3c)
+                    .and_then(|e_lfanew| read_u16_at(&data, offset + e_lfanew as usize + 4))
+                    .unwrap_or(0);
+                extraction.findings.push(format!("Resource {} embeds a PE image (machine 0x{:x}) at offset 0x{:x}", resource, machine, offset));
+            }
+        }
+        let file_name = format!("{}_{}_{}.{}", type_component(&resource.resource_type), file_name_component(&resource.name), file_name_component(&resource.language), content.extension());
+        extraction.resources.push(ExtractedResource { resource: resource.clone(), file_name, content, data });
+    }
+    extraction
+}
+
+// Display trait implementation for the structs
+
+impl fmt::Display for ExtractedResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {} ({:?}, 0x{:x} bytes)",
+        self.resource, self.file_name, self.content, self.data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+    use crate::utils::fixtures::put;
+    use crate::utils::fixtures::utf16;
+
+    // MZ header whose e_lfanew leads to a PE signature and an i386 machine
+    fn pe_image() -> Vec<u8> {
+        let mut data = b"MZ".to_vec();
+        put(&mut data, 0x3c, &0x40u32.to_le_bytes());
+        put(&mut data, 0x40, b"PE\0\0\x4c\x01");
+        data
+    }
+
+    // BITMAPINFOHEADER followed by the palette and the pixels
+    fn dib(bit_count: u16, compression: u32, colors_used: u32, tail: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [40u32, 2, 1] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&bit_count.to_le_bytes());
+        data.extend_from_slice(&compression.to_le_bytes());
+        data.resize(32, 0);
+        data.extend_from_slice(&colors_used.to_le_bytes());
+        data.resize(40 + tail, 0);
+        data
+    }
+
+    #[test]
+    fn sniffs_content() {
+        let mut utf16_html = vec![0xff, 0xfe];
+        utf16_html.extend_from_slice(&utf16("  <!DOCTYPE html><html></html>"));
+        let cases: [(&[u8], ContentKind); 12] = [
+            (&pe_image(), ContentKind::Pe),
+            (b"PK\x03\x04rest", ContentKind::Zip),
+            (b"\x89PNG\r\n\x1a\n", ContentKind::Png),
+            (b"\xff\xd8\xff\xe0", ContentKind::Jpeg),
+            (b"%PDF-1.7", ContentKind::Pdf),
+            (&utf16_html, ContentKind::Html),
+            (b"<?xml version=\"1.0\"?><root/>", ContentKind::Xml),
+            (b"@ECHO OFF\r\ndel %TEMP%\\x", ContentKind::Script("bat")),
+            (b"Set shell = CreateObject(\"WScript.Shell\")", ContentKind::Script("vbs")),
+            (b"plain readable text", ContentKind::Text),
+            (b"\x00\x01\x02\x03\x04\x05\x06\x07", ContentKind::Unknown),
+            // MZ alone is not enough
+            (b"MZ\x90\x00", ContentKind::Unknown),
+        ];
+        for (data, kind) in cases {
+            assert_eq!(sniff(data), kind, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn rebuilds_bitmap_headers() {
+        // Two palette entries as declared by biClrUsed
+        let bitmap = bitmap_to_file(&dib(8, 0, 2, 8 + 4)).unwrap();
+        assert_eq!(&bitmap[..2], b"BM");
+        assert_eq!(u32::from_le_bytes(bitmap[2..6].try_into().unwrap()), 14 + 52);
+        assert_eq!(u32::from_le_bytes(bitmap[10..14].try_into().unwrap()), 14 + 40 + 8);
+        // A full 1 bpp palette, then the BI_BITFIELDS masks
+        assert_eq!(u32::from_le_bytes(bitmap_to_file(&dib(1, 0, 0, 8)).unwrap()[10..14].try_into().unwrap()), 14 + 40 + 8);
+        assert_eq!(u32::from_le_bytes(bitmap_to_file(&dib(32, 3, 0, 12)).unwrap()[10..14].try_into().unwrap()), 14 + 40 + 12);
+        // BITMAPCOREHEADER with 3 byte palette entries
+        let mut core = vec![12, 0, 0, 0, 2, 0, 1, 0, 1, 0, 1, 0];
+        core.resize(12 + 6 + 4, 0);
+        assert_eq!(u32::from_le_bytes(bitmap_to_file(&core).unwrap()[10..14].try_into().unwrap()), 14 + 12 + 6);
+        // The 256 entry palette is missing
+        assert!(bitmap_to_file(&dib(8, 0, 0, 16)).is_none());
+    }
+
+    #[test]
+    fn extracts_resources() {
+        let mut payload = b"PK\x05\x06".to_vec();
+        payload.extend_from_slice(&pe_image());
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(2), ResourceId::Id(1), 0x409, &dib(8, 0, 2, 12)),
+            (ResourceId::Id(2), ResourceId::Id(2), 0x409, &dib(8, 0, 0, 4)),
+            (ResourceId::Id(10), ResourceId::Name(String::from("PAY LOAD")), 0, &payload),
+            (ResourceId::Name(String::from("SCRIPT")), ResourceId::Id(1), 0, b"#!/bin/sh\necho hi\n"),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let extraction = extract_resources(&file, &headers, &resources);
+        let names: Vec<(&str, ContentKind)> = extraction.resources.iter().map(|resource| (resource.file_name.as_str(), resource.content)).collect();
+        assert_eq!(names, vec![
+            ("RT_BITMAP_1_1033.bmp", ContentKind::Bmp),
+            ("RT_BITMAP_2_1033.bin", ContentKind::Unknown),
+            ("RT_RCDATA_PAY_LOAD_0.zip", ContentKind::Zip),
+            ("SCRIPT_1_0.sh", ContentKind::Script("sh")),
+        ]);
+        assert_eq!(extraction.anomalies, vec![String::from("Bitmap 2 has a malformed header")]);
+        assert_eq!(extraction.findings.len(), 1);
+        assert!(extraction.findings[0].ends_with("embeds a PE image (machine 0x14c) at offset 0x4"));
+    }
+}
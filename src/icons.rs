@@ -0,0 +1,443 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::png;
+use crate::resources::Resource;
+use crate::resources::Resources;
+use crate::resources::ResourceId;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum IconGroupKind {
+    Icon,
+    Cursor,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum FrameFormat {
+    Bitmap,
+    Png,
+}
+
+pub struct IconFrame {
+    pub id: u16,
+    pub width: u32,
+    pub height: u32,
+    pub bit_count: u16,
+    pub color_count: u8,
+    pub planes: u16,
+    pub format: FrameFormat,
+    // Cursors store their hotspot in front of the image
+    pub hotspot: Option<(u16, u16)>,
+    pub data: Vec<u8>,
+}
+
+pub struct IconGroup {
+    pub kind: IconGroupKind,
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub frames: Vec<IconFrame>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Parsing
+
+// Picks the frame in the group's language, falling back to any language
+fn find_frame<'a>(resources: &'a Resources, frame_type: &ResourceType, id: u16, language: &ResourceId) -> Option<&'a Resource> {
+    let candidates: Vec<&Resource> = resources.resources.iter()
+        .filter(|resource| resource.resource_type == *frame_type && resource.name == ResourceId::Id(id as u32))
+        .collect();
+    candidates.iter().find(|resource| resource.language == *language).or(candidates.first()).copied()
+}
+
+// Reads the real dimensions from the image itself, the directory entries only hold bytes
+fn decode_frame_header(data: &[u8]) -> Option<(FrameFormat, u32, u32, u16)> {
+    if png::is_png(data) {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        let bit_depth = *data.get(24)? as u16;
+        let channels = match data.get(25)? {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        };
+        return Some((FrameFormat::Png, width, height, bit_depth * channels));
+    }
+    let width = read_u32_at(data, 4)? as i32;
+    let height = read_u32_at(data, 8)? as i32;
+    let planes = read_u16_at(data, 12)?;
+    let bit_count = read_u16_at(data, 14)?;
+    // The height covers both the XOR and the AND masks
+    Some((FrameFormat::Bitmap, width.unsigned_abs(), height.unsigned_abs() / 2, bit_count * planes.max(1)))
+}
+
+fn parse_group(file: &[u8], headers: &Headers, resources: &Resources, group: &Resource, kind: IconGroupKind) -> IconGroup {
+    let mut icon_group = IconGroup {
+        kind,
+        name: group.name.clone(),
+        language: group.language.clone(),
+        frames: Vec::new(),
+        anomalies: Vec::new(),
+    };
+    let data = match resource_data(file, headers, group) {
+        Some(data) => data,
+        None => {
+            icon_group.anomalies.push(format!("Group resource at RVA 0x{:x} is not backed by the file", group.data.data_rva));
+            return icon_group;
+        }
+    };
+    let count = read_u16_at(data, 4).unwrap_or(0);
+    let frame_type = match kind {
+        IconGroupKind::Icon => ResourceType::RT_ICON,
+        IconGroupKind::Cursor => ResourceType::RT_CURSOR,
+    };
+    for x in 0..count as usize {
+        let entry = match data.get(6 + x * 14..6 + (x + 1) * 14) {
+            Some(entry) => entry,
+            None => {
+                icon_group.anomalies.push(format!("Group declares {} entries but is truncated after {}", count, x));
+                break;
+            }
+        };
+        let id = read_u16_at(entry, 12).unwrap_or(0);
+        let frame_resource = find_frame(resources, &frame_type, id, &group.language);
+        let frame_data = match frame_resource.and_then(|resource| resource_data(file, headers, resource)) {
+            Some(frame_data) => frame_data,
+            None => {
+                icon_group.anomalies.push(format!("Frame {} referenced by the group is missing", id));
+                continue;
+            }
+        };
+        let (hotspot, image) = match kind {
+            IconGroupKind::Cursor if frame_data.len() >= 4 => {
+                (Some((read_u16_at(frame_data, 0).unwrap_or(0), read_u16_at(frame_data, 2).unwrap_or(0))), &frame_data[4..])
+            }
+            _ => (None, frame_data),
+        };
+        let (format, width, height, bit_count) = match decode_frame_header(image) {
+            Some(header) => header,
+            None => {
+                icon_group.anomalies.push(format!("Frame {} has an unrecognised image format", id));
+                continue;
+            }
+        };
+        icon_group.frames.push(IconFrame {
+            id,
+            width,
+            height,
+            bit_count,
+            color_count: if kind == IconGroupKind::Icon { entry[2] } else { 0 },
+            planes: read_u16_at(entry, 4).unwrap_or(1).max(1),
+            format,
+            hotspot,
+            data: image.to_vec(),
+        });
+    }
+    icon_group
+}
+
+pub fn parse_icon_groups(file: &[u8], headers: &Headers, resources: &Resources) -> Vec<IconGroup> {
+    let mut groups = Vec::new();
+    for resource in &resources.resources {
+        let kind = match resource.resource_type {
+            ResourceType::RT_GROUP_ICON => IconGroupKind::Icon,
+            ResourceType::RT_GROUP_CURSOR => IconGroupKind::Cursor,
+            _ => continue,
+        };
+        groups.push(parse_group(file, headers, resources, resource, kind));
+    }
+    groups
+}
+
+// Explorer shows the first icon group in directory order, which is what the resource walker preserves
+pub fn main_icon(groups: &[IconGroup]) -> Option<&IconGroup> {
+    groups.iter().find(|group| group.kind == IconGroupKind::Icon)
+}
+
+
+// Reassembly
+
+impl IconGroup {
+    // Produces a standalone .ico or .cur file
+    pub fn to_file(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        output.extend_from_slice(&0u16.to_le_bytes());
+        output.extend_from_slice(&(if self.kind == IconGroupKind::Icon { 1u16 } else { 2u16 }).to_le_bytes());
+        output.extend_from_slice(&(self.frames.len() as u16).to_le_bytes());
+        let mut image_offset = 6 + 16 * self.frames.len() as u32;
+        for frame in &self.frames {
+            // 256 pixels and more are stored as 0
+            output.push(if frame.width >= 256 { 0 } else { frame.width as u8 });
+            output.push(if frame.height >= 256 { 0 } else { frame.height as u8 });
+            output.push(frame.color_count);
+            output.push(0);
+            match frame.hotspot {
+                Some((x, y)) => {
+                    output.extend_from_slice(&x.to_le_bytes());
+                    output.extend_from_slice(&y.to_le_bytes());
+                }
+                None => {
+                    output.extend_from_slice(&frame.planes.to_le_bytes());
+                    output.extend_from_slice(&frame.bit_count.to_le_bytes());
+                }
+            }
+            output.extend_from_slice(&(frame.data.len() as u32).to_le_bytes());
+            output.extend_from_slice(&image_offset.to_le_bytes());
+            image_offset += frame.data.len() as u32;
+        }
+        for frame in &self.frames {
+            output.extend_from_slice(&frame.data);
+        }
+        output
+    }
+
+    // The frame with the most pixels, ties broken by colour depth
+    pub fn largest_frame(&self) -> Option<&IconFrame> {
+        self.frames.iter().max_by_key(|frame| (frame.width * frame.height, frame.bit_count))
+    }
+}
+
+impl IconFrame {
+    // PNG frames are returned as they are, bitmaps are decoded and re-encoded
+    pub fn to_png(&self) -> Option<Vec<u8>> {
+        match self.format {
+            FrameFormat::Png => Some(self.data.clone()),
+            FrameFormat::Bitmap => {
+                let pixels = decode_dib(&self.data)?;
+                Some(png::encode_rgba(self.width, self.height, &pixels))
+            }
+        }
+    }
+}
+
+// Decodes an icon DIB (XOR image followed by the AND mask) into top-down RGBA
+fn decode_dib(data: &[u8]) -> Option<Vec<u8>> {
+    let header_size = read_u32_at(data, 0)? as usize;
+    let width = (read_u32_at(data, 4)? as i32).unsigned_abs() as usize;
+    let height = (read_u32_at(data, 8)? as i32).unsigned_abs() as usize / 2;
+    let bit_count = read_u16_at(data, 14)? as usize;
+    let compression = read_u32_at(data, 16)?;
+    let colors_used = read_u32_at(data, 32)? as usize;
+    if width == 0 || height == 0 || width > 0x4000 || height > 0x4000 {
+        return None;
+    }
+
+    let mut palette_start = header_size;
+    // BI_BITFIELDS masks trail a plain BITMAPINFOHEADER
+    if compression == 3 && header_size == 40 {
+        palette_start += 12;
+    }
+    let palette_size = if bit_count <= 8 {
+        if colors_used != 0 { colors_used.min(256) } else { 1 << bit_count }
+    } else {
+        0
+    };
+    let palette = data.get(palette_start..palette_start + palette_size * 4)?;
+    let xor_start = palette_start + palette_size * 4;
+    let xor_stride = (width * bit_count).div_ceil(32) * 4;
+    let and_start = xor_start + xor_stride * height;
+    let and_stride = width.div_ceil(32) * 4;
+
+    let mut pixels = vec![0u8; width * height * 4];
+    let mut has_alpha = false;
+    for y in 0..height {
+        // Bitmaps are stored bottom-up
+        let row = data.get(xor_start + (height - 1 - y) * xor_stride..xor_start + (height - y) * xor_stride)?;
+        for x in 0..width {
+            let (b, g, r, a) = match bit_count {
+                1 | 4 | 8 => {
+                    let bit = x * bit_count;
+                    let index = (row[bit / 8] >> (8 - bit_count - bit % 8)) & ((1 << bit_count) - 1) as u8;
+                    let color = palette.get(index as usize * 4..index as usize * 4 + 4)?;
+                    (color[0], color[1], color[2], 0xff)
+                }
+                16 => {
+                    let value = u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]);
+                    let scale = |v: u16| ((v & 0x1f) * 255 / 31) as u8;
+                    (scale(value), scale(value >> 5), scale(value >> 10), 0xff)
+                }
+                24 => (row[x * 3], row[x * 3 + 1], row[x * 3 + 2], 0xff),
+                32 => (row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]),
+                _ => return None,
+            };
+            if bit_count == 32 && a != 0 {
+                has_alpha = true;
+            }
+            let pixel = &mut pixels[(y * width + x) * 4..(y * width + x) * 4 + 4];
+            pixel.copy_from_slice(&[r, g, b, a]);
+        }
+    }
+    // Without an alpha channel transparency comes from the AND mask
+    if !has_alpha {
+        for y in 0..height {
+            let row = data.get(and_start + (height - 1 - y) * and_stride..and_start + (height - y) * and_stride);
+            for x in 0..width {
+                let transparent = row.map(|row| row[x / 8] & (0x80 >> (x % 8)) != 0).unwrap_or(false);
+                pixels[(y * width + x) * 4 + 3] = if transparent { 0 } else { 0xff };
+            }
+        }
+    }
+    Some(pixels)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for IconGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} group {} (language {})
+
+---------------------------",
+        self.kind, self.name, self.language)?;
+        for frame in &self.frames {
+            write!(f, "{}", frame)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for IconFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+id: {} {}x{} bit_count: {} format: {:?} size: 0x{:x}",
+        self.id, self.width, self.height, self.bit_count, self.format, self.data.len())?;
+        if let Some((x, y)) = self.hotspot {
+            write!(f, " hotspot: {},{}", x, y)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+
+    // 2x2 32 bpp DIB, bottom-up, with an all opaque AND mask
+    fn bitmap_frame() -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [40u32, 2, 4] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&32u16.to_le_bytes());
+        data.resize(40, 0);
+        // Bottom row blue and green, top row red and white, alpha 0xff
+        data.extend_from_slice(&[0xff, 0, 0, 0xff, 0, 0xff, 0, 0xff]);
+        data.extend_from_slice(&[0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        data.extend_from_slice(&[0; 8]);
+        data
+    }
+
+    // GRPICONDIR or GRPCURSORDIR with (id, width, height, planes or hotspot x, bit count or hotspot y, size) entries
+    fn group(kind: u16, entries: &[(u16, u8, u8, u16, u16, u32)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&kind.to_le_bytes());
+        data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (id, width, height, planes, bit_count, size) in entries {
+            data.extend_from_slice(&[*width, *height, 0, 0]);
+            data.extend_from_slice(&planes.to_le_bytes());
+            data.extend_from_slice(&bit_count.to_le_bytes());
+            data.extend_from_slice(&size.to_le_bytes());
+            data.extend_from_slice(&id.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn reassembles_icon_group() {
+        let bitmap = bitmap_frame();
+        let png = png::encode_rgba(3, 1, &[0x80; 12]);
+        let icon_group = group(1, &[(1, 2, 2, 1, 32, bitmap.len() as u32), (2, 3, 1, 1, 32, png.len() as u32)]);
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(3), ResourceId::Id(1), 0x409, &bitmap),
+            // Only present in another language, the group falls back to it
+            (ResourceId::Id(3), ResourceId::Id(2), 0x407, &png),
+            (ResourceId::Id(14), ResourceId::Name(String::from("MAINICON")), 0x409, &icon_group),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let groups = parse_icon_groups(&file, &headers, &resources);
+        assert_eq!(groups.len(), 1);
+        let icon = main_icon(&groups).unwrap();
+        assert!(icon.anomalies.is_empty(), "{:?}", icon.anomalies);
+        let formats: Vec<(FrameFormat, u32, u32, u16)> = icon.frames.iter().map(|frame| (frame.format, frame.width, frame.height, frame.bit_count)).collect();
+        assert_eq!(formats, vec![(FrameFormat::Bitmap, 2, 2, 32), (FrameFormat::Png, 3, 1, 32)]);
+        assert_eq!(icon.largest_frame().unwrap().id, 1);
+
+        let ico = icon.to_file();
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 2, 0]);
+        assert_eq!(&ico[6..10], &[2, 2, 0, 0]);
+        assert_eq!(u32::from_le_bytes(ico[18..22].try_into().unwrap()), 38);
+        assert_eq!(u32::from_le_bytes(ico[34..38].try_into().unwrap()), 38 + bitmap.len() as u32);
+        assert_eq!(&ico[38..38 + bitmap.len()], &bitmap[..]);
+        assert!(ico.ends_with(&png));
+
+        assert_eq!(icon.frames[1].to_png().unwrap(), png);
+        assert_eq!(icon.frames[0].to_png().unwrap(), png::encode_rgba(2, 2, &[
+            0xff, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0, 0, 0xff, 0xff, 0, 0xff, 0, 0xff,
+        ]));
+    }
+
+    #[test]
+    fn reassembles_cursor_group() {
+        let mut cursor = vec![5, 0, 7, 0];
+        cursor.extend_from_slice(&bitmap_frame());
+        let cursor_group = group(2, &[(1, 2, 4, 1, 32, cursor.len() as u32)]);
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(1), ResourceId::Id(1), 0, &cursor),
+            (ResourceId::Id(12), ResourceId::Id(100), 0, &cursor_group),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let groups = parse_icon_groups(&file, &headers, &resources);
+        assert!(main_icon(&groups).is_none());
+        assert_eq!(groups[0].kind, IconGroupKind::Cursor);
+        assert_eq!(groups[0].frames[0].hotspot, Some((5, 7)));
+        let cur = groups[0].to_file();
+        assert_eq!(&cur[..6], &[0, 0, 2, 0, 1, 0]);
+        assert_eq!(&cur[10..14], &[5, 0, 7, 0]);
+        assert_eq!(&cur[22..], &cursor[4..]);
+    }
+
+    #[test]
+    fn malformed_groups() {
+        let bitmap = bitmap_frame();
+        let mut icon_group = group(1, &[(1, 2, 2, 1, 32, 0), (2, 2, 2, 1, 32, bitmap.len() as u32), (9, 2, 2, 1, 32, 0)]);
+        icon_group[4] = 4;
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(3), ResourceId::Id(1), 0, b"not an image"),
+            (ResourceId::Id(3), ResourceId::Id(2), 0, &bitmap),
+            (ResourceId::Id(14), ResourceId::Id(1), 0, &icon_group),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let groups = parse_icon_groups(&file, &headers, &resources);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].name, ResourceId::Id(1));
+        // Only the frame that can be decoded is kept
+        let frames: Vec<(u16, FrameFormat, u32, u32)> = groups[0].frames.iter().map(|frame| (frame.id, frame.format, frame.width, frame.height)).collect();
+        assert_eq!(frames, vec![(2, FrameFormat::Bitmap, 2, 2)]);
+        assert_eq!(groups[0].frames[0].data, bitmap);
+        assert_eq!(groups[0].anomalies, vec![
+            String::from("Frame 1 has an unrecognised image format"),
+            String::from("Frame 9 referenced by the group is missing"),
+            String::from("Group declares 4 entries but is truncated after 3"),
+        ]);
+    }
+}
@@ -0,0 +1,261 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::vec::Vec;
+
+// Raw DEFLATE (RFC 1951) decoder, enough for the streams found in PE images
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+// Canonical Huffman code: number of codes per length and symbols sorted by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, position: 0, bit_buffer: 0, bit_count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.bit_count < count {
+            let byte = *self.data.get(self.position)? as u32;
+            self.position += 1;
+            self.bit_buffer |= byte << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer = if count == 32 { 0 } else { self.bit_buffer >> count };
+        self.bit_count -= count;
+        Some(value)
+    }
+
+    fn align(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+impl Huffman {
+    // None for over-subscribed codes, incomplete codes are allowed as zlib does
+    fn new(lengths: &[u8]) -> Option<Huffman> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        let mut left: i32 = 1;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return None;
+            }
+        }
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for length in 1..=MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Some(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - count < first {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths).unwrap(), Huffman::new(&[5u8; 30]).unwrap())
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Option<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return None;
+    }
+    let mut code_lengths = [0u8; 19];
+    for index in CODE_LENGTH_ORDER.iter().take(code_count) {
+        code_lengths[*index] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths)?;
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_huffman.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + reader.bits(2)? as usize),
+            17 => (0, 3 + reader.bits(3)? as usize),
+            18 => (0, 11 + reader.bits(7)? as usize),
+            _ => return None,
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return None;
+        }
+        lengths.extend(std::iter::repeat_n(value, repeat));
+    }
+    // A block without an end of block code cannot terminate
+    if lengths[256] == 0 {
+        return None;
+    }
+    Some((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..])?))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literals: &Huffman, distances: &Huffman, limit: usize) -> Option<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Some(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= 30 {
+                    return None;
+                }
+                let distance = DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return None;
+                }
+                let start = output.len() - distance;
+                // Copies may overlap their own output, byte by byte is required
+                for x in 0..length {
+                    output.push(output[start + x]);
+                }
+            }
+            _ => return None,
+        }
+        if output.len() > limit {
+            return None;
+        }
+    }
+}
+
+// The limit bounds the output size against decompression bombs
+pub fn inflate(data: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let mut reader = BitReader::new(data);
+    let mut output = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = u16::from_le_bytes(data.get(reader.position..reader.position + 2)?.try_into().ok()?);
+                let complement = u16::from_le_bytes(data.get(reader.position + 2..reader.position + 4)?.try_into().ok()?);
+                if length != !complement {
+                    return None;
+                }
+                reader.position += 4;
+                output.extend_from_slice(data.get(reader.position..reader.position + length as usize)?);
+                reader.position += length as usize;
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literals, &distances, limit)?;
+            }
+            _ => return None,
+        }
+        if output.len() > limit {
+            return None;
+        }
+        if last {
+            return Some(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Raw streams produced by zlib with wbits -15
+    const STORED: [u8; 17] = [0x01, 0x0c, 0x00, 0xf3, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x20, 0x62, 0x6c, 0x6f, 0x63, 0x6b];
+    const FIXED: [u8; 13] = [0x4b, 0x4c, 0x4a, 0x4e, 0x44, 0x45, 0x0a, 0x69, 0x99, 0x15, 0xa9, 0x29, 0x00];
+    const DYNAMIC: [u8; 79] = [
+        0xb5, 0xcb, 0xc7, 0x01, 0x80, 0x20, 0x10, 0x05, 0xd1, 0x56, 0x7e, 0x05, 0xd4, 0xe2, 0xc1, 0x06, 0x40, 0x49, 0x06, 0x56,
+        0xb2, 0x50, 0xbd, 0xdb, 0x84, 0xe7, 0x79, 0xb3, 0x3a, 0x8d, 0x58, 0xfd, 0x76, 0x42, 0x25, 0xea, 0x01, 0x86, 0x5e, 0x1c,
+        0xf5, 0x7e, 0x32, 0xa8, 0xe9, 0x84, 0xc2, 0xf9, 0x92, 0x73, 0x60, 0x27, 0x2b, 0xb0, 0xfe, 0x86, 0x17, 0xc9, 0xee, 0x1e,
+        0x50, 0x8c, 0xba, 0x2f, 0x0e, 0xc6, 0x37, 0xcd, 0x69, 0xea, 0x80, 0xcb, 0xc7, 0x4a, 0x89, 0x5f, 0x9b, 0xc5, 0x07,
+    ];
+
+    fn dynamic_text() -> Vec<u8> {
+        let mut text = b"The quick brown fox jumps over the lazy dog. ".repeat(3);
+        text.extend_from_slice(b"Pack my box with five dozen liquor jugs.");
+        text
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(inflate(&STORED, 1024).unwrap(), b"stored block");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(inflate(&FIXED, 1024).unwrap(), b"abcabcabcabcabcabc fixed");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(inflate(&DYNAMIC, 1024).unwrap(), dynamic_text());
+    }
+
+    #[test]
+    fn output_limit() {
+        assert!(inflate(&DYNAMIC, 100).is_none());
+    }
+
+    #[test]
+    fn malformed_streams() {
+        let mut complement = STORED;
+        complement[3] = 0xf4;
+        assert!(inflate(&complement, 1024).is_none());
+        assert!(inflate(&DYNAMIC[..40], 1024).is_none());
+        // Block type 3 is reserved
+        assert!(inflate(&[0x07], 1024).is_none());
+    }
+}
@@ -1,14 +1,46 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-mod structs;
+pub mod structs;
 mod utils;
+mod png;
+mod inflate;
+pub mod sha;
+mod der;
+pub mod relocations;
+pub mod resources;
+pub mod version;
+pub mod manifest;
+pub mod icons;
+pub mod string_tables;
+pub mod templates;
+pub mod extract;
+pub mod tls;
+pub mod load_config;
+pub mod cfg;
+pub mod safeseh;
+pub mod dvrt;
+pub mod chpe;
+pub mod unwind;
+pub mod arm_unwind;
+pub mod debug;
+pub mod codeview;
+pub mod build_info;
+pub mod portable_pdb;
+pub mod pkcs7;
+pub mod certificates;
+pub mod authenticode;
+mod bignum;
+mod rsa;
+pub mod verify;
+pub mod sign;
 use structs::DOSHeaders;
 use structs::COFFHeaders;
 use structs::StandardFields;
 use structs::WindowsSpecific;
 use structs::Headers;
 use structs::DataDirectories;
+use structs::SectionHeader;
 use structs::Characteristics;
 use structs::CharacteristicsVal;
 use utils::consume_u16_from_buffer;
@@ -16,39 +48,34 @@ use utils::consume_u32_from_buffer;
 use utils::consume_u64_from_buffer;
 use std::fs;
 use std::vec::Vec;
-use std::io::Read;
-
-fn read_file(filename: &String) -> Vec<u8> {
-    let mut file = fs::File::open(&filename).expect("Could not open file");
-    let metadata = fs::metadata(&filename).expect("unable to read metadata");
-    let mut file_content = vec![0; metadata.len() as usize];
-    file.read(&mut file_content).expect("Failed to read file");
-    file_content
+
+pub fn read_file(filename: &String) -> Vec<u8> {
+    fs::read(filename).expect("Could not open file")
 }
 
 fn parse_dos(dos_headers: &[u8], headers: &mut DOSHeaders) {
     let mut index: usize = 0;
-    headers.magic = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.last_size = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.pages_in_file = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.relocations = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.header_size_in_paragraph = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.min_extra_paragraph_needed = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.max_extra_paragraph_needed = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.initial_ss = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.initial_sp = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.checksum = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.initial_ip = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.initial_cs = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.file_add_of_relocation_table = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.overlay_number = consume_u16_from_buffer(&dos_headers, &mut index);
+    headers.magic = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.last_size = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.pages_in_file = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.relocations = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.header_size_in_paragraph = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.min_extra_paragraph_needed = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.max_extra_paragraph_needed = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.initial_ss = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.initial_sp = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.checksum = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.initial_ip = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.initial_cs = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.file_add_of_relocation_table = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.overlay_number = consume_u16_from_buffer(dos_headers, &mut index);
     headers.reserved_one = dos_headers[index..index+8].try_into().expect("Failed to convert slice to [u8; 36]");
     index += 8;
-    headers.oem_identifier = consume_u16_from_buffer(&dos_headers, &mut index);
-    headers.oem_information = consume_u16_from_buffer(&dos_headers, &mut index);
+    headers.oem_identifier = consume_u16_from_buffer(dos_headers, &mut index);
+    headers.oem_information = consume_u16_from_buffer(dos_headers, &mut index);
     headers.reserved_two = dos_headers[index..index+20].try_into().expect("Failed to convert slice to [u8; 36]");
     index += 20;
-    headers.offset_to_pe_headers =consume_u32_from_buffer(&dos_headers, &mut index);
+    headers.offset_to_pe_headers =consume_u32_from_buffer(dos_headers, &mut index);
 }
 
 fn parse_characteristics(value: &mut u16, characteristics: &mut Characteristics) {
@@ -65,83 +92,99 @@ fn parse_characteristics(value: &mut u16, characteristics: &mut Characteristics)
 
 fn parse_coff(coff_headers: &[u8], headers: &mut COFFHeaders) {
     let mut index: usize = 0;
-    headers.magic = consume_u32_from_buffer(&coff_headers, &mut index);
-    headers.target_machine = consume_u16_from_buffer(&coff_headers, &mut index);
-    headers.number_of_sections = consume_u16_from_buffer(&coff_headers, &mut index);
-    headers.time_date_stamp = consume_u32_from_buffer(&coff_headers, &mut index);
-    headers.pointer_to_symbol_table = consume_u32_from_buffer(&coff_headers, &mut index);
-    headers.number_of_symbols = consume_u32_from_buffer(&coff_headers, &mut index);
-    headers.size_of_optional_headers = consume_u16_from_buffer(&coff_headers, &mut index);
-    let mut characteristics_value = consume_u16_from_buffer(&coff_headers, &mut index);
+    headers.magic = consume_u32_from_buffer(coff_headers, &mut index);
+    headers.target_machine = consume_u16_from_buffer(coff_headers, &mut index);
+    headers.number_of_sections = consume_u16_from_buffer(coff_headers, &mut index);
+    headers.time_date_stamp = consume_u32_from_buffer(coff_headers, &mut index);
+    headers.pointer_to_symbol_table = consume_u32_from_buffer(coff_headers, &mut index);
+    headers.number_of_symbols = consume_u32_from_buffer(coff_headers, &mut index);
+    headers.size_of_optional_headers = consume_u16_from_buffer(coff_headers, &mut index);
+    let mut characteristics_value = consume_u16_from_buffer(coff_headers, &mut index);
     parse_characteristics(&mut characteristics_value, &mut headers.characteristics);
 }
 
 fn parse_standard_fields(standard_fields: &[u8], headers: &mut StandardFields) {
     let mut index: usize = 0;
-    headers.magic = consume_u16_from_buffer(&standard_fields, &mut index);
+    headers.magic = consume_u16_from_buffer(standard_fields, &mut index);
     headers.major_linker_version = standard_fields[index];
     headers.minor_linker_version = standard_fields[index + 1];
     index += 2;
-    headers.size_of_code = consume_u32_from_buffer(&standard_fields, &mut index);
-    headers.size_of_initialized_data = consume_u32_from_buffer(&standard_fields, &mut index);
-    headers.size_of_uninitialized_data = consume_u32_from_buffer(&standard_fields, &mut index);
-    headers.address_of_entry_point = consume_u32_from_buffer(&standard_fields, &mut index);
-    headers.base_of_code = consume_u32_from_buffer(&standard_fields, &mut index);
-    if headers.magic == 0x108 {
-        headers.base_of_data = consume_u32_from_buffer(&standard_fields, &mut index);
+    headers.size_of_code = consume_u32_from_buffer(standard_fields, &mut index);
+    headers.size_of_initialized_data = consume_u32_from_buffer(standard_fields, &mut index);
+    headers.size_of_uninitialized_data = consume_u32_from_buffer(standard_fields, &mut index);
+    headers.address_of_entry_point = consume_u32_from_buffer(standard_fields, &mut index);
+    headers.base_of_code = consume_u32_from_buffer(standard_fields, &mut index);
+    if headers.magic == 0x10b {
+        headers.base_of_data = consume_u32_from_buffer(standard_fields, &mut index);
     }
 }
 
 fn parse_windows_specific(windows_specific: &[u8], pe_32: &bool, headers: &mut WindowsSpecific) {
     let mut index: usize = 0;
     if *pe_32 {
-        headers.image_base = consume_u32_from_buffer(&windows_specific, &mut index) as u64;
+        headers.image_base = consume_u32_from_buffer(windows_specific, &mut index) as u64;
     } else {
-        headers.image_base = consume_u64_from_buffer(&windows_specific, &mut index);
+        headers.image_base = consume_u64_from_buffer(windows_specific, &mut index);
     }
-    headers.section_alignment = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.file_alignment = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.major_operating_system_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.minor_operating_system_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.major_image_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.minor_image_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.major_subsystem_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.minor_subsystem_version = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.win32_version_value = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.size_of_image = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.size_of_headers = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.checksum = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.subsystem = consume_u16_from_buffer(&windows_specific, &mut index);
-    headers.dll_characteristics = consume_u16_from_buffer(&windows_specific, &mut index);
+    headers.section_alignment = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.file_alignment = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.major_operating_system_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.minor_operating_system_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.major_image_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.minor_image_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.major_subsystem_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.minor_subsystem_version = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.win32_version_value = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.size_of_image = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.size_of_headers = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.checksum = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.subsystem = consume_u16_from_buffer(windows_specific, &mut index);
+    headers.dll_characteristics = consume_u16_from_buffer(windows_specific, &mut index);
     if *pe_32 {
-        headers.size_of_stack_reserve = consume_u32_from_buffer(&windows_specific, &mut index) as u64;
-        headers.size_of_stack_commit = consume_u32_from_buffer(&windows_specific, &mut index) as u64;
-        headers.size_of_heap_reserve = consume_u32_from_buffer(&windows_specific, &mut index) as u64;
-        headers.size_of_heap_commit = consume_u32_from_buffer(&windows_specific, &mut index) as u64;
+        headers.size_of_stack_reserve = consume_u32_from_buffer(windows_specific, &mut index) as u64;
+        headers.size_of_stack_commit = consume_u32_from_buffer(windows_specific, &mut index) as u64;
+        headers.size_of_heap_reserve = consume_u32_from_buffer(windows_specific, &mut index) as u64;
+        headers.size_of_heap_commit = consume_u32_from_buffer(windows_specific, &mut index) as u64;
     } else {
-        headers.size_of_stack_reserve = consume_u64_from_buffer(&windows_specific, &mut index);
-        headers.size_of_stack_commit = consume_u64_from_buffer(&windows_specific, &mut index);
-        headers.size_of_heap_reserve = consume_u64_from_buffer(&windows_specific, &mut index);
-        headers.size_of_heap_commit = consume_u64_from_buffer(&windows_specific, &mut index);
+        headers.size_of_stack_reserve = consume_u64_from_buffer(windows_specific, &mut index);
+        headers.size_of_stack_commit = consume_u64_from_buffer(windows_specific, &mut index);
+        headers.size_of_heap_reserve = consume_u64_from_buffer(windows_specific, &mut index);
+        headers.size_of_heap_commit = consume_u64_from_buffer(windows_specific, &mut index);
     }
-    headers.loader_flags = consume_u32_from_buffer(&windows_specific, &mut index);
-    headers.number_of_rva_and_sizes = consume_u32_from_buffer(&windows_specific, &mut index);
+    headers.loader_flags = consume_u32_from_buffer(windows_specific, &mut index);
+    headers.number_of_rva_and_sizes = consume_u32_from_buffer(windows_specific, &mut index);
 }
 
 fn parse_data_directories(data_directories: &[u8], number_of_directories: u32, headers: &mut DataDirectories) {
     let mut index: usize = 0;
-    for x in 0..number_of_directories {
-        headers.directories[(x as usize)].virtual_address = consume_u32_from_buffer(&data_directories, &mut index);
-        headers.directories[(x as usize)].size = consume_u32_from_buffer(&data_directories, &mut index);
+    for x in 0..number_of_directories.min(16) {
+        headers.directories[x as usize].virtual_address = consume_u32_from_buffer(data_directories, &mut index);
+        headers.directories[x as usize].size = consume_u32_from_buffer(data_directories, &mut index);
     }
 }
 
+fn parse_section_header(section_header: &[u8], header: &mut SectionHeader) {
+    let mut index: usize = 0;
+    header.name = String::from_utf8_lossy(&section_header[0..8]).trim_end_matches('\0').to_string();
+    index += 8;
+    header.virtual_size = consume_u32_from_buffer(section_header, &mut index);
+    header.virtual_address = consume_u32_from_buffer(section_header, &mut index);
+    header.size_of_raw_data = consume_u32_from_buffer(section_header, &mut index);
+    header.pointer_to_raw_data = consume_u32_from_buffer(section_header, &mut index);
+    header.pointer_to_relocations = consume_u32_from_buffer(section_header, &mut index);
+    header.pointer_to_linenumbers = consume_u32_from_buffer(section_header, &mut index);
+    header.number_of_relocations = consume_u16_from_buffer(section_header, &mut index);
+    header.number_of_linenumbers = consume_u16_from_buffer(section_header, &mut index);
+    header.characteristics = consume_u32_from_buffer(section_header, &mut index);
+}
+
 pub fn parse_pe_headers(filename: &String) -> Headers {
+    let file = read_file(filename);
+    parse_headers(&file)
+}
 
-    // false: pe32+, true: pe32
-    let pe_32: bool; 
+pub fn parse_headers(file: &[u8]) -> Headers {
 
-    let file = read_file(filename);
     let mut headers = Headers::default();
 
     // Parse DOS headers
@@ -155,7 +198,8 @@ pub fn parse_pe_headers(filename: &String) -> Headers {
     // Parse optional headers - standard fields
     let mut standard_fields_end = coff_headers_end + 28;
     parse_standard_fields(&file[coff_headers_end..standard_fields_end], &mut headers.optional_headers.standard_fields);
-    pe_32 = headers.optional_headers.standard_fields.magic == 0x108;
+    // false: pe32+, true: pe32
+    let pe_32 = headers.optional_headers.standard_fields.magic == 0x10b;
     if !pe_32 {
         standard_fields_end -= 4;
     }
@@ -168,8 +212,24 @@ pub fn parse_pe_headers(filename: &String) -> Headers {
     parse_windows_specific(&file[standard_fields_end..windows_specific_end], &pe_32, &mut headers.optional_headers.windows_specific);
 
     // Parse data directories
-    let data_directories_end = windows_specific_end + (8 * (headers.optional_headers.windows_specific.number_of_rva_and_sizes as usize));
+    let data_directories_end = windows_specific_end + (8 * (headers.optional_headers.windows_specific.number_of_rva_and_sizes.min(16) as usize));
     parse_data_directories(&file[windows_specific_end..data_directories_end], headers.optional_headers.windows_specific.number_of_rva_and_sizes, &mut headers.optional_headers.data_directories);
 
+    // Parse section table
+    let mut section_header_start = coff_headers_end + headers.coff_headers.size_of_optional_headers as usize;
+    for x in 0..headers.coff_headers.number_of_sections {
+        let section_header_bytes = match file.get(section_header_start..section_header_start + 40) {
+            Some(bytes) => bytes,
+            None => {
+                headers.anomalies.push(format!("Section table is truncated, only {} of {} section headers are in the file", x, headers.coff_headers.number_of_sections));
+                break;
+            }
+        };
+        let mut section_header = SectionHeader::default();
+        parse_section_header(section_header_bytes, &mut section_header);
+        headers.sections.push(section_header);
+        section_header_start += 40;
+    }
+
     headers
 }
\ No newline at end of file
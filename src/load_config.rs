@@ -0,0 +1,489 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::is_pe32;
+use crate::utils::read_pointer_at;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+
+const IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG: usize = 10;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum GuardFlag {
+    IMAGE_GUARD_CF_INSTRUMENTED = 0x00000100,
+    IMAGE_GUARD_CFW_INSTRUMENTED = 0x00000200,
+    IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT = 0x00000400,
+    IMAGE_GUARD_SECURITY_COOKIE_UNUSED = 0x00000800,
+    IMAGE_GUARD_PROTECT_DELAYLOAD_IAT = 0x00001000,
+    IMAGE_GUARD_DELAYLOAD_IAT_IN_ITS_OWN_SECTION = 0x00002000,
+    IMAGE_GUARD_CF_EXPORT_SUPPRESSION_INFO_PRESENT = 0x00004000,
+    IMAGE_GUARD_CF_ENABLE_EXPORT_SUPPRESSION = 0x00008000,
+    IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT = 0x00010000,
+    IMAGE_GUARD_RF_INSTRUMENTED = 0x00020000,
+    IMAGE_GUARD_RF_ENABLE = 0x00040000,
+    IMAGE_GUARD_RF_STRICT = 0x00080000,
+    IMAGE_GUARD_RETPOLINE_PRESENT = 0x00100000,
+    IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT = 0x00400000,
+    IMAGE_GUARD_XFG_ENABLED = 0x00800000,
+    IMAGE_GUARD_CASTGUARD_PRESENT = 0x01000000,
+    IMAGE_GUARD_MEMCPY_PRESENT = 0x02000000,
+}
+
+#[derive(Default)]
+pub struct CodeIntegrity {
+    pub flags: u16,
+    pub catalog: u16,
+    pub catalog_offset: u32,
+    pub reserved: u32,
+}
+
+// Every field after `size` is only present if the structure is large enough to hold it
+#[derive(Default)]
+pub struct LoadConfig {
+    pub size: u32,
+    pub time_date_stamp: Option<u32>,
+    pub major_version: Option<u16>,
+    pub minor_version: Option<u16>,
+    pub global_flags_clear: Option<u32>,
+    pub global_flags_set: Option<u32>,
+    pub critical_section_default_timeout: Option<u32>,
+    pub de_commit_free_block_threshold: Option<u64>,
+    pub de_commit_total_free_threshold: Option<u64>,
+    pub lock_prefix_table: Option<u64>,
+    pub maximum_allocation_size: Option<u64>,
+    pub virtual_memory_threshold: Option<u64>,
+    pub process_heap_flags: Option<u32>,
+    pub process_affinity_mask: Option<u64>,
+    pub csd_version: Option<u16>,
+    pub dependent_load_flags: Option<u16>,
+    pub edit_list: Option<u64>,
+    pub security_cookie: Option<u64>,
+    pub se_handler_table: Option<u64>,
+    pub se_handler_count: Option<u64>,
+    pub guard_cf_check_function_pointer: Option<u64>,
+    pub guard_cf_dispatch_function_pointer: Option<u64>,
+    pub guard_cf_function_table: Option<u64>,
+    pub guard_cf_function_count: Option<u64>,
+    pub guard_flags: Option<u32>,
+    pub guard_flags_list: Vec<GuardFlag>,
+    pub code_integrity: Option<CodeIntegrity>,
+    pub guard_address_taken_iat_entry_table: Option<u64>,
+    pub guard_address_taken_iat_entry_count: Option<u64>,
+    pub guard_long_jump_target_table: Option<u64>,
+    pub guard_long_jump_target_count: Option<u64>,
+    pub dynamic_value_reloc_table: Option<u64>,
+    pub chpe_metadata_pointer: Option<u64>,
+    pub guard_rf_failure_routine: Option<u64>,
+    pub guard_rf_failure_routine_function_pointer: Option<u64>,
+    pub dynamic_value_reloc_table_offset: Option<u32>,
+    pub dynamic_value_reloc_table_section: Option<u16>,
+    pub reserved2: Option<u16>,
+    pub guard_rf_verify_stack_pointer_function_pointer: Option<u64>,
+    pub hot_patch_table_offset: Option<u32>,
+    pub reserved3: Option<u32>,
+    pub enclave_configuration_pointer: Option<u64>,
+    pub volatile_metadata_pointer: Option<u64>,
+    pub guard_eh_continuation_table: Option<u64>,
+    pub guard_eh_continuation_count: Option<u64>,
+    pub guard_xfg_check_function_pointer: Option<u64>,
+    pub guard_xfg_dispatch_function_pointer: Option<u64>,
+    pub guard_xfg_table_dispatch_function_pointer: Option<u64>,
+    pub cast_guard_os_determined_failure_mode: Option<u64>,
+    pub guard_memcpy_function_pointer: Option<u64>,
+    pub uma_function_pointers: Option<u64>,
+    // Name of the last field covered by `size`, which identifies the structure version
+    pub last_field: &'static str,
+    // Bytes past the last field this parser knows about
+    pub trailing: Vec<u8>,
+    pub anomalies: Vec<String>,
+}
+
+// Reads the structure field by field, yielding None once `size` is exhausted
+struct FieldReader<'a> {
+    data: &'a [u8],
+    index: usize,
+    pe_32: bool,
+    last_field: &'static str,
+    // Set when `size` ends in the middle of a field
+    split_field: bool,
+}
+
+
+// Conversion implementation for the structs
+
+impl GuardFlag {
+    pub fn from_u32(val: u32) -> Option<GuardFlag> {
+        match val {
+            0x00000100 => Some(GuardFlag::IMAGE_GUARD_CF_INSTRUMENTED),
+            0x00000200 => Some(GuardFlag::IMAGE_GUARD_CFW_INSTRUMENTED),
+            0x00000400 => Some(GuardFlag::IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT),
+            0x00000800 => Some(GuardFlag::IMAGE_GUARD_SECURITY_COOKIE_UNUSED),
+            0x00001000 => Some(GuardFlag::IMAGE_GUARD_PROTECT_DELAYLOAD_IAT),
+            0x00002000 => Some(GuardFlag::IMAGE_GUARD_DELAYLOAD_IAT_IN_ITS_OWN_SECTION),
+            0x00004000 => Some(GuardFlag::IMAGE_GUARD_CF_EXPORT_SUPPRESSION_INFO_PRESENT),
+            0x00008000 => Some(GuardFlag::IMAGE_GUARD_CF_ENABLE_EXPORT_SUPPRESSION),
+            0x00010000 => Some(GuardFlag::IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT),
+            0x00020000 => Some(GuardFlag::IMAGE_GUARD_RF_INSTRUMENTED),
+            0x00040000 => Some(GuardFlag::IMAGE_GUARD_RF_ENABLE),
+            0x00080000 => Some(GuardFlag::IMAGE_GUARD_RF_STRICT),
+            0x00100000 => Some(GuardFlag::IMAGE_GUARD_RETPOLINE_PRESENT),
+            0x00400000 => Some(GuardFlag::IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT),
+            0x00800000 => Some(GuardFlag::IMAGE_GUARD_XFG_ENABLED),
+            0x01000000 => Some(GuardFlag::IMAGE_GUARD_CASTGUARD_PRESENT),
+            0x02000000 => Some(GuardFlag::IMAGE_GUARD_MEMCPY_PRESENT),
+            _ => None,
+        }
+    }
+}
+
+impl LoadConfig {
+    // Number of metadata bytes following each RVA in the CFG tables, from IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_MASK
+    pub fn guard_cf_function_table_stride(&self) -> u32 {
+        (self.guard_flags.unwrap_or(0) >> 28) & 0xf
+    }
+
+    pub fn has_guard_flag(&self, flag: GuardFlag) -> bool {
+        self.guard_flags.unwrap_or(0) & flag as u32 != 0
+    }
+}
+
+
+// Parsing
+
+impl<'a> FieldReader<'a> {
+    fn field(&mut self, name: &'static str, size: usize) -> Option<usize> {
+        let offset = self.index;
+        self.index += size;
+        if self.index > self.data.len() {
+            self.split_field |= offset < self.data.len();
+            return None;
+        }
+        self.last_field = name;
+        Some(offset)
+    }
+
+    fn u16(&mut self, name: &'static str) -> Option<u16> {
+        let offset = self.field(name, 2)?;
+        read_u16_at(self.data, offset)
+    }
+
+    fn u32(&mut self, name: &'static str) -> Option<u32> {
+        let offset = self.field(name, 4)?;
+        read_u32_at(self.data, offset)
+    }
+
+    fn pointer(&mut self, name: &'static str) -> Option<u64> {
+        let offset = self.field(name, if self.pe_32 { 4 } else { 8 })?;
+        read_pointer_at(self.data, offset, self.pe_32)
+    }
+
+    fn code_integrity(&mut self, name: &'static str) -> Option<CodeIntegrity> {
+        let offset = self.field(name, 12)?;
+        Some(CodeIntegrity {
+            flags: read_u16_at(self.data, offset)?,
+            catalog: read_u16_at(self.data, offset + 2)?,
+            catalog_offset: read_u32_at(self.data, offset + 4)?,
+            reserved: read_u32_at(self.data, offset + 8)?,
+        })
+    }
+}
+
+pub fn parse_load_config(file: &[u8], headers: &Headers) -> Option<LoadConfig> {
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG];
+    if directory.virtual_address == 0 {
+        return None;
+    }
+    let mut load_config = LoadConfig::default();
+    let offset = match rva_to_offset(headers, directory.virtual_address) {
+        Some(offset) if offset + 4 <= file.len() => offset,
+        _ => {
+            load_config.anomalies.push(format!("Load config directory at RVA 0x{:x} is not backed by the file", directory.virtual_address));
+            return Some(load_config);
+        }
+    };
+    let pe_32 = is_pe32(headers);
+    // The loader trusts the Size field of the structure rather than the data directory
+    load_config.size = read_u32_at(file, offset)?;
+    // Linkers targeting Windows XP keep the x86 directory size at 0x40 whatever the structure size
+    if load_config.size != directory.size && !(pe_32 && directory.size == 0x40) {
+        load_config.anomalies.push(format!("Load config Size field 0x{:x} differs from the data directory size 0x{:x}", load_config.size, directory.size));
+    }
+    let mut end = offset + load_config.size as usize;
+    if end > file.len() {
+        load_config.anomalies.push(format!("Load config of size 0x{:x} is truncated by the end of the file", load_config.size));
+        end = file.len();
+    }
+    let mut reader = FieldReader { data: &file[offset..end], index: 4, pe_32, last_field: "Size", split_field: false };
+
+    load_config.time_date_stamp = reader.u32("TimeDateStamp");
+    load_config.major_version = reader.u16("MajorVersion");
+    load_config.minor_version = reader.u16("MinorVersion");
+    load_config.global_flags_clear = reader.u32("GlobalFlagsClear");
+    load_config.global_flags_set = reader.u32("GlobalFlagsSet");
+    load_config.critical_section_default_timeout = reader.u32("CriticalSectionDefaultTimeout");
+    load_config.de_commit_free_block_threshold = reader.pointer("DeCommitFreeBlockThreshold");
+    load_config.de_commit_total_free_threshold = reader.pointer("DeCommitTotalFreeThreshold");
+    load_config.lock_prefix_table = reader.pointer("LockPrefixTable");
+    load_config.maximum_allocation_size = reader.pointer("MaximumAllocationSize");
+    load_config.virtual_memory_threshold = reader.pointer("VirtualMemoryThreshold");
+    // The two layouts disagree on the order of these fields
+    if pe_32 {
+        load_config.process_heap_flags = reader.u32("ProcessHeapFlags");
+        load_config.process_affinity_mask = reader.pointer("ProcessAffinityMask");
+    } else {
+        load_config.process_affinity_mask = reader.pointer("ProcessAffinityMask");
+        load_config.process_heap_flags = reader.u32("ProcessHeapFlags");
+    }
+    load_config.csd_version = reader.u16("CSDVersion");
+    load_config.dependent_load_flags = reader.u16("DependentLoadFlags");
+    load_config.edit_list = reader.pointer("EditList");
+    load_config.security_cookie = reader.pointer("SecurityCookie");
+    load_config.se_handler_table = reader.pointer("SEHandlerTable");
+    load_config.se_handler_count = reader.pointer("SEHandlerCount");
+    load_config.guard_cf_check_function_pointer = reader.pointer("GuardCFCheckFunctionPointer");
+    load_config.guard_cf_dispatch_function_pointer = reader.pointer("GuardCFDispatchFunctionPointer");
+    load_config.guard_cf_function_table = reader.pointer("GuardCFFunctionTable");
+    load_config.guard_cf_function_count = reader.pointer("GuardCFFunctionCount");
+    load_config.guard_flags = reader.u32("GuardFlags");
+    load_config.code_integrity = reader.code_integrity("CodeIntegrity");
+    load_config.guard_address_taken_iat_entry_table = reader.pointer("GuardAddressTakenIatEntryTable");
+    load_config.guard_address_taken_iat_entry_count = reader.pointer("GuardAddressTakenIatEntryCount");
+    load_config.guard_long_jump_target_table = reader.pointer("GuardLongJumpTargetTable");
+    load_config.guard_long_jump_target_count = reader.pointer("GuardLongJumpTargetCount");
+    load_config.dynamic_value_reloc_table = reader.pointer("DynamicValueRelocTable");
+    load_config.chpe_metadata_pointer = reader.pointer("CHPEMetadataPointer");
+    load_config.guard_rf_failure_routine = reader.pointer("GuardRFFailureRoutine");
+    load_config.guard_rf_failure_routine_function_pointer = reader.pointer("GuardRFFailureRoutineFunctionPointer");
+    load_config.dynamic_value_reloc_table_offset = reader.u32("DynamicValueRelocTableOffset");
+    load_config.dynamic_value_reloc_table_section = reader.u16("DynamicValueRelocTableSection");
+    load_config.reserved2 = reader.u16("Reserved2");
+    load_config.guard_rf_verify_stack_pointer_function_pointer = reader.pointer("GuardRFVerifyStackPointerFunctionPointer");
+    load_config.hot_patch_table_offset = reader.u32("HotPatchTableOffset");
+    load_config.reserved3 = reader.u32("Reserved3");
+    load_config.enclave_configuration_pointer = reader.pointer("EnclaveConfigurationPointer");
+    load_config.volatile_metadata_pointer = reader.pointer("VolatileMetadataPointer");
+    load_config.guard_eh_continuation_table = reader.pointer("GuardEHContinuationTable");
+    load_config.guard_eh_continuation_count = reader.pointer("GuardEHContinuationCount");
+    load_config.guard_xfg_check_function_pointer = reader.pointer("GuardXFGCheckFunctionPointer");
+    load_config.guard_xfg_dispatch_function_pointer = reader.pointer("GuardXFGDispatchFunctionPointer");
+    load_config.guard_xfg_table_dispatch_function_pointer = reader.pointer("GuardXFGTableDispatchFunctionPointer");
+    load_config.cast_guard_os_determined_failure_mode = reader.pointer("CastGuardOsDeterminedFailureMode");
+    load_config.guard_memcpy_function_pointer = reader.pointer("GuardMemcpyFunctionPointer");
+    load_config.uma_function_pointers = reader.pointer("UmaFunctionPointers");
+
+    load_config.last_field = reader.last_field;
+    if reader.index < reader.data.len() {
+        load_config.trailing = reader.data[reader.index..].to_vec();
+    } else if reader.split_field {
+        load_config.anomalies.push(format!("Load config size 0x{:x} ends in the middle of a field", load_config.size));
+    }
+
+    let mut bit: u32 = 0x100;
+    while bit <= 0x02000000 {
+        if load_config.guard_flags.unwrap_or(0) & bit != 0 {
+            load_config.guard_flags_list.extend(GuardFlag::from_u32(bit));
+        }
+        bit <<= 1;
+    }
+    Some(load_config)
+}
+
+
+// Display trait implementation for the structs
+
+fn hex<T: fmt::LowerHex>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => format!("0x{:x}", value),
+        None => String::from("-"),
+    }
+}
+
+impl fmt::Display for LoadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Load Config
+
+---------------------------
+size: 0x{:x}
+last_field: {}
+time_date_stamp: {}
+major_version: {}
+minor_version: {}
+global_flags_clear: {}
+global_flags_set: {}
+critical_section_default_timeout: {}
+de_commit_free_block_threshold: {}
+de_commit_total_free_threshold: {}
+lock_prefix_table: {}
+maximum_allocation_size: {}
+virtual_memory_threshold: {}
+process_heap_flags: {}
+process_affinity_mask: {}
+csd_version: {}
+dependent_load_flags: {}
+edit_list: {}
+security_cookie: {}
+se_handler_table: {}
+se_handler_count: {}
+guard_cf_check_function_pointer: {}
+guard_cf_dispatch_function_pointer: {}
+guard_cf_function_table: {}
+guard_cf_function_count: {}
+guard_flags: {} {:?}",
+        self.size, self.last_field, hex(&self.time_date_stamp), hex(&self.major_version), hex(&self.minor_version), hex(&self.global_flags_clear), hex(&self.global_flags_set), hex(&self.critical_section_default_timeout), hex(&self.de_commit_free_block_threshold), hex(&self.de_commit_total_free_threshold), hex(&self.lock_prefix_table), hex(&self.maximum_allocation_size), hex(&self.virtual_memory_threshold), hex(&self.process_heap_flags), hex(&self.process_affinity_mask), hex(&self.csd_version), hex(&self.dependent_load_flags), hex(&self.edit_list), hex(&self.security_cookie), hex(&self.se_handler_table), hex(&self.se_handler_count), hex(&self.guard_cf_check_function_pointer), hex(&self.guard_cf_dispatch_function_pointer), hex(&self.guard_cf_function_table), hex(&self.guard_cf_function_count), hex(&self.guard_flags), self.guard_flags_list)?;
+        if let Some(code_integrity) = &self.code_integrity {
+            write!(f, "
+code_integrity: flags 0x{:x} catalog 0x{:x} catalog_offset 0x{:x}", code_integrity.flags, code_integrity.catalog, code_integrity.catalog_offset)?;
+        }
+        write!(f, "
+guard_address_taken_iat_entry_table: {}
+guard_address_taken_iat_entry_count: {}
+guard_long_jump_target_table: {}
+guard_long_jump_target_count: {}
+dynamic_value_reloc_table: {}
+chpe_metadata_pointer: {}
+guard_rf_failure_routine: {}
+guard_rf_failure_routine_function_pointer: {}
+dynamic_value_reloc_table_offset: {}
+dynamic_value_reloc_table_section: {}
+guard_rf_verify_stack_pointer_function_pointer: {}
+hot_patch_table_offset: {}
+enclave_configuration_pointer: {}
+volatile_metadata_pointer: {}
+guard_eh_continuation_table: {}
+guard_eh_continuation_count: {}
+guard_xfg_check_function_pointer: {}
+guard_xfg_dispatch_function_pointer: {}
+guard_xfg_table_dispatch_function_pointer: {}
+cast_guard_os_determined_failure_mode: {}
+guard_memcpy_function_pointer: {}
+uma_function_pointers: {}
+trailing_bytes: 0x{:x}
+---------------------------",
+        hex(&self.guard_address_taken_iat_entry_table), hex(&self.guard_address_taken_iat_entry_count), hex(&self.guard_long_jump_target_table), hex(&self.guard_long_jump_target_count), hex(&self.dynamic_value_reloc_table), hex(&self.chpe_metadata_pointer), hex(&self.guard_rf_failure_routine), hex(&self.guard_rf_failure_routine_function_pointer), hex(&self.dynamic_value_reloc_table_offset), hex(&self.dynamic_value_reloc_table_section), hex(&self.guard_rf_verify_stack_pointer_function_pointer), hex(&self.hot_patch_table_offset), hex(&self.enclave_configuration_pointer), hex(&self.volatile_metadata_pointer), hex(&self.guard_eh_continuation_table), hex(&self.guard_eh_continuation_count), hex(&self.guard_xfg_check_function_pointer), hex(&self.guard_xfg_dispatch_function_pointer), hex(&self.guard_xfg_table_dispatch_function_pointer), hex(&self.cast_guard_os_determined_failure_mode), hex(&self.guard_memcpy_function_pointer), hex(&self.uma_function_pointers), self.trailing.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn image(pe_32: bool, size: u32, directory_size: u32) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; 0x1000];
+        put(&mut file, 0x800, &size.to_le_bytes());
+        let mut headers = flat_headers(pe_32, file.len());
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG].size = directory_size;
+        (file, headers)
+    }
+
+    #[test]
+    fn parses_pe32_safe_seh_version() {
+        // Windows XP era structure ending with the SafeSEH table, the directory size kept at 0x40
+        let (mut file, headers) = image(true, 0x48, 0x40);
+        put(&mut file, 0x82c, &0x1234u32.to_le_bytes());
+        put(&mut file, 0x830, &0x3u32.to_le_bytes());
+        put(&mut file, 0x83c, &0x403000u32.to_le_bytes());
+        put(&mut file, 0x840, &0x402000u32.to_le_bytes());
+        put(&mut file, 0x844, &0x5u32.to_le_bytes());
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.last_field, "SEHandlerCount");
+        assert_eq!(load_config.process_heap_flags, Some(0x1234));
+        assert_eq!(load_config.process_affinity_mask, Some(3));
+        assert_eq!(load_config.security_cookie, Some(0x403000));
+        assert_eq!(load_config.se_handler_table, Some(0x402000));
+        assert_eq!(load_config.se_handler_count, Some(5));
+        assert_eq!(load_config.guard_cf_check_function_pointer, None);
+        assert_eq!(load_config.guard_flags, None);
+        assert!(load_config.trailing.is_empty());
+        assert!(load_config.anomalies.is_empty());
+    }
+
+    #[test]
+    fn parses_pe32_plus_guard_version() {
+        // Windows 8.1 structure ending with GuardFlags
+        let (mut file, headers) = image(false, 0x94, 0x94);
+        put(&mut file, 0x840, &3u64.to_le_bytes());
+        put(&mut file, 0x848, &0x1234u32.to_le_bytes());
+        put(&mut file, 0x880, &0x140002000u64.to_le_bytes());
+        put(&mut file, 0x888, &7u64.to_le_bytes());
+        put(&mut file, 0x890, &0x10417500u32.to_le_bytes());
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.process_affinity_mask, Some(3));
+        assert_eq!(load_config.process_heap_flags, Some(0x1234));
+        assert_eq!(load_config.guard_cf_function_table, Some(0x140002000));
+        assert_eq!(load_config.guard_cf_function_count, Some(7));
+        assert_eq!(load_config.guard_cf_function_table_stride(), 1);
+        assert!(load_config.has_guard_flag(GuardFlag::IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT));
+        assert!(!load_config.has_guard_flag(GuardFlag::IMAGE_GUARD_XFG_ENABLED));
+        let flags: Vec<String> = load_config.guard_flags_list.iter().map(|flag| format!("{:?}", flag)).collect();
+        assert_eq!(flags, vec![
+            "IMAGE_GUARD_CF_INSTRUMENTED",
+            "IMAGE_GUARD_CF_FUNCTION_TABLE_PRESENT",
+            "IMAGE_GUARD_PROTECT_DELAYLOAD_IAT",
+            "IMAGE_GUARD_DELAYLOAD_IAT_IN_ITS_OWN_SECTION",
+            "IMAGE_GUARD_CF_EXPORT_SUPPRESSION_INFO_PRESENT",
+            "IMAGE_GUARD_CF_LONGJUMP_TABLE_PRESENT",
+            "IMAGE_GUARD_EH_CONTINUATION_TABLE_PRESENT",
+        ]);
+        assert_eq!(load_config.last_field, "GuardFlags");
+        assert!(load_config.code_integrity.is_none());
+        assert!(load_config.trailing.is_empty());
+        assert!(load_config.anomalies.is_empty());
+
+        // Structure larger than the last version this parser knows about
+        let (mut file, headers) = image(false, 0x14c, 0x14c);
+        put(&mut file, 0x894, &0x3u16.to_le_bytes());
+        put(&mut file, 0x948, &[0xaa, 0xbb, 0xcc, 0xdd]);
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.code_integrity.map(|code_integrity| code_integrity.flags), Some(3));
+        assert_eq!(load_config.last_field, "UmaFunctionPointers");
+        assert_eq!(load_config.trailing, vec![0xaa, 0xbb, 0xcc, 0xdd]);
+        assert!(load_config.anomalies.is_empty());
+    }
+
+    #[test]
+    fn malformed_structures() {
+        // Size ending inside CodeIntegrity and disagreeing with the data directory
+        let (file, headers) = image(false, 0x96, 0x100);
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.size, 0x96);
+        assert_eq!(load_config.last_field, "GuardFlags");
+        assert_eq!(load_config.guard_flags, Some(0));
+        assert!(load_config.code_integrity.is_none());
+        assert!(load_config.trailing.is_empty());
+        assert_eq!(load_config.anomalies, vec![
+            String::from("Load config Size field 0x96 differs from the data directory size 0x100"),
+            String::from("Load config size 0x96 ends in the middle of a field"),
+        ]);
+
+        // Structure cut by the end of the file
+        let (mut file, mut headers) = image(false, 0x94, 0x94);
+        put(&mut file, 0xff0, &0x94u32.to_le_bytes());
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG].virtual_address = 0xff0;
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.size, 0x94);
+        assert_eq!(load_config.last_field, "GlobalFlagsClear");
+        assert_eq!(load_config.global_flags_clear, Some(0));
+        assert_eq!(load_config.global_flags_set, None);
+        assert_eq!(load_config.anomalies, vec![String::from("Load config of size 0x94 is truncated by the end of the file")]);
+
+        // Directory outside of the file, then no directory at all
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG].virtual_address = 0x2000;
+        let load_config = parse_load_config(&file, &headers).unwrap();
+        assert_eq!(load_config.size, 0);
+        assert_eq!(load_config.time_date_stamp, None);
+        assert_eq!(load_config.anomalies, vec![String::from("Load config directory at RVA 0x2000 is not backed by the file")]);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_LOAD_CONFIG].virtual_address = 0;
+        assert!(parse_load_config(&file, &headers).is_none());
+    }
+}
@@ -1,6 +1,549 @@
-use pehp;
+use pehp::structs::Headers;
+use pehp::relocations;
+use pehp::resources;
+use pehp::extract;
+use pehp::resources::ResourceId;
+use pehp::resources::ResourceType;
+use pehp::version;
+use pehp::manifest;
+use pehp::icons;
+use pehp::string_tables;
+use pehp::templates;
+use pehp::tls;
+use pehp::load_config;
+use pehp::cfg;
+use pehp::safeseh;
+use pehp::dvrt;
+use pehp::chpe;
+use pehp::unwind;
+use pehp::arm_unwind;
+use pehp::debug;
+use pehp::codeview;
+use pehp::portable_pdb;
+use pehp::pkcs7;
+use pehp::certificates;
+use pehp::authenticode;
+use pehp::verify;
+use pehp::sign;
+use pehp::sha::HashAlgorithm;
 use std::env;
+use std::fs;
+use std::path::Path;
 use std::process;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+fn print_relocations(file: &[u8], headers: &Headers) {
+    let relocations = relocations::parse_base_relocations(file, headers);
+    println!("{}", relocations);
+    println!("Statistics\n");
+    for (relocation, count) in relocations::relocation_statistics(&relocations) {
+        println!("{:?}: {}", relocation, count);
+    }
+    for anomaly in &relocations.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_resources(file: &[u8], headers: &Headers, args: &[String]) {
+    let resources = resources::parse_resources(file, headers);
+    if args.is_empty() {
+        println!("{}", resources);
+        for anomaly in &resources.anomalies {
+            println!("Anomaly: {}", anomaly);
+        }
+        return;
+    }
+    if args[0] == "--export" && args.len() > 1 {
+        export_resources(file, headers, &resources, &args[1]);
+        return;
+    }
+    // Dump a single leaf, addressed as type/name/language
+    let path: Vec<&str> = args[0].split('/').collect();
+    if path.len() != 3 || args.len() < 2 {
+        println!("Usage: resources [<type>/<name>/<language> <output file> | --export <directory>]");
+        process::exit(0x1);
+    }
+    let resource_type = ResourceType::from_string(path[0]);
+    let name = ResourceId::from_string(path[1]);
+    let language = ResourceId::from_string(path[2]);
+    let data = resources::find_resource(&resources, &resource_type, &name, &language)
+        .and_then(|resource| resources::resource_data(file, headers, resource));
+    match data {
+        Some(data) => {
+            fs::write(&args[1], data).expect("Failed to write resource");
+            println!("Wrote 0x{:x} bytes to {}", data.len(), args[1]);
+        }
+        None => {
+            println!("Resource {} not found", args[0]);
+            process::exit(0x1);
+        }
+    }
+}
+
+fn export_resources(file: &[u8], headers: &Headers, resources: &resources::Resources, directory: &str) {
+    let extraction = extract::extract_resources(file, headers, resources);
+    fs::create_dir_all(directory).expect("Failed to create output directory");
+    for extracted in &extraction.resources {
+        let path = Path::new(directory).join(&extracted.file_name);
+        fs::write(&path, &extracted.data).expect("Failed to write resource");
+        println!("{}", extracted);
+    }
+    for finding in &extraction.findings {
+        println!("Finding: {}", finding);
+    }
+    for anomaly in &extraction.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_version(file: &[u8], headers: &Headers) {
+    let resources = resources::parse_resources(file, headers);
+    match version::parse_version_info(file, headers, &resources) {
+        Some(version_info) => {
+            println!("{}", version_info);
+            for anomaly in &version_info.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No version resource found"),
+    }
+}
+
+fn print_manifest(file: &[u8], headers: &Headers) {
+    let resources = resources::parse_resources(file, headers);
+    match manifest::parse_manifest(file, headers, &resources) {
+        Some(manifest) => {
+            println!("{}", manifest);
+            for anomaly in &manifest.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No manifest resource found"),
+    }
+}
+
+fn export_icon(file: &[u8], headers: &Headers, args: &[String]) {
+    if args.is_empty() {
+        println!("Usage: icon <output file> [--png]");
+        process::exit(0x1);
+    }
+    let resources = resources::parse_resources(file, headers);
+    let groups = icons::parse_icon_groups(file, headers, &resources);
+    let group = match icons::main_icon(&groups) {
+        Some(group) => group,
+        None => {
+            println!("No icon group found");
+            process::exit(0x1);
+        }
+    };
+    println!("{}", group);
+    for anomaly in &group.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+    fs::write(&args[0], group.to_file()).expect("Failed to write icon");
+    println!("Wrote {}", args[0]);
+    if args.get(1).map(|flag| flag.as_str()) == Some("--png") {
+        let stem = args[0].trim_end_matches(".ico");
+        for frame in &group.frames {
+            match frame.to_png() {
+                Some(png) => {
+                    let filename = format!("{}_{}x{}_{}.png", stem, frame.width, frame.height, frame.bit_count);
+                    fs::write(&filename, png).expect("Failed to write PNG");
+                    println!("Wrote {}", filename);
+                }
+                None => println!("Frame {} could not be converted to PNG", frame.id),
+            }
+        }
+    }
+}
+
+fn print_string_tables(file: &[u8], headers: &Headers) {
+    let resources = resources::parse_resources(file, headers);
+    let string_tables = string_tables::parse_string_tables(file, headers, &resources);
+    print!("{}", string_tables);
+    for anomaly in &string_tables.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_templates(file: &[u8], headers: &Headers, args: &[String]) {
+    let resources = resources::parse_resources(file, headers);
+    let templates = templates::parse_templates(file, headers, &resources);
+    if args.first().map(|flag| flag.as_str()) == Some("--rc") {
+        print!("{}", templates.to_rc());
+    } else {
+        print!("{}", templates);
+    }
+    for anomaly in &templates.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_tls(file: &[u8], headers: &Headers) {
+    match tls::parse_tls(file, headers) {
+        Some(tls) => {
+            println!("{}", tls);
+            for anomaly in &tls.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No TLS directory found"),
+    }
+}
+
+fn print_load_config(file: &[u8], headers: &Headers) {
+    match load_config::parse_load_config(file, headers) {
+        Some(load_config) => {
+            println!("{}", load_config);
+            for anomaly in &load_config.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No load config directory found"),
+    }
+}
+
+fn print_guard_tables(file: &[u8], headers: &Headers) {
+    let load_config = match load_config::parse_load_config(file, headers) {
+        Some(load_config) => load_config,
+        None => {
+            println!("No load config directory found, the image is not built with CFG");
+            return;
+        }
+    };
+    let tables = cfg::parse_guard_tables(file, headers, &load_config);
+    println!("{}", tables);
+    for anomaly in &tables.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_safe_seh(file: &[u8], headers: &Headers) {
+    let load_config = load_config::parse_load_config(file, headers);
+    let safe_seh = safeseh::parse_safe_seh(file, headers, load_config.as_ref());
+    println!("{}", safe_seh);
+    for anomaly in &safe_seh.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_dynamic_relocations(file: &[u8], headers: &Headers) {
+    let table = load_config::parse_load_config(file, headers).and_then(|load_config| dvrt::parse_dynamic_relocations(file, headers, &load_config));
+    match table {
+        Some(table) => {
+            println!("{}", table);
+            for anomaly in &table.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No dynamic value relocation table found"),
+    }
+}
+
+fn print_chpe(file: &[u8], headers: &Headers) {
+    let load_config = match load_config::parse_load_config(file, headers) {
+        Some(load_config) => load_config,
+        None => {
+            println!("No load config directory found");
+            return;
+        }
+    };
+    match chpe::parse_chpe(file, headers, &load_config) {
+        Some(chpe) => {
+            println!("{}", chpe);
+            for anomaly in &chpe.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No CHPE metadata found"),
+    }
+    if let Some(view) = chpe::alternate_view(file, headers, &load_config) {
+        let alternate = &view.headers;
+        println!("Alternate view ({} ARM64X relocations applied)", view.applied);
+        println!("machine: 0x{:x} -> 0x{:x}", headers.coff_headers.target_machine, alternate.coff_headers.target_machine);
+        println!("entry point: 0x{:x} -> 0x{:x}", headers.optional_headers.standard_fields.address_of_entry_point, alternate.optional_headers.standard_fields.address_of_entry_point);
+        let directories = headers.optional_headers.data_directories.directories.iter().zip(alternate.optional_headers.data_directories.directories.iter());
+        for (x, (native, other)) in directories.enumerate() {
+            if native.virtual_address != other.virtual_address || native.size != other.size {
+                println!("directory {}: 0x{:x}/0x{:x} -> 0x{:x}/0x{:x}", x, native.virtual_address, native.size, other.virtual_address, other.size);
+            }
+        }
+        for anomaly in &view.anomalies {
+            println!("Anomaly: {}", anomaly);
+        }
+    }
+}
+
+fn print_unwind(file: &[u8], headers: &Headers) {
+    let mut count = 0;
+    for function in unwind::runtime_functions(file, headers) {
+        println!("{}", function);
+        for anomaly in &function.anomalies {
+            println!("Anomaly: {}", anomaly);
+        }
+        count += 1;
+    }
+    for function in arm_unwind::arm_runtime_functions(file, headers) {
+        println!("{}", function);
+        for anomaly in &function.anomalies {
+            println!("Anomaly: {}", anomaly);
+        }
+        count += 1;
+    }
+    if count == 0 {
+        println!("No exception directory found");
+    }
+}
+
+fn print_debug(file: &[u8], headers: &Headers) {
+    match debug::parse_debug_directory(file, headers) {
+        Some(debug) => {
+            for entry in &debug.entries {
+                println!("{}", entry);
+            }
+            for anomaly in &debug.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No debug directory found"),
+    }
+}
+
+fn print_pdb_info(file: &[u8], headers: &Headers, file_name: &str) {
+    let name = Path::new(file_name).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let binary_key = codeview::binary_symbol_key(headers);
+    println!("Binary key: {}", binary_key);
+    println!("Binary path: {}/{}/{}", name, binary_key, name);
+    let debug = debug::parse_debug_directory(file, headers);
+    match debug.as_ref().and_then(|debug| debug.codeview()) {
+        Some(codeview) => {
+            println!("{}", codeview);
+            if let Some(guid) = codeview.guid_string() {
+                println!("GUID: {}", guid);
+            }
+            println!("Age: {}", codeview.age);
+            println!("PDB: {}", codeview.pdb_path);
+            if let (Some(key), Some(path)) = (codeview.pdb_symbol_key(), codeview.pdb_symbol_path()) {
+                println!("PDB key: {}", key);
+                println!("PDB path: {}", path);
+            }
+        }
+        None => println!("No CodeView record found"),
+    }
+}
+
+fn print_build_info(file: &[u8], headers: &Headers) {
+    let debug = match debug::parse_debug_directory(file, headers) {
+        Some(debug) => debug,
+        None => {
+            println!("No debug directory found");
+            return;
+        }
+    };
+    match debug.vc_feature() {
+        Some(vc_feature) => println!("{}", vc_feature),
+        None => println!("No VC_FEATURE entry"),
+    }
+    println!("ILTCG: {}", debug.has_iltcg());
+    match debug.repro() {
+        Some(repro) => println!("{}", repro),
+        None => println!("Not a reproducible build"),
+    }
+    match debug.ex_dll_characteristics() {
+        Some(characteristics) => println!("Extended DLL characteristics: {}", characteristics),
+        None => println!("No extended DLL characteristics"),
+    }
+    match debug.pogo() {
+        Some(pogo) => println!("{}", pogo),
+        None => println!("No POGO entry"),
+    }
+    for anomaly in &debug.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+}
+
+fn print_portable_pdb(file: &[u8], headers: &Headers, args: &[String]) {
+    let data = match args.first() {
+        Some(pdb_file) => pehp::read_file(pdb_file),
+        None => match debug::parse_debug_directory(file, headers).as_ref().and_then(|debug| debug.embedded_portable_pdb()) {
+            Some(embedded) => {
+                println!("{}\n", embedded);
+                embedded.data.clone()
+            }
+            None => {
+                println!("No embedded portable PDB found");
+                return;
+            }
+        },
+    };
+    match portable_pdb::parse_portable_pdb(&data) {
+        Some(pdb) => {
+            println!("{}", pdb);
+            for anomaly in &pdb.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("Not a portable PDB"),
+    }
+}
+
+fn print_pdb_checksum(file: &[u8], headers: &Headers, args: &[String]) {
+    let debug = debug::parse_debug_directory(file, headers);
+    let checksums = debug.as_ref().map(|debug| debug.pdb_checksums()).unwrap_or_default();
+    if checksums.is_empty() {
+        println!("No PDB checksum found");
+        return;
+    }
+    let pdb = args.first().map(pehp::read_file);
+    for checksum in checksums {
+        print!("{}", checksum);
+        match pdb.as_ref().map(|pdb| portable_pdb::verify_pdb_checksum(checksum, pdb)) {
+            Some(Some(true)) => println!(" matches"),
+            Some(Some(false)) => println!(" does not match"),
+            Some(None) => println!(" cannot be verified, unsupported algorithm or not a portable PDB"),
+            None => println!(),
+        }
+    }
+    if let (Some(pdb), Some(codeview)) = (pdb.as_ref().and_then(|pdb| portable_pdb::parse_portable_pdb(pdb)), debug.as_ref().and_then(|debug| debug.codeview())) {
+        if codeview.guid != Some(pdb.guid()) {
+            println!("Anomaly: PDB id does not match the CodeView GUID");
+        }
+    }
+}
+
+fn print_signed_data_anomalies(signed_data: &pkcs7::SignedData) {
+    for anomaly in &signed_data.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+    for signer in &signed_data.signers {
+        for nested in signer.timestamps.iter().chain(&signer.nested_signatures) {
+            print_signed_data_anomalies(nested);
+        }
+    }
+}
+
+fn print_certificates(file: &[u8], headers: &Headers) {
+    match certificates::parse_certificate_table(file, headers) {
+        Some(table) => {
+            for entry in &table.entries {
+                println!("{}\n", entry);
+                if let Some(signed_data) = &entry.signed_data {
+                    print_signed_data_anomalies(signed_data);
+                }
+            }
+            for anomaly in &table.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("No certificate table found"),
+    }
+}
+
+fn print_digest_checks(file: &[u8], headers: &Headers, signed_data: &pkcs7::SignedData, label: &str) {
+    match authenticode::check_digest(file, headers, signed_data) {
+        Some(check) => println!("{}: {}", label, check),
+        None => println!("{}: unsupported digest algorithm", label),
+    }
+    for nested in signed_data.signers.iter().flat_map(|signer| &signer.nested_signatures) {
+        print_digest_checks(file, headers, nested, "Nested signature");
+    }
+}
+
+fn print_authentihash(file: &[u8], headers: &Headers) {
+    for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256, HashAlgorithm::Sha384, HashAlgorithm::Sha512] {
+        println!("{:?}: {}", algorithm, pkcs7::hex(&authenticode::authentihash(file, headers, algorithm)));
+    }
+    for anomaly in &authenticode::hashed_ranges(file, headers).anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+    match certificates::parse_certificate_table(file, headers).as_ref().and_then(|table| table.signature()) {
+        Some(signed_data) => print_digest_checks(file, headers, signed_data, "Signature"),
+        None => println!("No Authenticode signature found"),
+    }
+}
+
+fn print_page_hash_checks(file: &[u8], headers: &Headers, signed_data: &pkcs7::SignedData, label: &str) {
+    match authenticode::check_page_hashes(file, headers, signed_data) {
+        Some(check) => {
+            println!("{}: {} page hashes in {:?}", label, check.pages.len(), check.algorithm.unwrap_or(HashAlgorithm::Sha256));
+            for page in &check.pages {
+                println!("{}", page);
+            }
+            let tampered = check.tampered().count();
+            if tampered == 0 {
+                println!("All pages match");
+            } else {
+                println!("{} tampered pages", tampered);
+            }
+            for anomaly in &check.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        None => println!("{}: no page hashes", label),
+    }
+    for nested in signed_data.signers.iter().flat_map(|signer| &signer.nested_signatures) {
+        print_page_hash_checks(file, headers, nested, "Nested signature");
+    }
+}
+
+fn print_page_hashes(file: &[u8], headers: &Headers) {
+    match certificates::parse_certificate_table(file, headers).as_ref().and_then(|table| table.signature()) {
+        Some(signed_data) => print_page_hash_checks(file, headers, signed_data, "Signature"),
+        None => println!("No Authenticode signature found"),
+    }
+}
+
+fn print_verify(file: &[u8], headers: &Headers, args: &[String]) {
+    let directory = match args.first() {
+        Some(directory) => directory,
+        None => {
+            println!("Please provide the trust store directory");
+            process::exit(0x1);
+        }
+    };
+    let store = verify::TrustStore::load(Path::new(directory));
+    for anomaly in &store.anomalies {
+        println!("Anomaly: {}", anomaly);
+    }
+    // Unix time to verify at, the current time by default
+    let time = match args.get(1) {
+        Some(time) => time.parse().unwrap_or_else(|_| {
+            println!("Invalid time: {}", time);
+            process::exit(0x1);
+        }),
+        None => SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs() as i64).unwrap_or(0),
+    };
+    println!("{}", verify::verify(file, headers, &store, time));
+}
+
+fn sign_file(file: &[u8], headers: &Headers, args: &[String]) {
+    if args.len() < 3 {
+        println!("Please provide the private key, the certificates and the output filename");
+        process::exit(0x1);
+    }
+    let key = pehp::read_file(&args[0]);
+    let certificates = pehp::read_file(&args[1]);
+    let timestamp = args.get(3).map(pehp::read_file);
+    match sign::sign(file, headers, &key, &certificates, HashAlgorithm::Sha256, timestamp.as_deref()) {
+        Ok(signed) => {
+            fs::write(&args[2], &signed).expect("Failed to write signed file");
+            println!("Signed image written to {}", args[2]);
+            // The request for a TSA, the token it returns is given back as the last argument
+            let signature = certificates::parse_certificate_table(&signed, &pehp::parse_headers(&signed))
+                .and_then(|table| table.signature().and_then(|signed_data| signed_data.signers.first()).map(|signer| signer.signature.clone()));
+            if let (None, Some(signature)) = (&timestamp, signature) {
+                let request = format!("{}.tsq", args[2]);
+                fs::write(&request, sign::timestamp_request(&signature, HashAlgorithm::Sha256)).expect("Failed to write timestamp request");
+                println!("Timestamp request written to {}", request);
+            }
+        }
+        Err(error) => {
+            println!("Signing failed: {}", error);
+            process::exit(0x1);
+        }
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -8,6 +551,42 @@ fn main() {
         println!("Please provide the filename of the PE to parse");
         process::exit(0x0);
     }
-    let headers = pehp::parse_pe_headers(&args[1]);
-    println!("{:?}", headers.coff_headers.characteristics.characteristics_list);
-}
\ No newline at end of file
+    let file = pehp::read_file(&args[1]);
+    let headers = pehp::parse_headers(&file);
+    match args.get(2).map(|command| command.as_str()) {
+        None => {
+            println!("{:?}", headers.coff_headers.characteristics.characteristics_list);
+            for anomaly in &headers.anomalies {
+                println!("Anomaly: {}", anomaly);
+            }
+        }
+        Some("relocs") => print_relocations(&file, &headers),
+        Some("resources") => print_resources(&file, &headers, &args[3..]),
+        Some("version") => print_version(&file, &headers),
+        Some("manifest") => print_manifest(&file, &headers),
+        Some("icon") => export_icon(&file, &headers, &args[3..]),
+        Some("strings-table") => print_string_tables(&file, &headers),
+        Some("templates") => print_templates(&file, &headers, &args[3..]),
+        Some("tls") => print_tls(&file, &headers),
+        Some("load-config") => print_load_config(&file, &headers),
+        Some("cfg") => print_guard_tables(&file, &headers),
+        Some("safeseh") => print_safe_seh(&file, &headers),
+        Some("dvrt") => print_dynamic_relocations(&file, &headers),
+        Some("chpe") => print_chpe(&file, &headers),
+        Some("unwind") => print_unwind(&file, &headers),
+        Some("debug") => print_debug(&file, &headers),
+        Some("pdb-info") => print_pdb_info(&file, &headers, &args[1]),
+        Some("build-info") => print_build_info(&file, &headers),
+        Some("portable-pdb") => print_portable_pdb(&file, &headers, &args[3..]),
+        Some("pdb-checksum") => print_pdb_checksum(&file, &headers, &args[3..]),
+        Some("certificates") => print_certificates(&file, &headers),
+        Some("authentihash") => print_authentihash(&file, &headers),
+        Some("verify") => print_verify(&file, &headers, &args[3..]),
+        Some("page-hashes") => print_page_hashes(&file, &headers),
+        Some("sign") => sign_file(&file, &headers, &args[3..]),
+        Some(command) => {
+            println!("Unknown command: {}", command);
+            process::exit(0x1);
+        }
+    }
+}
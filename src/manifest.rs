@@ -0,0 +1,397 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::resources::Resources;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+
+// Structures definitions
+
+#[derive(Default)]
+pub struct AssemblyIdentity {
+    pub assembly_type: Option<String>,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub processor_architecture: Option<String>,
+    pub public_key_token: Option<String>,
+    pub language: Option<String>,
+}
+
+pub struct SupportedOS {
+    pub id: String,
+    pub windows_version: &'static str,
+}
+
+#[derive(Default)]
+pub struct Manifest {
+    pub xml: String,
+    pub identity: Option<AssemblyIdentity>,
+    pub requested_execution_level: Option<String>,
+    pub ui_access: Option<bool>,
+    pub dependencies: Vec<AssemblyIdentity>,
+    pub supported_os: Vec<SupportedOS>,
+    pub dpi_aware: Option<String>,
+    pub dpi_awareness: Option<String>,
+    pub long_path_aware: Option<bool>,
+    pub active_code_page: Option<String>,
+    pub heap_type: Option<String>,
+    pub anomalies: Vec<String>,
+}
+
+enum XmlEvent {
+    Start(String, Vec<(String, String)>),
+    End(String),
+    Text(String),
+}
+
+
+// Conversion implementation for the structs
+
+impl SupportedOS {
+    pub fn from_id(id: &str) -> SupportedOS {
+        let windows_version = match id.trim_matches(|c| c == '{' || c == '}').to_ascii_lowercase().as_str() {
+            "e2011457-1546-43c5-a5fe-008deee3d3f0" => "Windows Vista",
+            "35138b9a-5d96-4fbd-8e2d-a2440225f93a" => "Windows 7",
+            "4a2f28e3-53b9-4441-ba9c-d69d4a4a6e38" => "Windows 8",
+            "1f676c76-80e1-4239-95bb-83d0f6d0da78" => "Windows 8.1",
+            "8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a" => "Windows 10 / Windows 11",
+            _ => "Unknown",
+        };
+        SupportedOS { id: id.to_string(), windows_version }
+    }
+}
+
+impl AssemblyIdentity {
+    fn from_attributes(attributes: &[(String, String)]) -> AssemblyIdentity {
+        let get = |key: &str| attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        AssemblyIdentity {
+            assembly_type: get("type"),
+            name: get("name"),
+            version: get("version"),
+            processor_architecture: get("processorArchitecture"),
+            public_key_token: get("publicKeyToken"),
+            language: get("language"),
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+
+// Parsing
+
+// Honours UTF-8 and UTF-16 byte order marks, falling back to UTF-8
+pub fn decode_xml(data: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], little_endian: bool| {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| {
+            if little_endian { u16::from_le_bytes([unit[0], unit[1]]) } else { u16::from_be_bytes([unit[0], unit[1]]) }
+        }).collect();
+        String::from_utf16_lossy(&units)
+    };
+    let text = match data {
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).to_string(),
+        [0xff, 0xfe, rest @ ..] => utf16(rest, true),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, false),
+        // UTF-16 without a BOM still starts with '<'
+        [b'<', 0, ..] => utf16(data, true),
+        [0, b'<', ..] => utf16(data, false),
+        _ => String::from_utf8_lossy(data).to_string(),
+    };
+    text.trim_end_matches('\0').to_string()
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+// Drops the namespace prefix, manifests mix asmv1:, asmv3: and default namespaces freely
+fn local_name(name: &str) -> String {
+    name.rsplit(':').next().unwrap_or(name).to_string()
+}
+
+fn parse_attributes(tag: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = tag;
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim();
+        let after = rest[equals + 1..].trim_start();
+        let quote = match after.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => break,
+        };
+        let value_end = match after[1..].find(quote) {
+            Some(end) => end + 1,
+            None => break,
+        };
+        attributes.push((local_name(key), unescape(&after[1..value_end])));
+        rest = &after[value_end + 1..];
+    }
+    attributes
+}
+
+fn tokenize(xml: &str, anomalies: &mut Vec<String>) -> Vec<XmlEvent> {
+    let mut events = Vec::new();
+    let mut rest = xml;
+    while !rest.is_empty() {
+        let start = match rest.find('<') {
+            Some(start) => start,
+            None => {
+                events.push(XmlEvent::Text(unescape(rest)));
+                break;
+            }
+        };
+        if start > 0 {
+            events.push(XmlEvent::Text(unescape(&rest[..start])));
+        }
+        rest = &rest[start..];
+        let terminator = if rest.starts_with("<!--") {
+            "-->"
+        } else if rest.starts_with("<![CDATA[") {
+            "]]>"
+        } else if rest.starts_with("<?") {
+            "?>"
+        } else {
+            ">"
+        };
+        let end = match rest.find(terminator) {
+            Some(end) => end,
+            None => {
+                anomalies.push(String::from("Manifest XML is truncated"));
+                break;
+            }
+        };
+        let tag = &rest[1..end];
+        if let Some(cdata) = tag.strip_prefix("![CDATA[") {
+            events.push(XmlEvent::Text(cdata.to_string()));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            events.push(XmlEvent::End(local_name(name.trim())));
+        } else if !tag.starts_with('!') && !tag.starts_with('?') {
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+            let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+            let name = local_name(&tag[..name_end]);
+            events.push(XmlEvent::Start(name.clone(), parse_attributes(&tag[name_end..])));
+            if self_closing {
+                events.push(XmlEvent::End(name));
+            }
+        }
+        rest = &rest[end + terminator.len()..];
+    }
+    events
+}
+
+pub fn parse_manifest_xml(data: &[u8]) -> Manifest {
+    let mut manifest = Manifest {
+        xml: decode_xml(data),
+        ..Default::default()
+    };
+    let events = tokenize(&manifest.xml, &mut manifest.anomalies);
+    let mut stack: Vec<String> = Vec::new();
+    for event in events {
+        match event {
+            XmlEvent::Start(name, attributes) => {
+                let get = |key: &str| attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+                let parent = stack.last().map(|parent| parent.as_str());
+                match (name.as_str(), parent) {
+                    ("assemblyIdentity", Some("dependentAssembly")) => manifest.dependencies.push(AssemblyIdentity::from_attributes(&attributes)),
+                    ("assemblyIdentity", Some("assembly")) => manifest.identity = Some(AssemblyIdentity::from_attributes(&attributes)),
+                    ("requestedExecutionLevel", _) => {
+                        manifest.requested_execution_level = get("level");
+                        manifest.ui_access = get("uiAccess").and_then(|value| parse_bool(&value));
+                    }
+                    ("supportedOS", _) => {
+                        if let Some(id) = get("Id") {
+                            manifest.supported_os.push(SupportedOS::from_id(&id));
+                        }
+                    }
+                    _ => {}
+                }
+                stack.push(name);
+            }
+            XmlEvent::End(name) => {
+                match stack.iter().rposition(|open| *open == name) {
+                    Some(position) => stack.truncate(position),
+                    None => manifest.anomalies.push(format!("Closing tag </{}> without a matching opening tag", name)),
+                }
+            }
+            XmlEvent::Text(text) => {
+                let text = text.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                match stack.last().map(|name| name.as_str()) {
+                    Some("dpiAware") => manifest.dpi_aware = Some(text.to_string()),
+                    Some("dpiAwareness") => manifest.dpi_awareness = Some(text.to_string()),
+                    Some("longPathAware") => manifest.long_path_aware = parse_bool(text),
+                    Some("activeCodePage") => manifest.active_code_page = Some(text.to_string()),
+                    Some("heapType") => manifest.heap_type = Some(text.to_string()),
+                    _ => {}
+                }
+            }
+        }
+    }
+    if !stack.is_empty() {
+        manifest.anomalies.push(format!("Unclosed elements at the end of the manifest: {}", stack.join(", ")));
+    }
+    manifest
+}
+
+// Decodes the first RT_MANIFEST resource, None if the image has none
+pub fn parse_manifest(file: &[u8], headers: &Headers, resources: &Resources) -> Option<Manifest> {
+    let resource = resources.resources.iter().find(|resource| resource.resource_type == ResourceType::RT_MANIFEST)?;
+    match resource_data(file, headers, resource) {
+        Some(data) => Some(parse_manifest_xml(data)),
+        None => {
+            let mut manifest = Manifest::default();
+            manifest.anomalies.push(format!("Manifest resource at RVA 0x{:x} is not backed by the file", resource.data.data_rva));
+            Some(manifest)
+        }
+    }
+}
+
+
+// Display trait implementation for the structs
+
+fn or_none<T: fmt::Display>(value: &Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("-"),
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Manifest
+
+---------------------------")?;
+        if let Some(identity) = &self.identity {
+            write!(f, "
+identity: {}", identity)?;
+        }
+        write!(f, "
+requested_execution_level: {}
+ui_access: {}
+dpi_aware: {}
+dpi_awareness: {}
+long_path_aware: {}
+active_code_page: {}
+heap_type: {}",
+        or_none(&self.requested_execution_level), or_none(&self.ui_access), or_none(&self.dpi_aware), or_none(&self.dpi_awareness), or_none(&self.long_path_aware), or_none(&self.active_code_page), or_none(&self.heap_type))?;
+        for dependency in &self.dependencies {
+            write!(f, "
+dependency: {}", dependency)?;
+        }
+        for supported_os in &self.supported_os {
+            write!(f, "
+supported_os: {} ({})", supported_os.id, supported_os.windows_version)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for AssemblyIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "name={} version={} type={} processorArchitecture={} publicKeyToken={} language={}",
+        or_none(&self.name), or_none(&self.version), or_none(&self.assembly_type), or_none(&self.processor_architecture), or_none(&self.public_key_token), or_none(&self.language))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::ResourceId;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+    use crate::utils::fixtures::utf16;
+
+    const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0" xmlns:asmv3="urn:schemas-microsoft-com:asm.v3">
+  <assemblyIdentity type="win32" name="Acme.Tool" version="1.2.3.4" processorArchitecture="amd64"/>
+  <!-- <requestedExecutionLevel level="ignored"/> -->
+  <dependency>
+    <dependentAssembly>
+      <assemblyIdentity type="win32" name="Microsoft.Windows.Common-Controls" version="6.0.0.0" publicKeyToken="6595b64144ccf1df" language="*"/>
+    </dependentAssembly>
+  </dependency>
+  <trustInfo xmlns="urn:schemas-microsoft-com:asm.v3">
+    <security><requestedPrivileges><requestedExecutionLevel level="asInvoker" uiAccess='false'/></requestedPrivileges></security>
+  </trustInfo>
+  <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
+    <application>
+      <supportedOS Id="{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}"/>
+      <supportedOS Id="{35138B9A-5D96-4FBD-8E2D-A2440225F93A}"/>
+    </application>
+  </compatibility>
+  <asmv3:application>
+    <asmv3:windowsSettings>
+      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">true/pm</dpiAware>
+      <longPathAware xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings"> TRUE </longPathAware>
+      <activeCodePage xmlns="http://schemas.microsoft.com/SMI/2019/WindowsSettings"><![CDATA[UTF-8]]></activeCodePage>
+    </asmv3:windowsSettings>
+  </asmv3:application>
+</assembly>
+"#;
+
+    #[test]
+    fn parses_manifest_resource() {
+        let (file, headers) = resource_image(&[(ResourceId::Id(24), ResourceId::Id(1), 0x409, MANIFEST.as_bytes())]);
+        let resources = parse_resources(&file, &headers);
+        let manifest = parse_manifest(&file, &headers, &resources).unwrap();
+        assert!(manifest.anomalies.is_empty(), "{:?}", manifest.anomalies);
+        let identity = manifest.identity.as_ref().unwrap();
+        assert_eq!(identity.name.as_deref(), Some("Acme.Tool"));
+        assert_eq!(identity.processor_architecture.as_deref(), Some("amd64"));
+        assert_eq!(manifest.dependencies.len(), 1);
+        assert_eq!(manifest.dependencies[0].public_key_token.as_deref(), Some("6595b64144ccf1df"));
+        assert_eq!(manifest.requested_execution_level.as_deref(), Some("asInvoker"));
+        assert_eq!(manifest.ui_access, Some(false));
+        let versions: Vec<&str> = manifest.supported_os.iter().map(|os| os.windows_version).collect();
+        assert_eq!(versions, vec!["Windows 10 / Windows 11", "Windows 7"]);
+        assert_eq!(manifest.dpi_aware.as_deref(), Some("true/pm"));
+        assert_eq!(manifest.long_path_aware, Some(true));
+        assert_eq!(manifest.active_code_page.as_deref(), Some("UTF-8"));
+        assert!(manifest.heap_type.is_none());
+    }
+
+    #[test]
+    fn decodes_encodings() {
+        let xml = "<a>&lt;é&gt;</a>";
+        let mut little_endian = vec![0xff, 0xfe];
+        little_endian.extend_from_slice(&utf16(xml));
+        let big_endian: Vec<u8> = xml.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect();
+        let mut utf8 = vec![0xef, 0xbb, 0xbf];
+        utf8.extend_from_slice(xml.as_bytes());
+        utf8.extend_from_slice(&[0, 0]);
+        for data in [little_endian, big_endian, utf16(xml), utf8] {
+            assert_eq!(decode_xml(&data), xml);
+        }
+    }
+
+    #[test]
+    fn malformed_manifests() {
+        let manifest = parse_manifest_xml(b"<assembly><dependency></assembly></trustInfo><heapType>SegmentHeap</heapType><security");
+        assert_eq!(manifest.heap_type.as_deref(), Some("SegmentHeap"));
+        assert!(manifest.identity.is_none());
+        assert!(manifest.dependencies.is_empty());
+        assert_eq!(manifest.requested_execution_level, None);
+        assert_eq!(manifest.anomalies, vec![
+            String::from("Manifest XML is truncated"),
+            String::from("Closing tag </trustInfo> without a matching opening tag"),
+        ]);
+        let manifest = parse_manifest_xml(b"<assembly><assemblyIdentity name=\"app\" version=\"1.0.0.0\"/><trustInfo>");
+        let identity = manifest.identity.as_ref().unwrap();
+        assert_eq!((identity.name.as_deref(), identity.version.as_deref()), (Some("app"), Some("1.0.0.0")));
+        assert_eq!(manifest.requested_execution_level, None);
+        assert_eq!(manifest.anomalies, vec![String::from("Unclosed elements at the end of the manifest: assembly, trustInfo")]);
+    }
+}
@@ -0,0 +1,1112 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::der;
+use crate::der::Element;
+use crate::der::format_time;
+use crate::sha::HashAlgorithm;
+use crate::sha::digest;
+
+pub const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+pub const OID_SIGNED_DATA: &str = "1.2.840.113549.1.7.2";
+pub const OID_CONTENT_TYPE: &str = "1.2.840.113549.1.9.3";
+pub const OID_MESSAGE_DIGEST: &str = "1.2.840.113549.1.9.4";
+pub const OID_SIGNING_TIME: &str = "1.2.840.113549.1.9.5";
+pub const OID_COUNTERSIGNATURE: &str = "1.2.840.113549.1.9.6";
+pub const OID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+pub const OID_SPC_INDIRECT_DATA: &str = "1.3.6.1.4.1.311.2.1.4";
+pub const OID_SPC_STATEMENT_TYPE: &str = "1.3.6.1.4.1.311.2.1.11";
+pub const OID_SPC_SP_OPUS_INFO: &str = "1.3.6.1.4.1.311.2.1.12";
+pub const OID_SPC_PE_IMAGE_DATA: &str = "1.3.6.1.4.1.311.2.1.15";
+pub const OID_SPC_PE_IMAGE_PAGE_HASHES_V1: &str = "1.3.6.1.4.1.311.2.3.1";
+pub const OID_SPC_PE_IMAGE_PAGE_HASHES_V2: &str = "1.3.6.1.4.1.311.2.3.2";
+pub const OID_NESTED_SIGNATURE: &str = "1.3.6.1.4.1.311.2.4.1";
+pub const OID_RFC3161_TIMESTAMP: &str = "1.3.6.1.4.1.311.3.3.1";
+pub const OID_BASIC_CONSTRAINTS: &str = "2.5.29.19";
+pub const OID_KEY_USAGE: &str = "2.5.29.15";
+pub const OID_EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+pub const OID_ANY_EXTENDED_KEY_USAGE: &str = "2.5.29.37.0";
+pub const OID_CODE_SIGNING: &str = "1.3.6.1.5.5.7.3.3";
+pub const OID_TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+// classId of the SpcSerializedObject holding the page hashes
+const SPC_PAGE_HASHES_CLASS_ID: [u8; 16] = [0xa6, 0xb5, 0x86, 0xd5, 0xb4, 0xa1, 0x24, 0x66, 0xae, 0x05, 0xa2, 0x17, 0xda, 0x8e, 0x60, 0xd6];
+// Countersignatures, timestamp tokens and nested signatures within a signature, Windows nests one or two levels
+const MAX_NESTING_DEPTH: usize = 4;
+// Key usage bits, bit 0 is the most significant bit of the BIT STRING
+pub const KEY_USAGE_DIGITAL_SIGNATURE: u16 = 1 << 0;
+pub const KEY_USAGE_KEY_CERT_SIGN: u16 = 1 << 5;
+pub const KEY_USAGE_CRL_SIGN: u16 = 1 << 6;
+
+// Structures definitions
+
+pub struct AlgorithmIdentifier {
+    pub oid: String,
+    pub parameters: Option<Vec<u8>>,
+}
+
+// Distinguished name, the raw encoding is kept for issuer and subject matching
+pub struct Name {
+    pub raw: Vec<u8>,
+    pub attributes: Vec<(String, String)>,
+}
+
+pub struct Extension {
+    pub oid: String,
+    pub critical: bool,
+    pub value: Vec<u8>,
+}
+
+pub struct Certificate {
+    pub raw: Vec<u8>,
+    pub tbs: Vec<u8>,
+    pub version: u64,
+    pub serial: Vec<u8>,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub issuer: Name,
+    pub subject: Name,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub public_key_algorithm: AlgorithmIdentifier,
+    pub public_key: Vec<u8>,
+    pub extensions: Vec<Extension>,
+    pub signature: Vec<u8>,
+}
+
+pub struct RevokedCertificate {
+    pub serial: Vec<u8>,
+    pub revocation_date: i64,
+}
+
+// X.509 CertificateList
+pub struct Crl {
+    pub raw: Vec<u8>,
+    pub tbs: Vec<u8>,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub issuer: Name,
+    pub this_update: i64,
+    pub next_update: Option<i64>,
+    pub revoked: Vec<RevokedCertificate>,
+    pub signature: Vec<u8>,
+}
+
+// SpcIndirectDataContent, the digest of the image the signature covers
+pub struct SpcIndirectData {
+    pub data_type: String,
+    pub data_value: Vec<u8>,
+    pub digest_algorithm: AlgorithmIdentifier,
+    pub digest: Vec<u8>,
+    pub page_hashes: Option<PageHashes>,
+}
+
+pub struct PageHash {
+    // File offset of the page, the last entry has the end of the image and an empty hash
+    pub offset: u32,
+    pub hash: Vec<u8>,
+}
+
+// SpcPeImagePageHashes, V1 is SHA-1 and V2 SHA-256
+pub struct PageHashes {
+    pub algorithm: HashAlgorithm,
+    pub pages: Vec<PageHash>,
+}
+
+// RFC 3161 TSTInfo
+pub struct TstInfo {
+    pub version: u64,
+    pub policy: String,
+    pub hash_algorithm: AlgorithmIdentifier,
+    pub message_imprint: Vec<u8>,
+    pub serial: Vec<u8>,
+    pub gen_time: i64,
+}
+
+pub struct Attribute {
+    pub oid: String,
+    pub values: Vec<Vec<u8>>,
+}
+
+pub struct SignerInfo {
+    pub version: u64,
+    pub issuer: Option<Name>,
+    pub serial: Vec<u8>,
+    pub subject_key_identifier: Option<Vec<u8>>,
+    pub digest_algorithm: AlgorithmIdentifier,
+    // The [0] IMPLICIT element as stored, it is hashed with a SET tag
+    pub authenticated_attributes_raw: Option<Vec<u8>>,
+    pub authenticated_attributes: Vec<Attribute>,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub signature: Vec<u8>,
+    pub unauthenticated_attributes: Vec<Attribute>,
+    pub content_type: Option<String>,
+    pub message_digest: Option<Vec<u8>>,
+    pub signing_time: Option<i64>,
+    pub program_name: Option<String>,
+    pub more_info_url: Option<String>,
+    pub countersignatures: Vec<SignerInfo>,
+    pub timestamps: Vec<SignedData>,
+    pub nested_signatures: Vec<SignedData>,
+}
+
+#[derive(Default)]
+pub struct SignedData {
+    pub raw: Vec<u8>,
+    pub version: u64,
+    pub digest_algorithms: Vec<AlgorithmIdentifier>,
+    pub content_type: String,
+    // Bytes covered by the messageDigest attribute
+    pub content: Vec<u8>,
+    pub indirect_data: Option<SpcIndirectData>,
+    pub tst_info: Option<TstInfo>,
+    pub certificates: Vec<Certificate>,
+    pub crls: Vec<Vec<u8>>,
+    pub signers: Vec<SignerInfo>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+pub fn oid_name(oid: &str) -> &str {
+    match oid {
+        "1.3.14.3.2.26" => "sha1",
+        "2.16.840.1.101.3.4.2.1" => "sha256",
+        "2.16.840.1.101.3.4.2.2" => "sha384",
+        "2.16.840.1.101.3.4.2.3" => "sha512",
+        "1.2.840.113549.2.5" => "md5",
+        "1.2.840.113549.1.1.1" => "rsaEncryption",
+        "1.2.840.113549.1.1.4" => "md5WithRSAEncryption",
+        "1.2.840.113549.1.1.5" => "sha1WithRSAEncryption",
+        "1.2.840.113549.1.1.10" => "rsassaPss",
+        "1.2.840.113549.1.1.11" => "sha256WithRSAEncryption",
+        "1.2.840.113549.1.1.12" => "sha384WithRSAEncryption",
+        "1.2.840.113549.1.1.13" => "sha512WithRSAEncryption",
+        "1.2.840.10045.2.1" => "ecPublicKey",
+        "1.2.840.10045.4.1" => "ecdsa-with-SHA1",
+        "1.2.840.10045.4.3.2" => "ecdsa-with-SHA256",
+        "1.2.840.10045.4.3.3" => "ecdsa-with-SHA384",
+        "1.2.840.10045.4.3.4" => "ecdsa-with-SHA512",
+        "1.2.840.113549.1.7.1" => "data",
+        OID_SIGNED_DATA => "signedData",
+        OID_CONTENT_TYPE => "contentType",
+        OID_MESSAGE_DIGEST => "messageDigest",
+        OID_SIGNING_TIME => "signingTime",
+        OID_COUNTERSIGNATURE => "countersignature",
+        OID_TST_INFO => "tstInfo",
+        OID_SPC_INDIRECT_DATA => "SPC_INDIRECT_DATA",
+        OID_SPC_STATEMENT_TYPE => "SPC_STATEMENT_TYPE",
+        OID_SPC_SP_OPUS_INFO => "SPC_SP_OPUS_INFO",
+        OID_SPC_PE_IMAGE_DATA => "SPC_PE_IMAGE_DATA",
+        OID_NESTED_SIGNATURE => "SPC_NESTED_SIGNATURE",
+        OID_RFC3161_TIMESTAMP => "SPC_RFC3161_TIMESTAMP",
+        "1.3.6.1.4.1.311.2.1.21" => "SPC_INDIVIDUAL_SP_KEY_PURPOSE",
+        "1.3.6.1.4.1.311.2.1.22" => "SPC_COMMERCIAL_SP_KEY_PURPOSE",
+        "1.3.6.1.4.1.311.10.3.3" => "SPC_ENCRYPTED_DIGEST_RETRY_COUNT",
+        OID_SPC_PE_IMAGE_PAGE_HASHES_V1 => "SPC_PE_IMAGE_PAGE_HASHES_V1",
+        OID_SPC_PE_IMAGE_PAGE_HASHES_V2 => "SPC_PE_IMAGE_PAGE_HASHES_V2",
+        OID_CODE_SIGNING => "codeSigning",
+        OID_TIME_STAMPING => "timeStamping",
+        OID_ANY_EXTENDED_KEY_USAGE => "anyExtendedKeyUsage",
+        "2.5.29.14" => "subjectKeyIdentifier",
+        OID_KEY_USAGE => "keyUsage",
+        "2.5.29.17" => "subjectAltName",
+        OID_BASIC_CONSTRAINTS => "basicConstraints",
+        "2.5.29.31" => "cRLDistributionPoints",
+        "2.5.29.32" => "certificatePolicies",
+        "2.5.29.35" => "authorityKeyIdentifier",
+        OID_EXTENDED_KEY_USAGE => "extKeyUsage",
+        "1.3.6.1.5.5.7.1.1" => "authorityInfoAccess",
+        _ => oid,
+    }
+}
+
+fn attribute_name(oid: &str) -> &str {
+    match oid {
+        "2.5.4.3" => "CN",
+        "2.5.4.5" => "SERIALNUMBER",
+        "2.5.4.6" => "C",
+        "2.5.4.7" => "L",
+        "2.5.4.8" => "ST",
+        "2.5.4.9" => "STREET",
+        "2.5.4.10" => "O",
+        "2.5.4.11" => "OU",
+        "2.5.4.15" => "businessCategory",
+        "1.2.840.113549.1.9.1" => "E",
+        "0.9.2342.19200300.100.1.25" => "DC",
+        "1.3.6.1.4.1.311.60.2.1.2" => "jurisdictionST",
+        "1.3.6.1.4.1.311.60.2.1.3" => "jurisdictionC",
+        _ => oid,
+    }
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl AlgorithmIdentifier {
+    pub fn name(&self) -> &str {
+        oid_name(&self.oid)
+    }
+
+    // Digest used by a digest or signature algorithm
+    pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+        match self.oid.as_str() {
+            "1.3.14.3.2.26" | "1.2.840.113549.1.1.5" | "1.2.840.10045.4.1" | "1.3.14.3.2.29" => Some(HashAlgorithm::Sha1),
+            "2.16.840.1.101.3.4.2.1" | "1.2.840.113549.1.1.11" | "1.2.840.10045.4.3.2" => Some(HashAlgorithm::Sha256),
+            "2.16.840.1.101.3.4.2.2" | "1.2.840.113549.1.1.12" | "1.2.840.10045.4.3.3" => Some(HashAlgorithm::Sha384),
+            "2.16.840.1.101.3.4.2.3" | "1.2.840.113549.1.1.13" | "1.2.840.10045.4.3.4" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+impl Name {
+    pub fn get(&self, attribute: &str) -> Option<&str> {
+        self.attributes.iter().find(|(oid, _)| attribute_name(oid) == attribute).map(|(_, value)| value.as_str())
+    }
+
+    pub fn common_name(&self) -> Option<&str> {
+        self.get("CN")
+    }
+}
+
+impl Certificate {
+    pub fn serial_string(&self) -> String {
+        hex(&self.serial)
+    }
+
+    pub fn sha1_thumbprint(&self) -> String {
+        hex(&digest(HashAlgorithm::Sha1, &self.raw))
+    }
+
+    pub fn sha256_thumbprint(&self) -> String {
+        hex(&digest(HashAlgorithm::Sha256, &self.raw))
+    }
+
+    pub fn extension(&self, oid: &str) -> Option<&Extension> {
+        self.extensions.iter().find(|extension| extension.oid == oid)
+    }
+
+    pub fn is_self_issued(&self) -> bool {
+        self.issuer.raw == self.subject.raw
+    }
+
+    // cA flag and path length constraint
+    pub fn basic_constraints(&self) -> Option<(bool, Option<u64>)> {
+        let constraints = der::parse(&self.extension(OID_BASIC_CONSTRAINTS)?.value)?;
+        let mut fields = constraints.children().peekable();
+        let ca = match fields.peek().and_then(|field| field.as_bool()) {
+            Some(ca) => {
+                fields.next();
+                ca
+            }
+            None => false,
+        };
+        Some((ca, fields.next().and_then(|length| length.as_u64())))
+    }
+
+    pub fn key_usage(&self) -> Option<u16> {
+        let usage = der::parse(&self.extension(OID_KEY_USAGE)?.value)?;
+        let bits = usage.as_bit_string()?;
+        let first = bits.first().copied().unwrap_or(0).reverse_bits() as u16;
+        let second = bits.get(1).copied().unwrap_or(0).reverse_bits() as u16;
+        Some(first | second << 8)
+    }
+
+    pub fn extended_key_usage(&self) -> Option<Vec<String>> {
+        let usage = der::parse(&self.extension(OID_EXTENDED_KEY_USAGE)?.value)?;
+        Some(usage.children().filter_map(|purpose| purpose.as_oid()).collect())
+    }
+}
+
+impl SignerInfo {
+    pub fn authenticated_attribute(&self, oid: &str) -> Option<&Attribute> {
+        self.authenticated_attributes.iter().find(|attribute| attribute.oid == oid)
+    }
+}
+
+impl SignedData {
+    // The signer certificate, matched on issuer and serial or on the subject key identifier
+    pub fn signer_certificate(&self, signer: &SignerInfo) -> Option<&Certificate> {
+        find_certificate(self.certificates.iter(), signer)
+    }
+
+    // Time asserted by the timestamp of the signer, RFC 3161 tokens first then legacy countersignatures
+    pub fn timestamp(&self) -> Option<i64> {
+        let signer = self.signers.first()?;
+        signer.timestamps.iter().find_map(|token| token.tst_info.as_ref().map(|info| info.gen_time))
+            .or_else(|| signer.countersignatures.iter().find_map(|countersignature| countersignature.signing_time))
+    }
+}
+
+// Certificate identified by the issuer and serial number or the subject key identifier of a signer
+pub fn find_certificate<'a>(mut certificates: impl Iterator<Item = &'a Certificate>, signer: &SignerInfo) -> Option<&'a Certificate> {
+    certificates.find(|certificate| match (&signer.issuer, &signer.subject_key_identifier) {
+        (Some(issuer), _) => certificate.issuer.raw == issuer.raw && certificate.serial == signer.serial,
+        (None, Some(identifier)) => subject_key_identifier(certificate).as_deref() == Some(identifier.as_slice()),
+        (None, None) => false,
+    })
+}
+
+pub fn subject_key_identifier(certificate: &Certificate) -> Option<Vec<u8>> {
+    let extension = certificate.extension("2.5.29.14")?;
+    let element = der::parse(&extension.value)?;
+    (element.tag == der::TAG_OCTET_STRING).then(|| element.content.to_vec())
+}
+
+
+// Parsing
+
+fn parse_algorithm(element: &Element) -> Option<AlgorithmIdentifier> {
+    Some(AlgorithmIdentifier {
+        oid: element.child(0)?.as_oid()?,
+        parameters: element.child(1).filter(|parameters| parameters.tag != der::TAG_NULL).map(|parameters| parameters.raw.to_vec()),
+    })
+}
+
+fn parse_name(element: &Element) -> Option<Name> {
+    let mut attributes = Vec::new();
+    for set in element.children() {
+        for attribute in set.children() {
+            let oid = attribute.child(0)?.as_oid()?;
+            let value = attribute.child(1)?;
+            attributes.push((oid, value.as_string().unwrap_or_else(|| hex(value.content))));
+        }
+    }
+    Some(Name { raw: element.raw.to_vec(), attributes })
+}
+
+pub fn parse_certificate(data: &[u8]) -> Option<Certificate> {
+    let certificate = der::parse(data)?;
+    let tbs = certificate.child(0)?;
+    let mut fields = tbs.children().peekable();
+    let version = match fields.peek() {
+        Some(field) if field.is_context(0) => fields.next()?.child(0)?.as_u64()?,
+        _ => 0,
+    };
+    let serial = fields.next()?.as_unsigned_bytes()?.to_vec();
+    let signature_algorithm = parse_algorithm(&fields.next()?)?;
+    let issuer = parse_name(&fields.next()?)?;
+    let validity = fields.next()?;
+    let subject = parse_name(&fields.next()?)?;
+    let public_key_info = fields.next()?;
+    // The outer algorithm has to repeat the signed one, the signature is checked with the latter
+    if certificate.child(1).and_then(|algorithm| parse_algorithm(&algorithm))?.oid != signature_algorithm.oid {
+        return None;
+    }
+    let mut extensions = Vec::new();
+    for field in fields {
+        if !field.is_context(3) {
+            continue;
+        }
+        for extension in field.child(0)?.children() {
+            let mut parts = extension.children();
+            let oid = parts.next()?.as_oid()?;
+            let mut value = parts.next()?;
+            let critical = match value.as_bool() {
+                Some(critical) => {
+                    value = parts.next()?;
+                    critical
+                }
+                None => false,
+            };
+            extensions.push(Extension { oid, critical, value: value.content.to_vec() });
+        }
+    }
+    Some(Certificate {
+        raw: certificate.raw.to_vec(),
+        tbs: tbs.raw.to_vec(),
+        version,
+        serial,
+        signature_algorithm,
+        issuer,
+        subject,
+        not_before: validity.child(0)?.as_time()?,
+        not_after: validity.child(1)?.as_time()?,
+        public_key_algorithm: parse_algorithm(&public_key_info.child(0)?)?,
+        public_key: public_key_info.child(1)?.as_bit_string()?.to_vec(),
+        extensions,
+        signature: certificate.child(2)?.as_bit_string()?.to_vec(),
+    })
+}
+
+pub fn parse_crl(data: &[u8]) -> Option<Crl> {
+    let crl = der::parse(data)?;
+    let tbs = crl.child(0)?;
+    let mut fields = tbs.children().peekable();
+    if fields.peek().is_some_and(|field| field.tag == der::TAG_INTEGER) {
+        fields.next();
+    }
+    let signature_algorithm = parse_algorithm(&fields.next()?)?;
+    let issuer = parse_name(&fields.next()?)?;
+    let this_update = fields.next()?.as_time()?;
+    let mut next_update = None;
+    let mut revoked = Vec::new();
+    for field in fields {
+        if let Some(time) = field.as_time() {
+            next_update = Some(time);
+        } else if field.tag == der::TAG_SEQUENCE {
+            for entry in field.children() {
+                revoked.push(RevokedCertificate {
+                    serial: entry.child(0)?.as_unsigned_bytes()?.to_vec(),
+                    revocation_date: entry.child(1)?.as_time()?,
+                });
+            }
+        }
+    }
+    Some(Crl {
+        raw: crl.raw.to_vec(),
+        tbs: tbs.raw.to_vec(),
+        signature_algorithm,
+        issuer,
+        this_update,
+        next_update,
+        revoked,
+        signature: crl.child(2)?.as_bit_string()?.to_vec(),
+    })
+}
+
+fn parse_attributes(element: &Element) -> Vec<Attribute> {
+    element.children().filter_map(|attribute| Some(Attribute {
+        oid: attribute.child(0)?.as_oid()?,
+        values: attribute.child(1)?.children().map(|value| value.raw.to_vec()).collect(),
+    })).collect()
+}
+
+// SpcString is a CHOICE of [0] BMPString and [1] IA5String
+fn parse_spc_string(element: &Element) -> Option<String> {
+    let inner = if element.is_constructed() { element.child(0)? } else { *element };
+    let tag = if inner.is_context(0) { der::TAG_BMP_STRING } else { der::TAG_IA5_STRING };
+    Element { tag, ..inner }.as_string()
+}
+
+// SpcSpOpusInfo, program name and the more info link
+fn parse_opus_info(data: &[u8]) -> Option<(Option<String>, Option<String>)> {
+    let opus = der::parse(data)?;
+    let program_name = opus.context_child(0).and_then(|name| parse_spc_string(&name.child(0)?));
+    let more_info = opus.context_child(1).and_then(|link| {
+        let link = link.child(0)?;
+        if link.is_context(0) {
+            Some(String::from_utf8_lossy(link.content).to_string())
+        } else if link.is_context(2) {
+            parse_spc_string(&link.child(0)?)
+        } else {
+            None
+        }
+    });
+    Some((program_name, more_info))
+}
+
+fn parse_signer_info(element: &Element, depth: usize, anomalies: &mut Vec<String>) -> Option<SignerInfo> {
+    let mut fields = element.children().peekable();
+    let version = fields.next()?.as_u64()?;
+    let identifier = fields.next()?;
+    let (issuer, serial, subject_key_identifier) = if identifier.is_context(0) {
+        (None, Vec::new(), Some(identifier.content.to_vec()))
+    } else {
+        (Some(parse_name(&identifier.child(0)?)?), identifier.child(1)?.as_unsigned_bytes()?.to_vec(), None)
+    };
+    let digest_algorithm = parse_algorithm(&fields.next()?)?;
+    let mut authenticated_attributes_raw = None;
+    let mut authenticated_attributes = Vec::new();
+    if fields.peek().is_some_and(|field| field.is_context(0)) {
+        let attributes = fields.next()?;
+        authenticated_attributes_raw = Some(attributes.raw.to_vec());
+        authenticated_attributes = parse_attributes(&attributes);
+    }
+    let signature_algorithm = parse_algorithm(&fields.next()?)?;
+    let signature = fields.next()?.content.to_vec();
+    let unauthenticated_attributes = match fields.next() {
+        Some(attributes) if attributes.is_context(1) => parse_attributes(&attributes),
+        _ => Vec::new(),
+    };
+    let mut signer = SignerInfo {
+        version,
+        issuer,
+        serial,
+        subject_key_identifier,
+        digest_algorithm,
+        authenticated_attributes_raw,
+        authenticated_attributes,
+        signature_algorithm,
+        signature,
+        unauthenticated_attributes,
+        content_type: None,
+        message_digest: None,
+        signing_time: None,
+        program_name: None,
+        more_info_url: None,
+        countersignatures: Vec::new(),
+        timestamps: Vec::new(),
+        nested_signatures: Vec::new(),
+    };
+    for attribute in &signer.authenticated_attributes {
+        let value = match attribute.values.first().and_then(|value| der::parse(value)) {
+            Some(value) => value,
+            None => continue,
+        };
+        match attribute.oid.as_str() {
+            OID_CONTENT_TYPE => signer.content_type = value.as_oid(),
+            OID_MESSAGE_DIGEST => signer.message_digest = Some(value.content.to_vec()),
+            OID_SIGNING_TIME => signer.signing_time = value.as_time(),
+            OID_SPC_SP_OPUS_INFO => {
+                if let Some((program_name, more_info_url)) = parse_opus_info(value.raw) {
+                    signer.program_name = program_name;
+                    signer.more_info_url = more_info_url;
+                }
+            }
+            _ => {}
+        }
+    }
+    for attribute in &signer.unauthenticated_attributes {
+        let nested = matches!(attribute.oid.as_str(), OID_COUNTERSIGNATURE | OID_RFC3161_TIMESTAMP | OID_NESTED_SIGNATURE);
+        if nested && depth >= MAX_NESTING_DEPTH {
+            anomalies.push(format!("{} nested deeper than {} levels is ignored", oid_name(&attribute.oid), MAX_NESTING_DEPTH));
+            continue;
+        }
+        for value in &attribute.values {
+            match attribute.oid.as_str() {
+                OID_COUNTERSIGNATURE => match der::parse(value).and_then(|value| parse_signer_info(&value, depth + 1, anomalies)) {
+                    Some(countersignature) => signer.countersignatures.push(countersignature),
+                    None => anomalies.push(String::from("Countersignature is malformed")),
+                },
+                OID_RFC3161_TIMESTAMP => match parse_nested_signed_data(value, depth + 1) {
+                    Some(token) => signer.timestamps.push(token),
+                    None => anomalies.push(String::from("RFC 3161 timestamp token is malformed")),
+                },
+                OID_NESTED_SIGNATURE => match parse_nested_signed_data(value, depth + 1) {
+                    Some(nested) => signer.nested_signatures.push(nested),
+                    None => anomalies.push(String::from("Nested signature is malformed")),
+                },
+                _ => {}
+            }
+        }
+    }
+    Some(signer)
+}
+
+// SpcPeImageData file [0] EXPLICIT SpcLink, moniker [1] IMPLICIT SpcSerializedObject
+fn parse_page_hashes(image_data: &Element) -> Option<PageHashes> {
+    let moniker = image_data.context_child(0)?.child(0)?;
+    if !moniker.is_context(1) || moniker.child(0)?.content != SPC_PAGE_HASHES_CLASS_ID {
+        return None;
+    }
+    let attributes = der::parse(moniker.child(1)?.content)?;
+    attributes.children().find_map(|attribute| {
+        let algorithm = match attribute.child(0)?.as_oid()?.as_str() {
+            OID_SPC_PE_IMAGE_PAGE_HASHES_V1 => HashAlgorithm::Sha1,
+            OID_SPC_PE_IMAGE_PAGE_HASHES_V2 => HashAlgorithm::Sha256,
+            _ => return None,
+        };
+        let table = attribute.child(1)?.child(0)?;
+        let pages = table.content.chunks_exact(4 + algorithm.digest_size())
+            .map(|entry| PageHash { offset: u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]), hash: entry[4..].to_vec() })
+            .collect();
+        Some(PageHashes { algorithm, pages })
+    })
+}
+
+fn parse_indirect_data(element: &Element) -> Option<SpcIndirectData> {
+    let data = element.child(0)?;
+    let digest_info = element.child(1)?;
+    let data_type = data.child(0)?.as_oid()?;
+    let page_hashes = match (data_type.as_str(), data.child(1)) {
+        (OID_SPC_PE_IMAGE_DATA, Some(image_data)) => parse_page_hashes(&image_data),
+        _ => None,
+    };
+    Some(SpcIndirectData {
+        data_type,
+        data_value: data.child(1).map(|value| value.raw.to_vec()).unwrap_or_default(),
+        digest_algorithm: parse_algorithm(&digest_info.child(0)?)?,
+        digest: digest_info.child(1)?.content.to_vec(),
+        page_hashes,
+    })
+}
+
+fn parse_tst_info(data: &[u8]) -> Option<TstInfo> {
+    let info = der::parse(data)?;
+    let imprint = info.child(2)?;
+    Some(TstInfo {
+        version: info.child(0)?.as_u64()?,
+        policy: info.child(1)?.as_oid()?,
+        hash_algorithm: parse_algorithm(&imprint.child(0)?)?,
+        message_imprint: imprint.child(1)?.content.to_vec(),
+        serial: info.child(3)?.as_unsigned_bytes()?.to_vec(),
+        gen_time: info.child(4)?.as_time()?,
+    })
+}
+
+// ContentInfo wrapping a SignedData, as stored in WIN_CERTIFICATE and in nested signatures
+pub fn parse_signed_data(data: &[u8]) -> Option<SignedData> {
+    parse_nested_signed_data(data, 0)
+}
+
+// Depth counts the signatures this one is nested in
+fn parse_nested_signed_data(data: &[u8], depth: usize) -> Option<SignedData> {
+    let content_info = der::parse(data)?;
+    if content_info.child(0)?.as_oid()? != OID_SIGNED_DATA {
+        return None;
+    }
+    let signed = content_info.child(1)?.child(0)?;
+    let mut fields = signed.children().peekable();
+    let mut signed_data = SignedData { raw: content_info.raw.to_vec(), ..Default::default() };
+    signed_data.version = fields.next()?.as_u64()?;
+    signed_data.digest_algorithms = fields.next()?.children().filter_map(|algorithm| parse_algorithm(&algorithm)).collect();
+    let encapsulated = fields.next()?;
+    signed_data.content_type = encapsulated.child(0)?.as_oid()?;
+    if let Some(content) = encapsulated.context_child(0).and_then(|wrapper| wrapper.child(0)) {
+        // Authenticode stores the SEQUENCE directly and hashes its value, CMS wraps it in an OCTET STRING
+        signed_data.content = content.content.to_vec();
+        match signed_data.content_type.as_str() {
+            OID_SPC_INDIRECT_DATA => signed_data.indirect_data = parse_indirect_data(&content),
+            OID_TST_INFO => signed_data.tst_info = parse_tst_info(content.content),
+            _ => {}
+        }
+    }
+    for field in fields {
+        if field.is_context(0) {
+            for certificate in field.children() {
+                match parse_certificate(certificate.raw) {
+                    Some(certificate) => signed_data.certificates.push(certificate),
+                    None => signed_data.anomalies.push(format!("Certificate with tag 0x{:x} could not be decoded", certificate.tag)),
+                }
+            }
+        } else if field.is_context(1) {
+            signed_data.crls = field.children().map(|crl| crl.raw.to_vec()).collect();
+        } else if field.tag == der::TAG_SET {
+            let mut anomalies = Vec::new();
+            for signer in field.children() {
+                match parse_signer_info(&signer, depth, &mut anomalies) {
+                    Some(signer) => signed_data.signers.push(signer),
+                    None => anomalies.push(String::from("SignerInfo could not be decoded")),
+                }
+            }
+            signed_data.anomalies.append(&mut anomalies);
+        }
+    }
+    if signed_data.content_type == OID_SPC_INDIRECT_DATA && signed_data.indirect_data.is_none() {
+        signed_data.anomalies.push(String::from("SpcIndirectDataContent could not be decoded"));
+    }
+    if signed_data.signers.len() != 1 && signed_data.content_type == OID_SPC_INDIRECT_DATA {
+        signed_data.anomalies.push(format!("Authenticode requires exactly one signer, found {}", signed_data.signers.len()));
+    }
+    Some(signed_data)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.attributes.iter().map(|(oid, value)| format!("{}={}", attribute_name(oid), value)).collect::<Vec<String>>().join(", "))
+    }
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Certificate
+
+---------------------------
+subject: {}
+issuer: {}
+serial: {}
+version: {}
+not_before: {}
+not_after: {}
+signature_algorithm: {}
+public_key_algorithm: {}
+sha1: {}
+sha256: {}
+extensions: {}
+---------------------------",
+        self.subject, self.issuer, self.serial_string(), self.version + 1, format_time(self.not_before), format_time(self.not_after),
+        self.signature_algorithm.name(), self.public_key_algorithm.name(), self.sha1_thumbprint(), self.sha256_thumbprint(),
+        self.extensions.iter().map(|extension| oid_name(&extension.oid)).collect::<Vec<&str>>().join(" "))
+    }
+}
+
+impl fmt::Display for SignerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Signer
+
+---------------------------
+version: {}
+issuer: {}
+serial: {}
+digest_algorithm: {}
+signature_algorithm: {}
+content_type: {}
+message_digest: {}",
+        self.version, self.issuer.as_ref().map(|issuer| issuer.to_string()).unwrap_or_else(|| hex(self.subject_key_identifier.as_deref().unwrap_or_default())),
+        hex(&self.serial), self.digest_algorithm.name(), self.signature_algorithm.name(),
+        self.content_type.as_deref().map(oid_name).unwrap_or("-"), self.message_digest.as_deref().map(hex).unwrap_or_else(|| String::from("-")))?;
+        if let Some(signing_time) = self.signing_time {
+            write!(f, "\nsigning_time: {}", format_time(signing_time))?;
+        }
+        if let Some(program_name) = &self.program_name {
+            write!(f, "\nprogram_name: {}", program_name)?;
+        }
+        if let Some(more_info_url) = &self.more_info_url {
+            write!(f, "\nmore_info_url: {}", more_info_url)?;
+        }
+        write!(f, "\nauthenticated_attributes: {}", self.authenticated_attributes.iter().map(|attribute| oid_name(&attribute.oid)).collect::<Vec<&str>>().join(" "))?;
+        write!(f, "\nunauthenticated_attributes: {}", self.unauthenticated_attributes.iter().map(|attribute| oid_name(&attribute.oid)).collect::<Vec<&str>>().join(" "))?;
+        write!(f, "\n---------------------------")?;
+        for countersignature in &self.countersignatures {
+            write!(f, "\n\nCountersignature: {}", countersignature)?;
+        }
+        for timestamp in &self.timestamps {
+            write!(f, "\n\nRFC 3161 timestamp: {}", timestamp)?;
+        }
+        for nested in &self.nested_signatures {
+            write!(f, "\n\nNested signature: {}", nested)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SignedData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SignedData
+
+---------------------------
+version: {}
+digest_algorithms: {}
+content_type: {}",
+        self.version, self.digest_algorithms.iter().map(|algorithm| algorithm.name()).collect::<Vec<&str>>().join(" "), oid_name(&self.content_type))?;
+        if let Some(indirect_data) = &self.indirect_data {
+            write!(f, "\ndata_type: {}\ndigest_algorithm: {}\ndigest: {}", oid_name(&indirect_data.data_type), indirect_data.digest_algorithm.name(), hex(&indirect_data.digest))?;
+            if let Some(page_hashes) = &indirect_data.page_hashes {
+                write!(f, "\npage_hashes: {} {:?}", page_hashes.pages.len(), page_hashes.algorithm)?;
+            }
+        }
+        if let Some(tst_info) = &self.tst_info {
+            write!(f, "\npolicy: {}\nhash_algorithm: {}\nmessage_imprint: {}\nserial: {}\ngen_time: {}",
+            tst_info.policy, tst_info.hash_algorithm.name(), hex(&tst_info.message_imprint), hex(&tst_info.serial), format_time(tst_info.gen_time))?;
+        }
+        write!(f, "\ncertificates: {}\ncrls: {}\n---------------------------", self.certificates.len(), self.crls.len())?;
+        for signer in &self.signers {
+            write!(f, "\n\n{}", signer)?;
+        }
+        for certificate in &self.certificates {
+            write!(f, "\n\n{}", certificate)?;
+        }
+        Ok(())
+    }
+}
+
+// Synthesized certificates and signatures, signed with the 512 bit test keys
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::der::days_from_civil;
+    use crate::rsa::RsaPrivateKey;
+    use crate::rsa::parse_private_key;
+    use crate::rsa::sign_pkcs1;
+    use crate::rsa::tests::bytes;
+    use crate::rsa::tests::PRIVATE_KEY;
+
+    pub const OID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+    pub const OID_SHA256_WITH_RSA: &str = "1.2.840.113549.1.1.11";
+    const OID_SHA1_WITH_RSA: &str = "1.2.840.113549.1.1.5";
+    const OID_COMMON_NAME: &str = "2.5.4.3";
+    pub const NOT_BEFORE: &str = "200101000000Z";
+    pub const NOT_AFTER: &str = "300101000000Z";
+    // A second key, what it signs does not verify under the first one
+    pub const OTHER_PRIVATE_KEY: &str = concat!(
+        "3082013a020100024100b180dc5af96a265bfbf2f1ac8af33bb49209d750a533b90a89d10935e7c7eb1b4e8cc7f99d1649e0aa1ac159b7c2b475007f6495be",
+        "bd1e88b19e0c1e351f475d020301000102407a65b80d076579f3fdc4e57f2c05534b4d5479e383372a46a907ba89303d7bd11f6e1b0af3a33b4b07383942c623",
+        "7465092a836ecd6a0e39c3ddc2c1c9f259a1022100dc9103181f865585445d17a28eacaded0135e7888a415fcec18225385ec36ee5022100ce04d76a766afc73",
+        "5b582e219851d385aecacbe788df9593f17b89b998b97719022047c7a98a2038477ca752e46e32a05fb9d1bb092e964fd02e292b819166c59341022035129437",
+        "ede7a3bf47f1d0788117db48ddfd8a47cc3fe4f5a6c8ef72eb8023c9022100be592cbdece0d5f140b21795c186f7e59dc7edaa90a1ec7bad0776c2373cad68",
+    );
+
+    pub fn key() -> RsaPrivateKey {
+        parse_private_key(&bytes(PRIVATE_KEY)).unwrap()
+    }
+
+    pub fn other_key() -> RsaPrivateKey {
+        parse_private_key(&bytes(OTHER_PRIVATE_KEY)).unwrap()
+    }
+
+    // RSAPublicKey as stored in the subjectPublicKey BIT STRING
+    pub fn public_key(key: &RsaPrivateKey) -> Vec<u8> {
+        let modulus = key.public.modulus.to_be_bytes(key.public.size).unwrap();
+        let exponent = key.public.exponent.to_be_bytes(3).unwrap();
+        der::encode_sequence(&[&der::encode_integer(&modulus), &der::encode_integer(&exponent)])
+    }
+
+    // Seconds since the Unix epoch of a UTCTime in the 2000s
+    pub fn time(text: &str) -> i64 {
+        let field = |start: usize| -> i64 { text[start..start + 2].parse().unwrap() };
+        days_from_civil(2000 + field(0), field(2), field(4)) * 86400 + field(6) * 3600 + field(8) * 60 + field(10)
+    }
+
+    pub fn algorithm_identifier(oid: &str) -> Vec<u8> {
+        der::encode_sequence(&[&der::encode_oid(oid), &der::encode(der::TAG_NULL, &[])])
+    }
+
+    pub fn name(common_name: &str) -> Vec<u8> {
+        let attribute = der::encode_sequence(&[&der::encode_oid(OID_COMMON_NAME), &der::encode(der::TAG_UTF8_STRING, common_name.as_bytes())]);
+        der::encode_sequence(&[&der::encode_set(&[&attribute])])
+    }
+
+    pub fn extension(oid: &str, critical: bool, value: &[u8]) -> Vec<u8> {
+        let oid = der::encode_oid(oid);
+        let value = der::encode(der::TAG_OCTET_STRING, value);
+        match critical {
+            true => der::encode_sequence(&[&oid, &der::encode(der::TAG_BOOLEAN, &[0xff]), &value]),
+            false => der::encode_sequence(&[&oid, &value]),
+        }
+    }
+
+    pub fn basic_constraints(path_length: Option<u8>) -> Vec<u8> {
+        let ca = der::encode(der::TAG_BOOLEAN, &[0xff]);
+        let value = match path_length {
+            Some(length) => der::encode_sequence(&[&ca, &der::encode_integer(&[length])]),
+            None => der::encode_sequence(&[&ca]),
+        };
+        extension(OID_BASIC_CONSTRAINTS, true, &value)
+    }
+
+    pub fn extended_key_usage(purpose: &str) -> Vec<u8> {
+        extension(OID_EXTENDED_KEY_USAGE, false, &der::encode_sequence(&[&der::encode_oid(purpose)]))
+    }
+
+    // SHA-256 RSA signature of the data
+    pub fn rsa_signature(key: &RsaPrivateKey, data: &[u8]) -> Vec<u8> {
+        sign_pkcs1(key, HashAlgorithm::Sha256, &digest(HashAlgorithm::Sha256, data)).unwrap()
+    }
+
+    // TBSCertificate or TBSCertList wrapped with the outer algorithm and the signature
+    pub fn signed(tbs: &[u8], algorithm: &str, key: &RsaPrivateKey) -> Vec<u8> {
+        let mut signature = vec![0];
+        signature.extend(rsa_signature(key, tbs));
+        der::encode_sequence(&[tbs, &algorithm_identifier(algorithm), &der::encode(der::TAG_BIT_STRING, &signature)])
+    }
+
+    // Certificate fields the tests vary
+    pub struct Template<'a> {
+        pub issuer: &'a str,
+        pub subject: &'a str,
+        pub serial: u8,
+        pub not_before: &'a str,
+        pub not_after: &'a str,
+        pub key: &'a RsaPrivateKey,
+        pub extensions: Vec<Vec<u8>>,
+    }
+
+    impl<'a> Template<'a> {
+        pub fn new(issuer: &'a str, subject: &'a str, serial: u8, key: &'a RsaPrivateKey) -> Template<'a> {
+            Template { issuer, subject, serial, not_before: NOT_BEFORE, not_after: NOT_AFTER, key, extensions: Vec::new() }
+        }
+
+        pub fn tbs(&self, algorithm: &str) -> Vec<u8> {
+            let validity = der::encode_sequence(&[&der::encode(der::TAG_UTC_TIME, self.not_before.as_bytes()), &der::encode(der::TAG_UTC_TIME, self.not_after.as_bytes())]);
+            let mut subject_public_key = vec![0];
+            subject_public_key.extend(public_key(self.key));
+            let public_key_info = der::encode_sequence(&[&algorithm_identifier(OID_RSA_ENCRYPTION), &der::encode(der::TAG_BIT_STRING, &subject_public_key)]);
+            let mut fields = vec![
+                der::encode_context(0, &der::encode_integer(&[2])),
+                der::encode_integer(&[self.serial]),
+                algorithm_identifier(algorithm),
+                name(self.issuer),
+                validity,
+                name(self.subject),
+                public_key_info,
+            ];
+            if !self.extensions.is_empty() {
+                fields.push(der::encode_context(3, &der::encode_sequence(&self.extensions.iter().map(|extension| extension.as_slice()).collect::<Vec<&[u8]>>())));
+            }
+            der::encode_sequence(&fields.iter().map(|field| field.as_slice()).collect::<Vec<&[u8]>>())
+        }
+
+        // sha256WithRSAEncryption signature by the issuer key
+        pub fn sign(&self, issuer_key: &RsaPrivateKey) -> Vec<u8> {
+            signed(&self.tbs(OID_SHA256_WITH_RSA), OID_SHA256_WITH_RSA, issuer_key)
+        }
+    }
+
+    pub fn attribute(oid: &str, value: &[u8]) -> Vec<u8> {
+        der::encode_sequence(&[&der::encode_oid(oid), &der::encode_set(&[value])])
+    }
+
+    // SignerInfo identified by issuer and serial, the SET of the authenticated attributes is signed
+    pub fn signer_info(issuer: &str, serial: u8, key: &RsaPrivateKey, authenticated: &[Vec<u8>], unauthenticated: &[Vec<u8>]) -> Vec<u8> {
+        let attributes = der::encode_set(&authenticated.iter().map(|attribute| attribute.as_slice()).collect::<Vec<&[u8]>>());
+        let signature = rsa_signature(key, &attributes);
+        let mut implicit_attributes = attributes.clone();
+        implicit_attributes[0] = 0xa0;
+        let mut fields = vec![
+            der::encode_integer(&[1]),
+            der::encode_sequence(&[&name(issuer), &der::encode_integer(&[serial])]),
+            algorithm_identifier(OID_SHA256),
+            implicit_attributes,
+            algorithm_identifier(OID_RSA_ENCRYPTION),
+            der::encode(der::TAG_OCTET_STRING, &signature),
+        ];
+        if !unauthenticated.is_empty() {
+            fields.push(der::encode(0xa1, &unauthenticated.concat()));
+        }
+        der::encode_sequence(&fields.iter().map(|field| field.as_slice()).collect::<Vec<&[u8]>>())
+    }
+
+    // ContentInfo with the encapsulated content stored as is in [0]
+    pub fn signed_data(content_type: &str, content: &[u8], certificates: &[Vec<u8>], signers: &[Vec<u8>]) -> Vec<u8> {
+        let encapsulated = der::encode_sequence(&[&der::encode_oid(content_type), &der::encode_context(0, content)]);
+        let signers = der::encode_set(&signers.iter().map(|signer| signer.as_slice()).collect::<Vec<&[u8]>>());
+        let signed_data = der::encode_sequence(&[
+            &der::encode_integer(&[1]),
+            &der::encode_set(&[&algorithm_identifier(OID_SHA256)]),
+            &encapsulated,
+            &der::encode_context(0, &certificates.concat()),
+            &signers,
+        ]);
+        der::encode_sequence(&[&der::encode_oid(OID_SIGNED_DATA), &der::encode_context(0, &signed_data)])
+    }
+
+    // SpcIndirectDataContent with a SHA-256 image digest
+    pub fn indirect_data(image_digest: &[u8]) -> Vec<u8> {
+        let data = der::encode_sequence(&[&der::encode_oid(OID_SPC_PE_IMAGE_DATA), &der::encode_sequence(&[&der::encode(der::TAG_BIT_STRING, &[0])])]);
+        let digest_info = der::encode_sequence(&[&algorithm_identifier(OID_SHA256), &der::encode(der::TAG_OCTET_STRING, image_digest)]);
+        der::encode_sequence(&[&data, &digest_info])
+    }
+
+    // Authenticode signature over the image digest by the "Test Signer" certificate issued by "Test CA" with serial 2
+    pub fn authenticode(image_digest: &[u8], unauthenticated: &[Vec<u8>]) -> Vec<u8> {
+        let key = key();
+        let certificate = Template::new("Test CA", "Test Signer", 2, &key).sign(&key);
+        let content = indirect_data(image_digest);
+        let value = der::parse(&content).unwrap().content.to_vec();
+        let authenticated = [
+            attribute(OID_CONTENT_TYPE, &der::encode_oid(OID_SPC_INDIRECT_DATA)),
+            attribute(OID_MESSAGE_DIGEST, &der::encode(der::TAG_OCTET_STRING, &digest(HashAlgorithm::Sha256, &value))),
+        ];
+        let signer = signer_info("Test CA", 2, &key, &authenticated, unauthenticated);
+        signed_data(OID_SPC_INDIRECT_DATA, &content, &[certificate], &[signer])
+    }
+
+    // RFC 3161 TSTInfo over a SHA-256 imprint
+    pub fn tst_info(imprint: &[u8], gen_time: &str) -> Vec<u8> {
+        let message_imprint = der::encode_sequence(&[&algorithm_identifier(OID_SHA256), &der::encode(der::TAG_OCTET_STRING, imprint)]);
+        der::encode_sequence(&[
+            &der::encode_integer(&[1]),
+            &der::encode_oid("1.2.3.4"),
+            &message_imprint,
+            &der::encode_integer(&[7]),
+            &der::encode(der::TAG_GENERALIZED_TIME, format!("20{}", gen_time).as_bytes()),
+        ])
+    }
+
+    // Unsigned SignedData whose SpcPeImageData carries the page hashes, the image digest is zero
+    pub fn page_hashes_signature(algorithm: HashAlgorithm, pages: &[PageHash]) -> Vec<u8> {
+        let oid = match algorithm {
+            HashAlgorithm::Sha1 => OID_SPC_PE_IMAGE_PAGE_HASHES_V1,
+            _ => OID_SPC_PE_IMAGE_PAGE_HASHES_V2,
+        };
+        let table: Vec<u8> = pages.iter().flat_map(|page| page.offset.to_le_bytes().into_iter().chain(page.hash.iter().copied())).collect();
+        let attribute = der::encode_sequence(&[&der::encode_oid(oid), &der::encode_set(&[&der::encode(der::TAG_OCTET_STRING, &table)])]);
+        let serialized = [der::encode(der::TAG_OCTET_STRING, &SPC_PAGE_HASHES_CLASS_ID), der::encode(der::TAG_OCTET_STRING, &der::encode_set(&[&attribute]))].concat();
+        let image_data = der::encode_sequence(&[&der::encode(der::TAG_BIT_STRING, &[0]), &der::encode_context(0, &der::encode(0xa1, &serialized))]);
+        let data = der::encode_sequence(&[&der::encode_oid(OID_SPC_PE_IMAGE_DATA), &image_data]);
+        let digest_info = der::encode_sequence(&[&algorithm_identifier(OID_SHA256), &der::encode(der::TAG_OCTET_STRING, &[0; 32])]);
+        signed_data(OID_SPC_INDIRECT_DATA, &der::encode_sequence(&[&data, &digest_info]), &[], &[])
+    }
+
+    #[test]
+    fn parses_signed_data() {
+        let key = key();
+        let mut template = Template::new("Test CA", "Test Signer", 2, &key);
+        template.extensions = vec![extended_key_usage(OID_CODE_SIGNING), basic_constraints(Some(0))];
+        let certificate = template.sign(&key);
+        let content = indirect_data(&[0x11; 32]);
+        let value = der::parse(&content).unwrap().content.to_vec();
+        let authenticated = [
+            attribute(OID_CONTENT_TYPE, &der::encode_oid(OID_SPC_INDIRECT_DATA)),
+            attribute(OID_MESSAGE_DIGEST, &der::encode(der::TAG_OCTET_STRING, &[0x22; 32])),
+            attribute(OID_SIGNING_TIME, &der::encode(der::TAG_UTC_TIME, b"210203040506Z")),
+        ];
+        let signer = signer_info("Test CA", 2, &key, &authenticated, &[]);
+        let signed_data = parse_signed_data(&signed_data(OID_SPC_INDIRECT_DATA, &content, std::slice::from_ref(&certificate), &[signer])).unwrap();
+        assert!(signed_data.anomalies.is_empty());
+        assert_eq!(signed_data.version, 1);
+        assert_eq!(signed_data.digest_algorithms[0].oid, OID_SHA256);
+        assert_eq!(signed_data.content_type, OID_SPC_INDIRECT_DATA);
+        assert_eq!(signed_data.content, value);
+        let indirect_data = signed_data.indirect_data.as_ref().unwrap();
+        assert_eq!(indirect_data.data_type, OID_SPC_PE_IMAGE_DATA);
+        assert_eq!(indirect_data.digest_algorithm.hash_algorithm(), Some(HashAlgorithm::Sha256));
+        assert_eq!(indirect_data.digest, vec![0x11; 32]);
+        assert!(indirect_data.page_hashes.is_none());
+
+        let parsed = &signed_data.certificates[0];
+        assert_eq!(parsed.raw, certificate);
+        assert_eq!(parsed.version, 2);
+        assert_eq!(parsed.serial, vec![2]);
+        assert_eq!(parsed.issuer.common_name(), Some("Test CA"));
+        assert_eq!(parsed.subject.common_name(), Some("Test Signer"));
+        assert_eq!(parsed.not_before, time(NOT_BEFORE));
+        assert_eq!(parsed.not_after, time(NOT_AFTER));
+        assert_eq!(parsed.signature_algorithm.oid, OID_SHA256_WITH_RSA);
+        assert_eq!(parsed.public_key, public_key(&key));
+        assert_eq!(parsed.extended_key_usage(), Some(vec![String::from(OID_CODE_SIGNING)]));
+        assert_eq!(parsed.basic_constraints(), Some((true, Some(0))));
+        assert!(parsed.extension(OID_BASIC_CONSTRAINTS).unwrap().critical);
+
+        let signer = &signed_data.signers[0];
+        assert_eq!(signer.version, 1);
+        assert_eq!(signer.serial, vec![2]);
+        assert_eq!(signer.content_type.as_deref(), Some(OID_SPC_INDIRECT_DATA));
+        assert_eq!(signer.message_digest, Some(vec![0x22; 32]));
+        assert_eq!(signer.signing_time, Some(time("210203040506Z")));
+        assert_eq!(signer.signature_algorithm.oid, OID_RSA_ENCRYPTION);
+        assert_eq!(signer.authenticated_attributes_raw.as_ref().unwrap()[0], 0xa0);
+        assert!(std::ptr::eq(signed_data.signer_certificate(signer).unwrap(), parsed));
+    }
+
+    #[test]
+    fn mismatched_certificate_algorithms() {
+        let key = key();
+        let template = Template::new("Test CA", "Test Signer", 2, &key);
+        let certificate = signed(&template.tbs(OID_SHA256_WITH_RSA), OID_SHA256_WITH_RSA, &key);
+        assert_eq!(parse_certificate(&certificate).unwrap().signature_algorithm.oid, OID_SHA256_WITH_RSA);
+        let mismatched = signed(&template.tbs(OID_SHA256_WITH_RSA), OID_SHA1_WITH_RSA, &key);
+        assert!(parse_certificate(&mismatched).is_none());
+        let signer = signer_info("Test CA", 2, &key, &[attribute(OID_CONTENT_TYPE, &der::encode_oid(OID_SPC_INDIRECT_DATA))], &[]);
+        let signed_data = parse_signed_data(&signed_data(OID_SPC_INDIRECT_DATA, &indirect_data(&[0; 32]), &[mismatched, certificate], &[signer])).unwrap();
+        assert_eq!(signed_data.certificates.len(), 1);
+        assert_eq!(signed_data.anomalies, vec![String::from("Certificate with tag 0x30 could not be decoded")]);
+    }
+
+    #[test]
+    fn nesting_depth() {
+        // Six signatures, each nested in the next one
+        let mut nested = authenticode(&[0; 32], &[]);
+        for _ in 0..5 {
+            nested = authenticode(&[0; 32], &[attribute(OID_NESTED_SIGNATURE, &nested)]);
+        }
+        let mut signed_data = parse_signed_data(&nested).unwrap();
+        for depth in 0..MAX_NESTING_DEPTH {
+            assert!(signed_data.anomalies.is_empty());
+            assert_eq!(signed_data.signers[0].nested_signatures.len(), 1);
+            signed_data = signed_data.signers.remove(0).nested_signatures.remove(0);
+        }
+        assert!(signed_data.signers[0].nested_signatures.is_empty());
+        assert_eq!(signed_data.anomalies, vec![String::from("SPC_NESTED_SIGNATURE nested deeper than 4 levels is ignored")]);
+
+        let key = key();
+        let mut countersignature = signer_info("Test CA", 2, &key, &[], &[]);
+        for _ in 0..5 {
+            countersignature = signer_info("Test CA", 2, &key, &[], &[attribute(OID_COUNTERSIGNATURE, &countersignature)]);
+        }
+        let signed_data = parse_signed_data(&authenticode(&[0; 32], &[attribute(OID_COUNTERSIGNATURE, &countersignature)])).unwrap();
+        let mut signer = &signed_data.signers[0];
+        for depth in 0..MAX_NESTING_DEPTH {
+            signer = &signer.countersignatures[0];
+        }
+        assert!(signer.countersignatures.is_empty());
+        assert_eq!(signer.unauthenticated_attributes[0].oid, OID_COUNTERSIGNATURE);
+        assert_eq!(signed_data.anomalies, vec![String::from("countersignature nested deeper than 4 levels is ignored")]);
+    }
+}
@@ -0,0 +1,114 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::vec::Vec;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0xffffffff;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// Wraps data in a zlib stream made of stored deflate blocks, size is traded for simplicity
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![0x78, 0x01];
+    let mut chunks = data.chunks(0xffff).peekable();
+    if chunks.peek().is_none() {
+        output.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        output.push(if chunks.peek().is_none() { 0x01 } else { 0x00 });
+        output.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        output.extend_from_slice(&(!(chunk.len() as u16)).to_le_bytes());
+        output.extend_from_slice(chunk);
+    }
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn write_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+    let crc = crc32(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Encodes top-down, 8-bit RGBA pixels
+pub fn encode_rgba(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let mut output = PNG_SIGNATURE.to_vec();
+
+    let mut ihdr = Vec::new();
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, colour type 6 (RGBA), default compression, filter and no interlacing
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut output, b"IHDR", &ihdr);
+
+    let stride = width as usize * 4;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in pixels.chunks(stride).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    write_chunk(&mut output, b"IDAT", &zlib_store(&raw));
+    write_chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+pub fn is_png(data: &[u8]) -> bool {
+    data.starts_with(&PNG_SIGNATURE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn encodes_rgba() {
+        let pixels = [0xff, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00, 0x80];
+        let png = encode_rgba(2, 1, &pixels);
+        assert!(is_png(&png));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 2, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        assert_eq!(u32::from_be_bytes(png[29..33].try_into().unwrap()), crc32(&png[12..29]));
+        // IDAT holds a zlib stream of the filtered rows
+        let length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let zlib = &png[41..41 + length];
+        assert_eq!(&zlib[..3], &[0x78, 0x01, 0x01]);
+        let stored = u16::from_le_bytes([zlib[3], zlib[4]]) as usize;
+        assert_eq!(u16::from_le_bytes([zlib[5], zlib[6]]), !(stored as u16));
+        assert_eq!(zlib.len(), 7 + stored + 4);
+        let raw = &zlib[7..7 + stored];
+        assert_eq!(raw, [&[0][..], &pixels[..]].concat());
+        assert_eq!(u32::from_be_bytes(zlib[zlib.len() - 4..].try_into().unwrap()), adler32(raw));
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
@@ -0,0 +1,652 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::inflate::inflate;
+use crate::sha::HashAlgorithm;
+use crate::sha::digest;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::read_u64_at;
+
+const MPDB_SIGNATURE: u32 = 0x4244504d;
+const METADATA_SIGNATURE: u32 = 0x424a5342;
+const PDB_ID_SIZE: usize = 20;
+const TABLE_DOCUMENT: usize = 0x30;
+const TABLE_METHOD_DEBUG_INFORMATION: usize = 0x31;
+const HIDDEN_LINE: u32 = 0xfeefee;
+
+const GUID_SHA1: [u8; 16] = [0xec, 0x16, 0x18, 0xff, 0x5e, 0xaa, 0x10, 0x4d, 0x87, 0xf7, 0x6f, 0x49, 0x63, 0x83, 0x34, 0x60];
+const GUID_SHA256: [u8; 16] = [0x0f, 0xd0, 0x29, 0x88, 0xb8, 0x11, 0x13, 0x42, 0x87, 0x8b, 0x77, 0x0e, 0x85, 0x97, 0xac, 0x16];
+const GUID_CSHARP: [u8; 16] = [0xf8, 0x62, 0x51, 0x3f, 0xc6, 0x07, 0xd3, 0x11, 0x90, 0x53, 0x00, 0xc0, 0x4f, 0xa3, 0x02, 0xa1];
+const GUID_VISUAL_BASIC: [u8; 16] = [0xb8, 0xd0, 0x12, 0x3a, 0x6c, 0xc2, 0xd0, 0x11, 0xb4, 0x42, 0x00, 0xa0, 0x24, 0x4a, 0x1d, 0xd2];
+const GUID_FSHARP: [u8; 16] = [0xc9, 0x38, 0x4f, 0xab, 0xe6, 0xb6, 0xba, 0x43, 0xbe, 0x3b, 0x58, 0x08, 0x0b, 0x2c, 0xcc, 0xe3];
+
+// Structures definitions
+
+// EMBEDDED_PORTABLE_PDB debug entry, the PDB is stored deflated after an MPDB header
+pub struct EmbeddedPdb {
+    pub uncompressed_size: u32,
+    pub compressed_size: usize,
+    pub data: Vec<u8>,
+}
+
+// PDBCHECKSUM debug entry
+pub struct PdbChecksum {
+    pub algorithm: String,
+    pub checksum: Vec<u8>,
+}
+
+pub struct StreamHeader {
+    pub offset: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+pub struct MetadataRoot {
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub version: String,
+    pub flags: u16,
+    pub streams: Vec<StreamHeader>,
+}
+
+pub struct Document {
+    pub name: String,
+    pub hash_algorithm: [u8; 16],
+    pub hash: Vec<u8>,
+    pub language: [u8; 16],
+}
+
+pub struct SequencePoint {
+    pub il_offset: u32,
+    // Row of the Document table, 1 based
+    pub document: u32,
+    pub start_line: u32,
+    pub start_column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+}
+
+pub struct MethodDebugInformation {
+    pub method_token: u32,
+    pub document: u32,
+    pub local_signature: u32,
+    pub sequence_points: Vec<SequencePoint>,
+}
+
+#[derive(Default)]
+pub struct PortablePdb {
+    pub metadata: Option<MetadataRoot>,
+    pub id: [u8; PDB_ID_SIZE],
+    pub entry_point: u32,
+    pub referenced_type_system_tables: u64,
+    pub type_system_rows: Vec<u32>,
+    pub table_rows: Vec<u32>,
+    pub documents: Vec<Document>,
+    pub methods: Vec<MethodDebugInformation>,
+    pub anomalies: Vec<String>,
+}
+
+struct Heaps<'a> {
+    strings: &'a [u8],
+    blob: &'a [u8],
+    guid: &'a [u8],
+    large_strings: bool,
+    large_guid: bool,
+    large_blob: bool,
+}
+
+
+// Conversion implementation for the structs
+
+fn format_guid(guid: &[u8; 16]) -> String {
+    format!("{:08x}-{:04x}-{:04x}-{}-{}",
+    u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]), u16::from_le_bytes([guid[4], guid[5]]), u16::from_le_bytes([guid[6], guid[7]]),
+    guid[8..10].iter().map(|byte| format!("{:02x}", byte)).collect::<String>(), guid[10..].iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl Document {
+    pub fn hash_algorithm_name(&self) -> String {
+        match self.hash_algorithm {
+            GUID_SHA1 => String::from("SHA1"),
+            GUID_SHA256 => String::from("SHA256"),
+            guid if guid == [0; 16] => String::from("-"),
+            guid => format_guid(&guid),
+        }
+    }
+
+    pub fn language_name(&self) -> String {
+        match self.language {
+            GUID_CSHARP => String::from("C#"),
+            GUID_VISUAL_BASIC => String::from("Visual Basic"),
+            GUID_FSHARP => String::from("F#"),
+            guid => format_guid(&guid),
+        }
+    }
+}
+
+impl SequencePoint {
+    pub fn is_hidden(&self) -> bool {
+        self.start_line == HIDDEN_LINE
+    }
+}
+
+impl PortablePdb {
+    // The PDB id is the GUID of the CodeView record followed by its TimeDateStamp
+    pub fn guid(&self) -> [u8; 16] {
+        self.id[..16].try_into().unwrap()
+    }
+
+    pub fn stamp(&self) -> u32 {
+        u32::from_le_bytes(self.id[16..].try_into().unwrap())
+    }
+
+    pub fn method(&self, token: u32) -> Option<&MethodDebugInformation> {
+        self.methods.iter().find(|method| method.method_token == token)
+    }
+}
+
+
+// Parsing
+
+// ECMA-335 II.23.2 compressed unsigned integer
+fn read_compressed(data: &[u8], offset: &mut usize) -> Option<u32> {
+    let first = *data.get(*offset)? as u32;
+    let (value, size) = if first & 0x80 == 0 {
+        (first, 1)
+    } else if first & 0xc0 == 0x80 {
+        (((first & 0x3f) << 8) | *data.get(*offset + 1)? as u32, 2)
+    } else if first & 0xe0 == 0xc0 {
+        (((first & 0x1f) << 24) | (*data.get(*offset + 1)? as u32) << 16 | (*data.get(*offset + 2)? as u32) << 8 | *data.get(*offset + 3)? as u32, 4)
+    } else {
+        return None;
+    };
+    *offset += size;
+    Some(value)
+}
+
+// Signed values are rotated so the sign bit ends up in bit 0
+fn read_compressed_signed(data: &[u8], offset: &mut usize) -> Option<i32> {
+    let start = *offset;
+    let value = read_compressed(data, offset)?;
+    let bits = match *offset - start {
+        1 => 7,
+        2 => 14,
+        _ => 29,
+    };
+    let magnitude = (value >> 1) as i32;
+    Some(if value & 1 == 0 { magnitude } else { magnitude - (1 << (bits - 1)) })
+}
+
+fn read_index(data: &[u8], offset: &mut usize, large: bool) -> Option<u32> {
+    let value = if large { read_u32_at(data, *offset)? } else { read_u16_at(data, *offset)? as u32 };
+    *offset += if large { 4 } else { 2 };
+    Some(value)
+}
+
+impl Heaps<'_> {
+    fn blob(&self, index: u32) -> Option<&[u8]> {
+        let mut offset = index as usize;
+        let length = read_compressed(self.blob, &mut offset)? as usize;
+        self.blob.get(offset..offset.checked_add(length)?)
+    }
+
+    fn guid(&self, index: u32) -> Option<[u8; 16]> {
+        if index == 0 {
+            return Some([0; 16]);
+        }
+        let start = (index as usize - 1) * 16;
+        self.guid.get(start..start + 16)?.try_into().ok()
+    }
+
+    fn string(&self, index: u32) -> Option<String> {
+        let data = self.strings.get(index as usize..)?;
+        let end = data.iter().position(|byte| *byte == 0)?;
+        Some(String::from_utf8_lossy(&data[..end]).to_string())
+    }
+
+    // Names are a separator followed by blob indexes of UTF-8 parts
+    fn document_name(&self, index: u32) -> Option<String> {
+        let blob = self.blob(index)?;
+        let separator = *blob.first()?;
+        let mut offset = 1;
+        let mut parts = Vec::new();
+        while offset < blob.len() {
+            let part = read_compressed(blob, &mut offset)?;
+            parts.push(if part == 0 { String::new() } else { String::from_utf8_lossy(self.blob(part)?).to_string() });
+        }
+        Some(if separator == 0 { parts.concat() } else { parts.join(&(separator as char).to_string()) })
+    }
+}
+
+pub fn parse_embedded_pdb(data: &[u8]) -> Option<EmbeddedPdb> {
+    if read_u32_at(data, 0)? != MPDB_SIGNATURE {
+        return None;
+    }
+    let uncompressed_size = read_u32_at(data, 4)?;
+    let pdb = inflate(data.get(8..)?, uncompressed_size as usize)?;
+    if pdb.len() != uncompressed_size as usize {
+        return None;
+    }
+    Some(EmbeddedPdb { uncompressed_size, compressed_size: data.len() - 8, data: pdb })
+}
+
+pub fn parse_pdb_checksum(data: &[u8]) -> Option<PdbChecksum> {
+    let end = data.iter().position(|byte| *byte == 0)?;
+    Some(PdbChecksum {
+        algorithm: String::from_utf8_lossy(&data[..end]).to_string(),
+        checksum: data[end + 1..].to_vec(),
+    })
+}
+
+pub fn parse_metadata_root(data: &[u8]) -> Option<MetadataRoot> {
+    if read_u32_at(data, 0)? != METADATA_SIGNATURE {
+        return None;
+    }
+    let length = read_u32_at(data, 12)? as usize;
+    let version = data.get(16..16 + length)?;
+    let end = version.iter().position(|byte| *byte == 0).unwrap_or(version.len());
+    let mut offset = 16 + length;
+    let flags = read_u16_at(data, offset)?;
+    let count = read_u16_at(data, offset + 2)?;
+    offset += 4;
+    let mut streams = Vec::new();
+    for x in 0..count {
+        let name_data = data.get(offset + 8..)?;
+        let name_end = name_data.iter().take(32).position(|byte| *byte == 0)?;
+        streams.push(StreamHeader {
+            offset: read_u32_at(data, offset)?,
+            size: read_u32_at(data, offset + 4)?,
+            name: String::from_utf8_lossy(&name_data[..name_end]).to_string(),
+        });
+        offset += 8 + ((name_end + 1 + 3) & !3);
+    }
+    Some(MetadataRoot {
+        major_version: read_u16_at(data, 4)?,
+        minor_version: read_u16_at(data, 6)?,
+        version: String::from_utf8_lossy(&version[..end]).to_string(),
+        flags,
+        streams,
+    })
+}
+
+fn stream<'a>(data: &'a [u8], metadata: &MetadataRoot, name: &str) -> Option<&'a [u8]> {
+    let header = metadata.streams.iter().find(|stream| stream.name == name)?;
+    data.get(header.offset as usize..(header.offset as usize).checked_add(header.size as usize)?)
+}
+
+fn parse_sequence_points(blob: &[u8], document: u32) -> Option<(u32, Vec<SequencePoint>)> {
+    let mut offset = 0;
+    let local_signature = read_compressed(blob, &mut offset)?;
+    let mut document = if document == 0 { read_compressed(blob, &mut offset)? } else { document };
+    let mut points = Vec::new();
+    let mut il_offset = 0u32;
+    let mut previous: Option<(u32, u32)> = None;
+    while offset < blob.len() {
+        let delta_il = read_compressed(blob, &mut offset)?;
+        // A zero delta after the first record switches the document
+        if !points.is_empty() && delta_il == 0 {
+            document = read_compressed(blob, &mut offset)?;
+            continue;
+        }
+        il_offset = if points.is_empty() { delta_il } else { il_offset.wrapping_add(delta_il) };
+        let delta_lines = read_compressed(blob, &mut offset)?;
+        let delta_columns = if delta_lines == 0 { read_compressed(blob, &mut offset)? as i64 } else { read_compressed_signed(blob, &mut offset)? as i64 };
+        if delta_lines == 0 && delta_columns == 0 {
+            points.push(SequencePoint { il_offset, document, start_line: HIDDEN_LINE, start_column: 0, end_line: HIDDEN_LINE, end_column: 0 });
+            continue;
+        }
+        let (start_line, start_column) = match previous {
+            None => (read_compressed(blob, &mut offset)?, read_compressed(blob, &mut offset)?),
+            Some((line, column)) => (
+                (line as i64 + read_compressed_signed(blob, &mut offset)? as i64) as u32,
+                (column as i64 + read_compressed_signed(blob, &mut offset)? as i64) as u32,
+            ),
+        };
+        previous = Some((start_line, start_column));
+        points.push(SequencePoint {
+            il_offset,
+            document,
+            start_line,
+            start_column,
+            end_line: start_line.wrapping_add(delta_lines),
+            end_column: (start_column as i64 + delta_columns) as u32,
+        });
+    }
+    Some((local_signature, points))
+}
+
+fn parse_tables(data: &[u8], heaps: &Heaps, pdb: &mut PortablePdb) -> Option<()> {
+    let valid = read_u64_at(data, 8)?;
+    let mut rows = vec![0u32; 64];
+    let mut offset = 24;
+    for (table, count) in rows.iter_mut().enumerate() {
+        if valid & (1u64 << table) != 0 {
+            *count = read_u32_at(data, offset)?;
+            offset += 4;
+        }
+    }
+    pdb.table_rows = rows.clone();
+    // Type system tables would have to be sized first, they never appear in a portable PDB
+    let type_system = valid & ((1u64 << TABLE_DOCUMENT) - 1);
+    if type_system != 0 {
+        pdb.anomalies.push(format!("Tables stream holds type system tables 0x{:x}, debug tables are not decoded", type_system));
+        return Some(());
+    }
+    let document_index_large = rows[TABLE_DOCUMENT] >= 0x10000;
+    for x in 0..rows[TABLE_DOCUMENT] {
+        let name = read_index(data, &mut offset, heaps.large_blob)?;
+        let hash_algorithm = read_index(data, &mut offset, heaps.large_guid)?;
+        let hash = read_index(data, &mut offset, heaps.large_blob)?;
+        let language = read_index(data, &mut offset, heaps.large_guid)?;
+        pdb.documents.push(Document {
+            name: heaps.document_name(name).unwrap_or_default(),
+            hash_algorithm: heaps.guid(hash_algorithm).unwrap_or([0; 16]),
+            hash: heaps.blob(hash).map(|hash| hash.to_vec()).unwrap_or_default(),
+            language: heaps.guid(language).unwrap_or([0; 16]),
+        });
+    }
+    for x in 0..rows[TABLE_METHOD_DEBUG_INFORMATION] {
+        let document = read_index(data, &mut offset, document_index_large)?;
+        let sequence_points = read_index(data, &mut offset, heaps.large_blob)?;
+        // Rows map one to one to the MethodDef table
+        let method_token = 0x06000000 | (x + 1);
+        let mut method = MethodDebugInformation { method_token, document, local_signature: 0, sequence_points: Vec::new() };
+        if sequence_points != 0 {
+            match heaps.blob(sequence_points).and_then(|blob| parse_sequence_points(blob, document)) {
+                Some((local_signature, points)) => {
+                    method.local_signature = local_signature;
+                    method.sequence_points = points;
+                }
+                None => pdb.anomalies.push(format!("Sequence points of method 0x{:08x} are malformed", method_token)),
+            }
+        }
+        pdb.methods.push(method);
+    }
+    Some(())
+}
+
+pub fn parse_portable_pdb(data: &[u8]) -> Option<PortablePdb> {
+    let metadata = parse_metadata_root(data)?;
+    let mut pdb = PortablePdb::default();
+    match stream(data, &metadata, "#Pdb") {
+        Some(pdb_stream) => {
+            pdb.id = pdb_stream.get(..PDB_ID_SIZE)?.try_into().ok()?;
+            pdb.entry_point = read_u32_at(pdb_stream, 20).unwrap_or(0);
+            pdb.referenced_type_system_tables = read_u64_at(pdb_stream, 24).unwrap_or(0);
+            let mut offset = 32;
+            for table in 0..64 {
+                if pdb.referenced_type_system_tables & (1u64 << table) != 0 {
+                    pdb.type_system_rows.push(read_u32_at(pdb_stream, offset).unwrap_or(0));
+                    offset += 4;
+                }
+            }
+        }
+        None => pdb.anomalies.push(String::from("Metadata has no #Pdb stream, this is not a portable PDB")),
+    }
+    let tables = stream(data, &metadata, "#~").or_else(|| stream(data, &metadata, "#-"));
+    if let Some(tables) = tables {
+        let heap_sizes = tables.get(6).copied().unwrap_or(0);
+        let heaps = Heaps {
+            strings: stream(data, &metadata, "#Strings").unwrap_or(&[]),
+            blob: stream(data, &metadata, "#Blob").unwrap_or(&[]),
+            guid: stream(data, &metadata, "#GUID").unwrap_or(&[]),
+            large_strings: heap_sizes & 0x1 != 0,
+            large_guid: heap_sizes & 0x2 != 0,
+            large_blob: heap_sizes & 0x4 != 0,
+        };
+        if parse_tables(tables, &heaps, &mut pdb).is_none() {
+            pdb.anomalies.push(String::from("Tables stream is truncated"));
+        }
+    } else {
+        pdb.anomalies.push(String::from("Metadata has no tables stream"));
+    }
+    pdb.metadata = Some(metadata);
+    Some(pdb)
+}
+
+// Portable PDBs are hashed with their id zeroed, None when the format or algorithm is not supported
+pub fn verify_pdb_checksum(checksum: &PdbChecksum, pdb: &[u8]) -> Option<bool> {
+    let algorithm = HashAlgorithm::from_name(&checksum.algorithm)?;
+    let metadata = parse_metadata_root(pdb)?;
+    let header = metadata.streams.iter().find(|stream| stream.name == "#Pdb")?;
+    let start = header.offset as usize;
+    let mut data = pdb.to_vec();
+    data.get_mut(start..start + PDB_ID_SIZE)?.fill(0);
+    Some(digest(algorithm, &data) == checksum.checksum)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for EmbeddedPdb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Embedded portable PDB, 0x{:x} bytes deflated to 0x{:x}", self.uncompressed_size, self.compressed_size)
+    }
+}
+
+impl fmt::Display for PdbChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.algorithm, hex(&self.checksum))
+    }
+}
+
+impl fmt::Display for PortablePdb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Portable PDB
+
+---------------------------
+id: {}
+guid: {}
+stamp: 0x{:08x}
+entry_point: 0x{:08x}
+referenced_type_system_tables: 0x{:x}",
+        hex(&self.id), format_guid(&self.guid()), self.stamp(), self.entry_point, self.referenced_type_system_tables)?;
+        if let Some(metadata) = &self.metadata {
+            write!(f, "\nmetadata: {} {}.{} streams: {}", metadata.version, metadata.major_version, metadata.minor_version,
+            metadata.streams.iter().map(|stream| stream.name.as_str()).collect::<Vec<_>>().join(" "))?;
+        }
+        write!(f, "
+---------------------------
+
+Documents
+")?;
+        for (x, document) in self.documents.iter().enumerate() {
+            write!(f, "\n{} {} {} {} {}", x + 1, document.name, document.language_name(), document.hash_algorithm_name(), hex(&document.hash))?;
+        }
+        write!(f, "
+
+Methods
+")?;
+        for method in self.methods.iter().filter(|method| !method.sequence_points.is_empty()) {
+            write!(f, "\n{}", method)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MethodDebugInformation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08x} document: {} local_signature: {}", self.method_token, self.document, self.local_signature)?;
+        for point in &self.sequence_points {
+            write!(f, "\n    {}", point)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SequencePoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_hidden() {
+            return write!(f, "IL_{:04x} hidden", self.il_offset);
+        }
+        write!(f, "IL_{:04x} document {} ({}, {}) - ({}, {})", self.il_offset, self.document, self.start_line, self.start_column, self.end_line, self.end_column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Blob heap builder, returns the index of each blob
+    fn push_blob(heap: &mut Vec<u8>, blob: &[u8]) -> u32 {
+        let index = heap.len() as u32;
+        heap.push(blob.len() as u8);
+        heap.extend_from_slice(blob);
+        index
+    }
+
+    fn metadata(streams: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let headers_size: usize = streams.iter().map(|(name, _)| 8 + ((name.len() + 1 + 3) & !3)).sum();
+        let mut data = Vec::new();
+        data.extend_from_slice(&METADATA_SIGNATURE.to_le_bytes());
+        data.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0, 0, 12, 0, 0, 0]);
+        data.extend_from_slice(b"PDB v1.0\0\0\0\0");
+        data.extend_from_slice(&[0, 0, streams.len() as u8, 0]);
+        let mut offset = data.len() + headers_size;
+        for (name, stream) in streams {
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            data.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+            let mut name = name.as_bytes().to_vec();
+            name.resize((name.len() + 1 + 3) & !3, 0);
+            data.extend(name);
+            offset += stream.len();
+        }
+        for (_, stream) in streams {
+            data.extend_from_slice(stream);
+        }
+        data
+    }
+
+    fn sample_pdb() -> Vec<u8> {
+        let mut blob = vec![0];
+        let src = push_blob(&mut blob, b"src");
+        let app = push_blob(&mut blob, b"app.cs");
+        let util = push_blob(&mut blob, b"util.cs");
+        let name = push_blob(&mut blob, &[b'/', src as u8, app as u8]);
+        let other = push_blob(&mut blob, &[0, util as u8]);
+        let hash = push_blob(&mut blob, &[1, 2, 3, 4]);
+        let points = push_blob(&mut blob, &[0x11, 0, 2, 20, 5, 9, 4, 0, 0, 3, 1, 8, 6, 0x7f, 0, 2, 2, 0, 5, 2, 0]);
+
+        let mut guid = GUID_CSHARP.to_vec();
+        guid.extend_from_slice(&GUID_SHA256);
+
+        let mut pdb = vec![0xaa; 16];
+        pdb.extend_from_slice(&0x12345678u32.to_le_bytes());
+        pdb.extend_from_slice(&0x06000001u32.to_le_bytes());
+        pdb.extend_from_slice(&0u64.to_le_bytes());
+
+        let mut tables = vec![0, 0, 0, 0, 2, 0, 0, 1];
+        tables.extend_from_slice(&(1u64 << TABLE_DOCUMENT | 1u64 << TABLE_METHOD_DEBUG_INFORMATION).to_le_bytes());
+        tables.extend_from_slice(&0u64.to_le_bytes());
+        tables.extend_from_slice(&2u32.to_le_bytes());
+        tables.extend_from_slice(&2u32.to_le_bytes());
+        for index in [name, 2, hash, 1, other, 0, 0, 1, 1, points, 0, 0] {
+            tables.extend_from_slice(&(index as u16).to_le_bytes());
+        }
+        metadata(&[("#Pdb", pdb), ("#~", tables), ("#Strings", vec![0; 4]), ("#GUID", guid), ("#Blob", blob)])
+    }
+
+    #[test]
+    fn parses_portable_pdb() {
+        let data = sample_pdb();
+        let pdb = parse_portable_pdb(&data).unwrap();
+        assert!(pdb.anomalies.is_empty());
+        let metadata = pdb.metadata.as_ref().unwrap();
+        assert_eq!((metadata.major_version, metadata.minor_version, metadata.version.as_str()), (1, 1, "PDB v1.0"));
+        assert_eq!(metadata.streams.iter().map(|stream| stream.name.as_str()).collect::<Vec<&str>>(), vec!["#Pdb", "#~", "#Strings", "#GUID", "#Blob"]);
+        assert_eq!((pdb.guid(), pdb.stamp(), pdb.entry_point), ([0xaa; 16], 0x12345678, 0x06000001));
+
+        let documents: Vec<(&str, String, &[u8], String)> = pdb.documents.iter()
+            .map(|document| (document.name.as_str(), document.hash_algorithm_name(), document.hash.as_slice(), document.language_name()))
+            .collect();
+        assert_eq!(documents, vec![
+            ("src/app.cs", String::from("SHA256"), &[1u8, 2, 3, 4][..], String::from("C#")),
+            ("util.cs", String::from("-"), &[][..], String::from("C#")),
+        ]);
+
+        let method = pdb.method(0x06000001).unwrap();
+        assert_eq!((method.document, method.local_signature), (1, 0x11));
+        let points: Vec<(u32, u32, u32, u32, u32, u32, bool)> = method.sequence_points.iter()
+            .map(|point| (point.il_offset, point.document, point.start_line, point.start_column, point.end_line, point.end_column, point.is_hidden()))
+            .collect();
+        assert_eq!(points, vec![
+            (0, 1, 5, 9, 7, 19, false),
+            (4, 1, HIDDEN_LINE, 0, HIDDEN_LINE, 0, true),
+            (7, 1, 8, 8, 9, 12, false),
+            (9, 2, 9, 8, 9, 13, false),
+        ]);
+        assert!(pdb.method(0x06000002).unwrap().sequence_points.is_empty());
+        assert!(pdb.method(0x06000003).is_none());
+    }
+
+    #[test]
+    fn verifies_checksums_and_embedded_pdbs() {
+        let data = sample_pdb();
+        let mut zeroed = data.clone();
+        let start = parse_metadata_root(&data).unwrap().streams[0].offset as usize;
+        zeroed[start..start + PDB_ID_SIZE].fill(0);
+        let mut entry = b"SHA256\0".to_vec();
+        entry.extend(digest(HashAlgorithm::Sha256, &zeroed));
+        let checksum = parse_pdb_checksum(&entry).unwrap();
+        assert_eq!(checksum.algorithm, "SHA256");
+        assert_eq!(verify_pdb_checksum(&checksum, &data), Some(true));
+        let mut tampered = data.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(verify_pdb_checksum(&checksum, &tampered), Some(false));
+        assert_eq!(verify_pdb_checksum(&PdbChecksum { algorithm: String::from("MD5"), checksum: Vec::new() }, &data), None);
+
+        // MPDB header followed by a single stored DEFLATE block
+        let mut embedded = MPDB_SIGNATURE.to_le_bytes().to_vec();
+        embedded.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        embedded.push(1);
+        embedded.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        embedded.extend_from_slice(&(!(data.len() as u16)).to_le_bytes());
+        embedded.extend_from_slice(&data);
+        let pdb = parse_embedded_pdb(&embedded).unwrap();
+        assert_eq!((pdb.uncompressed_size as usize, pdb.compressed_size), (data.len(), data.len() + 5));
+        assert_eq!(pdb.data, data);
+        embedded[4] ^= 1;
+        assert!(parse_embedded_pdb(&embedded).is_none());
+        assert!(parse_embedded_pdb(b"NOPE\0\0\0\0").is_none());
+    }
+
+    #[test]
+    fn malformed_metadata() {
+        let mut tables = vec![0; 8];
+        tables.extend_from_slice(&(1u64 << TABLE_DOCUMENT).to_le_bytes());
+        let pdb = parse_portable_pdb(&metadata(&[("#~", tables)])).unwrap();
+        let streams: Vec<&str> = pdb.metadata.as_ref().unwrap().streams.iter().map(|stream| stream.name.as_str()).collect();
+        assert_eq!(streams, vec!["#~"]);
+        assert_eq!(pdb.id, [0; PDB_ID_SIZE]);
+        assert!(pdb.table_rows.is_empty());
+        assert!(pdb.documents.is_empty());
+        assert_eq!(pdb.anomalies, vec![
+            String::from("Metadata has no #Pdb stream, this is not a portable PDB"),
+            String::from("Tables stream is truncated"),
+        ]);
+
+        let mut tables = vec![0; 8];
+        tables.extend_from_slice(&0x3u64.to_le_bytes());
+        tables.extend_from_slice(&[0; 8]);
+        tables.extend_from_slice(&5u32.to_le_bytes());
+        tables.extend_from_slice(&7u32.to_le_bytes());
+        let pdb = parse_portable_pdb(&metadata(&[("#Pdb", vec![0; 32]), ("#~", tables)])).unwrap();
+        assert_eq!(&pdb.table_rows[..3], &[5, 7, 0]);
+        assert!(pdb.documents.is_empty());
+        assert!(pdb.methods.is_empty());
+        assert_eq!(pdb.anomalies, vec![String::from("Tables stream holds type system tables 0x3, debug tables are not decoded")]);
+
+        let pdb = parse_portable_pdb(&metadata(&[("#Pdb", vec![0; 32])])).unwrap();
+        assert_eq!(pdb.metadata.as_ref().unwrap().streams.len(), 1);
+        assert!(pdb.table_rows.is_empty());
+        assert_eq!(pdb.anomalies, vec![String::from("Metadata has no tables stream")]);
+        assert!(parse_portable_pdb(b"MZ\0\0").is_none());
+    }
+}
@@ -0,0 +1,427 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+
+const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+
+const IMAGE_FILE_MACHINE_R3000: u16 = 0x0162;
+const IMAGE_FILE_MACHINE_R4000: u16 = 0x0166;
+const IMAGE_FILE_MACHINE_WCEMIPSV2: u16 = 0x0169;
+const IMAGE_FILE_MACHINE_ARM: u16 = 0x01c0;
+const IMAGE_FILE_MACHINE_THUMB: u16 = 0x01c2;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x01c4;
+const IMAGE_FILE_MACHINE_MIPS16: u16 = 0x0266;
+const IMAGE_FILE_MACHINE_MIPSFPU: u16 = 0x0366;
+const IMAGE_FILE_MACHINE_MIPSFPU16: u16 = 0x0466;
+const IMAGE_FILE_MACHINE_RISCV32: u16 = 0x5032;
+const IMAGE_FILE_MACHINE_RISCV64: u16 = 0x5064;
+const IMAGE_FILE_MACHINE_RISCV128: u16 = 0x5128;
+const IMAGE_FILE_MACHINE_LOONGARCH32: u16 = 0x6232;
+const IMAGE_FILE_MACHINE_LOONGARCH64: u16 = 0x6264;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[allow(non_camel_case_types)]
+pub enum Relocation {
+    IMAGE_REL_BASED_ABSOLUTE,
+    IMAGE_REL_BASED_HIGH,
+    IMAGE_REL_BASED_LOW,
+    IMAGE_REL_BASED_HIGHLOW,
+    IMAGE_REL_BASED_HIGHADJ,
+    IMAGE_REL_BASED_MIPS_JMPADDR,
+    IMAGE_REL_BASED_ARM_MOV32,
+    IMAGE_REL_BASED_RISCV_HIGH20,
+    IMAGE_REL_BASED_THUMB_MOV32,
+    IMAGE_REL_BASED_RISCV_LOW12I,
+    IMAGE_REL_BASED_RISCV_LOW12S,
+    IMAGE_REL_BASED_LOONGARCH32_MARK_LA,
+    IMAGE_REL_BASED_LOONGARCH64_MARK_LA,
+    IMAGE_REL_BASED_MIPS_JMPADDR16,
+    IMAGE_REL_BASED_DIR64,
+    UNKNOWN(u8),
+}
+
+pub struct RelocationEntry {
+    pub rva: u32,
+    pub offset: u16,
+    pub relocation: Relocation,
+    // Only present for IMAGE_REL_BASED_HIGHADJ, which consumes the following slot as the low 16 bits
+    pub adjustment: Option<u16>,
+}
+
+pub struct RelocationBlock {
+    pub page_rva: u32,
+    pub block_size: u32,
+    pub entries: Vec<RelocationEntry>,
+}
+
+#[derive(Default)]
+pub struct BaseRelocations {
+    pub blocks: Vec<RelocationBlock>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl Relocation {
+    // Types 5, 7, 8 and 9 are reused by different architectures, so the machine decides their meaning
+    pub fn from_type(value: u8, machine: u16) -> Relocation {
+        match (value, machine) {
+            (0, _) => Relocation::IMAGE_REL_BASED_ABSOLUTE,
+            (1, _) => Relocation::IMAGE_REL_BASED_HIGH,
+            (2, _) => Relocation::IMAGE_REL_BASED_LOW,
+            (3, _) => Relocation::IMAGE_REL_BASED_HIGHLOW,
+            (4, _) => Relocation::IMAGE_REL_BASED_HIGHADJ,
+            (5, m) if is_mips(m) => Relocation::IMAGE_REL_BASED_MIPS_JMPADDR,
+            (5, m) if is_arm(m) => Relocation::IMAGE_REL_BASED_ARM_MOV32,
+            (5, m) if is_riscv(m) => Relocation::IMAGE_REL_BASED_RISCV_HIGH20,
+            (7, m) if is_arm(m) => Relocation::IMAGE_REL_BASED_THUMB_MOV32,
+            (7, m) if is_riscv(m) => Relocation::IMAGE_REL_BASED_RISCV_LOW12I,
+            (8, m) if is_riscv(m) => Relocation::IMAGE_REL_BASED_RISCV_LOW12S,
+            (8, IMAGE_FILE_MACHINE_LOONGARCH32) => Relocation::IMAGE_REL_BASED_LOONGARCH32_MARK_LA,
+            (8, IMAGE_FILE_MACHINE_LOONGARCH64) => Relocation::IMAGE_REL_BASED_LOONGARCH64_MARK_LA,
+            (9, m) if is_mips(m) => Relocation::IMAGE_REL_BASED_MIPS_JMPADDR16,
+            (10, _) => Relocation::IMAGE_REL_BASED_DIR64,
+            (v, _) => Relocation::UNKNOWN(v),
+        }
+    }
+}
+
+fn is_mips(machine: u16) -> bool {
+    matches!(machine, IMAGE_FILE_MACHINE_R3000 | IMAGE_FILE_MACHINE_R4000 | IMAGE_FILE_MACHINE_WCEMIPSV2
+        | IMAGE_FILE_MACHINE_MIPS16 | IMAGE_FILE_MACHINE_MIPSFPU | IMAGE_FILE_MACHINE_MIPSFPU16)
+}
+
+fn is_arm(machine: u16) -> bool {
+    matches!(machine, IMAGE_FILE_MACHINE_ARM | IMAGE_FILE_MACHINE_THUMB | IMAGE_FILE_MACHINE_ARMNT)
+}
+
+fn is_riscv(machine: u16) -> bool {
+    matches!(machine, IMAGE_FILE_MACHINE_RISCV32 | IMAGE_FILE_MACHINE_RISCV64 | IMAGE_FILE_MACHINE_RISCV128)
+}
+
+
+// Parsing
+
+// True when some section overlaps the 4 KB page starting at the given RVA
+fn page_in_section(headers: &Headers, page_rva: u32) -> bool {
+    let page_end = page_rva.saturating_add(0x1000);
+    headers.sections.iter().any(|section| {
+        let size = section.virtual_size.max(section.size_of_raw_data);
+        section.virtual_address < page_end && page_rva < section.virtual_address.saturating_add(size)
+    })
+}
+
+fn parse_block(block: &[u8], page_rva: u32, machine: u16, size_of_image: u32, relocations: &mut BaseRelocations) -> Vec<RelocationEntry> {
+    let mut entries = Vec::new();
+    let mut index: usize = 8;
+    while let Some(value) = read_u16_at(block, index) {
+        index += 2;
+        let offset = value & 0x0fff;
+        let relocation = Relocation::from_type((value >> 12) as u8, machine);
+        let rva = page_rva.wrapping_add(offset as u32);
+        let mut adjustment = None;
+        match relocation {
+            Relocation::IMAGE_REL_BASED_HIGHADJ => {
+                adjustment = read_u16_at(block, index);
+                if adjustment.is_none() {
+                    relocations.anomalies.push(format!("HIGHADJ relocation at RVA 0x{:x} is missing its adjustment slot", rva));
+                }
+                index += 2;
+            }
+            Relocation::UNKNOWN(v) => {
+                relocations.anomalies.push(format!("Relocation at RVA 0x{:x} has type {} which is not valid for machine 0x{:x}", rva, v, machine));
+            }
+            _ => {}
+        }
+        if relocation != Relocation::IMAGE_REL_BASED_ABSOLUTE && rva >= size_of_image {
+            relocations.anomalies.push(format!("Relocation at RVA 0x{:x} points outside of the image", rva));
+        }
+        entries.push(RelocationEntry { rva, offset, relocation, adjustment });
+    }
+    entries
+}
+
+pub fn parse_base_relocations(file: &[u8], headers: &Headers) -> BaseRelocations {
+    let mut relocations = BaseRelocations::default();
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_BASERELOC];
+    if directory.virtual_address == 0 || directory.size == 0 {
+        return relocations;
+    }
+    let start = match rva_to_offset(headers, directory.virtual_address) {
+        Some(start) if start < file.len() => start,
+        _ => {
+            relocations.anomalies.push(format!("Base relocation directory RVA 0x{:x} is not backed by the file", directory.virtual_address));
+            return relocations;
+        }
+    };
+    let mut end = start + directory.size as usize;
+    if end > file.len() {
+        relocations.anomalies.push(format!("Base relocation directory is truncated: 0x{:x} bytes declared, 0x{:x} available", directory.size, file.len() - start));
+        end = file.len();
+    }
+    let directory_data = &file[start..end];
+
+    let machine = headers.coff_headers.target_machine;
+    let size_of_image = headers.optional_headers.windows_specific.size_of_image;
+    let mut index: usize = 0;
+    while index < directory_data.len() {
+        let (page_rva, block_size) = match (read_u32_at(directory_data, index), read_u32_at(directory_data, index + 4)) {
+            (Some(page_rva), Some(block_size)) => (page_rva, block_size),
+            _ => {
+                relocations.anomalies.push(format!("0x{:x} trailing bytes after the last relocation block", directory_data.len() - index));
+                break;
+            }
+        };
+        if block_size < 8 {
+            relocations.anomalies.push(format!("Block at directory offset 0x{:x} has invalid size 0x{:x}", index, block_size));
+            break;
+        }
+        if block_size % 4 != 0 {
+            relocations.anomalies.push(format!("Block at directory offset 0x{:x} has size 0x{:x} which is not 32-bit aligned", index, block_size));
+        }
+        if page_rva % 0x1000 != 0 {
+            relocations.anomalies.push(format!("Block at directory offset 0x{:x} has page RVA 0x{:x} which is not page aligned", index, page_rva));
+        }
+        if !page_in_section(headers, page_rva) {
+            relocations.anomalies.push(format!("Block at directory offset 0x{:x} has page RVA 0x{:x} which is outside of every section", index, page_rva));
+        }
+        let mut block_end = index + block_size as usize;
+        if block_end > directory_data.len() {
+            relocations.anomalies.push(format!("Block at directory offset 0x{:x} with size 0x{:x} overruns the directory", index, block_size));
+            block_end = directory_data.len();
+        }
+        let entries = parse_block(&directory_data[index..block_end], page_rva, machine, size_of_image, &mut relocations);
+        relocations.blocks.push(RelocationBlock { page_rva, block_size, entries });
+        index = block_end;
+    }
+    relocations
+}
+
+// Counts the entries of every relocation type, ordered by type
+pub fn relocation_statistics(relocations: &BaseRelocations) -> Vec<(Relocation, usize)> {
+    let mut statistics: Vec<(Relocation, usize)> = Vec::new();
+    for entry in relocations.blocks.iter().flat_map(|block| block.entries.iter()) {
+        match statistics.iter_mut().find(|(relocation, _)| *relocation == entry.relocation) {
+            Some((_, count)) => *count += 1,
+            None => statistics.push((entry.relocation, 1)),
+        }
+    }
+    statistics.sort();
+    statistics
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for BaseRelocations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Base Relocations
+
+---------------------------")?;
+        for block in &self.blocks {
+            write!(f, "{}", block)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for RelocationBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+page_rva: 0x{:x}
+block_size: 0x{:x}
+entries: {}",
+        self.page_rva, self.block_size, self.entries.len())?;
+        for entry in &self.entries {
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RelocationEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+    rva: 0x{:x} offset: 0x{:03x} type: {:?}",
+        self.rva, self.offset, self.relocation)?;
+        if let Some(adjustment) = self.adjustment {
+            write!(f, " adjustment: 0x{:x}", adjustment)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    const IMAGE_FILE_MACHINE_I386: u16 = 0x014c;
+    const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+    // Base relocation directory at 0x800 in an image whose single section covers page 0
+    fn image(machine: u16, directory: &[u8]) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; 0x1000];
+        put(&mut file, 0x800, directory);
+        let mut headers = flat_headers(true, file.len());
+        headers.coff_headers.target_machine = machine;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_BASERELOC].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_BASERELOC].size = directory.len() as u32;
+        (file, headers)
+    }
+
+    fn block(page_rva: u32, entries: &[u16]) -> Vec<u8> {
+        let mut data = page_rva.to_le_bytes().to_vec();
+        data.extend_from_slice(&(8 + entries.len() as u32 * 2).to_le_bytes());
+        data.extend(entries.iter().flat_map(|entry| entry.to_le_bytes()));
+        data
+    }
+
+    fn kinds(block: &RelocationBlock) -> Vec<(u32, Relocation, Option<u16>)> {
+        block.entries.iter().map(|entry| (entry.rva, entry.relocation, entry.adjustment)).collect()
+    }
+
+    #[test]
+    fn parses_blocks() {
+        let mut directory = block(0, &[0x3500, 0x4510, 0x1234, 0xa520, 0]);
+        // A HIGHADJ in the last slot of a block has no adjustment to consume
+        directory.extend(block(0, &[0x3600, 0x4610]));
+        let (file, headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(relocations.blocks.len(), 2);
+        assert_eq!((relocations.blocks[0].page_rva, relocations.blocks[0].block_size), (0, 18));
+        assert_eq!(kinds(&relocations.blocks[0]), vec![
+            (0x500, Relocation::IMAGE_REL_BASED_HIGHLOW, None),
+            (0x510, Relocation::IMAGE_REL_BASED_HIGHADJ, Some(0x1234)),
+            (0x520, Relocation::IMAGE_REL_BASED_DIR64, None),
+            (0, Relocation::IMAGE_REL_BASED_ABSOLUTE, None),
+        ]);
+        assert_eq!(relocations.blocks[0].entries[1].offset, 0x510);
+        assert_eq!(kinds(&relocations.blocks[1]), vec![
+            (0x600, Relocation::IMAGE_REL_BASED_HIGHLOW, None),
+            (0x610, Relocation::IMAGE_REL_BASED_HIGHADJ, None),
+        ]);
+        assert_eq!(relocations.anomalies, vec![
+            String::from("Block at directory offset 0x0 has size 0x12 which is not 32-bit aligned"),
+            String::from("HIGHADJ relocation at RVA 0x610 is missing its adjustment slot"),
+        ]);
+        assert_eq!(relocation_statistics(&relocations), vec![
+            (Relocation::IMAGE_REL_BASED_ABSOLUTE, 1),
+            (Relocation::IMAGE_REL_BASED_HIGHLOW, 2),
+            (Relocation::IMAGE_REL_BASED_HIGHADJ, 2),
+            (Relocation::IMAGE_REL_BASED_DIR64, 1),
+        ]);
+        assert!(relocation_statistics(&BaseRelocations::default()).is_empty());
+    }
+
+    #[test]
+    fn decodes_machine_dependent_types() {
+        let decode = |machine: u16| [5u8, 7, 8, 9].map(|value| Relocation::from_type(value, machine));
+        assert_eq!(decode(IMAGE_FILE_MACHINE_I386), [Relocation::UNKNOWN(5), Relocation::UNKNOWN(7), Relocation::UNKNOWN(8), Relocation::UNKNOWN(9)]);
+        assert_eq!(decode(IMAGE_FILE_MACHINE_ARMNT), [
+            Relocation::IMAGE_REL_BASED_ARM_MOV32,
+            Relocation::IMAGE_REL_BASED_THUMB_MOV32,
+            Relocation::UNKNOWN(8),
+            Relocation::UNKNOWN(9),
+        ]);
+        assert_eq!(decode(IMAGE_FILE_MACHINE_ARM64), [Relocation::UNKNOWN(5), Relocation::UNKNOWN(7), Relocation::UNKNOWN(8), Relocation::UNKNOWN(9)]);
+        assert_eq!(decode(IMAGE_FILE_MACHINE_RISCV64), [
+            Relocation::IMAGE_REL_BASED_RISCV_HIGH20,
+            Relocation::IMAGE_REL_BASED_RISCV_LOW12I,
+            Relocation::IMAGE_REL_BASED_RISCV_LOW12S,
+            Relocation::UNKNOWN(9),
+        ]);
+        assert_eq!(decode(IMAGE_FILE_MACHINE_R4000), [
+            Relocation::IMAGE_REL_BASED_MIPS_JMPADDR,
+            Relocation::UNKNOWN(7),
+            Relocation::UNKNOWN(8),
+            Relocation::IMAGE_REL_BASED_MIPS_JMPADDR16,
+        ]);
+        assert_eq!(decode(IMAGE_FILE_MACHINE_LOONGARCH64)[2], Relocation::IMAGE_REL_BASED_LOONGARCH64_MARK_LA);
+
+        // Types the machine does not define are reported, and still counted
+        let (file, headers) = image(IMAGE_FILE_MACHINE_ARM64, &block(0, &[0x5500, 0x7504]));
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(kinds(&relocations.blocks[0]), vec![(0x500, Relocation::UNKNOWN(5), None), (0x504, Relocation::UNKNOWN(7), None)]);
+        assert_eq!(relocations.anomalies, vec![
+            String::from("Relocation at RVA 0x500 has type 5 which is not valid for machine 0xaa64"),
+            String::from("Relocation at RVA 0x504 has type 7 which is not valid for machine 0xaa64"),
+        ]);
+        assert_eq!(relocation_statistics(&relocations), vec![(Relocation::UNKNOWN(5), 1), (Relocation::UNKNOWN(7), 1)]);
+        let (file, headers) = image(IMAGE_FILE_MACHINE_ARMNT, &block(0, &[0x5500, 0x7508]));
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(kinds(&relocations.blocks[0]), vec![
+            (0x500, Relocation::IMAGE_REL_BASED_ARM_MOV32, None),
+            (0x508, Relocation::IMAGE_REL_BASED_THUMB_MOV32, None),
+        ]);
+        assert!(relocations.anomalies.is_empty());
+    }
+
+    #[test]
+    fn malformed_blocks() {
+        // Sizes below the block header stop the walk
+        let mut directory = block(0, &[]);
+        put(&mut directory, 4, &4u32.to_le_bytes());
+        let (file, headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        let relocations = parse_base_relocations(&file, &headers);
+        assert!(relocations.blocks.is_empty());
+        assert_eq!(relocations.anomalies, vec![String::from("Block at directory offset 0x0 has invalid size 0x4")]);
+
+        // An odd size leaves its last byte undecoded and misaligns the next block
+        let mut directory = block(0, &[0x3500]);
+        directory.push(0xff);
+        put(&mut directory, 4, &11u32.to_le_bytes());
+        directory.extend(block(0, &[0x3600, 0]));
+        let (file, headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(relocations.blocks.len(), 2);
+        assert_eq!(kinds(&relocations.blocks[0]), vec![(0x500, Relocation::IMAGE_REL_BASED_HIGHLOW, None)]);
+        assert_eq!(kinds(&relocations.blocks[1]), vec![(0x600, Relocation::IMAGE_REL_BASED_HIGHLOW, None), (0, Relocation::IMAGE_REL_BASED_ABSOLUTE, None)]);
+        assert_eq!(relocations.anomalies, vec![String::from("Block at directory offset 0x0 has size 0xb which is not 32-bit aligned")]);
+
+        // Pages outside of every section, inside and then outside of the image
+        let mut directory = block(0x2000, &[0x3010, 0]);
+        directory.extend(block(0x5000, &[0x3020, 0]));
+        let (file, mut headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        headers.optional_headers.windows_specific.size_of_image = 0x4000;
+        let relocations = parse_base_relocations(&file, &headers);
+        let pages: Vec<u32> = relocations.blocks.iter().map(|block| block.page_rva).collect();
+        assert_eq!(pages, vec![0x2000, 0x5000]);
+        assert_eq!(kinds(&relocations.blocks[1])[0], (0x5020, Relocation::IMAGE_REL_BASED_HIGHLOW, None));
+        assert_eq!(relocations.anomalies, vec![
+            String::from("Block at directory offset 0x0 has page RVA 0x2000 which is outside of every section"),
+            String::from("Block at directory offset 0xc has page RVA 0x5000 which is outside of every section"),
+            String::from("Relocation at RVA 0x5020 points outside of the image"),
+        ]);
+
+        // Unaligned page, a block overrunning the directory, then bytes too short for a block header
+        let mut directory = block(0x10, &[0x3000, 0]);
+        put(&mut directory, 4, &0x20u32.to_le_bytes());
+        let (file, headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(kinds(&relocations.blocks[0]), vec![(0x10, Relocation::IMAGE_REL_BASED_HIGHLOW, None), (0x10, Relocation::IMAGE_REL_BASED_ABSOLUTE, None)]);
+        assert_eq!(relocations.anomalies, vec![
+            String::from("Block at directory offset 0x0 has page RVA 0x10 which is not page aligned"),
+            String::from("Block at directory offset 0x0 with size 0x20 overruns the directory"),
+        ]);
+        let mut directory = block(0, &[0x3500, 0]);
+        directory.extend_from_slice(&[0; 4]);
+        let (file, headers) = image(IMAGE_FILE_MACHINE_I386, &directory);
+        let relocations = parse_base_relocations(&file, &headers);
+        assert_eq!(relocations.blocks.len(), 1);
+        assert_eq!(relocations.anomalies, vec![String::from("0x4 trailing bytes after the last relocation block")]);
+    }
+}
@@ -0,0 +1,562 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::collections::HashSet;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::read_utf16_at;
+use crate::utils::rva_to_offset;
+use crate::utils::slice_at_rva;
+
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+
+// The tree is type/name/language, anything deeper than this is considered hostile
+const MAX_DEPTH: usize = 8;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResourceId {
+    Id(u32),
+    Name(String),
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+pub enum ResourceType {
+    RT_CURSOR,
+    RT_BITMAP,
+    RT_ICON,
+    RT_MENU,
+    RT_DIALOG,
+    RT_STRING,
+    RT_FONTDIR,
+    RT_FONT,
+    RT_ACCELERATOR,
+    RT_RCDATA,
+    RT_MESSAGETABLE,
+    RT_GROUP_CURSOR,
+    RT_GROUP_ICON,
+    RT_VERSION,
+    RT_DLGINCLUDE,
+    RT_PLUGPLAY,
+    RT_VXD,
+    RT_ANICURSOR,
+    RT_ANIICON,
+    RT_HTML,
+    RT_MANIFEST,
+    UNKNOWN(u32),
+    NAMED(String),
+}
+
+pub struct ResourceDirectory {
+    pub characteristics: u32,
+    pub time_date_stamp: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub number_of_named_entries: u16,
+    pub number_of_id_entries: u16,
+    pub entries: Vec<ResourceDirectoryEntry>,
+}
+
+pub struct ResourceDirectoryEntry {
+    pub id: ResourceId,
+    pub node: ResourceNode,
+}
+
+pub enum ResourceNode {
+    Directory(ResourceDirectory),
+    Data(ResourceDataEntry),
+}
+
+#[derive(Clone, Copy)]
+pub struct ResourceDataEntry {
+    pub data_rva: u32,
+    pub size: u32,
+    pub code_page: u32,
+    pub reserved: u32,
+}
+
+// A leaf of the tree together with the path leading to it
+#[derive(Clone)]
+pub struct Resource {
+    pub resource_type: ResourceType,
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub data: ResourceDataEntry,
+}
+
+#[derive(Default)]
+pub struct Resources {
+    pub root: Option<ResourceDirectory>,
+    pub resources: Vec<Resource>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl ResourceType {
+    pub fn from_id(id: &ResourceId) -> ResourceType {
+        match id {
+            ResourceId::Name(name) => ResourceType::NAMED(name.clone()),
+            ResourceId::Id(1) => ResourceType::RT_CURSOR,
+            ResourceId::Id(2) => ResourceType::RT_BITMAP,
+            ResourceId::Id(3) => ResourceType::RT_ICON,
+            ResourceId::Id(4) => ResourceType::RT_MENU,
+            ResourceId::Id(5) => ResourceType::RT_DIALOG,
+            ResourceId::Id(6) => ResourceType::RT_STRING,
+            ResourceId::Id(7) => ResourceType::RT_FONTDIR,
+            ResourceId::Id(8) => ResourceType::RT_FONT,
+            ResourceId::Id(9) => ResourceType::RT_ACCELERATOR,
+            ResourceId::Id(10) => ResourceType::RT_RCDATA,
+            ResourceId::Id(11) => ResourceType::RT_MESSAGETABLE,
+            ResourceId::Id(12) => ResourceType::RT_GROUP_CURSOR,
+            ResourceId::Id(14) => ResourceType::RT_GROUP_ICON,
+            ResourceId::Id(16) => ResourceType::RT_VERSION,
+            ResourceId::Id(17) => ResourceType::RT_DLGINCLUDE,
+            ResourceId::Id(19) => ResourceType::RT_PLUGPLAY,
+            ResourceId::Id(20) => ResourceType::RT_VXD,
+            ResourceId::Id(21) => ResourceType::RT_ANICURSOR,
+            ResourceId::Id(22) => ResourceType::RT_ANIICON,
+            ResourceId::Id(23) => ResourceType::RT_HTML,
+            ResourceId::Id(24) => ResourceType::RT_MANIFEST,
+            ResourceId::Id(v) => ResourceType::UNKNOWN(*v),
+        }
+    }
+
+    // Parses either an RT_* name, a numeric ID or a custom type name
+    pub fn from_string(value: &str) -> ResourceType {
+        let known = (1..=24).map(|id| ResourceType::from_id(&ResourceId::Id(id)))
+            .find(|resource_type| format!("{:?}", resource_type) == value);
+        match known {
+            Some(resource_type) => resource_type,
+            None => ResourceType::from_id(&ResourceId::from_string(value)),
+        }
+    }
+}
+
+impl ResourceId {
+    pub fn from_string(value: &str) -> ResourceId {
+        match value.parse::<u32>() {
+            Ok(id) => ResourceId::Id(id),
+            Err(_) => ResourceId::Name(value.to_string()),
+        }
+    }
+}
+
+
+// Parsing
+
+struct Walker<'a> {
+    section: &'a [u8],
+    visited: HashSet<usize>,
+    resources: Resources,
+}
+
+impl<'a> Walker<'a> {
+    fn parse_name(&mut self, offset: usize) -> ResourceId {
+        let name = read_u16_at(self.section, offset)
+            .and_then(|length| read_utf16_at(self.section, offset + 2, length as usize));
+        match name {
+            Some(name) => ResourceId::Name(name),
+            None => {
+                self.resources.anomalies.push(format!("Resource name at offset 0x{:x} is outside of the resource directory", offset));
+                ResourceId::Name(String::new())
+            }
+        }
+    }
+
+    fn parse_data_entry(&mut self, offset: usize) -> Option<ResourceDataEntry> {
+        match (read_u32_at(self.section, offset), read_u32_at(self.section, offset + 4), read_u32_at(self.section, offset + 8), read_u32_at(self.section, offset + 12)) {
+            (Some(data_rva), Some(size), Some(code_page), Some(reserved)) => Some(ResourceDataEntry { data_rva, size, code_page, reserved }),
+            _ => {
+                self.resources.anomalies.push(format!("Resource data entry at offset 0x{:x} is outside of the resource directory", offset));
+                None
+            }
+        }
+    }
+
+    fn parse_directory(&mut self, offset: usize, path: &mut Vec<ResourceId>) -> Option<ResourceDirectory> {
+        if self.visited.contains(&offset) {
+            self.resources.anomalies.push(format!("Resource directory at offset 0x{:x} is referenced more than once, possible loop", offset));
+            return None;
+        }
+        if path.len() >= MAX_DEPTH {
+            self.resources.anomalies.push(format!("Resource directory at offset 0x{:x} is nested too deeply", offset));
+            return None;
+        }
+        self.visited.insert(offset);
+        let header = self.section.get(offset..offset + 16);
+        if header.is_none() {
+            self.resources.anomalies.push(format!("Resource directory at offset 0x{:x} is outside of the resource directory", offset));
+            return None;
+        }
+        let mut directory = ResourceDirectory {
+            characteristics: read_u32_at(self.section, offset)?,
+            time_date_stamp: read_u32_at(self.section, offset + 4)?,
+            major_version: read_u16_at(self.section, offset + 8)?,
+            minor_version: read_u16_at(self.section, offset + 10)?,
+            number_of_named_entries: read_u16_at(self.section, offset + 12)?,
+            number_of_id_entries: read_u16_at(self.section, offset + 14)?,
+            entries: Vec::new(),
+        };
+        let count = directory.number_of_named_entries as usize + directory.number_of_id_entries as usize;
+        for x in 0..count {
+            let entry_offset = offset + 16 + x * 8;
+            let (name, target) = match (read_u32_at(self.section, entry_offset), read_u32_at(self.section, entry_offset + 4)) {
+                (Some(name), Some(target)) => (name, target),
+                _ => {
+                    self.resources.anomalies.push(format!("Resource directory at offset 0x{:x} declares {} entries but is truncated", offset, count));
+                    break;
+                }
+            };
+            let id = if name & 0x80000000 != 0 {
+                self.parse_name((name & 0x7fffffff) as usize)
+            } else {
+                ResourceId::Id(name)
+            };
+            path.push(id.clone());
+            let node = if target & 0x80000000 != 0 {
+                self.parse_directory((target & 0x7fffffff) as usize, path).map(ResourceNode::Directory)
+            } else {
+                self.parse_data_entry(target as usize).map(|data| {
+                    self.add_leaf(path, data);
+                    ResourceNode::Data(data)
+                })
+            };
+            path.pop();
+            if let Some(node) = node {
+                directory.entries.push(ResourceDirectoryEntry { id, node });
+            }
+        }
+        Some(directory)
+    }
+
+    fn add_leaf(&mut self, path: &[ResourceId], data: ResourceDataEntry) {
+        if path.len() != 3 {
+            let path: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+            self.resources.anomalies.push(format!("Resource data entry at depth {} ({}) instead of type/name/language", path.len(), path.join("/")));
+            return;
+        }
+        self.resources.resources.push(Resource {
+            resource_type: ResourceType::from_id(&path[0]),
+            name: path[1].clone(),
+            language: path[2].clone(),
+            data,
+        });
+    }
+}
+
+pub fn parse_resources(file: &[u8], headers: &Headers) -> Resources {
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_RESOURCE];
+    if directory.virtual_address == 0 || directory.size == 0 {
+        return Resources::default();
+    }
+    let start = match rva_to_offset(headers, directory.virtual_address) {
+        Some(start) if start < file.len() => start,
+        _ => {
+            let mut resources = Resources::default();
+            resources.anomalies.push(format!("Resource directory RVA 0x{:x} is not backed by the file", directory.virtual_address));
+            return resources;
+        }
+    };
+    // Names and data entries may legitimately live past the declared size, so the rest of the file is reachable
+    let mut walker = Walker {
+        section: &file[start..],
+        visited: HashSet::new(),
+        resources: Resources::default(),
+    };
+    walker.resources.root = walker.parse_directory(0, &mut Vec::new());
+    walker.resources
+}
+
+// Returns the raw bytes of a resource, None if they are not present in the file
+pub fn resource_data<'a>(file: &'a [u8], headers: &Headers, resource: &Resource) -> Option<&'a [u8]> {
+    slice_at_rva(file, headers, resource.data.data_rva, resource.data.size as usize)
+}
+
+// Finds a resource by its type/name/language path
+pub fn find_resource<'a>(resources: &'a Resources, resource_type: &ResourceType, name: &ResourceId, language: &ResourceId) -> Option<&'a Resource> {
+    resources.resources.iter().find(|resource| {
+        resource.resource_type == *resource_type && resource.name == *name && resource.language == *language
+    })
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "{}", id),
+            ResourceId::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceType::UNKNOWN(id) => write!(f, "{}", id),
+            ResourceType::NAMED(name) => write!(f, "\"{}\"", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+impl fmt::Display for Resources {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Resources
+
+---------------------------")?;
+        if let Some(root) = &self.root {
+            root.fmt_level(f, 0)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl ResourceDirectory {
+    fn fmt_level(&self, f: &mut fmt::Formatter, level: usize) -> fmt::Result {
+        let indent = "    ".repeat(level);
+        for entry in &self.entries {
+            let label = match level {
+                0 => format!("type: {}", ResourceType::from_id(&entry.id)),
+                1 => format!("name: {}", entry.id),
+                2 => format!("language: {}", entry.id),
+                _ => format!("id: {}", entry.id),
+            };
+            match &entry.node {
+                ResourceNode::Directory(directory) => {
+                    write!(f, "\n{}{}", indent, label)?;
+                    directory.fmt_level(f, level + 1)?;
+                }
+                ResourceNode::Data(data) => write!(f, "\n{}{} {}", indent, label, data)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ResourceDataEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "data_rva: 0x{:x} size: 0x{:x} code_page: {}",
+        self.data_rva, self.size, self.code_page)
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}/{} {}",
+        self.resource_type, self.name, self.language, self.data)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+    use crate::utils::fixtures::utf16;
+
+    pub const RESOURCE_RVA: u32 = 0x1000;
+
+    pub type Leaf<'a> = (ResourceId, ResourceId, u32, &'a [u8]);
+
+    fn directory(tree: &mut Vec<u8>, ids: &[&ResourceId]) -> usize {
+        let offset = tree.len();
+        let named = ids.iter().filter(|id| matches!(id, ResourceId::Name(_))).count();
+        tree.resize(offset + 12, 0);
+        tree.extend_from_slice(&(named as u16).to_le_bytes());
+        tree.extend_from_slice(&((ids.len() - named) as u16).to_le_bytes());
+        tree.resize(offset + 16 + ids.len() * 8, 0);
+        offset
+    }
+
+    fn entry(tree: &mut Vec<u8>, names: &mut Vec<(usize, String)>, offset: usize, id: &ResourceId, target: u32) {
+        match id {
+            ResourceId::Id(id) => put(tree, offset, &id.to_le_bytes()),
+            ResourceId::Name(name) => names.push((offset, name.clone())),
+        }
+        put(tree, offset + 4, &target.to_le_bytes());
+    }
+
+    fn unique<'a>(ids: impl Iterator<Item = &'a ResourceId>) -> Vec<&'a ResourceId> {
+        let mut unique: Vec<&ResourceId> = Vec::new();
+        for id in ids {
+            if !unique.contains(&id) {
+                unique.push(id);
+            }
+        }
+        unique
+    }
+
+    // Type/name/language tree laid out as the linker does: directories and data entries, then the names, then the data
+    pub fn build_tree(leaves: &[Leaf]) -> Vec<u8> {
+        let mut tree = Vec::new();
+        let mut names = Vec::new();
+        let mut data = Vec::new();
+        let types = unique(leaves.iter().map(|leaf| &leaf.0));
+        let root = directory(&mut tree, &types);
+        for (x, resource_type) in types.iter().enumerate() {
+            let of_type: Vec<&Leaf> = leaves.iter().filter(|leaf| leaf.0 == **resource_type).collect();
+            let type_names = unique(of_type.iter().map(|leaf| &leaf.1));
+            let type_directory = directory(&mut tree, &type_names);
+            entry(&mut tree, &mut names, root + 16 + x * 8, resource_type, 0x80000000 | type_directory as u32);
+            for (y, name) in type_names.iter().enumerate() {
+                let languages: Vec<&&Leaf> = of_type.iter().filter(|leaf| leaf.1 == **name).collect();
+                let language_ids: Vec<ResourceId> = languages.iter().map(|leaf| ResourceId::Id(leaf.2)).collect();
+                let name_directory = directory(&mut tree, &language_ids.iter().collect::<Vec<&ResourceId>>());
+                entry(&mut tree, &mut names, type_directory + 16 + y * 8, name, 0x80000000 | name_directory as u32);
+                for (z, leaf) in languages.iter().enumerate() {
+                    let data_entry = tree.len();
+                    tree.resize(data_entry + 16, 0);
+                    put(&mut tree, data_entry + 4, &(leaf.3.len() as u32).to_le_bytes());
+                    entry(&mut tree, &mut names, name_directory + 16 + z * 8, &language_ids[z], data_entry as u32);
+                    data.push((data_entry, leaf.3));
+                }
+            }
+        }
+        for (offset, name) in names {
+            let string = tree.len() as u32;
+            tree.extend_from_slice(&(name.encode_utf16().count() as u16).to_le_bytes());
+            tree.extend_from_slice(&utf16(&name));
+            put(&mut tree, offset, &(0x80000000 | string).to_le_bytes());
+        }
+        for (offset, bytes) in data {
+            tree.resize(tree.len().next_multiple_of(4), 0);
+            let rva = RESOURCE_RVA + tree.len() as u32;
+            put(&mut tree, offset, &rva.to_le_bytes());
+            tree.extend_from_slice(bytes);
+        }
+        tree
+    }
+
+    // Image with the given raw resource directory at RESOURCE_RVA
+    pub fn image_with_tree(tree: &[u8]) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; RESOURCE_RVA as usize];
+        file.extend_from_slice(tree);
+        let mut headers = flat_headers(true, file.len());
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_RESOURCE].virtual_address = RESOURCE_RVA;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_RESOURCE].size = tree.len() as u32;
+        (file, headers)
+    }
+
+    pub fn resource_image(leaves: &[Leaf]) -> (Vec<u8>, Headers) {
+        image_with_tree(&build_tree(leaves))
+    }
+
+    // Directory with a single ID entry pointing at target
+    fn single_entry_directory(id: u32, target: u32) -> Vec<u8> {
+        let mut directory = vec![0; 16];
+        put(&mut directory, 14, &1u16.to_le_bytes());
+        directory.extend_from_slice(&id.to_le_bytes());
+        directory.extend_from_slice(&target.to_le_bytes());
+        directory
+    }
+
+    #[test]
+    fn walks_tree() {
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(16), ResourceId::Id(1), 0x409, b"english"),
+            (ResourceId::Id(16), ResourceId::Id(1), 0x407, b"german"),
+            (ResourceId::Name(String::from("PNG")), ResourceId::Name(String::from("LOGO")), 0, b"\x89PNG"),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        assert!(resources.anomalies.is_empty(), "{:?}", resources.anomalies);
+        assert_eq!(resources.resources.len(), 3);
+        assert_eq!(resources.root.as_ref().unwrap().entries.len(), 2);
+        let german = find_resource(&resources, &ResourceType::RT_VERSION, &ResourceId::Id(1), &ResourceId::Id(0x407)).unwrap();
+        assert_eq!(resource_data(&file, &headers, german).unwrap(), b"german");
+        let logo = find_resource(&resources, &ResourceType::NAMED(String::from("PNG")), &ResourceId::Name(String::from("LOGO")), &ResourceId::Id(0)).unwrap();
+        assert_eq!(resource_data(&file, &headers, logo).unwrap(), b"\x89PNG");
+    }
+
+    #[test]
+    fn detects_loops() {
+        // root -> directory at 0x18 -> root
+        let mut tree = single_entry_directory(16, 0x80000018);
+        tree.extend_from_slice(&single_entry_directory(1, 0x80000000));
+        let (file, headers) = image_with_tree(&tree);
+        let resources = parse_resources(&file, &headers);
+        assert!(resources.resources.is_empty());
+        let root = resources.root.as_ref().unwrap();
+        assert_eq!(root.entries.len(), 1);
+        assert_eq!(root.entries[0].id, ResourceId::Id(16));
+        match &root.entries[0].node {
+            ResourceNode::Directory(directory) => {
+                assert_eq!(directory.number_of_id_entries, 1);
+                assert!(directory.entries.is_empty());
+            }
+            ResourceNode::Data(_) => panic!("expected a directory"),
+        }
+        assert_eq!(resources.anomalies, vec![String::from("Resource directory at offset 0x0 is referenced more than once, possible loop")]);
+    }
+
+    #[test]
+    fn stops_at_max_depth() {
+        // A chain of distinct directories, each one pointing at the next
+        let mut tree = Vec::new();
+        for x in 0..MAX_DEPTH + 4 {
+            tree.extend_from_slice(&single_entry_directory(1, 0x80000000 | ((x + 1) * 24) as u32));
+        }
+        tree.extend_from_slice(&[0; 16]);
+        let (file, headers) = image_with_tree(&tree);
+        let resources = parse_resources(&file, &headers);
+        assert!(resources.resources.is_empty());
+        let mut depth = 0;
+        let mut directory = resources.root.as_ref().unwrap();
+        while let Some(ResourceNode::Directory(child)) = directory.entries.first().map(|entry| &entry.node) {
+            depth += 1;
+            directory = child;
+        }
+        assert_eq!(depth, MAX_DEPTH - 1);
+        assert!(directory.entries.is_empty());
+        assert_eq!(resources.anomalies, vec![format!("Resource directory at offset 0x{:x} is nested too deeply", MAX_DEPTH * 24)]);
+    }
+
+    #[test]
+    fn malformed_trees() {
+        // A data entry directly under the root
+        let mut tree = single_entry_directory(10, 0x18);
+        tree.extend_from_slice(&[0; 16]);
+        let (file, headers) = image_with_tree(&tree);
+        let resources = parse_resources(&file, &headers);
+        assert!(resources.resources.is_empty());
+        let root = resources.root.as_ref().unwrap();
+        assert_eq!(root.entries[0].id, ResourceId::Id(10));
+        assert!(matches!(root.entries[0].node, ResourceNode::Data(ResourceDataEntry { data_rva: 0, size: 0, .. })));
+        assert_eq!(resources.anomalies, vec![String::from("Resource data entry at depth 1 (10) instead of type/name/language")]);
+
+        // More entries declared than present
+        let mut tree = single_entry_directory(10, 0x18);
+        tree[14] = 5;
+        let (file, headers) = image_with_tree(&tree);
+        let resources = parse_resources(&file, &headers);
+        let root = resources.root.as_ref().unwrap();
+        assert_eq!((root.number_of_named_entries, root.number_of_id_entries), (0, 5));
+        // The data entry of the only entry present points past the end of the tree
+        assert!(root.entries.is_empty());
+        assert_eq!(resources.anomalies, vec![
+            String::from("Resource data entry at offset 0x18 is outside of the resource directory"),
+            String::from("Resource directory at offset 0x0 declares 5 entries but is truncated"),
+        ]);
+
+        // Directory RVA past the end of the file
+        let (file, mut headers) = image_with_tree(&tree);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_RESOURCE].virtual_address = 0x8000;
+        let resources = parse_resources(&file, &headers);
+        assert!(resources.root.is_none());
+        assert!(resources.resources.is_empty());
+        assert_eq!(resources.anomalies, vec![String::from("Resource directory RVA 0x8000 is not backed by the file")]);
+    }
+}
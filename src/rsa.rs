@@ -0,0 +1,189 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::vec::Vec;
+use crate::bignum::BigUint;
+use crate::der;
+use crate::pkcs7::OID_RSA_ENCRYPTION;
+use crate::sha::HashAlgorithm;
+
+// RSASSA-PKCS1-v1_5 (RFC 8017)
+
+#[derive(PartialEq, Eq)]
+pub struct RsaPublicKey {
+    pub modulus: BigUint,
+    pub exponent: BigUint,
+    // Modulus length in bytes
+    pub size: usize,
+}
+
+pub struct RsaPrivateKey {
+    pub public: RsaPublicKey,
+    pub private_exponent: BigUint,
+}
+
+pub fn hash_oid(algorithm: HashAlgorithm) -> &'static str {
+    match algorithm {
+        HashAlgorithm::Sha1 => "1.3.14.3.2.26",
+        HashAlgorithm::Sha256 => "2.16.840.1.101.3.4.2.1",
+        HashAlgorithm::Sha384 => "2.16.840.1.101.3.4.2.2",
+        HashAlgorithm::Sha512 => "2.16.840.1.101.3.4.2.3",
+    }
+}
+
+// RSAPublicKey as found in the subjectPublicKey BIT STRING
+pub fn parse_public_key(data: &[u8]) -> Option<RsaPublicKey> {
+    let key = der::parse(data)?;
+    let modulus = key.child(0)?.as_unsigned_bytes()?;
+    let exponent = key.child(1)?.as_unsigned_bytes()?;
+    Some(RsaPublicKey { modulus: BigUint::from_be_bytes(modulus), exponent: BigUint::from_be_bytes(exponent), size: modulus.len() })
+}
+
+// PKCS#8 PrivateKeyInfo wrapping an RSAPrivateKey, or a bare PKCS#1 RSAPrivateKey
+pub fn parse_private_key(data: &[u8]) -> Option<RsaPrivateKey> {
+    let mut key = der::parse(data)?;
+    if key.child(1)?.tag == der::TAG_SEQUENCE {
+        if key.child(1)?.child(0)?.as_oid()? != OID_RSA_ENCRYPTION {
+            return None;
+        }
+        let private_key = key.child(2)?;
+        if private_key.tag != der::TAG_OCTET_STRING {
+            return None;
+        }
+        key = der::parse(private_key.content)?;
+    }
+    let modulus = key.child(1)?.as_unsigned_bytes()?;
+    let public = RsaPublicKey { modulus: BigUint::from_be_bytes(modulus), exponent: BigUint::from_be_bytes(key.child(2)?.as_unsigned_bytes()?), size: modulus.len() };
+    Some(RsaPrivateKey { public, private_exponent: BigUint::from_be_bytes(key.child(3)?.as_unsigned_bytes()?) })
+}
+
+fn digest_info(algorithm: HashAlgorithm, digest: &[u8]) -> Vec<u8> {
+    let algorithm = der::encode_sequence(&[&der::encode_oid(hash_oid(algorithm)), &der::encode(der::TAG_NULL, &[])]);
+    der::encode_sequence(&[&algorithm, &der::encode(der::TAG_OCTET_STRING, digest)])
+}
+
+// RFC 8017 allows the parameters to be omitted as well
+fn digest_info_without_parameters(algorithm: HashAlgorithm, digest: &[u8]) -> Vec<u8> {
+    let algorithm = der::encode_sequence(&[&der::encode_oid(hash_oid(algorithm))]);
+    der::encode_sequence(&[&algorithm, &der::encode(der::TAG_OCTET_STRING, digest)])
+}
+
+// EMSA-PKCS1-v1_5 encoding of a DigestInfo for a modulus of size bytes
+fn encode_pkcs1(info: &[u8], size: usize) -> Option<Vec<u8>> {
+    if size < info.len() + 11 {
+        return None;
+    }
+    let mut message = vec![0xff; size];
+    message[0] = 0;
+    message[1] = 1;
+    message[size - info.len() - 1] = 0;
+    message[size - info.len()..].copy_from_slice(info);
+    Some(message)
+}
+
+// RSASP1 over EM = 0x00 0x01 PS 0x00 DigestInfo, checked with the public key before it is returned
+pub fn sign_pkcs1(key: &RsaPrivateKey, algorithm: HashAlgorithm, digest: &[u8]) -> Option<Vec<u8>> {
+    let size = key.public.size;
+    let message = encode_pkcs1(&digest_info(algorithm, digest), size)?;
+    let signature = BigUint::from_be_bytes(&message).mod_pow(&key.private_exponent, &key.public.modulus)?.to_be_bytes(size)?;
+    verify_pkcs1(&key.public, algorithm, digest, &signature).then_some(signature)
+}
+
+// Signature representative to encoded message, RSAVP1
+fn encoded_message(key: &RsaPublicKey, signature: &[u8]) -> Option<Vec<u8>> {
+    if signature.len() != key.size {
+        return None;
+    }
+    BigUint::from_be_bytes(signature).mod_pow(&key.exponent, &key.modulus)?.to_be_bytes(key.size)
+}
+
+// EM = 0x00 0x01 PS 0x00 DigestInfo rebuilt and compared byte for byte, DigestInfo with NULL or absent parameters
+pub fn verify_pkcs1(key: &RsaPublicKey, algorithm: HashAlgorithm, digest: &[u8], signature: &[u8]) -> bool {
+    let message = match encoded_message(key, signature) {
+        Some(message) => message,
+        None => return false,
+    };
+    [digest_info(algorithm, digest), digest_info_without_parameters(algorithm, digest)].iter()
+        .any(|info| encode_pkcs1(info, key.size).is_some_and(|expected| expected == message))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::sha;
+
+    // 512 bit key and the signatures of "abc" made with openssl dgst -sign
+    pub const PRIVATE_KEY: &str = concat!(
+        "3082013b020100024100a5f6b8d213f5ed5bf31d007b60cda920f85db2353343c077df5102a8cd32814b319e8ac679dd795e5b9d36bcc6bfb4efa82b399b60cb",
+        "33eda3ad2326fe9f51bb020301000102400285b1615ff448db30e2abf334116c149831b457a2a3d0f5016bd5c282fbd5832cc49042338ac19ccd3611f5d08fa7",
+        "25008a3573bea453120e714abb602c8a21022100d6f8557f789e121efb7f153fb49e6a23c437bc472e4eea64a80dc0ce6499b9e7022100c5a3e35888b65e1bb1",
+        "a0ea00056a5d22553f0ed19a4e9c3342bad7611b95f70d022100cffd6c33ae4f3a77319c4ec1d98ee06104ab6c866e5eba58736503246e9f7207022100c0cfe2",
+        "3bbd242d2b575f64ea205e63fddaac6a0892419ea163b863e698c2f50102203236e17f997550c070c089876e78b1af552695f43ab046a62eb8476f6532b829",
+    );
+    pub const PUBLIC_KEY: &str = "3048024100a5f6b8d213f5ed5bf31d007b60cda920f85db2353343c077df5102a8cd32814b319e8ac679dd795e5b9d36bcc6bfb4efa82b399b60cb33eda3ad2326fe9f51bb0203010001";
+    const SHA256_SIGNATURE: &str = "2e0ddeae1129efaa1b119bf8fed721a9d7711b2b251b48d1affad33542f9b800966c08149c6dd306928d67c7356b46abc787d67d6e1ed325ad35aa133e51ce4d";
+    const SHA1_SIGNATURE: &str = "0d7799d25b3ec77bd3fe740e2f29b5f8cfd04330370cc7d5162da841ef3785823007feb3019ed067b59189794c37295473fcc9fea3f1f16ed0dc407f56d9971f";
+
+    pub fn bytes(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|x| u8::from_str_radix(&text[x..x + 2], 16).unwrap()).collect()
+    }
+
+    // Raw RSASP1 over an arbitrary encoded message
+    fn sign_message(key: &RsaPrivateKey, message: &[u8]) -> Vec<u8> {
+        BigUint::from_be_bytes(message).mod_pow(&key.private_exponent, &key.public.modulus).unwrap().to_be_bytes(key.public.size).unwrap()
+    }
+
+    #[test]
+    fn keys() {
+        let pkcs1 = bytes(PRIVATE_KEY);
+        let key = parse_private_key(&pkcs1).unwrap();
+        let public = parse_public_key(&bytes(PUBLIC_KEY)).unwrap();
+        assert_eq!(key.public.size, 64);
+        assert!(key.public == public);
+        let algorithm = der::encode_sequence(&[&der::encode_oid(OID_RSA_ENCRYPTION), &der::encode(der::TAG_NULL, &[])]);
+        let pkcs8 = der::encode_sequence(&[&der::encode_integer(&[0]), &algorithm, &der::encode(der::TAG_OCTET_STRING, &pkcs1)]);
+        assert!(parse_private_key(&pkcs8).unwrap().public == public);
+        let other = der::encode_sequence(&[&der::encode_oid("1.2.840.10045.2.1")]);
+        assert!(parse_private_key(&der::encode_sequence(&[&der::encode_integer(&[0]), &other, &der::encode(der::TAG_OCTET_STRING, &pkcs1)])).is_none());
+    }
+
+    #[test]
+    fn known_signatures() {
+        let key = parse_private_key(&bytes(PRIVATE_KEY)).unwrap();
+        for (algorithm, signature) in [(HashAlgorithm::Sha256, SHA256_SIGNATURE), (HashAlgorithm::Sha1, SHA1_SIGNATURE)] {
+            let digest = sha::digest(algorithm, b"abc");
+            assert_eq!(sign_pkcs1(&key, algorithm, &digest).unwrap(), bytes(signature));
+            assert!(verify_pkcs1(&key.public, algorithm, &digest, &bytes(signature)));
+        }
+    }
+
+    #[test]
+    fn rejected_signatures() {
+        let key = parse_private_key(&bytes(PRIVATE_KEY)).unwrap();
+        let digest = sha::digest(HashAlgorithm::Sha256, b"abc");
+        let signature = bytes(SHA256_SIGNATURE);
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha256, &sha::digest(HashAlgorithm::Sha256, b"abd"), &signature));
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha384, &digest, &signature));
+        let mut flipped = signature.clone();
+        flipped[10] ^= 1;
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha256, &digest, &flipped));
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha256, &digest, &signature[1..]));
+    }
+
+    #[test]
+    fn digest_info_parameters() {
+        let key = parse_private_key(&bytes(PRIVATE_KEY)).unwrap();
+        let digest = sha::digest(HashAlgorithm::Sha256, b"abc");
+        let absent = encode_pkcs1(&digest_info_without_parameters(HashAlgorithm::Sha256, &digest), 64).unwrap();
+        assert!(verify_pkcs1(&key.public, HashAlgorithm::Sha256, &digest, &sign_message(&key, &absent)));
+        // A DigestInfo with trailing garbage hidden in a shortened padding string
+        let mut info = digest_info(HashAlgorithm::Sha256, &digest);
+        info.push(0);
+        let trailing = encode_pkcs1(&info, 64).unwrap();
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha256, &digest, &sign_message(&key, &trailing)));
+        // Padding bytes other than 0xff
+        let mut padding = encode_pkcs1(&digest_info(HashAlgorithm::Sha256, &digest), 64).unwrap();
+        padding[3] = 0xfe;
+        assert!(!verify_pkcs1(&key.public, HashAlgorithm::Sha256, &digest, &sign_message(&key, &padding)));
+    }
+}
@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::load_config::LoadConfig;
+use crate::utils::is_executable_rva;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+use crate::utils::section_for_rva;
+use crate::utils::va_to_rva;
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x14c;
+const IMAGE_DLLCHARACTERISTICS_NO_SEH: u16 = 0x0400;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum SehStatus {
+    // Only registered handlers may be dispatched
+    SafeSehProtected,
+    // IMAGE_DLLCHARACTERISTICS_NO_SEH, no handler may be dispatched at all
+    SehFree,
+    // Any address on the stack is accepted as a handler, also when the SEHandlerTable cannot be read or is empty
+    Unprotected,
+    // Exception handling is table based outside of x86
+    NotApplicable,
+}
+
+pub struct SehHandler {
+    pub rva: u32,
+    pub section: Option<String>,
+}
+
+pub struct SafeSeh {
+    pub status: SehStatus,
+    pub no_seh: bool,
+    pub handler_table: u64,
+    pub handlers: Vec<SehHandler>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Parsing
+
+fn parse_handlers(file: &[u8], headers: &Headers, table: u64, count: u64, safe_seh: &mut SafeSeh) {
+    let offset = match va_to_rva(headers, table).and_then(|rva| rva_to_offset(headers, rva)) {
+        Some(offset) => offset,
+        None => {
+            safe_seh.anomalies.push(format!("SEHandlerTable at VA 0x{:x} is not backed by the file", table));
+            return;
+        }
+    };
+    let mut unsorted = false;
+    for x in 0..count as usize {
+        let rva = match read_u32_at(file, offset + x * 4) {
+            Some(rva) => rva,
+            None => {
+                safe_seh.anomalies.push(format!("SEHandlerTable declares {} handlers but the file ends after {}", count, x));
+                break;
+            }
+        };
+        if safe_seh.handlers.last().is_some_and(|previous| previous.rva >= rva) {
+            unsorted = true;
+        }
+        if !is_executable_rva(headers, rva) {
+            safe_seh.anomalies.push(format!("SEH handler 0x{:x} is not in an executable section", rva));
+        }
+        safe_seh.handlers.push(SehHandler { rva, section: section_for_rva(headers, rva).map(|section| section.name.clone()) });
+    }
+    // RtlIsValidHandler binary searches the table, unsorted entries can be missed
+    if unsorted {
+        safe_seh.anomalies.push(String::from("SEHandlerTable is not sorted"));
+    }
+}
+
+pub fn parse_safe_seh(file: &[u8], headers: &Headers, load_config: Option<&LoadConfig>) -> SafeSeh {
+    let no_seh = headers.optional_headers.windows_specific.dll_characteristics & IMAGE_DLLCHARACTERISTICS_NO_SEH != 0;
+    let mut safe_seh = SafeSeh { status: SehStatus::Unprotected, no_seh, handler_table: 0, handlers: Vec::new(), anomalies: Vec::new() };
+    if headers.coff_headers.target_machine != IMAGE_FILE_MACHINE_I386 {
+        safe_seh.status = SehStatus::NotApplicable;
+        return safe_seh;
+    }
+    let table = load_config.and_then(|load_config| Some((load_config.se_handler_table?, load_config.se_handler_count?)));
+    if let Some((handler_table, count)) = table {
+        safe_seh.handler_table = handler_table;
+        if handler_table != 0 {
+            parse_handlers(file, headers, handler_table, count, &mut safe_seh);
+        }
+    }
+    safe_seh.status = if no_seh {
+        if !safe_seh.handlers.is_empty() {
+            safe_seh.anomalies.push(String::from("IMAGE_DLLCHARACTERISTICS_NO_SEH is set but handlers are registered"));
+        }
+        SehStatus::SehFree
+    } else if !safe_seh.handlers.is_empty() {
+        SehStatus::SafeSehProtected
+    } else {
+        SehStatus::Unprotected
+    };
+    safe_seh
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for SafeSeh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SafeSEH
+
+---------------------------
+status: {:?}
+no_seh: {}
+handler_table: 0x{:x}
+handler_count: {}
+---------------------------",
+        self.status, self.no_seh, self.handler_table, self.handlers.len())?;
+        for handler in &self.handlers {
+            write!(f, "\n0x{:08x} {}", handler.rva, handler.section.as_deref().unwrap_or("-"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn image() -> (Vec<u8>, Headers) {
+        let file = vec![0; 0x1000];
+        let mut headers = flat_headers(true, file.len());
+        headers.coff_headers.target_machine = IMAGE_FILE_MACHINE_I386;
+        (file, headers)
+    }
+
+    fn load_config(handler_table: u64, count: u64) -> LoadConfig {
+        LoadConfig { se_handler_table: Some(handler_table), se_handler_count: Some(count), ..Default::default() }
+    }
+
+    #[test]
+    fn parses_handler_table() {
+        let (mut file, headers) = image();
+        put(&mut file, 0x800, &0x610u32.to_le_bytes());
+        put(&mut file, 0x804, &0x680u32.to_le_bytes());
+        let safe_seh = parse_safe_seh(&file, &headers, Some(&load_config(0x400800, 2)));
+        assert_eq!(safe_seh.status, SehStatus::SafeSehProtected);
+        assert_eq!(safe_seh.handlers.iter().map(|handler| handler.rva).collect::<Vec<u32>>(), vec![0x610, 0x680]);
+        assert_eq!(safe_seh.handlers[0].section.as_deref(), Some(".text"));
+        assert!(safe_seh.anomalies.is_empty());
+
+        // Old load config without the SafeSEH fields, or none at all
+        assert_eq!(parse_safe_seh(&file, &headers, Some(&LoadConfig::default())).status, SehStatus::Unprotected);
+        assert_eq!(parse_safe_seh(&file, &headers, None).status, SehStatus::Unprotected);
+
+        let (file, mut headers) = image();
+        headers.coff_headers.target_machine = 0x8664;
+        assert_eq!(parse_safe_seh(&file, &headers, Some(&load_config(0x400800, 2))).status, SehStatus::NotApplicable);
+    }
+
+    #[test]
+    fn malformed_handler_tables() {
+        let (mut file, mut headers) = image();
+        headers.optional_headers.windows_specific.dll_characteristics = IMAGE_DLLCHARACTERISTICS_NO_SEH;
+        put(&mut file, 0xff8, &0x680u32.to_le_bytes());
+        put(&mut file, 0xffc, &0x3000u32.to_le_bytes());
+        let safe_seh = parse_safe_seh(&file, &headers, Some(&load_config(0x400ff8, 3)));
+        assert_eq!(safe_seh.status, SehStatus::SehFree);
+        assert!(safe_seh.no_seh);
+        assert_eq!(safe_seh.handler_table, 0x400ff8);
+        assert_eq!(safe_seh.handlers.iter().map(|handler| handler.rva).collect::<Vec<u32>>(), vec![0x680, 0x3000]);
+        assert_eq!(safe_seh.handlers[0].section.as_deref(), Some(".text"));
+        assert_eq!(safe_seh.handlers[1].section, None);
+        assert_eq!(safe_seh.anomalies, vec![
+            String::from("SEH handler 0x3000 is not in an executable section"),
+            String::from("SEHandlerTable declares 3 handlers but the file ends after 2"),
+            String::from("IMAGE_DLLCHARACTERISTICS_NO_SEH is set but handlers are registered"),
+        ]);
+
+        let (mut file, headers) = image();
+        put(&mut file, 0x800, &0x680u32.to_le_bytes());
+        put(&mut file, 0x804, &0x610u32.to_le_bytes());
+        let safe_seh = parse_safe_seh(&file, &headers, Some(&load_config(0x400800, 2)));
+        assert_eq!(safe_seh.status, SehStatus::SafeSehProtected);
+        assert_eq!(safe_seh.handlers.iter().map(|handler| handler.rva).collect::<Vec<u32>>(), vec![0x680, 0x610]);
+        assert_eq!(safe_seh.anomalies, vec![String::from("SEHandlerTable is not sorted")]);
+
+        // A table that cannot be read or holds no handler does not protect anything
+        let safe_seh = parse_safe_seh(&file, &headers, Some(&load_config(0x300000, 2)));
+        assert_eq!(safe_seh.status, SehStatus::Unprotected);
+        assert_eq!(safe_seh.handler_table, 0x300000);
+        assert!(safe_seh.handlers.is_empty());
+        assert_eq!(safe_seh.anomalies, vec![String::from("SEHandlerTable at VA 0x300000 is not backed by the file")]);
+        let safe_seh = parse_safe_seh(&file, &headers, Some(&load_config(0x400800, 0)));
+        assert_eq!(safe_seh.status, SehStatus::Unprotected);
+        assert!(safe_seh.anomalies.is_empty());
+    }
+}
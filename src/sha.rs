@@ -0,0 +1,328 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::vec::Vec;
+
+// SHA-1 and SHA-2 (FIPS 180-4) digests
+
+const K256: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const K512: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc, 0x3956c25bf348b538,
+    0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118, 0xd807aa98a3030242, 0x12835b0145706fbe,
+    0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2, 0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235,
+    0xc19bf174cf692694, 0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5, 0x983e5152ee66dfab,
+    0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4, 0xc6e00bf33da88fc2, 0xd5a79147930aa725,
+    0x06ca6351e003826f, 0x142929670a0e6e70, 0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df, 0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30, 0xd192e819d6ef5218,
+    0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8, 0x19a4c116b8d2d0c8, 0x1e376c085141ab53,
+    0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8, 0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3, 0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b, 0xca273eceea26619c,
+    0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178, 0x06f067aa72176fba, 0x0a637dc5a2c898a6,
+    0x113f9804bef90dae, 0x1b710b35131c471b, 0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c, 0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+const SHA1_INIT: [u32; 8] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0, 0, 0, 0];
+const SHA256_INIT: [u32; 8] = [0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19];
+const SHA384_INIT: [u64; 8] = [0xcbbb9d5dc1059ed8, 0x629a292a367cd507, 0x9159015a3070dd17, 0x152fecd8f70e5939, 0x67332667ffc00b31, 0x8eb44a8768581511, 0xdb0c2e0d64f98fa7, 0x47b5481dbefa4fa4];
+const SHA512_INIT: [u64; 8] = [0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1, 0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179];
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+// Streaming hasher so callers can feed a file in several ranges
+#[derive(Clone)]
+pub struct Hasher {
+    algorithm: HashAlgorithm,
+    state32: [u32; 8],
+    state64: [u64; 8],
+    buffer: Vec<u8>,
+    length: u128,
+}
+
+// Only the first five words of the state are used
+fn compress1(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 80];
+    for x in 0..16 {
+        w[x] = u32::from_be_bytes(block[x * 4..x * 4 + 4].try_into().unwrap());
+    }
+    for x in 16..80 {
+        w[x] = (w[x - 3] ^ w[x - 8] ^ w[x - 14] ^ w[x - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, _, _, _] = *state;
+    for (x, word) in w.iter().enumerate() {
+        let (f, k) = match x {
+            0..=19 => ((b & c) | (!b & d), 0x5a827999),
+            20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+        let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e]) {
+        *value = value.wrapping_add(add);
+    }
+}
+
+fn compress256(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for x in 0..16 {
+        w[x] = u32::from_be_bytes(block[x * 4..x * 4 + 4].try_into().unwrap());
+    }
+    for x in 16..64 {
+        let s0 = w[x - 15].rotate_right(7) ^ w[x - 15].rotate_right(18) ^ (w[x - 15] >> 3);
+        let s1 = w[x - 2].rotate_right(17) ^ w[x - 2].rotate_right(19) ^ (w[x - 2] >> 10);
+        w[x] = w[x - 16].wrapping_add(s0).wrapping_add(w[x - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for x in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K256[x]).wrapping_add(w[x]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *value = value.wrapping_add(add);
+    }
+}
+
+fn compress512(state: &mut [u64; 8], block: &[u8]) {
+    let mut w = [0u64; 80];
+    for x in 0..16 {
+        w[x] = u64::from_be_bytes(block[x * 8..x * 8 + 8].try_into().unwrap());
+    }
+    for x in 16..80 {
+        let s0 = w[x - 15].rotate_right(1) ^ w[x - 15].rotate_right(8) ^ (w[x - 15] >> 7);
+        let s1 = w[x - 2].rotate_right(19) ^ w[x - 2].rotate_right(61) ^ (w[x - 2] >> 6);
+        w[x] = w[x - 16].wrapping_add(s0).wrapping_add(w[x - 7]).wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for x in 0..80 {
+        let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K512[x]).wrapping_add(w[x]);
+        let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *value = value.wrapping_add(add);
+    }
+}
+
+impl HashAlgorithm {
+    pub fn block_size(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 | HashAlgorithm::Sha256 => 64,
+            HashAlgorithm::Sha384 | HashAlgorithm::Sha512 => 128,
+        }
+    }
+
+    pub fn digest_size(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    // Names as used by PDB checksums and .NET, e.g. "SHA256"
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        match name.to_ascii_uppercase().replace('-', "").as_str() {
+            "SHA1" => Some(HashAlgorithm::Sha1),
+            "SHA256" => Some(HashAlgorithm::Sha256),
+            "SHA384" => Some(HashAlgorithm::Sha384),
+            "SHA512" => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Hasher {
+        Hasher {
+            algorithm,
+            state32: if algorithm == HashAlgorithm::Sha1 { SHA1_INIT } else { SHA256_INIT },
+            state64: if algorithm == HashAlgorithm::Sha384 { SHA384_INIT } else { SHA512_INIT },
+            buffer: Vec::new(),
+            length: 0,
+        }
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        match self.algorithm {
+            HashAlgorithm::Sha1 => compress1(&mut self.state32, block),
+            HashAlgorithm::Sha256 => compress256(&mut self.state32, block),
+            HashAlgorithm::Sha384 | HashAlgorithm::Sha512 => compress512(&mut self.state64, block),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let block_size = self.algorithm.block_size();
+        self.length += data.len() as u128;
+        let mut data = data;
+        if !self.buffer.is_empty() {
+            let missing = (block_size - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.buffer.len() < block_size {
+                return;
+            }
+            let block = std::mem::take(&mut self.buffer);
+            self.compress(&block);
+        }
+        let mut blocks = data.chunks_exact(block_size);
+        for block in blocks.by_ref() {
+            self.compress(block);
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let block_size = self.algorithm.block_size();
+        let length_size = if block_size == 64 { 8 } else { 16 };
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80u8];
+        while !(self.buffer.len() + padding.len() + length_size).is_multiple_of(block_size) {
+            padding.push(0);
+        }
+        if length_size == 8 {
+            padding.extend_from_slice(&(bit_length as u64).to_be_bytes());
+        } else {
+            padding.extend_from_slice(&bit_length.to_be_bytes());
+        }
+        self.update(&padding);
+        match self.algorithm {
+            HashAlgorithm::Sha1 => self.state32.iter().take(5).flat_map(|value| value.to_be_bytes()).collect(),
+            HashAlgorithm::Sha256 => self.state32.iter().flat_map(|value| value.to_be_bytes()).collect(),
+            HashAlgorithm::Sha384 => self.state64.iter().take(6).flat_map(|value| value.to_be_bytes()).collect(),
+            HashAlgorithm::Sha512 => self.state64.iter().flat_map(|value| value.to_be_bytes()).collect(),
+        }
+    }
+}
+
+pub fn digest(algorithm: HashAlgorithm, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkcs7::hex;
+
+    const TWO_BLOCKS: &str = "abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+    const TWO_BLOCKS_512: &str = "abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+
+    // FIPS 180-2 appendix examples: "abc", the empty message and the two-block messages
+    fn check(algorithm: HashAlgorithm, vectors: [&str; 4]) {
+        assert_eq!(hex(&digest(algorithm, b"abc")), vectors[0]);
+        assert_eq!(hex(&digest(algorithm, b"")), vectors[1]);
+        assert_eq!(hex(&digest(algorithm, TWO_BLOCKS.as_bytes())), vectors[2]);
+        assert_eq!(hex(&digest(algorithm, TWO_BLOCKS_512.as_bytes())), vectors[3]);
+        assert_eq!(digest(algorithm, b"").len(), algorithm.digest_size());
+    }
+
+    #[test]
+    fn sha1() {
+        check(HashAlgorithm::Sha1, [
+            "a9993e364706816aba3e25717850c26c9cd0d89d",
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            "a49b2446a02c645bf419f995b67091253a04a259",
+        ]);
+    }
+
+    #[test]
+    fn sha256() {
+        check(HashAlgorithm::Sha256, [
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            "cf5b16a778af8380036ce59e7b0492370b249b11e8f07a51afac45037afee9d1",
+        ]);
+    }
+
+    #[test]
+    fn sha384() {
+        check(HashAlgorithm::Sha384, [
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7",
+            "38b060a751ac96384cd9327eb1b1e36a21fdb71114be07434c0cc7bf63f6e1da274edebfe76f65fbd51ad2f14898b95b",
+            "3391fdddfc8dc7393707a65b1b4709397cf8b1d162af05abfe8f450de5f36bc6b0455a8520bc4e6f5fe95b1fe3c8452b",
+            "09330c33f71147e83d192fc782cd1b4753111b173b3b05d22fa08086e3b0f712fcc7c71a557e2db966c3e9fa91746039",
+        ]);
+    }
+
+    #[test]
+    fn sha512() {
+        check(HashAlgorithm::Sha512, [
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f",
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e",
+            "204a8fc6dda82f0a0ced7beb8e08a41657c16ef468b228a8279be331a703c33596fd15c13b1b07f9aa1d3bea57789ca031ad85c7a71dd70354ec631238ca3445",
+            "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909",
+        ]);
+    }
+
+    // One million "a" fed in updates that do not line up with the block size
+    #[test]
+    fn split_updates() {
+        let data = vec![b'a'; 1_000_000];
+        let expected = [
+            (HashAlgorithm::Sha1, "34aa973cd4c4daa4f61eeb2bdbad27316534016f"),
+            (HashAlgorithm::Sha256, "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"),
+            (HashAlgorithm::Sha512, "e718483d0ce769644e2e42c7bc15b4638e1f98b13b2044285632a803afa973ebde0ff244877ea60a4cb0432ce577c31beb009c5c2c49aa2e4eadb217ad8cc09b"),
+        ];
+        for (algorithm, expected) in expected {
+            let mut hasher = Hasher::new(algorithm);
+            for chunk in data.chunks(997) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hex(&hasher.finish()), expected);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::authenticode::authentihash;
+use crate::authenticode::checksum_offset;
+use crate::authenticode::compute_checksum;
+use crate::authenticode::security_directory_offset;
+use crate::certificates::IMAGE_DIRECTORY_ENTRY_SECURITY;
+use crate::der;
+use crate::pkcs7::Certificate;
+use crate::pkcs7::parse_certificate;
+use crate::pkcs7::parse_signed_data;
+use crate::pkcs7::OID_CONTENT_TYPE;
+use crate::pkcs7::OID_MESSAGE_DIGEST;
+use crate::pkcs7::OID_RSA_ENCRYPTION;
+use crate::pkcs7::OID_RFC3161_TIMESTAMP;
+use crate::pkcs7::OID_SIGNED_DATA;
+use crate::pkcs7::OID_SPC_INDIRECT_DATA;
+use crate::pkcs7::OID_SPC_PE_IMAGE_DATA;
+use crate::pkcs7::OID_SPC_SP_OPUS_INFO;
+use crate::pkcs7::OID_SPC_STATEMENT_TYPE;
+use crate::rsa::RsaPrivateKey;
+use crate::rsa::hash_oid;
+use crate::rsa::parse_private_key;
+use crate::rsa::parse_public_key;
+use crate::rsa::sign_pkcs1;
+use crate::sha::HashAlgorithm;
+use crate::sha::digest;
+
+const WIN_CERT_REVISION_2_0: u16 = 0x0200;
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 2;
+const OID_SPC_INDIVIDUAL_SP_KEY_PURPOSE: &str = "1.3.6.1.4.1.311.2.1.21";
+// SpcLink file [2] holding the SpcString [0] "<<<Obsolete>>>", as signtool writes it
+const SPC_OBSOLETE_LINK: [u8; 34] = [
+    0xa0, 0x20, 0xa2, 0x1e, 0x80, 0x1c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0x3c, 0x00, 0x4f, 0x00, 0x62, 0x00,
+    0x73, 0x00, 0x6f, 0x00, 0x6c, 0x00, 0x65, 0x00, 0x74, 0x00, 0x65, 0x00, 0x3e, 0x00, 0x3e, 0x00, 0x3e,
+];
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
+pub enum SignError {
+    // Encrypted PKCS#8 and non RSA keys are not supported
+    UnsupportedKey,
+    NoCertificate,
+    // None of the certificates holds the public key of the private key
+    CertificateKeyMismatch,
+    KeyTooSmall,
+    NoSecurityDirectory,
+    // An existing certificate table is only replaced when it ends the file
+    CertificateTableNotAtEnd,
+    MalformedTimestamp,
+    // The token does not timestamp this signature
+    TimestampMismatch,
+}
+
+
+// Parsing
+
+// PEM "PRIVATE KEY" or "RSA PRIVATE KEY", otherwise DER
+fn load_private_key(data: &[u8]) -> Result<RsaPrivateKey, SignError> {
+    let der = der::pem_blocks(data, "PRIVATE KEY").into_iter().chain(der::pem_blocks(data, "RSA PRIVATE KEY")).next();
+    parse_private_key(der.as_deref().unwrap_or(data)).ok_or(SignError::UnsupportedKey)
+}
+
+// PEM bundle or a single DER certificate
+fn load_certificates(data: &[u8]) -> Vec<Certificate> {
+    let blocks = der::pem_blocks(data, "CERTIFICATE");
+    if blocks.is_empty() {
+        return parse_certificate(data).into_iter().collect();
+    }
+    blocks.iter().filter_map(|block| parse_certificate(block)).collect()
+}
+
+fn algorithm_identifier(oid: &str) -> Vec<u8> {
+    der::encode_sequence(&[&der::encode_oid(oid), &der::encode(der::TAG_NULL, &[])])
+}
+
+fn attribute(oid: &str, value: &[u8]) -> Vec<u8> {
+    der::encode_sequence(&[&der::encode_oid(oid), &der::encode_set(&[value])])
+}
+
+// SpcIndirectDataContent with SpcPeImageData and the image digest
+fn indirect_data(algorithm: HashAlgorithm, image_digest: &[u8]) -> Vec<u8> {
+    let image_data = der::encode_sequence(&[&der::encode(der::TAG_BIT_STRING, &[0]), &SPC_OBSOLETE_LINK]);
+    let data = der::encode_sequence(&[&der::encode_oid(OID_SPC_PE_IMAGE_DATA), &image_data]);
+    let digest_info = der::encode_sequence(&[&algorithm_identifier(hash_oid(algorithm)), &der::encode(der::TAG_OCTET_STRING, image_digest)]);
+    der::encode_sequence(&[&data, &digest_info])
+}
+
+// A TimeStampResp is unwrapped to its TimeStampToken, which must cover the signature value
+fn timestamp_attribute(token: &[u8], signature: &[u8]) -> Result<Vec<u8>, SignError> {
+    let element = der::parse(token).ok_or(SignError::MalformedTimestamp)?;
+    let token = match element.child(0) {
+        Some(status) if status.tag == der::TAG_SEQUENCE => element.child(1).ok_or(SignError::MalformedTimestamp)?.raw,
+        _ => element.raw,
+    };
+    let signed_data = parse_signed_data(token).ok_or(SignError::MalformedTimestamp)?;
+    let info = signed_data.tst_info.as_ref().ok_or(SignError::MalformedTimestamp)?;
+    let algorithm = info.hash_algorithm.hash_algorithm().ok_or(SignError::MalformedTimestamp)?;
+    if digest(algorithm, signature) != info.message_imprint {
+        return Err(SignError::TimestampMismatch);
+    }
+    Ok(attribute(OID_RFC3161_TIMESTAMP, token))
+}
+
+// TimeStampReq for the signature value, asking for the TSA certificate
+pub fn timestamp_request(signature: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    let imprint = der::encode_sequence(&[&algorithm_identifier(hash_oid(algorithm)), &der::encode(der::TAG_OCTET_STRING, &digest(algorithm, signature))]);
+    der::encode_sequence(&[&der::encode_integer(&[1]), &imprint, &der::encode(der::TAG_BOOLEAN, &[0xff])])
+}
+
+fn signed_data(algorithm: HashAlgorithm, image_digest: &[u8], key: &RsaPrivateKey, signer: &Certificate, certificates: &[Certificate], timestamp: Option<&[u8]>) -> Result<Vec<u8>, SignError> {
+    let content = indirect_data(algorithm, image_digest);
+    // messageDigest covers the value of the SpcIndirectDataContent SEQUENCE
+    let value = der::parse(&content).map(|element| element.content).unwrap_or_default();
+    let attributes = [
+        attribute(OID_CONTENT_TYPE, &der::encode_oid(OID_SPC_INDIRECT_DATA)),
+        attribute(OID_SPC_SP_OPUS_INFO, &der::encode_sequence(&[])),
+        attribute(OID_SPC_STATEMENT_TYPE, &der::encode_sequence(&[&der::encode_oid(OID_SPC_INDIVIDUAL_SP_KEY_PURPOSE)])),
+        attribute(OID_MESSAGE_DIGEST, &der::encode(der::TAG_OCTET_STRING, &digest(algorithm, value))),
+    ];
+    let attributes = der::encode_set(&attributes.iter().map(|attribute| attribute.as_slice()).collect::<Vec<&[u8]>>());
+    let signature = sign_pkcs1(key, algorithm, &digest(algorithm, &attributes)).ok_or(SignError::KeyTooSmall)?;
+    // The signed attributes are [0] IMPLICIT in the SignerInfo
+    let mut implicit_attributes = attributes.clone();
+    implicit_attributes[0] = 0xa0;
+    let issuer_and_serial = der::encode_sequence(&[&signer.issuer.raw, &der::encode_integer(&signer.serial)]);
+    let mut signer_info = vec![
+        der::encode_integer(&[1]),
+        issuer_and_serial,
+        algorithm_identifier(hash_oid(algorithm)),
+        implicit_attributes,
+        algorithm_identifier(OID_RSA_ENCRYPTION),
+        der::encode(der::TAG_OCTET_STRING, &signature),
+    ];
+    if let Some(token) = timestamp {
+        signer_info.push(der::encode(0xa1, &timestamp_attribute(token, &signature)?));
+    }
+    let signer_info = der::encode_sequence(&signer_info.iter().map(|field| field.as_slice()).collect::<Vec<&[u8]>>());
+    let certificates: Vec<u8> = certificates.iter().flat_map(|certificate| certificate.raw.iter().copied()).collect();
+    let encapsulated = der::encode_sequence(&[&der::encode_oid(OID_SPC_INDIRECT_DATA), &der::encode_context(0, &content)]);
+    let signed_data = der::encode_sequence(&[
+        &der::encode_integer(&[1]),
+        &der::encode_set(&[&algorithm_identifier(hash_oid(algorithm))]),
+        &encapsulated,
+        &der::encode_context(0, &certificates),
+        &der::encode_set(&[&signer_info]),
+    ]);
+    Ok(der::encode_sequence(&[&der::encode_oid(OID_SIGNED_DATA), &der::encode_context(0, &signed_data)]))
+}
+
+fn write_u32(file: &mut [u8], offset: usize, value: u32) {
+    file[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+// Signs the image with a PKCS#8 or PKCS#1 RSA key and its certificate chain, PEM or DER, and returns the signed file.
+// An existing signature is replaced. The RFC 3161 token, a TimeStampResp or TimeStampToken, must be for the signature
+// this produces, which is deterministic: sign once, request a token with timestamp_request and sign again with it
+pub fn sign(file: &[u8], headers: &Headers, key: &[u8], certificates: &[u8], algorithm: HashAlgorithm, timestamp: Option<&[u8]>) -> Result<Vec<u8>, SignError> {
+    let key = load_private_key(key)?;
+    let certificates = load_certificates(certificates);
+    if certificates.is_empty() {
+        return Err(SignError::NoCertificate);
+    }
+    let signer = certificates.iter()
+        .find(|certificate| certificate.public_key_algorithm.oid == OID_RSA_ENCRYPTION && parse_public_key(&certificate.public_key).as_ref() == Some(&key.public))
+        .ok_or(SignError::CertificateKeyMismatch)?;
+    if headers.optional_headers.windows_specific.number_of_rva_and_sizes as usize <= IMAGE_DIRECTORY_ENTRY_SECURITY {
+        return Err(SignError::NoSecurityDirectory);
+    }
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY];
+    let mut image = file.to_vec();
+    if directory.virtual_address != 0 {
+        if directory.virtual_address as usize + directory.size as usize != file.len() {
+            return Err(SignError::CertificateTableNotAtEnd);
+        }
+        image.truncate(directory.virtual_address as usize);
+    }
+    // The certificate table is 8 byte aligned, the padding is part of the hashed image
+    image.resize(image.len().next_multiple_of(8), 0);
+    let security = security_directory_offset(headers);
+    image[security..security + 8].fill(0);
+    let headers = crate::parse_headers(&image);
+    let image_digest = authentihash(&image, &headers, algorithm);
+    let signature = signed_data(algorithm, &image_digest, &key, signer, &certificates, timestamp)?;
+    let length = (8 + signature.len()).next_multiple_of(8);
+    let offset = image.len();
+    image.extend_from_slice(&(length as u32).to_le_bytes());
+    image.extend_from_slice(&WIN_CERT_REVISION_2_0.to_le_bytes());
+    image.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+    image.extend_from_slice(&signature);
+    image.resize(offset + length, 0);
+    write_u32(&mut image, security, offset as u32);
+    write_u32(&mut image, security + 4, length as u32);
+    let checksum = compute_checksum(&image, &headers);
+    write_u32(&mut image, checksum_offset(&headers), checksum);
+    Ok(image)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignError::UnsupportedKey => write!(f, "The key is not an unencrypted PKCS#8 or PKCS#1 RSA private key"),
+            SignError::NoCertificate => write!(f, "No certificate found"),
+            SignError::CertificateKeyMismatch => write!(f, "No certificate matches the private key"),
+            SignError::KeyTooSmall => write!(f, "The key is too small for the digest algorithm"),
+            SignError::NoSecurityDirectory => write!(f, "The image has no security directory entry"),
+            SignError::CertificateTableNotAtEnd => write!(f, "The existing certificate table is not at the end of the file"),
+            SignError::MalformedTimestamp => write!(f, "The timestamp is not an RFC 3161 TimeStampResp or TimeStampToken"),
+            SignError::TimestampMismatch => write!(f, "The timestamp token does not cover this signature"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticode::check_digest;
+    use crate::certificates::parse_certificate_table;
+    use crate::certificates::CertificateRevision;
+    use crate::certificates::CertificateType;
+    use crate::pkcs7::OID_TST_INFO;
+    use crate::pkcs7::tests::Template;
+    use crate::pkcs7::tests::key;
+    use crate::pkcs7::tests::other_key;
+    use crate::pkcs7::tests::signed_data as content_info;
+    use crate::pkcs7::tests::tst_info;
+    use crate::rsa::tests::bytes;
+    use crate::rsa::tests::PRIVATE_KEY;
+    use crate::rsa::verify_pkcs1;
+    use crate::utils::fixtures::flat_image;
+
+    // 0x1000 byte image followed by 3 bytes of overlay, so that the certificate table needs padding
+    fn image(pe_32: bool) -> Vec<u8> {
+        let mut file = flat_image(pe_32, 0x1000);
+        for (x, byte) in file[0x400..].iter_mut().enumerate() {
+            *byte = (x * 11 + 1) as u8;
+        }
+        file.extend_from_slice(&[1, 2, 3]);
+        file
+    }
+
+    fn certificate() -> Vec<u8> {
+        let key = key();
+        Template::new("Test CA", "Test Signer", 2, &key).sign(&key)
+    }
+
+    fn timestamp_token(imprint: &[u8]) -> Vec<u8> {
+        content_info(OID_TST_INFO, &der::encode(der::TAG_OCTET_STRING, &tst_info(imprint, "220101000000Z")), &[], &[])
+    }
+
+    #[test]
+    fn round_trip() {
+        for pe_32 in [true, false] {
+            let file = image(pe_32);
+            let key = bytes(PRIVATE_KEY);
+            let signed = sign(&file, &crate::parse_headers(&file), &key, &certificate(), HashAlgorithm::Sha256, None).unwrap();
+            let headers = crate::parse_headers(&signed);
+            // Only CheckSum and the security entry change, the certificate table follows 5 bytes of padding
+            let mut unsigned = signed[..0x1003].to_vec();
+            unsigned[checksum_offset(&headers)..checksum_offset(&headers) + 4].fill(0);
+            unsigned[security_directory_offset(&headers)..security_directory_offset(&headers) + 8].fill(0);
+            assert_eq!(unsigned, file);
+            assert_eq!(&signed[0x1003..0x1008], &[0; 5]);
+
+            let table = parse_certificate_table(&signed, &headers).unwrap();
+            assert!(table.anomalies.is_empty());
+            assert_eq!(table.entries.len(), 1);
+            let entry = &table.entries[0];
+            assert_eq!(entry.offset, 0x1008);
+            assert_eq!(entry.length % 8, 0);
+            assert_eq!(entry.offset + entry.length as usize, signed.len());
+            assert_eq!(entry.revision, CertificateRevision::WIN_CERT_REVISION_2_0);
+            assert_eq!(entry.certificate_type, CertificateType::WIN_CERT_TYPE_PKCS_SIGNED_DATA);
+            let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY];
+            assert_eq!((directory.virtual_address, directory.size), (0x1008, entry.length));
+            assert_ne!(headers.optional_headers.windows_specific.checksum, 0);
+            assert_eq!(compute_checksum(&signed, &headers), headers.optional_headers.windows_specific.checksum);
+
+            let signed_data = table.signature().unwrap();
+            assert!(signed_data.anomalies.is_empty());
+            assert!(check_digest(&signed, &headers, signed_data).unwrap().matches());
+            let signer = &signed_data.signers[0];
+            assert_eq!(signer.message_digest, Some(digest(HashAlgorithm::Sha256, &signed_data.content)));
+            let mut attributes = signer.authenticated_attributes_raw.clone().unwrap();
+            attributes[0] = der::TAG_SET;
+            let public = parse_public_key(&signed_data.certificates[0].public_key).unwrap();
+            assert!(verify_pkcs1(&public, HashAlgorithm::Sha256, &digest(HashAlgorithm::Sha256, &attributes), &signer.signature));
+
+            // Signing replaces the signature and is deterministic
+            assert_eq!(sign(&signed, &headers, &key, &certificate(), HashAlgorithm::Sha256, None).unwrap(), signed);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let file = image(true);
+        let headers = crate::parse_headers(&file);
+        let key = bytes(PRIVATE_KEY);
+        let signed = sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, None).unwrap();
+        let signed_headers = crate::parse_headers(&signed);
+        let table = parse_certificate_table(&signed, &signed_headers).unwrap();
+        let signature = table.signature().unwrap().signers[0].signature.clone();
+
+        let token = timestamp_token(&digest(HashAlgorithm::Sha256, &signature));
+        let stamped = sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, Some(&token)).unwrap();
+        let stamped_headers = crate::parse_headers(&stamped);
+        let table = parse_certificate_table(&stamped, &stamped_headers).unwrap();
+        let signer = &table.signature().unwrap().signers[0];
+        assert_eq!(signer.signature, signature);
+        assert_eq!(signer.timestamps.len(), 1);
+        assert_eq!(signer.timestamps[0].raw, token);
+
+        // A TimeStampResp is unwrapped to its token
+        let response = der::encode_sequence(&[&der::encode_sequence(&[&der::encode_integer(&[0])]), &token]);
+        assert_eq!(sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, Some(&response)).unwrap(), stamped);
+
+        let other = timestamp_token(&digest(HashAlgorithm::Sha256, b"another signature"));
+        assert_eq!(sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, Some(&other)).err(), Some(SignError::TimestampMismatch));
+        assert_eq!(sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, Some(&[0x30, 0x00])).err(), Some(SignError::MalformedTimestamp));
+    }
+
+    #[test]
+    fn errors() {
+        let file = image(true);
+        let headers = crate::parse_headers(&file);
+        let key = bytes(PRIVATE_KEY);
+        let other = other_key();
+        let mismatched = Template::new("Test CA", "Other Signer", 3, &other).sign(&other);
+        assert_eq!(sign(&file, &headers, b"not a key", &certificate(), HashAlgorithm::Sha256, None).err(), Some(SignError::UnsupportedKey));
+        assert_eq!(sign(&file, &headers, &key, b"", HashAlgorithm::Sha256, None).err(), Some(SignError::NoCertificate));
+        assert_eq!(sign(&file, &headers, &key, &mismatched, HashAlgorithm::Sha256, None).err(), Some(SignError::CertificateKeyMismatch));
+        // A 512 bit modulus cannot hold a SHA-512 DigestInfo
+        assert_eq!(sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha512, None).err(), Some(SignError::KeyTooSmall));
+        let mut signed = sign(&file, &headers, &key, &certificate(), HashAlgorithm::Sha256, None).unwrap();
+        signed.push(0);
+        assert_eq!(sign(&signed, &crate::parse_headers(&signed), &key, &certificate(), HashAlgorithm::Sha256, None).err(), Some(SignError::CertificateTableNotAtEnd));
+        let mut truncated = headers;
+        truncated.optional_headers.windows_specific.number_of_rva_and_sizes = 4;
+        assert_eq!(sign(&file, &truncated, &key, &certificate(), HashAlgorithm::Sha256, None).err(), Some(SignError::NoSecurityDirectory));
+    }
+}
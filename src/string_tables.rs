@@ -0,0 +1,267 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::resources::Resource;
+use crate::resources::Resources;
+use crate::resources::ResourceId;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::read_utf16_at;
+
+// Structures definitions
+
+pub struct LanguageStrings {
+    pub language: ResourceId,
+    pub strings: BTreeMap<u32, String>,
+}
+
+#[derive(Default)]
+pub struct StringTables {
+    pub string_tables: Vec<LanguageStrings>,
+    pub message_tables: Vec<LanguageStrings>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Parsing
+
+fn strings_for<'a>(tables: &'a mut Vec<LanguageStrings>, language: &ResourceId) -> &'a mut BTreeMap<u32, String> {
+    let position = match tables.iter().position(|table| table.language == *language) {
+        Some(position) => position,
+        None => {
+            tables.push(LanguageStrings { language: language.clone(), strings: BTreeMap::new() });
+            tables.len() - 1
+        }
+    };
+    &mut tables[position].strings
+}
+
+// Every RT_STRING bundle holds 16 length-prefixed strings, bundle N covering IDs (N - 1) * 16 to N * 16 - 1
+fn parse_string_bundle(data: &[u8], resource: &Resource, string_tables: &mut StringTables) {
+    let bundle = match resource.name {
+        ResourceId::Id(bundle) if bundle > 0 => bundle,
+        _ => {
+            string_tables.anomalies.push(format!("String bundle {} does not have a valid numeric ID", resource.name));
+            return;
+        }
+    };
+    let mut index: usize = 0;
+    for x in 0..16 {
+        let length = match read_u16_at(data, index) {
+            Some(length) => length as usize,
+            None => {
+                string_tables.anomalies.push(format!("String bundle {} is truncated after {} strings", bundle, x));
+                return;
+            }
+        };
+        index += 2;
+        if length == 0 {
+            continue;
+        }
+        match read_utf16_at(data, index, length) {
+            Some(string) => {
+                strings_for(&mut string_tables.string_tables, &resource.language).insert((bundle - 1) * 16 + x, string);
+            }
+            None => {
+                string_tables.anomalies.push(format!("String {} of bundle {} overruns the resource", x, bundle));
+                return;
+            }
+        }
+        index += length * 2;
+    }
+}
+
+// MESSAGE_RESOURCE_DATA: blocks of ID ranges pointing at runs of MESSAGE_RESOURCE_ENTRY
+fn parse_message_table(data: &[u8], resource: &Resource, string_tables: &mut StringTables) {
+    let number_of_blocks = read_u32_at(data, 0).unwrap_or(0) as usize;
+    for x in 0..number_of_blocks {
+        let (low_id, high_id, offset_to_entries) = match (read_u32_at(data, 4 + x * 12), read_u32_at(data, 8 + x * 12), read_u32_at(data, 12 + x * 12)) {
+            (Some(low_id), Some(high_id), Some(offset_to_entries)) => (low_id, high_id, offset_to_entries as usize),
+            _ => {
+                string_tables.anomalies.push(format!("Message table {} declares {} blocks but is truncated", resource.name, number_of_blocks));
+                return;
+            }
+        };
+        if high_id < low_id {
+            string_tables.anomalies.push(format!("Message table block 0x{:x}-0x{:x} has an inverted ID range", low_id, high_id));
+            continue;
+        }
+        let mut index = offset_to_entries;
+        for id in low_id..=high_id {
+            let (length, flags) = match (read_u16_at(data, index), read_u16_at(data, index + 2)) {
+                (Some(length), Some(flags)) if length >= 4 => (length as usize, flags),
+                _ => {
+                    string_tables.anomalies.push(format!("Message 0x{:x} is outside of the resource or has an invalid length", id));
+                    break;
+                }
+            };
+            let text = match data.get(index + 4..index + length) {
+                Some(text) => text,
+                None => {
+                    string_tables.anomalies.push(format!("Message 0x{:x} overruns the resource", id));
+                    break;
+                }
+            };
+            let message = if flags & 1 != 0 {
+                read_utf16_at(text, 0, text.len() / 2).unwrap_or_default()
+            } else {
+                // ANSI entries are decoded as Latin-1 since the code page is not recorded
+                text.iter().map(|byte| *byte as char).collect()
+            };
+            strings_for(&mut string_tables.message_tables, &resource.language).insert(id, message.trim_end_matches('\0').to_string());
+            index += length;
+        }
+    }
+}
+
+pub fn parse_string_tables(file: &[u8], headers: &Headers, resources: &Resources) -> StringTables {
+    let mut string_tables = StringTables::default();
+    for resource in &resources.resources {
+        let is_string_table = match resource.resource_type {
+            ResourceType::RT_STRING => true,
+            ResourceType::RT_MESSAGETABLE => false,
+            _ => continue,
+        };
+        let data = match resource_data(file, headers, resource) {
+            Some(data) => data,
+            None => {
+                string_tables.anomalies.push(format!("Resource {} is not backed by the file", resource));
+                continue;
+            }
+        };
+        if is_string_table {
+            parse_string_bundle(data, resource, &mut string_tables);
+        } else {
+            parse_message_table(data, resource, &mut string_tables);
+        }
+    }
+    string_tables.string_tables.sort_by(|a, b| a.language.cmp(&b.language));
+    string_tables.message_tables.sort_by(|a, b| a.language.cmp(&b.language));
+    string_tables
+}
+
+
+// Display trait implementation for the structs
+
+// One line per string with control characters escaped, so outputs can be diffed between releases
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\r', "\\r").replace('\n', "\\n").replace('\t', "\\t")
+}
+
+impl fmt::Display for StringTables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for table in &self.string_tables {
+            for (id, string) in &table.strings {
+                writeln!(f, "RT_STRING {} {}: {}", table.language, id, escape(string))?;
+            }
+        }
+        for table in &self.message_tables {
+            for (id, string) in &table.strings {
+                writeln!(f, "RT_MESSAGETABLE {} 0x{:08x}: {}", table.language, id, escape(string))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+    use crate::utils::fixtures::utf16;
+
+    // 16 length-prefixed UTF-16 strings, None for the empty slots
+    fn bundle(strings: &[Option<&str>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for x in 0..16 {
+            let string = strings.get(x).copied().flatten().unwrap_or("");
+            data.extend_from_slice(&(string.encode_utf16().count() as u16).to_le_bytes());
+            data.extend_from_slice(&utf16(string));
+        }
+        data
+    }
+
+    fn message_entry(text: &[u8], unicode: bool) -> Vec<u8> {
+        let mut entry = Vec::new();
+        let length = (4 + text.len()).next_multiple_of(4);
+        entry.extend_from_slice(&(length as u16).to_le_bytes());
+        entry.extend_from_slice(&(unicode as u16).to_le_bytes());
+        entry.extend_from_slice(text);
+        entry.resize(length, 0);
+        entry
+    }
+
+    #[test]
+    fn decodes_tables() {
+        let first = bundle(&[None, Some("Open"), Some("Line\r\nbreak")]);
+        let second = bundle(&[Some("Öffnen")]);
+        let mut messages = Vec::new();
+        messages.extend_from_slice(&1u32.to_le_bytes());
+        for value in [0x100u32, 0x101, 16] {
+            messages.extend_from_slice(&value.to_le_bytes());
+        }
+        messages.extend_from_slice(&message_entry(b"caf\xe9\r\n", false));
+        messages.extend_from_slice(&message_entry(&utf16("Done.\r\n"), true));
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(6), ResourceId::Id(1), 0x409, &first),
+            (ResourceId::Id(6), ResourceId::Id(3), 0x407, &second),
+            (ResourceId::Id(11), ResourceId::Id(1), 0x409, &messages),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let tables = parse_string_tables(&file, &headers, &resources);
+        assert!(tables.anomalies.is_empty(), "{:?}", tables.anomalies);
+        assert_eq!(tables.string_tables.len(), 2);
+        assert_eq!(tables.string_tables[0].language, ResourceId::Id(0x407));
+        assert_eq!(tables.string_tables[0].strings.get(&32).map(|string| string.as_str()), Some("Öffnen"));
+        let english: Vec<(&u32, &String)> = tables.string_tables[1].strings.iter().collect();
+        assert_eq!(english, vec![(&1, &String::from("Open")), (&2, &String::from("Line\r\nbreak"))]);
+        let messages: Vec<(&u32, &String)> = tables.message_tables[0].strings.iter().collect();
+        assert_eq!(messages, vec![(&0x100, &String::from("café\r\n")), (&0x101, &String::from("Done.\r\n"))]);
+        assert_eq!(tables.to_string(), "RT_STRING 1031 32: Öffnen\nRT_STRING 1033 1: Open\nRT_STRING 1033 2: Line\\r\\nbreak\n\
+            RT_MESSAGETABLE 1033 0x00000100: café\\r\\n\nRT_MESSAGETABLE 1033 0x00000101: Done.\\r\\n\n");
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let mut truncated = bundle(&[Some("one"), Some("two")]);
+        truncated.truncate(16);
+        let mut overrun = bundle(&[]);
+        overrun[0] = 0x40;
+        let mut messages = Vec::new();
+        messages.extend_from_slice(&2u32.to_le_bytes());
+        for value in [5u32, 4, 28, 1, 2, 28] {
+            messages.extend_from_slice(&value.to_le_bytes());
+        }
+        messages.extend_from_slice(&message_entry(b"ok", false));
+        messages.extend_from_slice(&[2, 0, 0, 0]);
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(6), ResourceId::Id(2), 0, &truncated),
+            (ResourceId::Id(6), ResourceId::Id(3), 0, &overrun),
+            (ResourceId::Id(6), ResourceId::Name(String::from("NAMED")), 0, &bundle(&[])),
+            (ResourceId::Id(11), ResourceId::Id(1), 0, &messages),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let tables = parse_string_tables(&file, &headers, &resources);
+        // Strings decoded before each error are kept
+        assert_eq!(tables.string_tables.len(), 1);
+        let strings: Vec<(u32, &str)> = tables.string_tables[0].strings.iter().map(|(id, string)| (*id, string.as_str())).collect();
+        assert_eq!(strings, vec![(16, "one"), (17, "two")]);
+        assert_eq!(tables.message_tables.len(), 1);
+        let messages: Vec<(u32, &str)> = tables.message_tables[0].strings.iter().map(|(id, string)| (*id, string.as_str())).collect();
+        assert_eq!(messages, vec![(1, "ok")]);
+        assert_eq!(tables.anomalies, vec![
+            String::from("String bundle 2 is truncated after 2 strings"),
+            String::from("String 0 of bundle 3 overruns the resource"),
+            String::from("String bundle \"NAMED\" does not have a valid numeric ID"),
+            String::from("Message table block 0x5-0x4 has an inverted ID range"),
+            String::from("Message 0x2 is outside of the resource or has an invalid length"),
+        ]);
+    }
+}
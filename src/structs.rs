@@ -6,12 +6,16 @@ use std::vec::Vec;
 
 // Structures definitions
 
+#[derive(Default)]
 pub struct Headers {
     pub dos_headers: DOSHeaders,
     pub coff_headers: COFFHeaders,
     pub optional_headers: OptionalHeaders,
+    pub sections: Vec<SectionHeader>,
+    pub anomalies: Vec<String>,
 }
 
+#[derive(Default)]
 pub struct DOSHeaders {
     pub magic: u16,
     pub last_size: u16,
@@ -35,6 +39,7 @@ pub struct DOSHeaders {
 }
 
 #[derive(Debug)]
+#[allow(non_camel_case_types)]
 pub enum CharacteristicsVal {
     IMAGE_FILE_RELOCS_STRIPPED = 0x0001,
     IMAGE_FILE_EXECUTABLE_IMAGE = 0x0002,
@@ -53,12 +58,14 @@ pub enum CharacteristicsVal {
     IMAGE_FILE_BYTES_REVERSED_HI = 0x8000,
 }
 
+#[derive(Default)]
 pub struct Characteristics {
     pub value: u16,
     pub characteristics_list: Vec<CharacteristicsVal>,
 }
 
-pub struct COFFHeaders { 
+#[derive(Default)]
+pub struct COFFHeaders {
     pub magic: u32,
     pub target_machine: u16,
     pub number_of_sections: u16,
@@ -69,12 +76,14 @@ pub struct COFFHeaders {
     pub characteristics: Characteristics,
 }
 
+#[derive(Default)]
 pub struct OptionalHeaders {
     pub standard_fields: StandardFields,
     pub windows_specific: WindowsSpecific,
     pub data_directories: DataDirectories,
 }
 
+#[derive(Default)]
 pub struct StandardFields {
     pub magic: u16,
     pub major_linker_version: u8,
@@ -87,6 +96,7 @@ pub struct StandardFields {
     pub base_of_data: u32,
 }
 
+#[derive(Default)]
 pub struct WindowsSpecific {
     pub image_base: u64,
     pub section_alignment: u32,
@@ -111,150 +121,33 @@ pub struct WindowsSpecific {
     pub number_of_rva_and_sizes: u32,
 }
 
+#[derive(Default)]
 pub struct DataDirectories {
     pub directories: [DataDirectory; 16],
 }
 
 #[derive(Copy)]
 #[derive(Clone)]
+#[derive(Default)]
 pub struct DataDirectory {
     pub virtual_address: u32,
     pub size: u32,
 }
 
-
-// Default trait implementation for the structs
-
-impl Default for Headers {
-    fn default() -> Headers {
-        Headers {
-            dos_headers: DOSHeaders::default(),
-            coff_headers: COFFHeaders::default(),
-            optional_headers: OptionalHeaders::default(),
-        }
-    }
-}
-
-impl Default for DOSHeaders {
-    fn default() -> DOSHeaders {
-        DOSHeaders {
-            magic: 0,
-            last_size: 0,
-            pages_in_file: 0,
-            relocations: 0,
-            header_size_in_paragraph: 0,
-            min_extra_paragraph_needed: 0,
-            max_extra_paragraph_needed: 0,
-            initial_ss: 0,
-            initial_sp: 0,
-            checksum: 0,
-            initial_ip: 0,
-            initial_cs: 0,
-            file_add_of_relocation_table: 0,
-            overlay_number: 0,
-            reserved_one: [0; 8],
-            oem_identifier: 0,
-            oem_information: 0,
-            reserved_two: [0; 20],
-            offset_to_pe_headers: 0,            
-        }
-    }
-}
-
-impl Default for Characteristics {
-    fn default() -> Characteristics { 
-        Characteristics {
-            characteristics_list: Vec::new(),
-            value: 0,
-        }
-    }
-}
-
-impl Default for COFFHeaders {
-    fn default() -> COFFHeaders {
-        COFFHeaders {
-            magic: 0,
-            target_machine: 0,
-            number_of_sections: 0,
-            time_date_stamp: 0,
-            pointer_to_symbol_table: 0,
-            number_of_symbols: 0,
-            size_of_optional_headers: 0,
-            characteristics: Characteristics::default(),
-        }
-    }
-}
-
-impl Default for OptionalHeaders {
-    fn default() -> OptionalHeaders {
-        OptionalHeaders {
-            standard_fields: StandardFields::default(),
-            windows_specific: WindowsSpecific::default(),
-            data_directories: DataDirectories::default(),
-        }
-    }
-}
-
-impl Default for StandardFields {
-    fn default() -> StandardFields {
-        StandardFields {
-            magic: 0,
-            major_linker_version: 0,
-            minor_linker_version: 0,
-            size_of_code: 0,
-            size_of_initialized_data: 0,
-            size_of_uninitialized_data: 0,
-            address_of_entry_point: 0,
-            base_of_code: 0,
-            base_of_data: 0,
-        }
-    }
-}
-
-impl Default for WindowsSpecific {
-    fn default() -> WindowsSpecific {
-        WindowsSpecific {
-            image_base: 0,
-            section_alignment: 0,
-            file_alignment: 0,
-            major_operating_system_version: 0,
-            minor_operating_system_version: 0,
-            major_image_version: 0,
-            minor_image_version: 0,
-            major_subsystem_version: 0,
-            minor_subsystem_version: 0,
-            win32_version_value: 0,
-            size_of_image: 0,
-            size_of_headers: 0,
-            checksum: 0,
-            subsystem: 0,
-            dll_characteristics: 0,
-            size_of_stack_reserve: 0,
-            size_of_stack_commit: 0,
-            size_of_heap_reserve: 0,
-            size_of_heap_commit: 0,
-            loader_flags: 0,
-            number_of_rva_and_sizes: 0,
-        }
-    }
+#[derive(Default)]
+pub struct SectionHeader {
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub pointer_to_relocations: u32,
+    pub pointer_to_linenumbers: u32,
+    pub number_of_relocations: u16,
+    pub number_of_linenumbers: u16,
+    pub characteristics: u32,
 }
 
-impl Default for DataDirectories {
-    fn default() -> DataDirectories {
-        DataDirectories {
-            directories: [DataDirectory::default(); 16],
-        }
-    }
-}
-
-impl Default for DataDirectory {
-    fn default() -> DataDirectory {
-        DataDirectory {
-            virtual_address: 0,
-            size: 0,
-        }
-    }
-}
 
 // TryFrom implementation for structs
 
@@ -402,9 +295,9 @@ impl fmt::Display for DataDirectories {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Data Directories
 
----------------------------");
+---------------------------")?;
         for d in self.directories {
-            write!(f, "{}", d);
+            write!(f, "{}", d)?;
         }
         Ok(())
     }
@@ -420,8 +313,22 @@ size: 0x{:x}
     }
 }
 
+impl fmt::Display for SectionHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Section Header
 
-
-
-
-
+---------------------------
+name: {}
+virtual_size: 0x{:x}
+virtual_address: 0x{:x}
+size_of_raw_data: 0x{:x}
+pointer_to_raw_data: 0x{:x}
+pointer_to_relocations: 0x{:x}
+pointer_to_linenumbers: 0x{:x}
+number_of_relocations: 0x{:x}
+number_of_linenumbers: 0x{:x}
+characteristics: 0x{:x}
+---------------------------",
+        self.name, self.virtual_size, self.virtual_address, self.size_of_raw_data, self.pointer_to_raw_data, self.pointer_to_relocations, self.pointer_to_linenumbers, self.number_of_relocations, self.number_of_linenumbers, self.characteristics)
+    }
+}
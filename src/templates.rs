@@ -0,0 +1,869 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::resources::Resource;
+use crate::resources::Resources;
+use crate::resources::ResourceId;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+const DS_SETFONT: u32 = 0x40;
+const MF_POPUP: u16 = 0x10;
+const MF_END: u16 = 0x80;
+const MFT_SEPARATOR: u32 = 0x800;
+const FVIRTKEY: u16 = 0x01;
+const FNOINVERT: u16 = 0x02;
+const FSHIFT: u16 = 0x04;
+const FCONTROL: u16 = 0x08;
+const FALT: u16 = 0x10;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub enum NameOrOrdinal {
+    None,
+    Ordinal(u16),
+    Name(String),
+}
+
+pub struct DialogFont {
+    pub point_size: u16,
+    pub weight: u16,
+    pub italic: u8,
+    pub charset: u8,
+    pub typeface: String,
+}
+
+pub struct DialogControl {
+    pub help_id: u32,
+    pub style: u32,
+    pub ex_style: u32,
+    pub x: i16,
+    pub y: i16,
+    pub cx: i16,
+    pub cy: i16,
+    pub id: u32,
+    pub class: NameOrOrdinal,
+    pub text: NameOrOrdinal,
+    pub extra: Vec<u8>,
+}
+
+pub struct Dialog {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    // DLGTEMPLATEEX rather than DLGTEMPLATE
+    pub extended: bool,
+    pub help_id: u32,
+    pub style: u32,
+    pub ex_style: u32,
+    pub x: i16,
+    pub y: i16,
+    pub cx: i16,
+    pub cy: i16,
+    pub menu: NameOrOrdinal,
+    pub class: NameOrOrdinal,
+    pub title: String,
+    pub font: Option<DialogFont>,
+    pub controls: Vec<DialogControl>,
+}
+
+pub struct MenuItem {
+    pub text: String,
+    pub id: u32,
+    // MF_* flags for standard menus, MFT_* type for MENUEX
+    pub flags: u32,
+    pub state: u32,
+    pub help_id: u32,
+    pub popup: bool,
+    pub children: Vec<MenuItem>,
+}
+
+pub struct Menu {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    // MENUEX rather than a standard MENU
+    pub extended: bool,
+    pub help_id: u32,
+    pub items: Vec<MenuItem>,
+}
+
+pub struct Accelerator {
+    pub flags: u16,
+    pub key: u16,
+    pub id: u16,
+}
+
+pub struct AcceleratorTable {
+    pub name: ResourceId,
+    pub language: ResourceId,
+    pub accelerators: Vec<Accelerator>,
+}
+
+#[derive(Default)]
+pub struct Templates {
+    pub dialogs: Vec<Dialog>,
+    pub menus: Vec<Menu>,
+    pub accelerator_tables: Vec<AcceleratorTable>,
+    pub anomalies: Vec<String>,
+}
+
+// Sequential reader over a template, alignment is relative to the start of the resource
+struct Cursor<'a> {
+    data: &'a [u8],
+    index: usize,
+}
+
+
+// Conversion implementation for the structs
+
+impl NameOrOrdinal {
+    // Ordinals 0x80-0x85 are the predefined control classes
+    pub fn class_name(&self) -> String {
+        match self {
+            NameOrOrdinal::Ordinal(0x80) => String::from("Button"),
+            NameOrOrdinal::Ordinal(0x81) => String::from("Edit"),
+            NameOrOrdinal::Ordinal(0x82) => String::from("Static"),
+            NameOrOrdinal::Ordinal(0x83) => String::from("ListBox"),
+            NameOrOrdinal::Ordinal(0x84) => String::from("ScrollBar"),
+            NameOrOrdinal::Ordinal(0x85) => String::from("ComboBox"),
+            NameOrOrdinal::Ordinal(ordinal) => ordinal.to_string(),
+            NameOrOrdinal::Name(name) => name.clone(),
+            NameOrOrdinal::None => String::new(),
+        }
+    }
+}
+
+impl Accelerator {
+    pub fn key_name(&self) -> String {
+        let key = self.key;
+        if self.flags & FVIRTKEY == 0 {
+            return match key {
+                0x01..=0x1a => format!("\"^{}\"", (b'A' + key as u8 - 1) as char),
+                0x20..=0x7e => format!("\"{}\"", escape_rc(&(key as u8 as char).to_string())),
+                _ => format!("{}", key),
+            };
+        }
+        match key {
+            0x30..=0x39 | 0x41..=0x5a => format!("\"{}\"", key as u8 as char),
+            0x70..=0x87 => format!("VK_F{}", key - 0x6f),
+            0x08 => String::from("VK_BACK"),
+            0x09 => String::from("VK_TAB"),
+            0x0d => String::from("VK_RETURN"),
+            0x1b => String::from("VK_ESCAPE"),
+            0x20 => String::from("VK_SPACE"),
+            0x21 => String::from("VK_PRIOR"),
+            0x22 => String::from("VK_NEXT"),
+            0x23 => String::from("VK_END"),
+            0x24 => String::from("VK_HOME"),
+            0x25 => String::from("VK_LEFT"),
+            0x26 => String::from("VK_UP"),
+            0x27 => String::from("VK_RIGHT"),
+            0x28 => String::from("VK_DOWN"),
+            0x2d => String::from("VK_INSERT"),
+            0x2e => String::from("VK_DELETE"),
+            _ => format!("0x{:x}", key),
+        }
+    }
+}
+
+
+// Parsing
+
+impl<'a> Cursor<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.index)?;
+        self.index += 1;
+        Some(value)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let value = read_u16_at(self.data, self.index)?;
+        self.index += 2;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let value = read_u32_at(self.data, self.index)?;
+        self.index += 4;
+        Some(value)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.u16().map(|value| value as i16)
+    }
+
+    fn align4(&mut self) {
+        self.index = (self.index + 3) & !3;
+    }
+
+    fn sz(&mut self) -> Option<String> {
+        let mut units = Vec::new();
+        loop {
+            match self.u16()? {
+                0 => break,
+                unit => units.push(unit),
+            }
+        }
+        Some(String::from_utf16_lossy(&units))
+    }
+
+    fn sz_or_ord(&mut self) -> Option<NameOrOrdinal> {
+        match read_u16_at(self.data, self.index)? {
+            0x0000 => {
+                self.index += 2;
+                Some(NameOrOrdinal::None)
+            }
+            0xffff => {
+                self.index += 2;
+                Some(NameOrOrdinal::Ordinal(self.u16()?))
+            }
+            _ => Some(NameOrOrdinal::Name(self.sz()?)),
+        }
+    }
+}
+
+fn parse_dialog(data: &[u8], resource: &Resource) -> Option<Dialog> {
+    let mut cursor = Cursor { data, index: 0 };
+    let extended = read_u16_at(data, 0)? == 1 && read_u16_at(data, 2)? == 0xffff;
+    let mut dialog = Dialog {
+        name: resource.name.clone(),
+        language: resource.language.clone(),
+        extended,
+        help_id: 0,
+        style: 0,
+        ex_style: 0,
+        x: 0,
+        y: 0,
+        cx: 0,
+        cy: 0,
+        menu: NameOrOrdinal::None,
+        class: NameOrOrdinal::None,
+        title: String::new(),
+        font: None,
+        controls: Vec::new(),
+    };
+    if extended {
+        cursor.index = 4;
+        dialog.help_id = cursor.u32()?;
+        dialog.ex_style = cursor.u32()?;
+        dialog.style = cursor.u32()?;
+    } else {
+        dialog.style = cursor.u32()?;
+        dialog.ex_style = cursor.u32()?;
+    }
+    let count = cursor.u16()?;
+    dialog.x = cursor.i16()?;
+    dialog.y = cursor.i16()?;
+    dialog.cx = cursor.i16()?;
+    dialog.cy = cursor.i16()?;
+    dialog.menu = cursor.sz_or_ord()?;
+    dialog.class = cursor.sz_or_ord()?;
+    dialog.title = cursor.sz()?;
+    // DS_SHELLFONT includes DS_SETFONT
+    if dialog.style & DS_SETFONT != 0 {
+        let point_size = cursor.u16()?;
+        let (weight, italic, charset) = if extended {
+            (cursor.u16()?, cursor.u8()?, cursor.u8()?)
+        } else {
+            (0, 0, 0)
+        };
+        let typeface = cursor.sz()?;
+        dialog.font = Some(DialogFont { point_size, weight, italic, charset, typeface });
+    }
+    for _ in 0..count {
+        cursor.align4();
+        let mut control = DialogControl {
+            help_id: 0,
+            style: 0,
+            ex_style: 0,
+            x: 0,
+            y: 0,
+            cx: 0,
+            cy: 0,
+            id: 0,
+            class: NameOrOrdinal::None,
+            text: NameOrOrdinal::None,
+            extra: Vec::new(),
+        };
+        if extended {
+            control.help_id = cursor.u32()?;
+            control.ex_style = cursor.u32()?;
+            control.style = cursor.u32()?;
+        } else {
+            control.style = cursor.u32()?;
+            control.ex_style = cursor.u32()?;
+        }
+        control.x = cursor.i16()?;
+        control.y = cursor.i16()?;
+        control.cx = cursor.i16()?;
+        control.cy = cursor.i16()?;
+        // Standard templates store 16-bit IDs, IDC_STATIC (-1) has to stay negative
+        control.id = if extended { cursor.u32()? } else { cursor.i16()? as i32 as u32 };
+        control.class = cursor.sz_or_ord()?;
+        control.text = cursor.sz_or_ord()?;
+        let mut extra_count = cursor.u16()? as usize;
+        // DLGITEMTEMPLATE counts the size word itself, DLGITEMTEMPLATEEX only what follows it
+        if !extended {
+            extra_count = extra_count.saturating_sub(2);
+        }
+        if extra_count > 0 {
+            control.extra = data.get(cursor.index..cursor.index + extra_count)?.to_vec();
+            cursor.index += extra_count;
+        }
+        dialog.controls.push(control);
+    }
+    Some(dialog)
+}
+
+fn parse_menu_items(cursor: &mut Cursor) -> Option<Vec<MenuItem>> {
+    let mut items = Vec::new();
+    loop {
+        let flags = cursor.u16()?;
+        let popup = flags & MF_POPUP != 0;
+        let id = if popup { 0 } else { cursor.u16()? as u32 };
+        let text = cursor.sz()?;
+        let children = if popup { parse_menu_items(cursor)? } else { Vec::new() };
+        items.push(MenuItem { text, id, flags: (flags & !MF_END) as u32, state: 0, help_id: 0, popup, children });
+        if flags & MF_END != 0 {
+            break;
+        }
+    }
+    Some(items)
+}
+
+fn parse_menuex_items(cursor: &mut Cursor) -> Option<Vec<MenuItem>> {
+    let mut items = Vec::new();
+    loop {
+        cursor.align4();
+        let flags = cursor.u32()?;
+        let state = cursor.u32()?;
+        let id = cursor.u32()?;
+        let res_info = cursor.u16()?;
+        let text = cursor.sz()?;
+        let popup = res_info & 0x01 != 0;
+        let mut help_id = 0;
+        let mut children = Vec::new();
+        if popup {
+            cursor.align4();
+            help_id = cursor.u32()?;
+            children = parse_menuex_items(cursor)?;
+        }
+        items.push(MenuItem { text, id, flags, state, help_id, popup, children });
+        if res_info & 0x80 != 0 {
+            break;
+        }
+    }
+    Some(items)
+}
+
+fn parse_menu(data: &[u8], resource: &Resource) -> Option<Menu> {
+    let mut cursor = Cursor { data, index: 0 };
+    let version = cursor.u16()?;
+    let offset = cursor.u16()?;
+    let mut menu = Menu {
+        name: resource.name.clone(),
+        language: resource.language.clone(),
+        extended: version == 1,
+        help_id: 0,
+        items: Vec::new(),
+    };
+    if menu.extended {
+        menu.help_id = read_u32_at(data, 4).unwrap_or(0);
+        // The offset is relative to the end of the offset field itself
+        cursor.index = 4 + offset as usize;
+        menu.items = parse_menuex_items(&mut cursor)?;
+    } else {
+        cursor.index = 4 + offset as usize;
+        menu.items = parse_menu_items(&mut cursor)?;
+    }
+    Some(menu)
+}
+
+fn parse_accelerators(data: &[u8], resource: &Resource) -> AcceleratorTable {
+    let mut table = AcceleratorTable {
+        name: resource.name.clone(),
+        language: resource.language.clone(),
+        accelerators: Vec::new(),
+    };
+    for entry in data.chunks_exact(8) {
+        let flags = u16::from_le_bytes([entry[0], entry[1]]);
+        table.accelerators.push(Accelerator {
+            flags: flags & !0x80,
+            key: u16::from_le_bytes([entry[2], entry[3]]),
+            id: u16::from_le_bytes([entry[4], entry[5]]),
+        });
+        if flags & 0x80 != 0 {
+            break;
+        }
+    }
+    table
+}
+
+pub fn parse_templates(file: &[u8], headers: &Headers, resources: &Resources) -> Templates {
+    let mut templates = Templates::default();
+    for resource in &resources.resources {
+        if !matches!(resource.resource_type, ResourceType::RT_DIALOG | ResourceType::RT_MENU | ResourceType::RT_ACCELERATOR) {
+            continue;
+        }
+        let data = match resource_data(file, headers, resource) {
+            Some(data) => data,
+            None => {
+                templates.anomalies.push(format!("Resource {} is not backed by the file", resource));
+                continue;
+            }
+        };
+        match resource.resource_type {
+            ResourceType::RT_DIALOG => match parse_dialog(data, resource) {
+                Some(dialog) => templates.dialogs.push(dialog),
+                None => templates.anomalies.push(format!("Dialog {} is truncated", resource.name)),
+            },
+            ResourceType::RT_MENU => match parse_menu(data, resource) {
+                Some(menu) => templates.menus.push(menu),
+                None => templates.anomalies.push(format!("Menu {} is truncated", resource.name)),
+            },
+            _ => templates.accelerator_tables.push(parse_accelerators(data, resource)),
+        }
+    }
+    templates
+}
+
+
+// Resource script rendering
+
+fn escape_rc(value: &str) -> String {
+    value.replace('"', "\"\"").replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t")
+}
+
+fn rc_name(name: &ResourceId) -> String {
+    match name {
+        ResourceId::Id(id) => id.to_string(),
+        ResourceId::Name(name) => name.clone(),
+    }
+}
+
+fn rc_language(language: &ResourceId) -> String {
+    match language {
+        ResourceId::Id(id) => format!("LANGUAGE 0x{:x}, 0x{:x}", id & 0x3ff, id >> 10),
+        ResourceId::Name(name) => format!("// LANGUAGE {}", name),
+    }
+}
+
+fn rc_text(text: &NameOrOrdinal) -> String {
+    match text {
+        NameOrOrdinal::None => String::from("\"\""),
+        NameOrOrdinal::Ordinal(ordinal) => ordinal.to_string(),
+        NameOrOrdinal::Name(name) => format!("\"{}\"", escape_rc(name)),
+    }
+}
+
+impl Dialog {
+    pub fn to_rc(&self) -> String {
+        let mut rc = format!("{} {} {}, {}, {}, {}\n", rc_name(&self.name), if self.extended { "DIALOGEX" } else { "DIALOG" }, self.x, self.y, self.cx, self.cy);
+        rc += &format!("STYLE 0x{:08x}\n", self.style);
+        if self.ex_style != 0 {
+            rc += &format!("EXSTYLE 0x{:08x}\n", self.ex_style);
+        }
+        if !self.title.is_empty() {
+            rc += &format!("CAPTION \"{}\"\n", escape_rc(&self.title));
+        }
+        if self.class != NameOrOrdinal::None {
+            rc += &format!("CLASS {}\n", rc_text(&self.class));
+        }
+        if self.menu != NameOrOrdinal::None {
+            rc += &format!("MENU {}\n", rc_text(&self.menu).trim_matches('"'));
+        }
+        if let Some(font) = &self.font {
+            if self.extended {
+                rc += &format!("FONT {}, \"{}\", {}, {}, 0x{:x}\n", font.point_size, escape_rc(&font.typeface), font.weight, font.italic, font.charset);
+            } else {
+                rc += &format!("FONT {}, \"{}\"\n", font.point_size, escape_rc(&font.typeface));
+            }
+        }
+        rc += "BEGIN\n";
+        for control in &self.controls {
+            rc += &format!("    CONTROL {}, {}, \"{}\", 0x{:08x}, {}, {}, {}, {}", rc_text(&control.text), control.id as i32, escape_rc(&control.class.class_name()), control.style, control.x, control.y, control.cx, control.cy);
+            if control.ex_style != 0 || control.help_id != 0 {
+                rc += &format!(", 0x{:08x}", control.ex_style);
+            }
+            if control.help_id != 0 {
+                rc += &format!(", {}", control.help_id);
+            }
+            rc += "\n";
+        }
+        rc += "END\n";
+        rc
+    }
+}
+
+fn menu_items_to_rc(items: &[MenuItem], extended: bool, level: usize, rc: &mut String) {
+    let indent = "    ".repeat(level);
+    for item in items {
+        if item.popup {
+            if extended {
+                *rc += &format!("{}POPUP \"{}\", {}, 0x{:x}, 0x{:x}, {}\n", indent, escape_rc(&item.text), item.id, item.flags, item.state, item.help_id);
+            } else {
+                *rc += &format!("{}POPUP \"{}\"\n", indent, escape_rc(&item.text));
+            }
+            *rc += &format!("{}BEGIN\n", indent);
+            menu_items_to_rc(&item.children, extended, level + 1, rc);
+            *rc += &format!("{}END\n", indent);
+        } else if extended {
+            if item.flags & MFT_SEPARATOR != 0 {
+                *rc += &format!("{}MENUITEM \"\", {}, 0x{:x}, 0x{:x}\n", indent, item.id, item.flags, item.state);
+            } else {
+                *rc += &format!("{}MENUITEM \"{}\", {}, 0x{:x}, 0x{:x}\n", indent, escape_rc(&item.text), item.id, item.flags, item.state);
+            }
+        } else if item.id == 0 && item.text.is_empty() && item.flags == 0 {
+            *rc += &format!("{}MENUITEM SEPARATOR\n", indent);
+        } else {
+            let mut options = Vec::new();
+            for (flag, option) in [(0x01, "GRAYED"), (0x02, "INACTIVE"), (0x08, "CHECKED"), (0x20, "MENUBARBREAK"), (0x40, "MENUBREAK"), (0x4000, "HELP")] {
+                if item.flags & flag != 0 {
+                    options.push(option);
+                }
+            }
+            let mut line = format!("{}MENUITEM \"{}\", {}", indent, escape_rc(&item.text), item.id);
+            if !options.is_empty() {
+                line += &format!(", {}", options.join(", "));
+            }
+            *rc += &line;
+            *rc += "\n";
+        }
+    }
+}
+
+impl Menu {
+    pub fn to_rc(&self) -> String {
+        let mut rc = format!("{} {}\nBEGIN\n", rc_name(&self.name), if self.extended { "MENUEX" } else { "MENU" });
+        menu_items_to_rc(&self.items, self.extended, 1, &mut rc);
+        rc += "END\n";
+        rc
+    }
+}
+
+impl AcceleratorTable {
+    pub fn to_rc(&self) -> String {
+        let mut rc = format!("{} ACCELERATORS\nBEGIN\n", rc_name(&self.name));
+        for accelerator in &self.accelerators {
+            let mut line = format!("    {}, {}", accelerator.key_name(), accelerator.id);
+            let mut options = Vec::new();
+            options.push(if accelerator.flags & FVIRTKEY != 0 { "VIRTKEY" } else { "ASCII" });
+            for (flag, option) in [(FNOINVERT, "NOINVERT"), (FSHIFT, "SHIFT"), (FCONTROL, "CONTROL"), (FALT, "ALT")] {
+                if accelerator.flags & flag != 0 {
+                    options.push(option);
+                }
+            }
+            line += &format!(", {}\n", options.join(", "));
+            rc += &line;
+        }
+        rc += "END\n";
+        rc
+    }
+}
+
+impl Templates {
+    pub fn to_rc(&self) -> String {
+        let mut rc = String::new();
+        for dialog in &self.dialogs {
+            rc += &format!("{}\n{}\n", rc_language(&dialog.language), dialog.to_rc());
+        }
+        for menu in &self.menus {
+            rc += &format!("{}\n{}\n", rc_language(&menu.language), menu.to_rc());
+        }
+        for table in &self.accelerator_tables {
+            rc += &format!("{}\n{}\n", rc_language(&table.language), table.to_rc());
+        }
+        rc
+    }
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for NameOrOrdinal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameOrOrdinal::None => write!(f, "-"),
+            NameOrOrdinal::Ordinal(ordinal) => write!(f, "#{}", ordinal),
+            NameOrOrdinal::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl fmt::Display for Dialog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Dialog {} (language {})
+
+---------------------------
+extended: {}
+style: 0x{:x}
+ex_style: 0x{:x}
+help_id: {}
+position: {}, {}, {}, {}
+menu: {}
+class: {}
+title: \"{}\"",
+        self.name, self.language, self.extended, self.style, self.ex_style, self.help_id, self.x, self.y, self.cx, self.cy, self.menu, self.class, self.title)?;
+        if let Some(font) = &self.font {
+            write!(f, "
+font: {}pt \"{}\" weight {} italic {} charset {}", font.point_size, font.typeface, font.weight, font.italic, font.charset)?;
+        }
+        for control in &self.controls {
+            write!(f, "
+    control id: {} class: {} text: {} style: 0x{:x} ex_style: 0x{:x} position: {}, {}, {}, {}",
+            control.id as i32, control.class.class_name(), control.text, control.style, control.ex_style, control.x, control.y, control.cx, control.cy)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+fn fmt_menu_items(f: &mut fmt::Formatter, items: &[MenuItem], level: usize) -> fmt::Result {
+    let indent = "    ".repeat(level);
+    for item in items {
+        write!(f, "
+{}\"{}\" id: {} flags: 0x{:x} state: 0x{:x}", indent, item.text, item.id, item.flags, item.state)?;
+        fmt_menu_items(f, &item.children, level + 1)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Menu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Menu {} (language {})
+
+---------------------------
+extended: {}",
+        self.name, self.language, self.extended)?;
+        fmt_menu_items(f, &self.items, 0)?;
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for AcceleratorTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Accelerators {} (language {})
+
+---------------------------",
+        self.name, self.language)?;
+        for accelerator in &self.accelerators {
+            write!(f, "
+key: {} id: {} flags: 0x{:x}", accelerator.key_name(), accelerator.id, accelerator.flags)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for Templates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for dialog in &self.dialogs {
+            writeln!(f, "{}", dialog)?;
+        }
+        for menu in &self.menus {
+            writeln!(f, "{}", menu)?;
+        }
+        for table in &self.accelerator_tables {
+            writeln!(f, "{}", table)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+    use crate::utils::fixtures::utf16;
+
+    fn u16s(data: &mut Vec<u8>, values: &[u16]) {
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn u32s(data: &mut Vec<u8>, values: &[u32]) {
+        for value in values {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn sz(data: &mut Vec<u8>, text: &str) {
+        data.extend_from_slice(&utf16(text));
+        data.extend_from_slice(&[0, 0]);
+    }
+
+    fn align4(data: &mut Vec<u8>) {
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    // DLGTEMPLATE with a font, an OK button and a static with IDC_STATIC
+    fn dialog() -> Vec<u8> {
+        let mut data = Vec::new();
+        u32s(&mut data, &[0x80c800c0, 0]);
+        u16s(&mut data, &[2, 10, 20, 200, 100, 0, 0]);
+        sz(&mut data, "About \"pehp\"");
+        u16s(&mut data, &[8]);
+        sz(&mut data, "MS Shell Dlg");
+        align4(&mut data);
+        u32s(&mut data, &[0x50010001, 0]);
+        u16s(&mut data, &[140, 80, 50, 14, 1, 0xffff, 0x80]);
+        sz(&mut data, "OK");
+        u16s(&mut data, &[0]);
+        align4(&mut data);
+        u32s(&mut data, &[0x50000000, 0]);
+        u16s(&mut data, &[7, 7, 100, 8, 0xffff, 0xffff, 0x82]);
+        sz(&mut data, "Version 1.0");
+        u16s(&mut data, &[0]);
+        data
+    }
+
+    // DLGTEMPLATEEX with a named class control carrying creation data
+    fn dialog_ex() -> Vec<u8> {
+        let mut data = Vec::new();
+        u16s(&mut data, &[1, 0xffff]);
+        u32s(&mut data, &[0, 0x00000100, 0x80c80048]);
+        u16s(&mut data, &[1, 0, 0, 100, 50, 0xffff, 200, 0]);
+        sz(&mut data, "Files");
+        u16s(&mut data, &[9, 700]);
+        data.extend_from_slice(&[1, 1]);
+        sz(&mut data, "Segoe UI");
+        align4(&mut data);
+        u32s(&mut data, &[42, 0x200, 0x50010000]);
+        u16s(&mut data, &[5, 5, 90, 40]);
+        u32s(&mut data, &[1000]);
+        sz(&mut data, "SysListView32");
+        u16s(&mut data, &[0, 2, 0xbeef]);
+        data
+    }
+
+    // &File popup with Open, a separator and Exit, then a grayed Help item
+    fn menu() -> Vec<u8> {
+        let mut data = Vec::new();
+        u16s(&mut data, &[0, 0, MF_POPUP]);
+        sz(&mut data, "&File");
+        u16s(&mut data, &[0, 100]);
+        sz(&mut data, "&Open\tCtrl+O");
+        u16s(&mut data, &[0, 0]);
+        sz(&mut data, "");
+        u16s(&mut data, &[MF_END, 101]);
+        sz(&mut data, "E&xit");
+        u16s(&mut data, &[MF_END | 0x01, 102]);
+        sz(&mut data, "&Help");
+        data
+    }
+
+    fn menu_ex() -> Vec<u8> {
+        let mut data = Vec::new();
+        u16s(&mut data, &[1, 4]);
+        u32s(&mut data, &[7]);
+        u32s(&mut data, &[0, 0, 300]);
+        u16s(&mut data, &[0x81]);
+        sz(&mut data, "&View");
+        align4(&mut data);
+        u32s(&mut data, &[11]);
+        u32s(&mut data, &[MFT_SEPARATOR, 0, 0]);
+        u16s(&mut data, &[0]);
+        sz(&mut data, "");
+        align4(&mut data);
+        u32s(&mut data, &[0, 8, 301]);
+        u16s(&mut data, &[0x80]);
+        sz(&mut data, "&Status Bar");
+        data
+    }
+
+    fn accelerators() -> Vec<u8> {
+        let mut data = Vec::new();
+        u16s(&mut data, &[FVIRTKEY | FCONTROL, 0x4f, 100, 0]);
+        u16s(&mut data, &[FVIRTKEY | FNOINVERT, 0x74, 200, 0]);
+        u16s(&mut data, &[0x80, 0x03, 201, 0]);
+        // Past the last entry
+        u16s(&mut data, &[FVIRTKEY, 0x41, 202, 0]);
+        data
+    }
+
+    #[test]
+    fn renders_templates() {
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(5), ResourceId::Id(100), 0x409, &dialog()),
+            (ResourceId::Id(5), ResourceId::Name(String::from("FILES")), 0x409, &dialog_ex()),
+            (ResourceId::Id(4), ResourceId::Id(1), 0x409, &menu()),
+            (ResourceId::Id(4), ResourceId::Id(2), 0x409, &menu_ex()),
+            (ResourceId::Id(9), ResourceId::Id(1), 0x409, &accelerators()),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let templates = parse_templates(&file, &headers, &resources);
+        assert!(templates.anomalies.is_empty(), "{:?}", templates.anomalies);
+        assert_eq!(templates.dialogs[0].to_rc(), "100 DIALOG 10, 20, 200, 100\n\
+            STYLE 0x80c800c0\n\
+            CAPTION \"About \"\"pehp\"\"\"\n\
+            FONT 8, \"MS Shell Dlg\"\n\
+            BEGIN\n    \
+            CONTROL \"OK\", 1, \"Button\", 0x50010001, 140, 80, 50, 14\n    \
+            CONTROL \"Version 1.0\", -1, \"Static\", 0x50000000, 7, 7, 100, 8\n\
+            END\n");
+        let dialog = &templates.dialogs[1];
+        assert_eq!(dialog.controls[0].extra, [0xef, 0xbe]);
+        assert_eq!(dialog.to_rc(), "FILES DIALOGEX 0, 0, 100, 50\n\
+            STYLE 0x80c80048\n\
+            EXSTYLE 0x00000100\n\
+            CAPTION \"Files\"\n\
+            MENU 200\n\
+            FONT 9, \"Segoe UI\", 700, 1, 0x1\n\
+            BEGIN\n    \
+            CONTROL \"\", 1000, \"SysListView32\", 0x50010000, 5, 5, 90, 40, 0x00000200, 42\n\
+            END\n");
+        assert_eq!(templates.menus[0].to_rc(), "1 MENU\nBEGIN\n    \
+            POPUP \"&File\"\n    BEGIN\n        \
+            MENUITEM \"&Open\\tCtrl+O\", 100\n        \
+            MENUITEM SEPARATOR\n        \
+            MENUITEM \"E&xit\", 101\n    \
+            END\n    \
+            MENUITEM \"&Help\", 102, GRAYED\n\
+            END\n");
+        let menu = &templates.menus[1];
+        assert_eq!(menu.help_id, 7);
+        assert_eq!(menu.to_rc(), "2 MENUEX\nBEGIN\n    \
+            POPUP \"&View\", 300, 0x0, 0x0, 11\n    BEGIN\n        \
+            MENUITEM \"\", 0, 0x800, 0x0\n        \
+            MENUITEM \"&Status Bar\", 301, 0x0, 0x8\n    \
+            END\n\
+            END\n");
+        assert_eq!(templates.accelerator_tables[0].to_rc(), "1 ACCELERATORS\nBEGIN\n    \
+            \"O\", 100, VIRTKEY, CONTROL\n    \
+            VK_F5, 200, VIRTKEY, NOINVERT\n    \
+            \"^C\", 201, ASCII\n\
+            END\n");
+    }
+
+    #[test]
+    fn truncated_templates() {
+        let mut truncated_dialog = dialog();
+        truncated_dialog.truncate(60);
+        let mut truncated_menu = menu();
+        truncated_menu.truncate(truncated_menu.len() - 4);
+        let (file, headers) = resource_image(&[
+            (ResourceId::Id(5), ResourceId::Id(100), 0, &truncated_dialog),
+            (ResourceId::Id(4), ResourceId::Id(1), 0, &truncated_menu),
+            (ResourceId::Id(9), ResourceId::Id(2), 0, &accelerators()),
+        ]);
+        let resources = parse_resources(&file, &headers);
+        let templates = parse_templates(&file, &headers, &resources);
+        // Truncated templates are dropped, the other templates are still decoded
+        assert!(templates.dialogs.is_empty() && templates.menus.is_empty());
+        assert_eq!(templates.accelerator_tables.len(), 1);
+        assert_eq!(templates.accelerator_tables[0].name, ResourceId::Id(2));
+        let ids: Vec<u16> = templates.accelerator_tables[0].accelerators.iter().map(|accelerator| accelerator.id).collect();
+        assert_eq!(ids, vec![100, 200, 201]);
+        assert_eq!(templates.anomalies, vec![String::from("Dialog 100 is truncated"), String::from("Menu 1 is truncated")]);
+    }
+}
@@ -0,0 +1,269 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::is_pe32;
+use crate::utils::read_pointer_at;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+use crate::utils::section_for_rva;
+use crate::utils::va_to_rva;
+
+const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+
+// Guards against callback arrays that are not terminated
+const MAX_CALLBACKS: usize = 1024;
+
+// Structures definitions
+
+pub struct TlsCallback {
+    pub va: u64,
+    pub rva: Option<u32>,
+    pub section: Option<String>,
+}
+
+#[derive(Default)]
+pub struct TlsDirectory {
+    pub start_address_of_raw_data: u64,
+    pub end_address_of_raw_data: u64,
+    pub address_of_index: u64,
+    pub address_of_callbacks: u64,
+    pub size_of_zero_fill: u32,
+    pub characteristics: u32,
+    pub callbacks: Vec<TlsCallback>,
+    pub anomalies: Vec<String>,
+}
+
+
+// Conversion implementation for the structs
+
+impl TlsDirectory {
+    // Decoded from the IMAGE_SCN_ALIGN_* bits, None when unspecified
+    pub fn alignment(&self) -> Option<u32> {
+        match (self.characteristics >> 20) & 0xf {
+            0 => None,
+            bits => Some(1 << (bits - 1)),
+        }
+    }
+
+    pub fn raw_data_size(&self) -> u64 {
+        self.end_address_of_raw_data.saturating_sub(self.start_address_of_raw_data)
+    }
+}
+
+
+// Parsing
+
+fn parse_callbacks(file: &[u8], headers: &Headers, tls: &mut TlsDirectory, pe_32: bool) {
+    if tls.address_of_callbacks == 0 {
+        return;
+    }
+    let offset = match va_to_rva(headers, tls.address_of_callbacks).and_then(|rva| rva_to_offset(headers, rva)) {
+        Some(offset) => offset,
+        None => {
+            tls.anomalies.push(format!("TLS callback array at VA 0x{:x} is not backed by the file", tls.address_of_callbacks));
+            return;
+        }
+    };
+    let pointer_size = if pe_32 { 4 } else { 8 };
+    for x in 0..=MAX_CALLBACKS {
+        let va = match read_pointer_at(file, offset + x * pointer_size, pe_32) {
+            Some(va) => va,
+            None => {
+                tls.anomalies.push(String::from("TLS callback array runs past the end of the file"));
+                return;
+            }
+        };
+        if va == 0 {
+            return;
+        }
+        if x == MAX_CALLBACKS {
+            tls.anomalies.push(format!("TLS callback array has more than {} entries, stopped walking", MAX_CALLBACKS));
+            return;
+        }
+        let rva = va_to_rva(headers, va);
+        let section = rva.and_then(|rva| section_for_rva(headers, rva)).map(|section| section.name.clone());
+        match (rva, &section) {
+            (None, _) => tls.anomalies.push(format!("TLS callback 0x{:x} lies below the image base", va)),
+            (Some(rva), None) => tls.anomalies.push(format!("TLS callback at RVA 0x{:x} is outside of every section", rva)),
+            _ => {}
+        }
+        tls.callbacks.push(TlsCallback { va, rva, section });
+    }
+}
+
+pub fn parse_tls(file: &[u8], headers: &Headers) -> Option<TlsDirectory> {
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_TLS];
+    if directory.virtual_address == 0 {
+        return None;
+    }
+    let mut tls = TlsDirectory::default();
+    let pe_32 = is_pe32(headers);
+    let expected_size = if pe_32 { 24 } else { 40 };
+    if directory.size != 0 && directory.size < expected_size {
+        tls.anomalies.push(format!("TLS directory size 0x{:x} is smaller than 0x{:x}", directory.size, expected_size));
+    }
+    let offset = match rva_to_offset(headers, directory.virtual_address) {
+        Some(offset) if offset + expected_size as usize <= file.len() => offset,
+        _ => {
+            tls.anomalies.push(format!("TLS directory at RVA 0x{:x} is not backed by the file", directory.virtual_address));
+            return Some(tls);
+        }
+    };
+    let pointer_size = if pe_32 { 4 } else { 8 };
+    let mut index = offset;
+    let mut next_pointer = || {
+        let value = read_pointer_at(file, index, pe_32).unwrap_or(0);
+        index += pointer_size;
+        value
+    };
+    tls.start_address_of_raw_data = next_pointer();
+    tls.end_address_of_raw_data = next_pointer();
+    tls.address_of_index = next_pointer();
+    tls.address_of_callbacks = next_pointer();
+    tls.size_of_zero_fill = read_u32_at(file, offset + 4 * pointer_size).unwrap_or(0);
+    tls.characteristics = read_u32_at(file, offset + 4 * pointer_size + 4).unwrap_or(0);
+    if tls.end_address_of_raw_data < tls.start_address_of_raw_data {
+        tls.anomalies.push(String::from("TLS raw data ends before it starts"));
+    }
+    parse_callbacks(file, headers, &mut tls, pe_32);
+    Some(tls)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for TlsDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TLS Directory
+
+---------------------------
+start_address_of_raw_data: 0x{:x}
+end_address_of_raw_data: 0x{:x}
+address_of_index: 0x{:x}
+address_of_callbacks: 0x{:x}
+size_of_zero_fill: 0x{:x}
+characteristics: 0x{:x}
+alignment: {}",
+        self.start_address_of_raw_data, self.end_address_of_raw_data, self.address_of_index, self.address_of_callbacks, self.size_of_zero_fill, self.characteristics,
+        self.alignment().map(|alignment| alignment.to_string()).unwrap_or_else(|| String::from("-")))?;
+        for callback in &self.callbacks {
+            write!(f, "{}", callback)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for TlsCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+callback: va 0x{:x}", self.va)?;
+        if let Some(rva) = self.rva {
+            write!(f, " rva 0x{:x}", rva)?;
+        }
+        if let Some(section) = &self.section {
+            write!(f, " section {}", section)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn image(pe_32: bool, directory_size: u32) -> (Vec<u8>, Headers) {
+        let file = vec![0; 0x1000];
+        let mut headers = flat_headers(pe_32, file.len());
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_TLS].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_TLS].size = directory_size;
+        (file, headers)
+    }
+
+    #[test]
+    fn parses_pe32_plus_directory() {
+        let (mut file, headers) = image(false, 40);
+        let base = headers.optional_headers.windows_specific.image_base;
+        for (x, value) in [base + 0xa00, base + 0xa40, base + 0xb00, base + 0x900].iter().enumerate() {
+            put(&mut file, 0x800 + x * 8, &value.to_le_bytes());
+        }
+        put(&mut file, 0x820, &0x10u32.to_le_bytes());
+        put(&mut file, 0x824, &0x00500000u32.to_le_bytes());
+        put(&mut file, 0x900, &(base + 0x610).to_le_bytes());
+        put(&mut file, 0x908, &(base + 0x2000).to_le_bytes());
+        let tls = parse_tls(&file, &headers).unwrap();
+        assert_eq!(tls.raw_data_size(), 0x40);
+        assert_eq!(tls.size_of_zero_fill, 0x10);
+        assert_eq!(tls.alignment(), Some(16));
+        let callbacks: Vec<(Option<u32>, Option<&str>)> = tls.callbacks.iter().map(|callback| (callback.rva, callback.section.as_deref())).collect();
+        assert_eq!(callbacks, vec![(Some(0x610), Some(".text")), (Some(0x2000), None)]);
+        assert_eq!(tls.anomalies, vec![String::from("TLS callback at RVA 0x2000 is outside of every section")]);
+    }
+
+    #[test]
+    fn parses_pe32_directory() {
+        let (mut file, headers) = image(true, 0x10);
+        for (x, value) in [0x400a40u32, 0x400a00, 0x400b00, 0x400900].iter().enumerate() {
+            put(&mut file, 0x800 + x * 4, &value.to_le_bytes());
+        }
+        put(&mut file, 0x900, &0x401000u32.to_le_bytes());
+        put(&mut file, 0x904, &0x1000u32.to_le_bytes());
+        let tls = parse_tls(&file, &headers).unwrap();
+        assert_eq!((tls.start_address_of_raw_data, tls.end_address_of_raw_data), (0x400a40, 0x400a00));
+        assert_eq!((tls.address_of_index, tls.address_of_callbacks), (0x400b00, 0x400900));
+        assert_eq!(tls.alignment(), None);
+        let callbacks: Vec<(u64, Option<u32>)> = tls.callbacks.iter().map(|callback| (callback.va, callback.rva)).collect();
+        assert_eq!(callbacks, vec![(0x401000, Some(0x1000)), (0x1000, None)]);
+        assert_eq!(tls.anomalies, vec![
+            String::from("TLS directory size 0x10 is smaller than 0x18"),
+            String::from("TLS raw data ends before it starts"),
+            String::from("TLS callback at RVA 0x1000 is outside of every section"),
+            String::from("TLS callback 0x1000 lies below the image base"),
+        ]);
+    }
+
+    #[test]
+    fn malformed_callback_arrays() {
+        // Not terminated before the end of the file
+        let (mut file, headers) = image(true, 24);
+        put(&mut file, 0x80c, &0x400ff8u32.to_le_bytes());
+        put(&mut file, 0xff8, &[0x10, 0x05, 0x40, 0x00, 0x20, 0x05, 0x40, 0x00]);
+        let tls = parse_tls(&file, &headers).unwrap();
+        let callbacks: Vec<(u64, Option<u32>)> = tls.callbacks.iter().map(|callback| (callback.va, callback.rva)).collect();
+        assert_eq!(callbacks, vec![(0x400510, Some(0x510)), (0x400520, Some(0x520))]);
+        assert_eq!(tls.anomalies, vec![String::from("TLS callback array runs past the end of the file")]);
+
+        // More callbacks than the walker accepts
+        let (mut file, headers) = image(true, 24);
+        for _ in 0..=MAX_CALLBACKS {
+            file.extend_from_slice(&0x400500u32.to_le_bytes());
+        }
+        let mut headers_past = flat_headers(true, file.len());
+        headers_past.optional_headers.data_directories = headers.optional_headers.data_directories;
+        put(&mut file, 0x80c, &0x401000u32.to_le_bytes());
+        let tls = parse_tls(&file, &headers_past).unwrap();
+        assert_eq!(tls.callbacks.len(), MAX_CALLBACKS);
+        assert!(tls.callbacks.iter().all(|callback| callback.rva == Some(0x500)));
+        assert_eq!(tls.anomalies, vec![format!("TLS callback array has more than {} entries, stopped walking", MAX_CALLBACKS)]);
+
+        // Callback array outside of the image, then a directory outside of the file
+        let (mut file, mut headers) = image(true, 24);
+        put(&mut file, 0x80c, &0x480000u32.to_le_bytes());
+        let tls = parse_tls(&file, &headers).unwrap();
+        assert_eq!(tls.address_of_callbacks, 0x480000);
+        assert!(tls.callbacks.is_empty());
+        assert_eq!(tls.anomalies, vec![String::from("TLS callback array at VA 0x480000 is not backed by the file")]);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_TLS].virtual_address = 0xff0;
+        let tls = parse_tls(&file, &headers).unwrap();
+        assert_eq!(tls.address_of_callbacks, 0);
+        assert!(tls.callbacks.is_empty());
+        assert_eq!(tls.anomalies, vec![String::from("TLS directory at RVA 0xff0 is not backed by the file")]);
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_TLS].virtual_address = 0;
+        assert!(parse_tls(&file, &headers).is_none());
+    }
+}
@@ -0,0 +1,446 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::utils::is_executable_rva;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+use crate::utils::rva_to_offset;
+
+const IMAGE_DIRECTORY_ENTRY_EXCEPTION: usize = 3;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+
+// Chains deeper than this are considered to be loops
+const MAX_CHAIN_DEPTH: usize = 32;
+
+const REGISTERS: [&str; 16] = ["RAX", "RCX", "RDX", "RBX", "RSP", "RBP", "RSI", "RDI", "R8", "R9", "R10", "R11", "R12", "R13", "R14", "R15"];
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum UnwindFlag {
+    UNW_FLAG_EHANDLER = 0x1,
+    UNW_FLAG_UHANDLER = 0x2,
+    UNW_FLAG_CHAININFO = 0x4,
+}
+
+#[derive(Debug)]
+#[allow(non_camel_case_types)]
+pub enum UnwindOperation {
+    UWOP_PUSH_NONVOL { register: &'static str },
+    UWOP_ALLOC_LARGE { size: u32 },
+    UWOP_ALLOC_SMALL { size: u32 },
+    UWOP_SET_FPREG,
+    UWOP_SAVE_NONVOL { register: &'static str, offset: u32 },
+    UWOP_SAVE_NONVOL_FAR { register: &'static str, offset: u32 },
+    // Version 2 only, describes the location of an epilog
+    UWOP_EPILOG { size_or_offset: u16, flags: u8 },
+    UWOP_SPARE_CODE,
+    // Version 1 encodings of opcodes 6 and 7
+    UWOP_SAVE_XMM { register: u8, offset: u32 },
+    UWOP_SAVE_XMM_FAR { register: u8, offset: u32 },
+    UWOP_SAVE_XMM128 { register: u8, offset: u32 },
+    UWOP_SAVE_XMM128_FAR { register: u8, offset: u32 },
+    UWOP_PUSH_MACHFRAME { error_code: bool },
+    UNKNOWN(u8),
+}
+
+pub struct UnwindCode {
+    pub code_offset: u8,
+    pub operation: UnwindOperation,
+}
+
+pub struct UnwindInfo {
+    pub version: u8,
+    pub flags: u8,
+    pub flags_list: Vec<UnwindFlag>,
+    pub size_of_prolog: u8,
+    pub count_of_codes: u8,
+    pub frame_register: Option<&'static str>,
+    // Already scaled by 16
+    pub frame_offset: u32,
+    pub codes: Vec<UnwindCode>,
+    pub exception_handler: Option<u32>,
+    // Start of the language specific data following the handler RVA
+    pub handler_data_rva: Option<u32>,
+    pub chained: Option<Box<RuntimeFunction>>,
+}
+
+pub struct RuntimeFunction {
+    pub begin_address: u32,
+    pub end_address: u32,
+    pub unwind_info_address: u32,
+    pub unwind_info: Option<UnwindInfo>,
+    pub anomalies: Vec<String>,
+}
+
+// Walks the RUNTIME_FUNCTION array of the exception directory lazily
+pub struct RuntimeFunctions<'a> {
+    file: &'a [u8],
+    headers: &'a Headers,
+    offset: usize,
+    index: usize,
+    count: usize,
+    previous_begin: Option<u32>,
+}
+
+
+// Conversion implementation for the structs
+
+impl UnwindFlag {
+    pub fn from_u8(val: u8) -> Vec<UnwindFlag> {
+        let mut flags = Vec::new();
+        for flag in [UnwindFlag::UNW_FLAG_EHANDLER, UnwindFlag::UNW_FLAG_UHANDLER, UnwindFlag::UNW_FLAG_CHAININFO] {
+            if val & flag as u8 != 0 {
+                flags.push(flag);
+            }
+        }
+        flags
+    }
+}
+
+
+// Parsing
+
+fn parse_codes(file: &[u8], offset: usize, count: usize, version: u8, anomalies: &mut Vec<String>) -> Vec<UnwindCode> {
+    let mut codes = Vec::new();
+    let slot = |x: usize| read_u16_at(file, offset + x * 2);
+    let mut x = 0;
+    while x < count {
+        let value = match slot(x) {
+            Some(value) => value,
+            None => break,
+        };
+        let code_offset = (value & 0xff) as u8;
+        let op = ((value >> 8) & 0xf) as u8;
+        let info = (value >> 12) as u8;
+        let register = REGISTERS[info as usize];
+        // Large operations store their operand in the following slots
+        let next = slot(x + 1).unwrap_or(0) as u32;
+        let next_u32 = next | (slot(x + 2).unwrap_or(0) as u32) << 16;
+        let (operation, slots) = match op {
+            0 => (UnwindOperation::UWOP_PUSH_NONVOL { register }, 1),
+            1 if info == 0 => (UnwindOperation::UWOP_ALLOC_LARGE { size: next * 8 }, 2),
+            1 => (UnwindOperation::UWOP_ALLOC_LARGE { size: next_u32 }, 3),
+            2 => (UnwindOperation::UWOP_ALLOC_SMALL { size: info as u32 * 8 + 8 }, 1),
+            3 => (UnwindOperation::UWOP_SET_FPREG, 1),
+            4 => (UnwindOperation::UWOP_SAVE_NONVOL { register, offset: next * 8 }, 2),
+            5 => (UnwindOperation::UWOP_SAVE_NONVOL_FAR { register, offset: next_u32 }, 3),
+            6 if version >= 2 => (UnwindOperation::UWOP_EPILOG { size_or_offset: code_offset as u16 | (info as u16) << 8, flags: info }, 1),
+            6 => (UnwindOperation::UWOP_SAVE_XMM { register: info, offset: next * 8 }, 2),
+            7 if version >= 2 => (UnwindOperation::UWOP_SPARE_CODE, 1),
+            7 => (UnwindOperation::UWOP_SAVE_XMM_FAR { register: info, offset: next_u32 }, 3),
+            8 => (UnwindOperation::UWOP_SAVE_XMM128 { register: info, offset: next * 16 }, 2),
+            9 => (UnwindOperation::UWOP_SAVE_XMM128_FAR { register: info, offset: next_u32 }, 3),
+            10 => (UnwindOperation::UWOP_PUSH_MACHFRAME { error_code: info != 0 }, 1),
+            _ => (UnwindOperation::UNKNOWN(op), 1),
+        };
+        if x + slots > count {
+            anomalies.push(format!("Unwind code {:?} needs more slots than CountOfCodes provides", operation));
+        }
+        codes.push(UnwindCode { code_offset, operation });
+        x += slots;
+    }
+    codes
+}
+
+fn parse_unwind_info(file: &[u8], headers: &Headers, rva: u32, depth: usize, anomalies: &mut Vec<String>) -> Option<UnwindInfo> {
+    let offset = match rva_to_offset(headers, rva) {
+        Some(offset) if offset + 4 <= file.len() => offset,
+        _ => {
+            anomalies.push(format!("UNWIND_INFO at RVA 0x{:x} is not backed by the file", rva));
+            return None;
+        }
+    };
+    let version = file[offset] & 0x7;
+    let flags = file[offset] >> 3;
+    if version != 1 && version != 2 {
+        anomalies.push(format!("UNWIND_INFO at RVA 0x{:x} has the unknown version {}", rva, version));
+    }
+    let count_of_codes = file[offset + 2];
+    let frame_register = file[offset + 3] & 0xf;
+    let mut unwind_info = UnwindInfo {
+        version,
+        flags,
+        flags_list: UnwindFlag::from_u8(flags),
+        size_of_prolog: file[offset + 1],
+        count_of_codes,
+        frame_register: if frame_register != 0 { Some(REGISTERS[frame_register as usize]) } else { None },
+        frame_offset: (file[offset + 3] >> 4) as u32 * 16,
+        codes: parse_codes(file, offset + 4, count_of_codes as usize, version, anomalies),
+        exception_handler: None,
+        handler_data_rva: None,
+        chained: None,
+    };
+    // The code array is padded to an even number of slots
+    let trailer_rva = rva + 4 + (count_of_codes as u32).next_multiple_of(2) * 2;
+    let trailer = rva_to_offset(headers, trailer_rva);
+    if flags & UnwindFlag::UNW_FLAG_CHAININFO as u8 != 0 {
+        if depth >= MAX_CHAIN_DEPTH {
+            anomalies.push(format!("Unwind chain starting at RVA 0x{:x} is too deep, stopped walking", rva));
+        } else if let Some(chained) = trailer.and_then(|trailer| parse_runtime_function(file, headers, trailer, depth + 1)) {
+            unwind_info.chained = Some(Box::new(chained));
+        } else {
+            anomalies.push(format!("Chained RUNTIME_FUNCTION of UNWIND_INFO 0x{:x} is not backed by the file", rva));
+        }
+    } else if flags & (UnwindFlag::UNW_FLAG_EHANDLER as u8 | UnwindFlag::UNW_FLAG_UHANDLER as u8) != 0 {
+        unwind_info.exception_handler = trailer.and_then(|trailer| read_u32_at(file, trailer));
+        unwind_info.handler_data_rva = Some(trailer_rva + 4);
+        match unwind_info.exception_handler {
+            Some(handler) if !is_executable_rva(headers, handler) => {
+                anomalies.push(format!("Exception handler 0x{:x} is not in an executable section", handler));
+            }
+            None => anomalies.push(format!("Exception handler of UNWIND_INFO 0x{:x} is not backed by the file", rva)),
+            _ => {}
+        }
+    }
+    Some(unwind_info)
+}
+
+fn parse_runtime_function(file: &[u8], headers: &Headers, offset: usize, depth: usize) -> Option<RuntimeFunction> {
+    let mut function = RuntimeFunction {
+        begin_address: read_u32_at(file, offset)?,
+        end_address: read_u32_at(file, offset + 4)?,
+        unwind_info_address: read_u32_at(file, offset + 8)?,
+        unwind_info: None,
+        anomalies: Vec::new(),
+    };
+    if function.end_address <= function.begin_address {
+        function.anomalies.push(format!("Function 0x{:x} ends at 0x{:x}, before it starts", function.begin_address, function.end_address));
+    }
+    if !is_executable_rva(headers, function.begin_address) {
+        function.anomalies.push(format!("Function 0x{:x} is not in an executable section", function.begin_address));
+    }
+    // An odd unwind address points at the RUNTIME_FUNCTION this one is chained to
+    if function.unwind_info_address & 1 != 0 {
+        if depth >= MAX_CHAIN_DEPTH {
+            function.anomalies.push(format!("Unwind chain of function 0x{:x} is too deep, stopped walking", function.begin_address));
+            return Some(function);
+        }
+        let parent = rva_to_offset(headers, function.unwind_info_address & !1)
+            .and_then(|parent| parse_runtime_function(file, headers, parent, depth + 1));
+        if let Some(parent) = parent {
+            function.unwind_info = parent.unwind_info;
+            function.anomalies.extend(parent.anomalies);
+        }
+        return Some(function);
+    }
+    function.unwind_info = parse_unwind_info(file, headers, function.unwind_info_address, depth, &mut function.anomalies);
+    Some(function)
+}
+
+impl<'a> Iterator for RuntimeFunctions<'a> {
+    type Item = RuntimeFunction;
+
+    fn next(&mut self) -> Option<RuntimeFunction> {
+        if self.index >= self.count {
+            return None;
+        }
+        let mut function = parse_runtime_function(self.file, self.headers, self.offset + self.index * 12, 0)?;
+        self.index += 1;
+        // RtlLookupFunctionEntry binary searches the array
+        if self.previous_begin.is_some_and(|previous| previous >= function.begin_address) {
+            function.anomalies.push(format!("Function 0x{:x} breaks the ordering of the exception directory", function.begin_address));
+        }
+        self.previous_begin = Some(function.begin_address);
+        Some(function)
+    }
+}
+
+pub fn runtime_functions<'a>(file: &'a [u8], headers: &'a Headers) -> RuntimeFunctions<'a> {
+    let mut functions = RuntimeFunctions { file, headers, offset: 0, index: 0, count: 0, previous_begin: None };
+    let directory = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION];
+    if directory.virtual_address == 0 || headers.coff_headers.target_machine != IMAGE_FILE_MACHINE_AMD64 {
+        return functions;
+    }
+    if let Some(offset) = rva_to_offset(headers, directory.virtual_address) {
+        functions.offset = offset;
+        // Entries not entirely backed by the file are dropped
+        functions.count = (directory.size as usize / 12).min(file.len().saturating_sub(offset) / 12);
+    }
+    functions
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for RuntimeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08x}-0x{:08x} unwind 0x{:x}", self.begin_address, self.end_address, self.unwind_info_address)?;
+        if let Some(unwind_info) = &self.unwind_info {
+            write!(f, "{}", unwind_info)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for UnwindInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, " version: {} prolog: 0x{:x}", self.version, self.size_of_prolog)?;
+        if let Some(register) = self.frame_register {
+            write!(f, " frame: {}+0x{:x}", register, self.frame_offset)?;
+        }
+        if !self.flags_list.is_empty() {
+            write!(f, " {:?}", self.flags_list)?;
+        }
+        if let Some(handler) = self.exception_handler {
+            write!(f, " handler: 0x{:x}", handler)?;
+        }
+        for code in &self.codes {
+            write!(f, "\n  0x{:02x} {:?}", code.code_offset, code.operation)?;
+        }
+        if let Some(chained) = &self.chained {
+            write!(f, "\n  chained to {}", chained)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::fixtures::flat_headers;
+    use crate::utils::fixtures::put;
+
+    fn image(functions: &[[u32; 3]]) -> (Vec<u8>, Headers) {
+        let mut file = vec![0; 0x1000];
+        let mut headers = flat_headers(false, file.len());
+        headers.coff_headers.target_machine = IMAGE_FILE_MACHINE_AMD64;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION].virtual_address = 0x800;
+        headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_EXCEPTION].size = functions.len() as u32 * 12;
+        for (x, function) in functions.iter().enumerate() {
+            put_function(&mut file, 0x800 + x * 12, *function);
+        }
+        (file, headers)
+    }
+
+    fn put_function(file: &mut Vec<u8>, offset: usize, function: [u32; 3]) {
+        for (x, value) in function.iter().enumerate() {
+            put(file, offset + x * 4, &value.to_le_bytes());
+        }
+    }
+
+    fn put_unwind_info(file: &mut Vec<u8>, offset: usize, header: [u8; 4], codes: &[u16]) {
+        put(file, offset, &header);
+        for (x, code) in codes.iter().enumerate() {
+            put(file, offset + 4 + x * 2, &code.to_le_bytes());
+        }
+    }
+
+    fn operations(function: &RuntimeFunction) -> Vec<String> {
+        function.unwind_info.as_ref().unwrap().codes.iter().map(|code| format!("0x{:02x} {:?}", code.code_offset, code.operation)).collect()
+    }
+
+    #[test]
+    fn parses_unwind_info() {
+        let (mut file, headers) = image(&[[0x500, 0x540, 0x900], [0x540, 0x560, 0x941], [0x580, 0x5a0, 0x960]]);
+        put_unwind_info(&mut file, 0x900, [0x09, 0x1a, 10, 0x25], &[0x5001, 0x3205, 0x010c, 0x20, 0x030f, 0x3410, 4, 0x6814, 2, 0x1a16]);
+        put(&mut file, 0x918, &0x600u32.to_le_bytes());
+        put_function(&mut file, 0x940, [0x500, 0x540, 0x950]);
+        put_unwind_info(&mut file, 0x950, [0x01, 0x01, 1, 0], &[0x5001]);
+        put_unwind_info(&mut file, 0x960, [0x21, 0x04, 1, 0], &[0x3204]);
+        put_function(&mut file, 0x968, [0x500, 0x540, 0x950]);
+        let functions: Vec<RuntimeFunction> = runtime_functions(&file, &headers).collect();
+        assert_eq!(functions.len(), 3);
+        assert!(functions.iter().all(|function| function.anomalies.is_empty()));
+
+        let unwind_info = functions[0].unwind_info.as_ref().unwrap();
+        assert_eq!((unwind_info.version, unwind_info.size_of_prolog, unwind_info.count_of_codes), (1, 0x1a, 10));
+        assert_eq!((unwind_info.frame_register, unwind_info.frame_offset), (Some("RBP"), 0x20));
+        assert_eq!(format!("{:?}", unwind_info.flags_list), "[UNW_FLAG_EHANDLER]");
+        assert_eq!((unwind_info.exception_handler, unwind_info.handler_data_rva), (Some(0x600), Some(0x91c)));
+        assert_eq!(operations(&functions[0]), vec![
+            "0x01 UWOP_PUSH_NONVOL { register: \"RBP\" }",
+            "0x05 UWOP_ALLOC_SMALL { size: 32 }",
+            "0x0c UWOP_ALLOC_LARGE { size: 256 }",
+            "0x0f UWOP_SET_FPREG",
+            "0x10 UWOP_SAVE_NONVOL { register: \"RBX\", offset: 32 }",
+            "0x14 UWOP_SAVE_XMM128 { register: 6, offset: 32 }",
+            "0x16 UWOP_PUSH_MACHFRAME { error_code: true }",
+        ]);
+
+        // Chained through an odd unwind address, then through UNW_FLAG_CHAININFO
+        assert_eq!(operations(&functions[1]), vec!["0x01 UWOP_PUSH_NONVOL { register: \"RBP\" }"]);
+        let chained = functions[2].unwind_info.as_ref().unwrap().chained.as_ref().unwrap();
+        assert_eq!((chained.begin_address, chained.unwind_info_address), (0x500, 0x950));
+        assert_eq!(operations(chained), vec!["0x01 UWOP_PUSH_NONVOL { register: \"RBP\" }"]);
+
+        let (file, mut headers) = image(&[[0x500, 0x540, 0x900]]);
+        headers.coff_headers.target_machine = 0x14c;
+        assert_eq!(runtime_functions(&file, &headers).count(), 0);
+    }
+
+    #[test]
+    fn parses_version_2_epilogs() {
+        let (mut file, headers) = image(&[[0x500, 0x540, 0x900]]);
+        put_unwind_info(&mut file, 0x900, [0x02, 0x04, 4, 0], &[0x1602, 0x0600, 0x0700, 0x5004]);
+        let function = runtime_functions(&file, &headers).next().unwrap();
+        assert_eq!(operations(&function), vec![
+            "0x02 UWOP_EPILOG { size_or_offset: 258, flags: 1 }",
+            "0x00 UWOP_EPILOG { size_or_offset: 0, flags: 0 }",
+            "0x00 UWOP_SPARE_CODE",
+            "0x04 UWOP_PUSH_NONVOL { register: \"RBP\" }",
+        ]);
+    }
+
+    #[test]
+    fn malformed_functions() {
+        let (mut file, headers) = image(&[
+            [0x540, 0x500, 0x900],
+            [0x520, 0x530, 0x3000],
+            [0x580, 0x5a0, 0x981],
+            [0x5a0, 0x5c0, 0x9a0],
+            [0x1100, 0x1110, 0x920],
+        ]);
+        put_unwind_info(&mut file, 0x900, [0x09, 0, 1, 0], &[0x010c]);
+        put(&mut file, 0x908, &0x2000u32.to_le_bytes());
+        put_unwind_info(&mut file, 0x920, [0x03, 0, 0, 0], &[]);
+        put_function(&mut file, 0x980, [0x580, 0x5a0, 0x981]);
+        put_unwind_info(&mut file, 0x9a0, [0x21, 0, 0, 0], &[]);
+        put_function(&mut file, 0x9a4, [0x5a0, 0x5c0, 0x9a0]);
+        let functions: Vec<RuntimeFunction> = runtime_functions(&file, &headers).collect();
+        assert_eq!(functions.len(), 5);
+        assert_eq!((functions[0].begin_address, functions[0].end_address), (0x540, 0x500));
+        let unwind_info = functions[0].unwind_info.as_ref().unwrap();
+        assert_eq!((unwind_info.version, unwind_info.flags, unwind_info.count_of_codes), (1, 1, 1));
+        assert_eq!(unwind_info.codes.len(), 1);
+        assert!(matches!(unwind_info.codes[0].operation, UnwindOperation::UWOP_ALLOC_LARGE { size: 0 }));
+        assert_eq!(unwind_info.exception_handler, Some(0x2000));
+        assert_eq!(unwind_info.handler_data_rva, Some(0x90c));
+        assert_eq!(functions[0].anomalies, vec![
+            String::from("Function 0x540 ends at 0x500, before it starts"),
+            String::from("Unwind code UWOP_ALLOC_LARGE { size: 0 } needs more slots than CountOfCodes provides"),
+            String::from("Exception handler 0x2000 is not in an executable section"),
+        ]);
+        assert_eq!(functions[1].unwind_info_address, 0x3000);
+        assert!(functions[1].unwind_info.is_none());
+        assert_eq!(functions[1].anomalies, vec![
+            String::from("UNWIND_INFO at RVA 0x3000 is not backed by the file"),
+            String::from("Function 0x520 breaks the ordering of the exception directory"),
+        ]);
+        // Both kinds of chains looping onto themselves stop at MAX_CHAIN_DEPTH
+        assert_eq!(functions[2].anomalies, vec![String::from("Unwind chain of function 0x580 is too deep, stopped walking")]);
+        let mut depth = 0;
+        let mut unwind_info = functions[3].unwind_info.as_ref().unwrap();
+        while let Some(chained) = &unwind_info.chained {
+            depth += 1;
+            unwind_info = chained.unwind_info.as_ref().unwrap();
+            if unwind_info.chained.is_none() {
+                assert_eq!(chained.anomalies, vec![String::from("Unwind chain starting at RVA 0x9a0 is too deep, stopped walking")]);
+            }
+        }
+        assert_eq!(depth, MAX_CHAIN_DEPTH);
+        let unwind_info = functions[4].unwind_info.as_ref().unwrap();
+        assert_eq!(unwind_info.version, 3);
+        assert!(unwind_info.codes.is_empty());
+        assert!(unwind_info.chained.is_none());
+        assert_eq!(functions[4].anomalies, vec![
+            String::from("Function 0x1100 is not in an executable section"),
+            String::from("UNWIND_INFO at RVA 0x920 has the unknown version 3"),
+        ]);
+    }
+}
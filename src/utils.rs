@@ -1,6 +1,9 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
+use crate::structs::Headers;
+use crate::structs::SectionHeader;
+
 pub fn consume_u64_from_buffer(buffer: &[u8], index: &mut usize) -> u64 {
     let mut arr: [u8; 8] = [0; 8];
     arr.copy_from_slice(&buffer[*index..*index+8]);
@@ -23,4 +26,156 @@ pub fn consume_u16_from_buffer(buffer: &[u8], index: &mut usize) -> u16 {
     let ret = u16::from_le_bytes(arr);
     *index += 2;
     ret
-}
\ No newline at end of file
+}
+pub fn read_u16_at(buffer: &[u8], offset: usize) -> Option<u16> {
+    let bytes = buffer.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32_at(buffer: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buffer.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+pub fn read_u64_at(buffer: &[u8], offset: usize) -> Option<u64> {
+    let bytes = buffer.get(offset..offset.checked_add(8)?)?;
+    let mut arr: [u8; 8] = [0; 8];
+    arr.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(arr))
+}
+
+// Returns the section whose virtual range contains the given RVA
+pub fn section_for_rva(headers: &Headers, rva: u32) -> Option<&SectionHeader> {
+    headers.sections.iter().find(|section| {
+        let size = section.virtual_size.max(section.size_of_raw_data);
+        rva >= section.virtual_address && (rva - section.virtual_address) < size
+    })
+}
+
+// Translates an RVA into an offset in the file, RVAs falling into the headers map onto themselves
+pub fn rva_to_offset(headers: &Headers, rva: u32) -> Option<usize> {
+    if rva < headers.optional_headers.windows_specific.size_of_headers {
+        return Some(rva as usize);
+    }
+    let section = section_for_rva(headers, rva)?;
+    let delta = rva - section.virtual_address;
+    if delta >= section.size_of_raw_data {
+        return None;
+    }
+    section.pointer_to_raw_data.checked_add(delta).map(|offset| offset as usize)
+}
+
+// Returns the bytes backing [rva, rva + size) or None if they are not entirely present in the file
+pub fn slice_at_rva<'a>(file: &'a [u8], headers: &Headers, rva: u32, size: usize) -> Option<&'a [u8]> {
+    let offset = rva_to_offset(headers, rva)?;
+    file.get(offset..offset.checked_add(size)?)
+}
+
+// Decodes `length` UTF-16LE code units starting at offset
+pub fn read_utf16_at(buffer: &[u8], offset: usize, length: usize) -> Option<String> {
+    let bytes = buffer.get(offset..offset.checked_add(length.checked_mul(2)?)?)?;
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+    Some(String::from_utf16_lossy(&units))
+}
+
+pub fn is_pe32(headers: &Headers) -> bool {
+    headers.optional_headers.standard_fields.magic == 0x10b
+}
+
+// Converts a virtual address into an RVA, None if it lies below the image base
+pub fn va_to_rva(headers: &Headers, va: u64) -> Option<u32> {
+    let rva = va.checked_sub(headers.optional_headers.windows_specific.image_base)?;
+    u32::try_from(rva).ok()
+}
+
+// Reads a pointer sized value, 4 bytes for PE32 and 8 bytes for PE32+
+pub fn read_pointer_at(buffer: &[u8], offset: usize, pe_32: bool) -> Option<u64> {
+    if pe_32 {
+        read_u32_at(buffer, offset).map(|value| value as u64)
+    } else {
+        read_u64_at(buffer, offset)
+    }
+}
+
+// True when the RVA falls into a section mapped with IMAGE_SCN_MEM_EXECUTE or flagged as code
+pub fn is_executable_rva(headers: &Headers, rva: u32) -> bool {
+    match section_for_rva(headers, rva) {
+        Some(section) => section.characteristics & 0x20000020 != 0,
+        None => false,
+    }
+}
+
+// Synthesized images for the decoder tests
+#[cfg(test)]
+pub mod fixtures {
+    use crate::structs::Headers;
+    use crate::structs::SectionHeader;
+
+    pub const SIZE_OF_HEADERS: u32 = 0x400;
+
+    // Headers followed by a single executable section at the same RVA and file offset, so that RVAs are file offsets
+    pub fn flat_headers(pe_32: bool, size: usize) -> Headers {
+        let mut headers = Headers::default();
+        headers.optional_headers.standard_fields.magic = if pe_32 { 0x10b } else { 0x20b };
+        headers.optional_headers.windows_specific.image_base = if pe_32 { 0x400000 } else { 0x140000000 };
+        headers.optional_headers.windows_specific.size_of_headers = SIZE_OF_HEADERS;
+        headers.optional_headers.windows_specific.size_of_image = size as u32;
+        let size = size as u32 - SIZE_OF_HEADERS;
+        headers.sections.push(SectionHeader {
+            name: String::from(".text"),
+            virtual_size: size,
+            virtual_address: SIZE_OF_HEADERS,
+            size_of_raw_data: size,
+            pointer_to_raw_data: SIZE_OF_HEADERS,
+            characteristics: 0x60000020,
+            ..Default::default()
+        });
+        headers
+    }
+
+    // File with the headers of flat_headers and a zero filled section, for code that parses the headers itself
+    pub fn flat_image(pe_32: bool, size: usize) -> Vec<u8> {
+        let headers = flat_headers(pe_32, size);
+        let section = &headers.sections[0];
+        let mut file = vec![0; size];
+        let pe = 0x80;
+        let optional = pe + 24;
+        let size_of_optional_header: u16 = if pe_32 { 224 } else { 240 };
+        put(&mut file, 0, b"MZ");
+        put(&mut file, 0x3c, &(pe as u32).to_le_bytes());
+        put(&mut file, pe, b"PE\0\0");
+        put(&mut file, pe + 4, &(if pe_32 { 0x14cu16 } else { 0x8664 }).to_le_bytes());
+        put(&mut file, pe + 6, &1u16.to_le_bytes());
+        put(&mut file, pe + 20, &size_of_optional_header.to_le_bytes());
+        put(&mut file, optional, &headers.optional_headers.standard_fields.magic.to_le_bytes());
+        match pe_32 {
+            true => put(&mut file, optional + 28, &(headers.optional_headers.windows_specific.image_base as u32).to_le_bytes()),
+            false => put(&mut file, optional + 24, &headers.optional_headers.windows_specific.image_base.to_le_bytes()),
+        }
+        put(&mut file, optional + 32, &0x1000u32.to_le_bytes());
+        put(&mut file, optional + 36, &0x200u32.to_le_bytes());
+        put(&mut file, optional + 56, &headers.optional_headers.windows_specific.size_of_image.to_le_bytes());
+        put(&mut file, optional + 60, &SIZE_OF_HEADERS.to_le_bytes());
+        put(&mut file, optional + if pe_32 { 92 } else { 108 }, &16u32.to_le_bytes());
+        let table = optional + size_of_optional_header as usize;
+        put(&mut file, table, section.name.as_bytes());
+        put(&mut file, table + 8, &section.virtual_size.to_le_bytes());
+        put(&mut file, table + 12, &section.virtual_address.to_le_bytes());
+        put(&mut file, table + 16, &section.size_of_raw_data.to_le_bytes());
+        put(&mut file, table + 20, &section.pointer_to_raw_data.to_le_bytes());
+        put(&mut file, table + 36, &section.characteristics.to_le_bytes());
+        file
+    }
+
+    // Writes a little endian value into the image, growing it as needed
+    pub fn put(file: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+        if file.len() < offset + bytes.len() {
+            file.resize(offset + bytes.len(), 0);
+        }
+        file[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    pub fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect()
+    }
+}
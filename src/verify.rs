@@ -0,0 +1,864 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::authenticode::check_digest;
+use crate::authenticode::check_page_hashes;
+use crate::certificates::parse_certificate_table;
+use crate::der;
+use crate::der::format_time;
+use crate::pkcs7::Certificate;
+use crate::pkcs7::Crl;
+use crate::pkcs7::SignedData;
+use crate::pkcs7::SignerInfo;
+use crate::pkcs7::find_certificate;
+use crate::pkcs7::parse_certificate;
+use crate::pkcs7::parse_crl;
+use crate::pkcs7::OID_ANY_EXTENDED_KEY_USAGE;
+use crate::pkcs7::OID_BASIC_CONSTRAINTS;
+use crate::pkcs7::OID_CODE_SIGNING;
+use crate::pkcs7::OID_EXTENDED_KEY_USAGE;
+use crate::pkcs7::OID_KEY_USAGE;
+use crate::pkcs7::OID_RSA_ENCRYPTION;
+use crate::pkcs7::OID_TIME_STAMPING;
+use crate::pkcs7::KEY_USAGE_CRL_SIGN;
+use crate::pkcs7::KEY_USAGE_DIGITAL_SIGNATURE;
+use crate::pkcs7::KEY_USAGE_KEY_CERT_SIGN;
+use crate::rsa::parse_public_key;
+use crate::rsa::verify_pkcs1;
+use crate::sha::HashAlgorithm;
+use crate::sha::digest;
+
+const MAX_CHAIN_LENGTH: usize = 8;
+// Critical extensions the chain validation understands
+const KNOWN_CRITICAL_EXTENSIONS: [&str; 6] = [OID_BASIC_CONSTRAINTS, OID_KEY_USAGE, OID_EXTENDED_KEY_USAGE, "2.5.29.17", "2.5.29.32", "2.5.29.14"];
+
+// Structures definitions
+
+// Trusted roots, intermediates and CRLs read from a directory, DER or PEM encoded
+#[derive(Default)]
+pub struct TrustStore {
+    pub certificates: Vec<Certificate>,
+    pub crls: Vec<Crl>,
+    pub anomalies: Vec<String>,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq, Eq)]
+pub enum Reason {
+    NoSignature,
+    MalformedSignature(String),
+    UnsupportedAlgorithm(String),
+    ImageDigestMismatch,
+    // File offset and section of a page whose hash does not match
+    PageTampered(u32, Option<String>),
+    ContentTypeMismatch,
+    ContentDigestMismatch,
+    SignerCertificateMissing,
+    SignatureInvalid(String),
+    ChainIncomplete(String),
+    UntrustedRoot(String),
+    CertificateSignatureInvalid(String),
+    NotCertificateAuthority(String),
+    PathLengthExceeded(String),
+    KeyUsageInvalid(String),
+    MissingExtendedKeyUsage(String, String),
+    UnsupportedCriticalExtension(String, String),
+    CertificateNotYetValid(String),
+    CertificateExpired(String),
+    CertificateRevoked(String, i64),
+    CrlSignatureInvalid(String),
+    TimestampImprintMismatch,
+    // Index of a nested signature that failed, its own verdict has the details
+    NestedSignatureInvalid(usize),
+    // A problem with the countersignature or RFC 3161 token, the signature is then checked at the current time
+    Timestamp(Box<Reason>),
+}
+
+pub struct Verdict {
+    pub valid: bool,
+    pub digest_algorithm: Option<HashAlgorithm>,
+    pub signer: Option<String>,
+    // Subjects from the signer up to the trusted root
+    pub chain: Vec<String>,
+    pub timestamp: Option<i64>,
+    pub timestamp_authority: Option<String>,
+    // Time certificates were checked at, the timestamp when it is valid
+    pub verification_time: i64,
+    pub reasons: Vec<Reason>,
+    // Verdicts of the nested signatures, valid is false when one of them is
+    pub nested: Vec<Verdict>,
+}
+
+
+// Conversion implementation for the structs
+
+impl TrustStore {
+    pub fn load(directory: &Path) -> TrustStore {
+        let mut store = TrustStore::default();
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) => {
+                store.anomalies.push(format!("Trust store {} cannot be read: {}", directory.display(), error));
+                return store;
+            }
+        };
+        let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_file()).collect();
+        paths.sort();
+        for path in paths {
+            let data = match fs::read(&path) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            store.add(&data, &path.display().to_string());
+        }
+        store
+    }
+
+    // PEM files may hold several blocks, anything else is tried as a DER certificate then as a DER CRL
+    pub fn add(&mut self, data: &[u8], origin: &str) {
+        let certificates = der::pem_blocks(data, "CERTIFICATE");
+        let crls = der::pem_blocks(data, "X509 CRL");
+        if certificates.is_empty() && crls.is_empty() {
+            if let Some(certificate) = parse_certificate(data) {
+                self.certificates.push(certificate);
+            } else if let Some(crl) = parse_crl(data) {
+                self.crls.push(crl);
+            } else {
+                self.anomalies.push(format!("{} is neither a certificate nor a CRL", origin));
+            }
+            return;
+        }
+        for block in certificates {
+            match parse_certificate(&block) {
+                Some(certificate) => self.certificates.push(certificate),
+                None => self.anomalies.push(format!("{} holds a malformed certificate", origin)),
+            }
+        }
+        for block in crls {
+            match parse_crl(&block) {
+                Some(crl) => self.crls.push(crl),
+                None => self.anomalies.push(format!("{} holds a malformed CRL", origin)),
+            }
+        }
+    }
+
+    // Only self-signed certificates of the store anchor a chain, the others help building it
+    fn is_anchor(&self, certificate: &Certificate) -> bool {
+        certificate.is_self_issued() && self.certificates.iter().any(|anchor| anchor.raw == certificate.raw)
+    }
+}
+
+fn subject(certificate: &Certificate) -> String {
+    certificate.subject.to_string()
+}
+
+
+// Parsing
+
+// None when the key or signature algorithm is not supported, only RSA PKCS#1 v1.5 is
+fn verify_signature(certificate: &Certificate, algorithm: HashAlgorithm, data: &[u8], signature: &[u8]) -> Option<bool> {
+    if certificate.public_key_algorithm.oid != OID_RSA_ENCRYPTION {
+        return None;
+    }
+    let key = parse_public_key(&certificate.public_key)?;
+    Some(verify_pkcs1(&key, algorithm, &digest(algorithm, data), signature))
+}
+
+fn verify_issued_by(issuer: &Certificate, tbs: &[u8], algorithm: &crate::pkcs7::AlgorithmIdentifier, signature: &[u8]) -> Result<bool, Reason> {
+    let hash = algorithm.hash_algorithm().ok_or_else(|| Reason::UnsupportedAlgorithm(algorithm.name().to_string()))?;
+    verify_signature(issuer, hash, tbs, signature).ok_or_else(|| Reason::UnsupportedAlgorithm(issuer.public_key_algorithm.name().to_string()))
+}
+
+// The signed attributes are hashed as a SET, not with their [0] IMPLICIT tag
+fn verify_signer<'a>(signer: &SignerInfo, content: &[u8], content_type: Option<&str>, certificates: &[&'a Certificate]) -> Result<&'a Certificate, Reason> {
+    let algorithm = signer.digest_algorithm.hash_algorithm().ok_or_else(|| Reason::UnsupportedAlgorithm(signer.digest_algorithm.name().to_string()))?;
+    let certificate = find_certificate(certificates.iter().copied(), signer).ok_or(Reason::SignerCertificateMissing)?;
+    let signed = match &signer.authenticated_attributes_raw {
+        Some(attributes) => {
+            if content_type.is_some() && signer.content_type.as_deref() != content_type {
+                return Err(Reason::ContentTypeMismatch);
+            }
+            if signer.message_digest.as_deref() != Some(digest(algorithm, content).as_slice()) {
+                return Err(Reason::ContentDigestMismatch);
+            }
+            let mut attributes = attributes.clone();
+            attributes[0] = der::TAG_SET;
+            attributes
+        }
+        None => content.to_vec(),
+    };
+    match verify_signature(certificate, algorithm, &signed, &signer.signature) {
+        Some(true) => Ok(certificate),
+        Some(false) => Err(Reason::SignatureInvalid(subject(certificate))),
+        None => Err(Reason::UnsupportedAlgorithm(certificate.public_key_algorithm.name().to_string())),
+    }
+}
+
+// Depth first search of a path to a trust anchor, issuers must have verified the certificate signature
+fn build_chain<'a>(certificate: &'a Certificate, pool: &[&'a Certificate], store: &TrustStore, depth: usize) -> Result<Vec<&'a Certificate>, Reason> {
+    if store.is_anchor(certificate) {
+        return Ok(vec![certificate]);
+    }
+    if depth >= MAX_CHAIN_LENGTH {
+        return Err(Reason::ChainIncomplete(subject(certificate)));
+    }
+    let mut error = None;
+    for issuer in pool.iter().filter(|issuer| issuer.subject.raw == certificate.issuer.raw && issuer.raw != certificate.raw) {
+        match verify_issued_by(issuer, &certificate.tbs, &certificate.signature_algorithm, &certificate.signature) {
+            Ok(true) => match build_chain(issuer, pool, store, depth + 1) {
+                Ok(mut chain) => {
+                    chain.insert(0, certificate);
+                    return Ok(chain);
+                }
+                Err(reason) => error = Some(reason),
+            },
+            Ok(false) => error = error.or(Some(Reason::CertificateSignatureInvalid(subject(certificate)))),
+            Err(reason) => error = error.or(Some(reason)),
+        }
+    }
+    if let Some(reason) = error {
+        return Err(reason);
+    }
+    if certificate.is_self_issued() {
+        return Err(Reason::UntrustedRoot(subject(certificate)));
+    }
+    Err(Reason::ChainIncomplete(subject(certificate)))
+}
+
+fn allows_usage(certificate: &Certificate, usage: &str) -> bool {
+    match certificate.extended_key_usage() {
+        Some(usages) => usages.iter().any(|purpose| purpose == usage || purpose == OID_ANY_EXTENDED_KEY_USAGE),
+        None => true,
+    }
+}
+
+// Certificates revoked after the time of the check still validate signatures made before
+fn check_revocation(certificate: &Certificate, issuer: &Certificate, crls: &[&Crl], time: i64, reasons: &mut Vec<Reason>) {
+    for crl in crls.iter().filter(|crl| crl.issuer.raw == certificate.issuer.raw) {
+        if issuer.key_usage().is_some_and(|usage| usage & KEY_USAGE_CRL_SIGN == 0) {
+            reasons.push(Reason::KeyUsageInvalid(subject(issuer)));
+            continue;
+        }
+        if verify_issued_by(issuer, &crl.tbs, &crl.signature_algorithm, &crl.signature) != Ok(true) {
+            reasons.push(Reason::CrlSignatureInvalid(subject(issuer)));
+            continue;
+        }
+        if let Some(revoked) = crl.revoked.iter().find(|revoked| revoked.serial == certificate.serial && revoked.revocation_date <= time) {
+            reasons.push(Reason::CertificateRevoked(subject(certificate), revoked.revocation_date));
+        }
+    }
+}
+
+fn validate_chain(chain: &[&Certificate], usage: &str, crls: &[&Crl], time: i64) -> Vec<Reason> {
+    let mut reasons = Vec::new();
+    for (x, certificate) in chain.iter().enumerate() {
+        let name = subject(certificate);
+        if time < certificate.not_before {
+            reasons.push(Reason::CertificateNotYetValid(name.clone()));
+        }
+        if time > certificate.not_after {
+            reasons.push(Reason::CertificateExpired(name.clone()));
+        }
+        for extension in certificate.extensions.iter().filter(|extension| extension.critical && !KNOWN_CRITICAL_EXTENSIONS.contains(&extension.oid.as_str())) {
+            reasons.push(Reason::UnsupportedCriticalExtension(name.clone(), extension.oid.clone()));
+        }
+        if x == 0 {
+            if certificate.key_usage().is_some_and(|usage| usage & KEY_USAGE_DIGITAL_SIGNATURE == 0) {
+                reasons.push(Reason::KeyUsageInvalid(name.clone()));
+            }
+        } else {
+            match certificate.basic_constraints() {
+                Some((true, path_length)) => {
+                    // Intermediates between this certificate and the signer
+                    if path_length.is_some_and(|length| (x - 1) as u64 > length) {
+                        reasons.push(Reason::PathLengthExceeded(name.clone()));
+                    }
+                }
+                _ => reasons.push(Reason::NotCertificateAuthority(name.clone())),
+            }
+            if certificate.key_usage().is_some_and(|usage| usage & KEY_USAGE_KEY_CERT_SIGN == 0) {
+                reasons.push(Reason::KeyUsageInvalid(name.clone()));
+            }
+        }
+        // The anchor is trusted for every usage
+        if x + 1 < chain.len() {
+            if !allows_usage(certificate, usage) {
+                reasons.push(Reason::MissingExtendedKeyUsage(name.clone(), usage.to_string()));
+            }
+            check_revocation(certificate, chain[x + 1], crls, time, &mut reasons);
+        }
+    }
+    reasons
+}
+
+struct Context<'a> {
+    store: &'a TrustStore,
+    crls: Vec<&'a Crl>,
+    time: i64,
+}
+
+// Signer certificate and chain validated for a usage, the chain subjects are returned
+fn validate_signer_chain(signer: &Certificate, certificates: &[&Certificate], context: &Context, usage: &str, time: i64, reasons: &mut Vec<Reason>) -> Vec<String> {
+    match build_chain(signer, certificates, context.store, 0) {
+        Ok(chain) => {
+            reasons.append(&mut validate_chain(&chain, usage, &context.crls, time));
+            chain.iter().map(|certificate| subject(certificate)).collect()
+        }
+        Err(reason) => {
+            reasons.push(reason);
+            vec![subject(signer)]
+        }
+    }
+}
+
+// RFC 3161 token over the signature value, or a legacy PKCS#9 countersignature
+fn verify_timestamp(signer: &SignerInfo, signed_data: &SignedData, context: &Context) -> Option<Result<(i64, String), Reason>> {
+    if let Some(token) = signer.timestamps.first() {
+        return Some(verify_rfc3161(token, &signer.signature, context));
+    }
+    let countersignature = signer.countersignatures.first()?;
+    let mut certificates: Vec<&Certificate> = signed_data.certificates.iter().collect();
+    certificates.extend(context.store.certificates.iter());
+    let certificate = match verify_signer(countersignature, &signer.signature, None, &certificates) {
+        Ok(certificate) => certificate,
+        Err(reason) => return Some(Err(reason)),
+    };
+    let time = match countersignature.signing_time {
+        Some(time) => time,
+        None => return Some(Err(Reason::MalformedSignature(String::from("countersignature has no signing time")))),
+    };
+    let mut reasons = Vec::new();
+    validate_signer_chain(certificate, &certificates, context, OID_TIME_STAMPING, time, &mut reasons);
+    Some(match reasons.into_iter().next() {
+        Some(reason) => Err(reason),
+        None => Ok((time, subject(certificate))),
+    })
+}
+
+fn verify_rfc3161(token: &SignedData, signature: &[u8], context: &Context) -> Result<(i64, String), Reason> {
+    let info = token.tst_info.as_ref().ok_or_else(|| Reason::MalformedSignature(String::from("timestamp token has no TSTInfo")))?;
+    let algorithm = info.hash_algorithm.hash_algorithm().ok_or_else(|| Reason::UnsupportedAlgorithm(info.hash_algorithm.name().to_string()))?;
+    if digest(algorithm, signature) != info.message_imprint {
+        return Err(Reason::TimestampImprintMismatch);
+    }
+    let signer = token.signers.first().ok_or_else(|| Reason::MalformedSignature(String::from("timestamp token has no signer")))?;
+    let mut certificates: Vec<&Certificate> = token.certificates.iter().collect();
+    certificates.extend(context.store.certificates.iter());
+    let certificate = verify_signer(signer, &token.content, Some(&token.content_type), &certificates)?;
+    let mut reasons = Vec::new();
+    validate_signer_chain(certificate, &certificates, context, OID_TIME_STAMPING, info.gen_time, &mut reasons);
+    match reasons.into_iter().next() {
+        Some(reason) => Err(reason),
+        None => Ok((info.gen_time, subject(certificate))),
+    }
+}
+
+fn verify_signed_data(file: &[u8], headers: &Headers, signed_data: &SignedData, context: &Context) -> Verdict {
+    let mut verdict = Verdict {
+        valid: false,
+        digest_algorithm: None,
+        signer: None,
+        chain: Vec::new(),
+        timestamp: None,
+        timestamp_authority: None,
+        verification_time: context.time,
+        reasons: Vec::new(),
+        nested: Vec::new(),
+    };
+    match check_digest(file, headers, signed_data) {
+        Some(check) => {
+            verdict.digest_algorithm = Some(check.algorithm);
+            if !check.matches() {
+                verdict.reasons.push(Reason::ImageDigestMismatch);
+            }
+        }
+        None => verdict.reasons.push(match &signed_data.indirect_data {
+            Some(indirect_data) => Reason::UnsupportedAlgorithm(indirect_data.digest_algorithm.name().to_string()),
+            None => Reason::MalformedSignature(String::from("no SpcIndirectDataContent")),
+        }),
+    }
+    if let Some(check) = check_page_hashes(file, headers, signed_data) {
+        verdict.reasons.extend(check.tampered().map(|page| Reason::PageTampered(page.offset, page.section.clone())));
+    }
+    let signer = match signed_data.signers.as_slice() {
+        [signer] => signer,
+        _ => {
+            verdict.reasons.push(Reason::MalformedSignature(format!("{} signers instead of one", signed_data.signers.len())));
+            return verdict;
+        }
+    };
+    let mut certificates: Vec<&Certificate> = signed_data.certificates.iter().collect();
+    certificates.extend(context.store.certificates.iter());
+    match verify_signer(signer, &signed_data.content, Some(&signed_data.content_type), &certificates) {
+        Ok(certificate) => {
+            verdict.signer = Some(subject(certificate));
+            match verify_timestamp(signer, signed_data, context) {
+                Some(Ok((time, authority))) => {
+                    verdict.timestamp = Some(time);
+                    verdict.timestamp_authority = Some(authority);
+                    verdict.verification_time = time;
+                }
+                Some(Err(reason)) => verdict.reasons.push(Reason::Timestamp(Box::new(reason))),
+                None => {}
+            }
+            let time = verdict.verification_time;
+            verdict.chain = validate_signer_chain(certificate, &certificates, context, OID_CODE_SIGNING, time, &mut verdict.reasons);
+        }
+        Err(reason) => verdict.reasons.push(reason),
+    }
+    // Every signature of the image has to hold, not only the primary one
+    for (x, nested) in signer.nested_signatures.iter().enumerate() {
+        let nested = verify_signed_data(file, headers, nested, context);
+        if !nested.valid {
+            verdict.reasons.push(Reason::NestedSignatureInvalid(x));
+        }
+        verdict.nested.push(nested);
+    }
+    verdict.valid = verdict.reasons.is_empty();
+    verdict
+}
+
+// Offline verification, time is the current time in seconds since the Unix epoch
+pub fn verify(file: &[u8], headers: &Headers, store: &TrustStore, time: i64) -> Verdict {
+    let table = parse_certificate_table(file, headers);
+    let signed_data = match table.as_ref().and_then(|table| table.signature()) {
+        Some(signed_data) => signed_data,
+        None => return Verdict {
+            valid: false,
+            digest_algorithm: None,
+            signer: None,
+            chain: Vec::new(),
+            timestamp: None,
+            timestamp_authority: None,
+            verification_time: time,
+            reasons: vec![Reason::NoSignature],
+            nested: Vec::new(),
+        },
+    };
+    let embedded: Vec<Crl> = signed_data.crls.iter().filter_map(|crl| parse_crl(crl)).collect();
+    let mut crls: Vec<&Crl> = store.crls.iter().collect();
+    crls.extend(embedded.iter());
+    let context = Context { store, crls, time };
+    verify_signed_data(file, headers, signed_data, &context)
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::NoSignature => write!(f, "The image is not signed"),
+            Reason::MalformedSignature(detail) => write!(f, "Malformed signature: {}", detail),
+            Reason::UnsupportedAlgorithm(algorithm) => write!(f, "Unsupported algorithm {}", algorithm),
+            Reason::ImageDigestMismatch => write!(f, "The image digest does not match the signed digest"),
+            Reason::PageTampered(offset, section) => write!(f, "Page at 0x{:x} ({}) does not match its page hash", offset, section.as_deref().unwrap_or("headers")),
+            Reason::ContentTypeMismatch => write!(f, "The contentType attribute does not match the signed content"),
+            Reason::ContentDigestMismatch => write!(f, "The messageDigest attribute does not match the signed content"),
+            Reason::SignerCertificateMissing => write!(f, "The signer certificate is not in the signature"),
+            Reason::SignatureInvalid(subject) => write!(f, "Signature by {} is invalid", subject),
+            Reason::ChainIncomplete(subject) => write!(f, "No issuer found for {}", subject),
+            Reason::UntrustedRoot(subject) => write!(f, "Root {} is not trusted", subject),
+            Reason::CertificateSignatureInvalid(subject) => write!(f, "Signature of certificate {} is invalid", subject),
+            Reason::NotCertificateAuthority(subject) => write!(f, "{} is not a certificate authority", subject),
+            Reason::PathLengthExceeded(subject) => write!(f, "Path length constraint of {} is exceeded", subject),
+            Reason::KeyUsageInvalid(subject) => write!(f, "Key usage of {} does not allow this use", subject),
+            Reason::MissingExtendedKeyUsage(subject, usage) => write!(f, "{} is not valid for {}", subject, crate::pkcs7::oid_name(usage)),
+            Reason::UnsupportedCriticalExtension(subject, oid) => write!(f, "{} has an unsupported critical extension {}", subject, oid),
+            Reason::CertificateNotYetValid(subject) => write!(f, "{} is not yet valid", subject),
+            Reason::CertificateExpired(subject) => write!(f, "{} has expired", subject),
+            Reason::CertificateRevoked(subject, date) => write!(f, "{} was revoked on {}", subject, format_time(*date)),
+            Reason::CrlSignatureInvalid(issuer) => write!(f, "CRL of {} has an invalid signature", issuer),
+            Reason::TimestampImprintMismatch => write!(f, "The timestamp does not cover the signature"),
+            Reason::NestedSignatureInvalid(index) => write!(f, "Nested signature {} is invalid", index),
+            Reason::Timestamp(reason) => write!(f, "Timestamp: {}", reason),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Authenticode verification
+
+---------------------------
+valid: {}
+digest_algorithm: {}
+signer: {}
+timestamp: {}
+timestamp_authority: {}
+verification_time: {}",
+        self.valid, self.digest_algorithm.map(|algorithm| format!("{:?}", algorithm)).unwrap_or_else(|| String::from("-")),
+        self.signer.as_deref().unwrap_or("-"), self.timestamp.map(format_time).unwrap_or_else(|| String::from("-")),
+        self.timestamp_authority.as_deref().unwrap_or("-"), format_time(self.verification_time))?;
+        for (x, subject) in self.chain.iter().enumerate() {
+            write!(f, "\nchain[{}]: {}", x, subject)?;
+        }
+        for reason in &self.reasons {
+            write!(f, "\nreason: {}", reason)?;
+        }
+        write!(f, "\n---------------------------")?;
+        for nested in &self.nested {
+            write!(f, "\n\nNested {}", nested)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticode::security_directory_offset;
+    use crate::certificates::IMAGE_DIRECTORY_ENTRY_SECURITY;
+    use crate::pkcs7::OID_CONTENT_TYPE;
+    use crate::pkcs7::OID_COUNTERSIGNATURE;
+    use crate::pkcs7::OID_MESSAGE_DIGEST;
+    use crate::pkcs7::OID_NESTED_SIGNATURE;
+    use crate::pkcs7::OID_RFC3161_TIMESTAMP;
+    use crate::pkcs7::OID_SIGNING_TIME;
+    use crate::pkcs7::OID_TST_INFO;
+    use crate::pkcs7::tests::algorithm_identifier;
+    use crate::pkcs7::tests::attribute;
+    use crate::pkcs7::tests::basic_constraints;
+    use crate::pkcs7::tests::extended_key_usage;
+    use crate::pkcs7::tests::extension;
+    use crate::pkcs7::tests::key;
+    use crate::pkcs7::tests::name;
+    use crate::pkcs7::tests::other_key;
+    use crate::pkcs7::tests::signed;
+    use crate::pkcs7::tests::signed_data as content_info;
+    use crate::pkcs7::tests::signer_info;
+    use crate::pkcs7::tests::time;
+    use crate::pkcs7::tests::tst_info;
+    use crate::pkcs7::tests::Template;
+    use crate::pkcs7::tests::OID_SHA256_WITH_RSA;
+    use crate::pkcs7::tests::OTHER_PRIVATE_KEY;
+    use crate::rsa::RsaPrivateKey;
+    use crate::rsa::tests::bytes;
+    use crate::rsa::tests::PRIVATE_KEY;
+    use crate::sign::sign;
+    use crate::utils::fixtures::flat_image;
+
+    const NOW: &str = "240101000000Z";
+
+    // Certificate for the first key
+    fn certificate(issuer: &str, subject: &str, serial: u8, extensions: Vec<Vec<u8>>, issuer_key: &RsaPrivateKey) -> Vec<u8> {
+        let key = key();
+        let mut template = Template::new(issuer, subject, serial, &key);
+        template.extensions = extensions;
+        template.sign(issuer_key)
+    }
+
+    // Self-signed with the second key
+    fn root() -> Vec<u8> {
+        let other = other_key();
+        let mut template = Template::new("Test Root", "Test Root", 1, &other);
+        template.extensions = vec![basic_constraints(None)];
+        template.sign(&other)
+    }
+
+    fn intermediate() -> Vec<u8> {
+        certificate("Test Root", "Test CA", 1, vec![basic_constraints(None)], &other_key())
+    }
+
+    fn leaf(extensions: Vec<Vec<u8>>) -> Vec<u8> {
+        certificate("Test CA", "Test Signer", 2, extensions, &key())
+    }
+
+    fn code_signing() -> Vec<u8> {
+        leaf(vec![extended_key_usage(OID_CODE_SIGNING)])
+    }
+
+    fn tsa() -> Vec<u8> {
+        certificate("Test Root", "Test TSA", 5, vec![extended_key_usage(OID_TIME_STAMPING)], &other_key())
+    }
+
+    fn store(entries: &[&[u8]]) -> TrustStore {
+        let mut store = TrustStore::default();
+        for entry in entries {
+            store.add(entry, "test");
+        }
+        assert!(store.anomalies.is_empty());
+        store
+    }
+
+    fn sign_image(key: &str, certificate: &[u8], algorithm: HashAlgorithm, timestamp: Option<&[u8]>) -> Vec<u8> {
+        let mut file = flat_image(true, 0x1000);
+        for (x, byte) in file[0x400..].iter_mut().enumerate() {
+            *byte = (x * 11 + 1) as u8;
+        }
+        sign(&file, &crate::parse_headers(&file), &bytes(key), certificate, algorithm, timestamp).unwrap()
+    }
+
+    fn signed_image(certificate: &[u8]) -> Vec<u8> {
+        sign_image(PRIVATE_KEY, certificate, HashAlgorithm::Sha256, None)
+    }
+
+    fn check(file: &[u8], store: &TrustStore, now: &str) -> Verdict {
+        verify(file, &crate::parse_headers(file), store, time(now))
+    }
+
+    fn signature(file: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let table = parse_certificate_table(file, &crate::parse_headers(file)).unwrap();
+        let signed_data = table.signature().unwrap();
+        (signed_data.raw.clone(), signed_data.signers[0].signature.clone())
+    }
+
+    fn sequence(fields: &[Vec<u8>]) -> Vec<u8> {
+        der::encode_sequence(&fields.iter().map(|field| field.as_slice()).collect::<Vec<&[u8]>>())
+    }
+
+    // The image with the unauthenticated attribute added to its signer, which the image digest does not cover
+    fn add_unauthenticated(file: &[u8], attribute: &[u8]) -> Vec<u8> {
+        let (raw, _) = signature(file);
+        let content_info = der::parse(&raw).unwrap();
+        let signed_data = content_info.child(1).unwrap().child(0).unwrap();
+        let mut fields: Vec<Vec<u8>> = signed_data.children().map(|field| field.raw.to_vec()).collect();
+        let signers = fields.pop().unwrap();
+        let signer = der::parse(&signers).unwrap().child(0).unwrap();
+        let mut signer_fields: Vec<Vec<u8>> = signer.children().map(|field| field.raw.to_vec()).collect();
+        let mut unauthenticated = Vec::new();
+        if let Some(last) = signer.children().last().filter(|last| last.is_context(1)) {
+            signer_fields.pop();
+            unauthenticated.extend_from_slice(last.content);
+        }
+        unauthenticated.extend_from_slice(attribute);
+        signer_fields.push(der::encode(0xa1, &unauthenticated));
+        fields.push(der::encode_set(&[&sequence(&signer_fields)]));
+        let data = der::encode_sequence(&[content_info.child(0).unwrap().raw, &der::encode_context(0, &sequence(&fields))]);
+        let headers = crate::parse_headers(file);
+        let offset = headers.optional_headers.data_directories.directories[IMAGE_DIRECTORY_ENTRY_SECURITY].virtual_address as usize;
+        let length = (8 + data.len()).next_multiple_of(8);
+        let mut image = file[..offset].to_vec();
+        image.extend_from_slice(&(length as u32).to_le_bytes());
+        image.extend_from_slice(&[0x00, 0x02, 0x02, 0x00]);
+        image.extend_from_slice(&data);
+        image.resize(offset + length, 0);
+        let security = security_directory_offset(&headers);
+        image[security + 4..security + 8].copy_from_slice(&(length as u32).to_le_bytes());
+        image
+    }
+
+    // Token by the TSA over the signature value
+    fn timestamp_token(signature: &[u8], gen_time: &str) -> Vec<u8> {
+        let info = tst_info(&digest(HashAlgorithm::Sha256, signature), gen_time);
+        let authenticated = [
+            attribute(OID_CONTENT_TYPE, &der::encode_oid(OID_TST_INFO)),
+            attribute(OID_MESSAGE_DIGEST, &der::encode(der::TAG_OCTET_STRING, &digest(HashAlgorithm::Sha256, &info))),
+        ];
+        let signer = signer_info("Test Root", 5, &key(), &authenticated, &[]);
+        content_info(OID_TST_INFO, &der::encode(der::TAG_OCTET_STRING, &info), &[tsa()], &[signer])
+    }
+
+    // Legacy PKCS#9 countersignature by the TSA
+    fn countersignature(signature: &[u8], signing_time: &str) -> Vec<u8> {
+        let authenticated = [
+            attribute(OID_SIGNING_TIME, &der::encode(der::TAG_UTC_TIME, signing_time.as_bytes())),
+            attribute(OID_MESSAGE_DIGEST, &der::encode(der::TAG_OCTET_STRING, &digest(HashAlgorithm::Sha256, signature))),
+        ];
+        attribute(OID_COUNTERSIGNATURE, &signer_info("Test Root", 5, &key(), &authenticated, &[]))
+    }
+
+    fn crl(issuer_key: &RsaPrivateKey, serial: u8, revocation_date: &str) -> Vec<u8> {
+        let revoked = der::encode_sequence(&[&der::encode_integer(&[serial]), &der::encode(der::TAG_UTC_TIME, revocation_date.as_bytes())]);
+        let tbs = der::encode_sequence(&[
+            &der::encode_integer(&[1]),
+            &algorithm_identifier(OID_SHA256_WITH_RSA),
+            &name("Test CA"),
+            &der::encode(der::TAG_UTC_TIME, b"200101000000Z"),
+            &der::encode(der::TAG_UTC_TIME, b"300101000000Z"),
+            &der::encode_sequence(&[&revoked]),
+        ]);
+        signed(&tbs, OID_SHA256_WITH_RSA, issuer_key)
+    }
+
+    #[test]
+    fn valid_signature() {
+        let file = signed_image(&code_signing());
+        let verdict = check(&file, &store(&[&root(), &intermediate()]), NOW);
+        assert_eq!(verdict.reasons, vec![]);
+        assert!(verdict.valid);
+        assert_eq!(verdict.digest_algorithm, Some(HashAlgorithm::Sha256));
+        assert_eq!(verdict.signer.as_deref(), Some("CN=Test Signer"));
+        assert_eq!(verdict.chain, vec!["CN=Test Signer", "CN=Test CA", "CN=Test Root"]);
+        assert_eq!(verdict.timestamp, None);
+        assert_eq!(verdict.verification_time, time(NOW));
+
+        let mut tampered = file.clone();
+        tampered[0x800] ^= 1;
+        assert_eq!(check(&tampered, &store(&[&root(), &intermediate()]), NOW).reasons, vec![Reason::ImageDigestMismatch]);
+        let unsigned = flat_image(true, 0x1000);
+        assert_eq!(check(&unsigned, &TrustStore::default(), NOW).reasons, vec![Reason::NoSignature]);
+    }
+
+    #[test]
+    fn trust_anchors() {
+        let key = key();
+        let self_signed = certificate("Test Signer", "Test Signer", 2, Vec::new(), &key);
+        let file = signed_image(&self_signed);
+        assert_eq!(check(&file, &TrustStore::default(), NOW).reasons, vec![Reason::UntrustedRoot(String::from("CN=Test Signer"))]);
+        let verdict = check(&file, &store(&[&self_signed]), NOW);
+        assert!(verdict.valid);
+        assert_eq!(verdict.chain, vec!["CN=Test Signer"]);
+        // Anchors match on the encoding, a certificate with the same name and another key does not vouch for it
+        let other = other_key();
+        let impostor = Template::new("Test Signer", "Test Signer", 2, &other).sign(&other);
+        assert_eq!(check(&file, &store(&[&impostor]), NOW).reasons, vec![Reason::CertificateSignatureInvalid(String::from("CN=Test Signer"))]);
+        // Certificates that are not self-signed only help building the chain
+        let file = signed_image(&code_signing());
+        assert_eq!(check(&file, &store(&[&intermediate()]), NOW).reasons, vec![Reason::ChainIncomplete(String::from("CN=Test CA"))]);
+
+        let mut store = TrustStore::default();
+        store.add(b"junk", "junk.bin");
+        assert_eq!(store.anomalies, vec![String::from("junk.bin is neither a certificate nor a CRL")]);
+    }
+
+    #[test]
+    fn expired_certificate_and_timestamp() {
+        let key = key();
+        let mut template = Template::new("Test CA", "Test Signer", 2, &key);
+        template.not_after = "230101000000Z";
+        let expired = template.sign(&key);
+        let store = store(&[&root(), &intermediate(), &tsa()]);
+        let file = signed_image(&expired);
+        assert_eq!(check(&file, &store, NOW).reasons, vec![Reason::CertificateExpired(String::from("CN=Test Signer"))]);
+        assert_eq!(check(&file, &store, "190101000000Z").reasons, vec![
+            Reason::CertificateNotYetValid(String::from("CN=Test Signer")),
+            Reason::CertificateNotYetValid(String::from("CN=Test CA")),
+            Reason::CertificateNotYetValid(String::from("CN=Test Root")),
+        ]);
+
+        // An RFC 3161 token from before the expiry moves the check to its time
+        let (_, value) = signature(&file);
+        let token = timestamp_token(&value, "220101000000Z");
+        let stamped = sign_image(PRIVATE_KEY, &expired, HashAlgorithm::Sha256, Some(&token));
+        let verdict = check(&stamped, &store, NOW);
+        assert_eq!(verdict.reasons, vec![]);
+        assert_eq!(verdict.timestamp, Some(time("220101000000Z")));
+        assert_eq!(verdict.timestamp_authority.as_deref(), Some("CN=Test TSA"));
+        assert_eq!(verdict.verification_time, time("220101000000Z"));
+
+        // A token from after the expiry does not help
+        let late = sign_image(PRIVATE_KEY, &expired, HashAlgorithm::Sha256, Some(&timestamp_token(&value, "230601000000Z")));
+        assert_eq!(check(&late, &store, NOW).reasons, vec![Reason::CertificateExpired(String::from("CN=Test Signer"))]);
+    }
+
+    #[test]
+    fn extended_key_usage_and_critical_extensions() {
+        let store = store(&[&root(), &intermediate()]);
+        let timestamping = signed_image(&leaf(vec![extended_key_usage(OID_TIME_STAMPING)]));
+        assert_eq!(check(&timestamping, &store, NOW).reasons, vec![Reason::MissingExtendedKeyUsage(String::from("CN=Test Signer"), String::from(OID_CODE_SIGNING))]);
+        assert!(check(&signed_image(&leaf(Vec::new())), &store, NOW).valid);
+        assert!(check(&signed_image(&leaf(vec![extended_key_usage(OID_ANY_EXTENDED_KEY_USAGE)])), &store, NOW).valid);
+
+        let critical = signed_image(&leaf(vec![extension("1.2.3.4", true, &[5, 0])]));
+        assert_eq!(check(&critical, &store, NOW).reasons, vec![Reason::UnsupportedCriticalExtension(String::from("CN=Test Signer"), String::from("1.2.3.4"))]);
+        assert!(check(&signed_image(&leaf(vec![extension("1.2.3.4", false, &[5, 0])])), &store, NOW).valid);
+    }
+
+    #[test]
+    fn basic_constraints_and_path_length() {
+        let file = signed_image(&code_signing());
+        let not_ca = certificate("Test Root", "Test CA", 1, Vec::new(), &other_key());
+        assert_eq!(check(&file, &store(&[&root(), &not_ca]), NOW).reasons, vec![Reason::NotCertificateAuthority(String::from("CN=Test CA"))]);
+
+        // Root, Policy CA with a path length of 0, Test CA, signer
+        let policy = certificate("Test Root", "Policy CA", 3, vec![basic_constraints(Some(0))], &other_key());
+        let issuing = certificate("Policy CA", "Test CA", 4, vec![basic_constraints(None)], &key());
+        let verdict = check(&file, &store(&[&root(), &policy, &issuing]), NOW);
+        assert_eq!(verdict.chain, vec!["CN=Test Signer", "CN=Test CA", "CN=Policy CA", "CN=Test Root"]);
+        assert_eq!(verdict.reasons, vec![Reason::PathLengthExceeded(String::from("CN=Policy CA"))]);
+        let policy = certificate("Test Root", "Policy CA", 3, vec![basic_constraints(Some(1))], &other_key());
+        assert!(check(&file, &store(&[&root(), &policy, &issuing]), NOW).valid);
+    }
+
+    #[test]
+    fn chain_length() {
+        let file = signed_image(&code_signing());
+        // Test CA issued by CA 1, issued by CA 2 and so on, the last one by the root
+        let chain = |count: u8| -> Vec<Vec<u8>> {
+            let mut certificates = vec![root()];
+            let names: Vec<String> = (0..count).map(|x| if x == 0 { String::from("Test CA") } else { format!("CA {}", x) }).collect();
+            for x in 0..count as usize {
+                let issuer = names.get(x + 1).map(|name| name.as_str()).unwrap_or("Test Root");
+                let issuer_key = if x + 1 == count as usize { other_key() } else { key() };
+                certificates.push(certificate(issuer, &names[x], 10 + x as u8, vec![basic_constraints(None)], &issuer_key));
+            }
+            certificates
+        };
+        let longest = chain(MAX_CHAIN_LENGTH as u8 - 1);
+        let verdict = check(&file, &store(&longest.iter().map(|certificate| certificate.as_slice()).collect::<Vec<&[u8]>>()), NOW);
+        assert_eq!(verdict.reasons, vec![]);
+        assert_eq!(verdict.chain.len(), MAX_CHAIN_LENGTH + 1);
+        let too_long = chain(MAX_CHAIN_LENGTH as u8);
+        let verdict = check(&file, &store(&too_long.iter().map(|certificate| certificate.as_slice()).collect::<Vec<&[u8]>>()), NOW);
+        assert_eq!(verdict.reasons, vec![Reason::ChainIncomplete(String::from("CN=CA 7"))]);
+    }
+
+    #[test]
+    fn revocation() {
+        let file = signed_image(&code_signing());
+        let revoked = crl(&key(), 2, "220101000000Z");
+        let verdict = check(&file, &store(&[&root(), &intermediate(), &revoked]), NOW);
+        assert_eq!(verdict.reasons, vec![Reason::CertificateRevoked(String::from("CN=Test Signer"), time("220101000000Z"))]);
+        // Revoked after the time of the check
+        assert!(check(&file, &store(&[&root(), &intermediate(), &revoked]), "211201000000Z").valid);
+        assert!(check(&file, &store(&[&root(), &intermediate(), &crl(&key(), 3, "220101000000Z")]), NOW).valid);
+        let forged = crl(&other_key(), 2, "220101000000Z");
+        assert_eq!(check(&file, &store(&[&root(), &intermediate(), &forged]), NOW).reasons, vec![Reason::CrlSignatureInvalid(String::from("CN=Test CA"))]);
+    }
+
+    #[test]
+    fn rfc3161_and_countersignatures() {
+        let store = store(&[&root(), &intermediate(), &tsa()]);
+        let file = signed_image(&code_signing());
+        let (_, value) = signature(&file);
+
+        let countersigned = add_unauthenticated(&file, &countersignature(&value, "210601000000Z"));
+        let verdict = check(&countersigned, &store, NOW);
+        assert_eq!(verdict.reasons, vec![]);
+        assert_eq!(verdict.timestamp, Some(time("210601000000Z")));
+        assert_eq!(verdict.timestamp_authority.as_deref(), Some("CN=Test TSA"));
+
+        // The RFC 3161 token takes precedence over the countersignature
+        let token = timestamp_token(&value, "220101000000Z");
+        let both = add_unauthenticated(&countersigned, &attribute(OID_RFC3161_TIMESTAMP, &token));
+        assert_eq!(check(&both, &store, NOW).timestamp, Some(time("220101000000Z")));
+
+        let other = add_unauthenticated(&file, &attribute(OID_RFC3161_TIMESTAMP, &timestamp_token(b"another signature", "220101000000Z")));
+        let verdict = check(&other, &store, NOW);
+        assert_eq!(verdict.reasons, vec![Reason::Timestamp(Box::new(Reason::TimestampImprintMismatch))]);
+        assert_eq!(verdict.timestamp, None);
+        let other = add_unauthenticated(&file, &countersignature(b"another signature", "210601000000Z"));
+        assert_eq!(check(&other, &store, NOW).reasons, vec![Reason::Timestamp(Box::new(Reason::ContentDigestMismatch))]);
+
+        // The TSA certificate needs the timeStamping usage
+        let store = self::store(&[&root(), &intermediate(), &certificate("Test Root", "Test TSA", 5, vec![extended_key_usage(OID_CODE_SIGNING)], &other_key())]);
+        assert_eq!(check(&countersigned, &store, NOW).reasons, vec![Reason::Timestamp(Box::new(Reason::MissingExtendedKeyUsage(String::from("CN=Test TSA"), String::from(OID_TIME_STAMPING))))]);
+    }
+
+    #[test]
+    fn nested_signatures() {
+        let store = store(&[&root(), &intermediate()]);
+        let file = signed_image(&code_signing());
+        let (nested, _) = signature(&sign_image(PRIVATE_KEY, &code_signing(), HashAlgorithm::Sha1, None));
+        let verdict = check(&add_unauthenticated(&file, &attribute(OID_NESTED_SIGNATURE, &nested)), &store, NOW);
+        assert_eq!(verdict.reasons, vec![]);
+        assert_eq!(verdict.nested.len(), 1);
+        assert_eq!(verdict.nested[0].digest_algorithm, Some(HashAlgorithm::Sha1));
+        assert!(verdict.nested[0].valid);
+
+        let other = other_key();
+        let rogue = Template::new("Rogue", "Rogue", 9, &other).sign(&other);
+        let (nested, _) = signature(&sign_image(OTHER_PRIVATE_KEY, &rogue, HashAlgorithm::Sha256, None));
+        let verdict = check(&add_unauthenticated(&file, &attribute(OID_NESTED_SIGNATURE, &nested)), &store, NOW);
+        assert!(!verdict.valid);
+        assert_eq!(verdict.reasons, vec![Reason::NestedSignatureInvalid(0)]);
+        assert_eq!(verdict.nested[0].reasons, vec![Reason::UntrustedRoot(String::from("CN=Rogue"))]);
+    }
+}
@@ -0,0 +1,510 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+
+use std::fmt;
+use std::vec::Vec;
+use crate::structs::Headers;
+use crate::resources::Resources;
+use crate::resources::ResourceType;
+use crate::resources::resource_data;
+use crate::utils::read_u16_at;
+use crate::utils::read_u32_at;
+
+const VS_FFI_SIGNATURE: u32 = 0xfeef04bd;
+
+// Structures definitions
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub enum FileFlag {
+    VS_FF_DEBUG = 0x01,
+    VS_FF_PRERELEASE = 0x02,
+    VS_FF_PATCHED = 0x04,
+    VS_FF_PRIVATEBUILD = 0x08,
+    VS_FF_INFOINFERRED = 0x10,
+    VS_FF_SPECIALBUILD = 0x20,
+}
+
+#[derive(Default)]
+pub struct FixedFileInfo {
+    pub signature: u32,
+    pub struc_version: u32,
+    pub file_version_ms: u32,
+    pub file_version_ls: u32,
+    pub product_version_ms: u32,
+    pub product_version_ls: u32,
+    pub file_flags_mask: u32,
+    pub file_flags: u32,
+    pub file_flags_list: Vec<FileFlag>,
+    pub file_os: u32,
+    pub file_type: u32,
+    pub file_subtype: u32,
+    pub file_date_ms: u32,
+    pub file_date_ls: u32,
+}
+
+#[derive(Default)]
+pub struct StringTable {
+    // Eight hex digits, language followed by code page
+    pub key: String,
+    pub language: u16,
+    pub code_page: u16,
+    pub strings: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct VersionInfo {
+    pub fixed_file_info: Option<FixedFileInfo>,
+    pub string_tables: Vec<StringTable>,
+    // (language, code page) pairs from VarFileInfo\Translation
+    pub translations: Vec<(u16, u16)>,
+    pub anomalies: Vec<String>,
+}
+
+// A generic version block: wLength, wValueLength, wType, szKey, Value, Children
+struct Block<'a> {
+    key: String,
+    value_length: u16,
+    value_type: u16,
+    value: &'a [u8],
+    value_start: usize,
+    children_start: usize,
+    end: usize,
+}
+
+
+// Conversion implementation for the structs
+
+impl FileFlag {
+    pub fn from_u32(val: u32) -> Option<FileFlag> {
+        match val {
+            0x01 => Some(FileFlag::VS_FF_DEBUG),
+            0x02 => Some(FileFlag::VS_FF_PRERELEASE),
+            0x04 => Some(FileFlag::VS_FF_PATCHED),
+            0x08 => Some(FileFlag::VS_FF_PRIVATEBUILD),
+            0x10 => Some(FileFlag::VS_FF_INFOINFERRED),
+            0x20 => Some(FileFlag::VS_FF_SPECIALBUILD),
+            _ => None,
+        }
+    }
+}
+
+impl FixedFileInfo {
+    pub fn file_version(&self) -> String {
+        format_version(self.file_version_ms, self.file_version_ls)
+    }
+
+    pub fn product_version(&self) -> String {
+        format_version(self.product_version_ms, self.product_version_ls)
+    }
+
+    pub fn file_date(&self) -> u64 {
+        ((self.file_date_ms as u64) << 32) | self.file_date_ls as u64
+    }
+
+    pub fn file_os_name(&self) -> &'static str {
+        match self.file_os {
+            0x00000000 => "VOS_UNKNOWN",
+            0x00010000 => "VOS_DOS",
+            0x00020000 => "VOS_OS216",
+            0x00030000 => "VOS_OS232",
+            0x00040000 => "VOS_NT",
+            0x00000001 => "VOS__WINDOWS16",
+            0x00000002 => "VOS__PM16",
+            0x00000003 => "VOS__PM32",
+            0x00000004 => "VOS__WINDOWS32",
+            0x00010001 => "VOS_DOS_WINDOWS16",
+            0x00010004 => "VOS_DOS_WINDOWS32",
+            0x00020002 => "VOS_OS216_PM16",
+            0x00030003 => "VOS_OS232_PM32",
+            0x00040004 => "VOS_NT_WINDOWS32",
+            _ => "UNKNOWN",
+        }
+    }
+
+    pub fn file_type_name(&self) -> &'static str {
+        match self.file_type {
+            0 => "VFT_UNKNOWN",
+            1 => "VFT_APP",
+            2 => "VFT_DLL",
+            3 => "VFT_DRV",
+            4 => "VFT_FONT",
+            5 => "VFT_VXD",
+            7 => "VFT_STATIC_LIB",
+            _ => "UNKNOWN",
+        }
+    }
+
+    // The subtype is only meaningful for drivers and fonts, for VxDs it holds the virtual device identifier
+    pub fn file_subtype_name(&self) -> &'static str {
+        match (self.file_type, self.file_subtype) {
+            (_, 0) => "VFT2_UNKNOWN",
+            (3, 1) => "VFT2_DRV_PRINTER",
+            (3, 2) => "VFT2_DRV_KEYBOARD",
+            (3, 3) => "VFT2_DRV_LANGUAGE",
+            (3, 4) => "VFT2_DRV_DISPLAY",
+            (3, 5) => "VFT2_DRV_MOUSE",
+            (3, 6) => "VFT2_DRV_NETWORK",
+            (3, 7) => "VFT2_DRV_SYSTEM",
+            (3, 8) => "VFT2_DRV_INSTALLABLE",
+            (3, 9) => "VFT2_DRV_SOUND",
+            (3, 10) => "VFT2_DRV_COMM",
+            (3, 12) => "VFT2_DRV_VERSIONED_PRINTER",
+            (4, 1) => "VFT2_FONT_RASTER",
+            (4, 2) => "VFT2_FONT_VECTOR",
+            (4, 3) => "VFT2_FONT_TRUETYPE",
+            _ => "UNKNOWN",
+        }
+    }
+}
+
+fn format_version(ms: u32, ls: u32) -> String {
+    format!("{}.{}.{}.{}", ms >> 16, ms & 0xffff, ls >> 16, ls & 0xffff)
+}
+
+impl StringTable {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+
+// Parsing
+
+fn align4(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+// Reads a NUL terminated UTF-16 string, returning it together with the offset past the terminator
+fn read_sz(buffer: &[u8], mut index: usize) -> (String, usize) {
+    let mut units = Vec::new();
+    while let Some(unit) = read_u16_at(buffer, index) {
+        index += 2;
+        if unit == 0 {
+            break;
+        }
+        units.push(unit);
+    }
+    (String::from_utf16_lossy(&units), index)
+}
+
+// Offsets are relative to the start of the version resource, which is where the 32-bit alignment is anchored
+fn parse_block<'a>(data: &'a [u8], start: usize, end: usize, anomalies: &mut Vec<String>) -> Option<Block<'a>> {
+    let length = read_u16_at(data, start)? as usize;
+    let value_length = read_u16_at(data, start + 2)?;
+    let value_type = read_u16_at(data, start + 4)?;
+    if length < 6 {
+        anomalies.push(format!("Version block at offset 0x{:x} has invalid length 0x{:x}", start, length));
+        return None;
+    }
+    let mut block_end = start + length;
+    if block_end > end {
+        anomalies.push(format!("Version block at offset 0x{:x} overruns its parent", start));
+        block_end = end;
+    }
+    let (key, key_end) = read_sz(&data[..block_end], start + 6);
+    let value_start = align4(key_end).min(block_end);
+    // Text values are measured in characters, binary ones in bytes
+    let value_size = if value_type == 1 { value_length as usize * 2 } else { value_length as usize };
+    let value_end = (value_start + value_size).min(block_end);
+    let children_start = align4(value_end).min(block_end);
+    Some(Block {
+        key,
+        value_length,
+        value_type,
+        value: &data[value_start..value_end],
+        value_start,
+        children_start,
+        end: block_end,
+    })
+}
+
+// Walks consecutive sibling blocks packed in a children area
+fn parse_children<'a>(data: &'a [u8], start: usize, end: usize, anomalies: &mut Vec<String>) -> Vec<Block<'a>> {
+    let mut blocks = Vec::new();
+    let mut index = align4(start);
+    while index + 6 <= end {
+        // Some linkers pad with zeroed words between blocks
+        if read_u16_at(data, index) == Some(0) {
+            index += 2;
+            continue;
+        }
+        match parse_block(data, index, end, anomalies) {
+            Some(block) => {
+                index = align4(block.end);
+                blocks.push(block);
+            }
+            None => break,
+        }
+    }
+    blocks
+}
+
+fn parse_fixed_file_info(value: &[u8], anomalies: &mut Vec<String>) -> Option<FixedFileInfo> {
+    if value.len() < 52 {
+        if !value.is_empty() {
+            anomalies.push(format!("VS_FIXEDFILEINFO is truncated to 0x{:x} bytes", value.len()));
+        }
+        return None;
+    }
+    let mut info = FixedFileInfo {
+        signature: read_u32_at(value, 0)?,
+        struc_version: read_u32_at(value, 4)?,
+        file_version_ms: read_u32_at(value, 8)?,
+        file_version_ls: read_u32_at(value, 12)?,
+        product_version_ms: read_u32_at(value, 16)?,
+        product_version_ls: read_u32_at(value, 20)?,
+        file_flags_mask: read_u32_at(value, 24)?,
+        file_flags: read_u32_at(value, 28)?,
+        file_flags_list: Vec::new(),
+        file_os: read_u32_at(value, 32)?,
+        file_type: read_u32_at(value, 36)?,
+        file_subtype: read_u32_at(value, 40)?,
+        file_date_ms: read_u32_at(value, 44)?,
+        file_date_ls: read_u32_at(value, 48)?,
+    };
+    if info.signature != VS_FFI_SIGNATURE {
+        anomalies.push(format!("VS_FIXEDFILEINFO has signature 0x{:x} instead of 0x{:x}", info.signature, VS_FFI_SIGNATURE));
+    }
+    // Only the bits declared valid by the mask are meaningful
+    let flags = info.file_flags & info.file_flags_mask;
+    let mut bit: u32 = 1;
+    while bit <= 0x20 {
+        if flags & bit != 0 {
+            info.file_flags_list.extend(FileFlag::from_u32(bit));
+        }
+        bit <<= 1;
+    }
+    Some(info)
+}
+
+fn parse_string_table(data: &[u8], table: &Block, anomalies: &mut Vec<String>) -> StringTable {
+    let mut string_table = StringTable {
+        key: table.key.clone(),
+        ..Default::default()
+    };
+    if table.key.len() == 8 && table.key.is_ascii() {
+        string_table.language = u16::from_str_radix(&table.key[0..4], 16).unwrap_or(0);
+        string_table.code_page = u16::from_str_radix(&table.key[4..8], 16).unwrap_or(0);
+    } else {
+        anomalies.push(format!("StringTable key \"{}\" is not a language and code page pair", table.key));
+    }
+    for string in parse_children(data, table.children_start, table.end, anomalies) {
+        // wValueLength is unreliable in the wild, so the value runs up to its terminator
+        let (value, _) = read_sz(&data[..string.end], string.value_start);
+        string_table.strings.push((string.key, value));
+    }
+    string_table
+}
+
+pub fn parse_version_resource(data: &[u8]) -> VersionInfo {
+    let mut version_info = VersionInfo::default();
+    let root = match parse_block(data, 0, data.len(), &mut version_info.anomalies) {
+        Some(root) => root,
+        None => {
+            version_info.anomalies.push(String::from("Version resource is too short"));
+            return version_info;
+        }
+    };
+    if root.key != "VS_VERSION_INFO" {
+        version_info.anomalies.push(format!("Root version block has key \"{}\" instead of VS_VERSION_INFO", root.key));
+    }
+    version_info.fixed_file_info = parse_fixed_file_info(root.value, &mut version_info.anomalies);
+
+    for child in parse_children(data, root.children_start, root.end, &mut version_info.anomalies) {
+        let grandchildren = parse_children(data, child.children_start, child.end, &mut version_info.anomalies);
+        match child.key.as_str() {
+            "StringFileInfo" => {
+                for table in grandchildren {
+                    let string_table = parse_string_table(data, &table, &mut version_info.anomalies);
+                    version_info.string_tables.push(string_table);
+                }
+            }
+            "VarFileInfo" => {
+                for var in grandchildren.iter().filter(|var| var.key == "Translation") {
+                    for translation in var.value.chunks_exact(4) {
+                        let language = u16::from_le_bytes([translation[0], translation[1]]);
+                        let code_page = u16::from_le_bytes([translation[2], translation[3]]);
+                        version_info.translations.push((language, code_page));
+                    }
+                }
+            }
+            key => version_info.anomalies.push(format!("Unexpected version block \"{}\"", key)),
+        }
+    }
+    version_info
+}
+
+// Decodes the first RT_VERSION resource, None if the image has none
+pub fn parse_version_info(file: &[u8], headers: &Headers, resources: &Resources) -> Option<VersionInfo> {
+    let resource = resources.resources.iter().find(|resource| resource.resource_type == ResourceType::RT_VERSION)?;
+    match resource_data(file, headers, resource) {
+        Some(data) => Some(parse_version_resource(data)),
+        None => {
+            let mut version_info = VersionInfo::default();
+            version_info.anomalies.push(format!("Version resource at RVA 0x{:x} is not backed by the file", resource.data.data_rva));
+            Some(version_info)
+        }
+    }
+}
+
+
+// Display trait implementation for the structs
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Version Information")?;
+        if let Some(fixed_file_info) = &self.fixed_file_info {
+            write!(f, "{}", fixed_file_info)?;
+        }
+        for string_table in &self.string_tables {
+            write!(f, "{}", string_table)?;
+        }
+        write!(f, "
+Translations
+
+---------------------------")?;
+        for (language, code_page) in &self.translations {
+            write!(f, "
+language: 0x{:04x} code_page: {}", language, code_page)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+impl fmt::Display for FixedFileInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+Fixed File Info
+
+---------------------------
+signature: 0x{:x}
+struc_version: 0x{:x}
+file_version: {}
+product_version: {}
+file_flags_mask: 0x{:x}
+file_flags: 0x{:x} {:?}
+file_os: 0x{:x} ({})
+file_type: 0x{:x} ({})
+file_subtype: 0x{:x} ({})
+file_date: 0x{:x}
+---------------------------",
+        self.signature, self.struc_version, self.file_version(), self.product_version(), self.file_flags_mask, self.file_flags, self.file_flags_list, self.file_os, self.file_os_name(), self.file_type, self.file_type_name(), self.file_subtype, self.file_subtype_name(), self.file_date())
+    }
+}
+
+impl fmt::Display for StringTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "
+String Table {} (language 0x{:04x}, code page {})
+
+---------------------------",
+        self.key, self.language, self.code_page)?;
+        for (key, value) in &self.strings {
+            write!(f, "
+{}: {}", key, value)?;
+        }
+        write!(f, "
+---------------------------")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::ResourceId;
+    use crate::resources::parse_resources;
+    use crate::resources::tests::resource_image;
+    use crate::utils::fixtures::utf16;
+
+    fn pad(data: &mut Vec<u8>) {
+        data.resize(data.len().next_multiple_of(4), 0);
+    }
+
+    // wLength, wValueLength, wType, szKey, Value and Children, each part 32-bit aligned
+    fn block(key: &str, value_type: u16, value_length: u16, value: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = vec![0; 6];
+        data.extend_from_slice(&utf16(key));
+        data.extend_from_slice(&[0, 0]);
+        pad(&mut data);
+        data.extend_from_slice(value);
+        for child in children {
+            pad(&mut data);
+            data.extend_from_slice(child);
+        }
+        let length = data.len() as u16;
+        data[0..2].copy_from_slice(&length.to_le_bytes());
+        data[2..4].copy_from_slice(&value_length.to_le_bytes());
+        data[4..6].copy_from_slice(&value_type.to_le_bytes());
+        data
+    }
+
+    fn string(key: &str, value: &str) -> Vec<u8> {
+        let mut text = utf16(value);
+        text.extend_from_slice(&[0, 0]);
+        block(key, 1, value.len() as u16 + 1, &text, &[])
+    }
+
+    fn fixed_file_info() -> Vec<u8> {
+        [VS_FFI_SIGNATURE, 0x10000, 0x00010002, 0x00030004, 0x00010002, 0x00030000, 0x3f, 0x21, 0x40004, 1, 0, 0, 0]
+            .iter().flat_map(|value: &u32| value.to_le_bytes()).collect()
+    }
+
+    fn version_resource(table_key: &str) -> Vec<u8> {
+        let table = block(table_key, 1, 0, &[], &[string("CompanyName", "Acme"), string("FileVersion", "1.2.3.4")]);
+        let string_file_info = block("StringFileInfo", 1, 0, &[], &[table]);
+        let translation = block("Translation", 0, 4, &[0x09, 0x04, 0xb0, 0x04], &[]);
+        let var_file_info = block("VarFileInfo", 1, 0, &[], &[translation]);
+        block("VS_VERSION_INFO", 0, 52, &fixed_file_info(), &[string_file_info, var_file_info])
+    }
+
+    #[test]
+    fn decodes_version_resource() {
+        let data = version_resource("040904b0");
+        let (file, headers) = resource_image(&[(ResourceId::Id(16), ResourceId::Id(1), 0x409, &data)]);
+        let resources = parse_resources(&file, &headers);
+        let version_info = parse_version_info(&file, &headers, &resources).unwrap();
+        assert!(version_info.anomalies.is_empty(), "{:?}", version_info.anomalies);
+        let fixed = version_info.fixed_file_info.as_ref().unwrap();
+        assert_eq!(fixed.file_version(), "1.2.3.4");
+        assert_eq!(fixed.product_version(), "1.2.3.0");
+        assert_eq!(fixed.file_os_name(), "VOS_NT_WINDOWS32");
+        assert!(matches!(fixed.file_flags_list[..], [FileFlag::VS_FF_DEBUG, FileFlag::VS_FF_SPECIALBUILD]));
+        let table = &version_info.string_tables[0];
+        assert_eq!((table.language, table.code_page), (0x409, 0x4b0));
+        assert_eq!(table.strings, vec![(String::from("CompanyName"), String::from("Acme")), (String::from("FileVersion"), String::from("1.2.3.4"))]);
+        assert_eq!(version_info.translations, vec![(0x409, 0x4b0)]);
+    }
+
+    #[test]
+    fn rejects_non_ascii_table_key() {
+        // Eight bytes with a character straddling the language and code page halves
+        let version_info = parse_version_resource(&version_resource("040é904"));
+        assert_eq!(version_info.string_tables[0].language, 0);
+        assert_eq!(version_info.anomalies, vec![String::from("StringTable key \"040é904\" is not a language and code page pair")]);
+    }
+
+    #[test]
+    fn malformed_blocks() {
+        let other = block("Other", 0, 0, &[], &[]);
+        let mut data = block("VS_VERSION_INFX", 0, 8, &[0xbd, 0x04, 0xef, 0xfe, 0, 0, 0, 0], std::slice::from_ref(&other));
+        // The child claims more bytes than its parent holds
+        let child = data.len() - other.len();
+        data[child] = 0x40;
+        let version_info = parse_version_resource(&data);
+        assert!(version_info.fixed_file_info.is_none());
+        assert!(version_info.string_tables.is_empty());
+        assert!(version_info.translations.is_empty());
+        assert_eq!(version_info.anomalies, vec![
+            String::from("Root version block has key \"VS_VERSION_INFX\" instead of VS_VERSION_INFO"),
+            String::from("VS_FIXEDFILEINFO is truncated to 0x8 bytes"),
+            format!("Version block at offset 0x{:x} overruns its parent", child),
+            String::from("Unexpected version block \"Other\""),
+        ]);
+        let version_info = parse_version_resource(&[4, 0, 0, 0]);
+        assert!(version_info.fixed_file_info.is_none());
+        assert_eq!(version_info.anomalies, vec![String::from("Version resource is too short")]);
+    }
+}